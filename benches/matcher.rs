@@ -0,0 +1,72 @@
+//! Edit distance of a query and every word of a made up index, the way a fuzzy search
+//! compares them, against `strsim` as a baseline.
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use sozluk::matcher::{LevenshteinMatcher, WordMatcher};
+use strsim::{generic_levenshtein, levenshtein};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// `count` words of 3 to 14 letters of `alphabet`, the same ones on every run.
+fn words(alphabet: &[char], count: usize) -> Vec<String> {
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state as usize
+    };
+    (0..count)
+        .map(|_| {
+            let length = 3 + next() % 12;
+            (0..length)
+                .map(|_| alphabet[next() % alphabet.len()])
+                .collect()
+        })
+        .collect()
+}
+
+fn edit_distance(c: &mut Criterion) {
+    let matcher = LevenshteinMatcher { level: 2 };
+    let ascii: Vec<char> = ('a'..='z').collect();
+    let ascii_words = words(&ascii, 10_000);
+    c.bench_function("myers distance ascii", |b| {
+        b.iter(|| {
+            ascii_words
+                .iter()
+                .map(|w| matcher.distance(black_box("kitaplik"), w).unwrap())
+                .sum::<usize>()
+        })
+    });
+    c.bench_function("strsim distance ascii", |b| {
+        b.iter(|| {
+            ascii_words
+                .iter()
+                .map(|w| levenshtein(black_box("kitaplik"), w))
+                .sum::<usize>()
+        })
+    });
+
+    let turkish: Vec<char> = "abcçdefgğhıijklmnoöprsştuüvyz".chars().collect();
+    let turkish_words = words(&turkish, 10_000);
+    c.bench_function("myers distance graphemes", |b| {
+        b.iter(|| {
+            turkish_words
+                .iter()
+                .map(|w| matcher.distance(black_box("kitaplık"), w).unwrap())
+                .sum::<usize>()
+        })
+    });
+    c.bench_function("strsim distance graphemes", |b| {
+        b.iter(|| {
+            turkish_words
+                .iter()
+                .map(|w| {
+                    let query: Vec<&str> = black_box("kitaplık").graphemes(true).collect();
+                    generic_levenshtein(&query, &w.graphemes(true).collect::<Vec<&str>>())
+                })
+                .sum::<usize>()
+        })
+    });
+}
+
+criterion_group!(benches, edit_distance);
+criterion_main!(benches);
@@ -0,0 +1,365 @@
+//! Decodes PNG and uncompressed BMP images to pixels, the formats sixels can be drawn
+//! from without depending on an image decoder. Interlaced PNGs and compressed or
+//! paletted BMPs aren't decoded.
+use flate2::read::ZlibDecoder;
+use std::convert::TryInto;
+use std::io::Read;
+
+/// Most pixels an image is decoded with, so a header can't ask for gigabytes.
+const MAX_PIXELS: usize = 4_000_000;
+
+/// Pixels of an image, four bytes of RGBA each, row by row from the top.
+#[derive(Debug, PartialEq)]
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Bitmap {
+    /// Pixels of the PNG or BMP image `data`, `None` if it's neither or can't be
+    /// decoded.
+    pub fn decode(data: &[u8]) -> Option<Bitmap> {
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            decode_png(data)
+        } else if data.starts_with(b"BM") {
+            decode_bmp(data)
+        } else {
+            None
+        }
+    }
+
+    /// RGBA of the pixel at `x`, `y`.
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let n = (y * self.width + x) * 4;
+        self.pixels[n..n + 4].try_into().unwrap()
+    }
+
+    /// Scaled down by nearest neighbour to fit in `max_width` by `max_height`, keeping
+    /// its aspect ratio. Images that fit are returned as they are.
+    pub fn fit(self, max_width: usize, max_height: usize) -> Bitmap {
+        if self.width <= max_width && self.height <= max_height {
+            return self;
+        }
+        //The side furthest over its limit decides the scale.
+        let (width, height) = if self.width * max_height >= self.height * max_width {
+            (max_width, (self.height * max_width / self.width).max(1))
+        } else {
+            ((self.width * max_height / self.height).max(1), max_height)
+        };
+        let mut pixels = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            for x in 0..width {
+                pixels.extend_from_slice(
+                    &self.pixel(x * self.width / width, y * self.height / height),
+                );
+            }
+        }
+        Bitmap {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+fn be_u32(bytes: &[u8]) -> Option<usize> {
+    Some(u32::from_be_bytes(bytes.get(..4)?.try_into().ok()?) as usize)
+}
+
+fn le_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// `n`th sample of `row` of samples `depth` bits each, the high byte of 16 bit ones.
+fn sample(row: &[u8], n: usize, depth: u8) -> u8 {
+    match depth {
+        16 => row[2 * n],
+        8 => row[n],
+        _ => {
+            let bit = n * depth as usize;
+            (row[bit / 8] >> (8 - depth as usize - bit % 8)) & ((1 << depth) - 1)
+        }
+    }
+}
+
+/// Pixels of a PNG image. The transparent color of grayscale and truecolor images
+/// without an alpha channel is ignored, they are opaque.
+fn decode_png(data: &[u8]) -> Option<Bitmap> {
+    let mut rest = &data[8..];
+    let mut header: Option<&[u8]> = None;
+    let mut palette: &[u8] = &[];
+    let mut transparency: &[u8] = &[];
+    let mut compressed = Vec::new();
+    while rest.len() >= 12 {
+        let length = be_u32(rest)?;
+        let body = rest.get(8..8 + length)?;
+        match &rest[4..8] {
+            b"IHDR" => header = Some(body),
+            b"PLTE" => palette = body,
+            b"tRNS" => transparency = body,
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => (),
+        }
+        rest = rest.get(12 + length..)?;
+    }
+    let header = header.filter(|n| n.len() >= 13)?;
+    let (width, height) = (be_u32(header)?, be_u32(&header[4..])?);
+    let (depth, color, interlace) = (header[8], header[9], header[12]);
+    let channels = match color {
+        0 | 3 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        _ => return None,
+    };
+    let valid_depth = match depth {
+        8 => true,
+        1 | 2 | 4 => color == 0 || color == 3,
+        16 => color != 3,
+        _ => false,
+    };
+    if !valid_depth || interlace != 0 || width == 0 || height == 0 {
+        return None;
+    }
+    if width.checked_mul(height)? > MAX_PIXELS {
+        return None;
+    }
+    let bits = channels * depth as usize;
+    let stride = (width * bits).div_ceil(8);
+    //Filters compare a byte with the one of the previous pixel, or the previous byte.
+    let distance = (bits / 8).max(1);
+    let mut raw = Vec::new();
+    ZlibDecoder::new(&compressed[..])
+        .take(((stride + 1) * height) as u64)
+        .read_to_end(&mut raw)
+        .ok()?;
+    if raw.len() < (stride + 1) * height {
+        return None;
+    }
+    let mut rows = vec![0u8; stride * height];
+    for (y, line) in raw.chunks(stride + 1).enumerate() {
+        let (done, current) = rows.split_at_mut(y * stride);
+        let previous = if y > 0 {
+            &done[(y - 1) * stride..]
+        } else {
+            &[]
+        };
+        for i in 0..stride {
+            let a = if i >= distance {
+                current[i - distance]
+            } else {
+                0
+            };
+            let b = previous.get(i).copied().unwrap_or(0);
+            let c = if i >= distance {
+                previous.get(i - distance).copied().unwrap_or(0)
+            } else {
+                0
+            };
+            let predicted = match line[0] {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return None,
+            };
+            current[i] = line[i + 1].wrapping_add(predicted);
+        }
+    }
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    for row in rows.chunks(stride) {
+        for x in 0..width {
+            let s = |n: usize| sample(row, x * channels + n, depth);
+            let rgba = match color {
+                0 if depth < 8 => {
+                    let v = s(0) * (255 / ((1 << depth) - 1));
+                    [v, v, v, 255]
+                }
+                0 => [s(0), s(0), s(0), 255],
+                2 => [s(0), s(1), s(2), 255],
+                3 => {
+                    let n = s(0) as usize;
+                    let rgb = palette.get(3 * n..3 * n + 3)?;
+                    let alpha = transparency.get(n).copied().unwrap_or(255);
+                    [rgb[0], rgb[1], rgb[2], alpha]
+                }
+                4 => [s(0), s(0), s(0), s(1)],
+                _ => [s(0), s(1), s(2), s(3)],
+            };
+            pixels.extend_from_slice(&rgba);
+        }
+    }
+    Some(Bitmap {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Pixels of an uncompressed 24 or 32 bit BMP image, the fourth byte of 32 bit pixels
+/// is ignored like most programs do.
+fn decode_bmp(data: &[u8]) -> Option<Bitmap> {
+    let offset = le_u32(data, 10)? as usize;
+    let width = le_u32(data, 18)? as i32;
+    //Rows are stored from the bottom unless the height is negative.
+    let height = le_u32(data, 22)? as i32;
+    let bpp = u16::from_le_bytes(data.get(28..30)?.try_into().ok()?);
+    let compression = le_u32(data, 30)?;
+    if compression != 0 || (bpp != 24 && bpp != 32) || width <= 0 || height == 0 {
+        return None;
+    }
+    let (width, rows) = (width as usize, height.unsigned_abs() as usize);
+    if width.checked_mul(rows)? > MAX_PIXELS {
+        return None;
+    }
+    let bytes = bpp as usize / 8;
+    let stride = (width * bytes).div_ceil(4) * 4;
+    let body = data.get(offset..offset + stride * rows)?;
+    let mut pixels = Vec::with_capacity(width * rows * 4);
+    for y in 0..rows {
+        let row = if height < 0 { y } else { rows - 1 - y };
+        for pixel in body[row * stride..][..width * bytes].chunks(bytes) {
+            pixels.extend_from_slice(&[pixel[2], pixel[1], pixel[0], 255]);
+        }
+    }
+    Some(Bitmap {
+        width,
+        height: rows,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    /// PNG image of `rows`, each starting with its filter type byte.
+    pub(crate) fn png(
+        width: u32,
+        height: u32,
+        depth: u8,
+        color: u8,
+        chunks: &[(&[u8; 4], &[u8])],
+        rows: &[u8],
+    ) -> Vec<u8> {
+        let chunk = |png: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]| {
+            png.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            png.extend_from_slice(kind);
+            png.extend_from_slice(body);
+            let crc = crc32fast::hash(&[&kind[..], body].concat());
+            png.extend_from_slice(&crc.to_be_bytes());
+        };
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        let header = [
+            &width.to_be_bytes()[..],
+            &height.to_be_bytes(),
+            &[depth, color, 0, 0, 0],
+        ]
+        .concat();
+        chunk(&mut png, b"IHDR", &header);
+        for (kind, body) in chunks {
+            chunk(&mut png, kind, body);
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(rows).unwrap();
+        chunk(&mut png, b"IDAT", &encoder.finish().unwrap());
+        chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    #[test]
+    fn should_decode_filtered_png_rows() {
+        //Row 0 with Sub, row 1 with Up, row 2 with Paeth.
+        let rows = [
+            &[1, 10, 20, 30, 255, 5, 5, 5, 0][..],
+            &[2, 1, 1, 1, 0, 0, 0, 0, 0],
+            &[4, 0, 0, 0, 0, 1, 1, 1, 0],
+        ]
+        .concat();
+        let image = Bitmap::decode(&png(2, 3, 8, 6, &[], &rows)).unwrap();
+        assert_eq!((image.width, image.height), (2, 3));
+        assert_eq!(image.pixel(0, 0), [10, 20, 30, 255]);
+        assert_eq!(image.pixel(1, 0), [15, 25, 35, 255]);
+        assert_eq!(image.pixel(0, 1), [11, 21, 31, 255]);
+        assert_eq!(image.pixel(1, 1), [15, 25, 35, 255]);
+        assert_eq!(image.pixel(0, 2), [11, 21, 31, 255]);
+        assert_eq!(image.pixel(1, 2), [16, 26, 36, 255]);
+    }
+
+    #[test]
+    fn should_decode_paletted_and_grayscale_png() {
+        let palette: &[u8] = &[255, 0, 0, 0, 0, 255];
+        let chunks: &[(&[u8; 4], &[u8])] = &[(b"PLTE", palette), (b"tRNS", &[0])];
+        let image = Bitmap::decode(&png(3, 1, 1, 3, chunks, &[0, 0b0100_0000])).unwrap();
+        assert_eq!(image.pixel(0, 0), [255, 0, 0, 0]);
+        assert_eq!(image.pixel(1, 0), [0, 0, 255, 255]);
+        assert_eq!(image.pixel(2, 0), [255, 0, 0, 0]);
+
+        let image = Bitmap::decode(&png(2, 1, 2, 0, &[], &[0, 0b1101_0000])).unwrap();
+        assert_eq!(image.pixel(0, 0), [255, 255, 255, 255]);
+        assert_eq!(image.pixel(1, 0), [85, 85, 85, 255]);
+    }
+
+    #[test]
+    fn should_not_decode_unknown_images() {
+        assert_eq!(Bitmap::decode(b"GIF89a"), None);
+        let mut interlaced = png(1, 1, 8, 0, &[], &[0, 0]);
+        interlaced[28] = 1;
+        assert_eq!(Bitmap::decode(&interlaced), None);
+        assert_eq!(Bitmap::decode(&png(1, 1, 8, 0, &[], &[5, 0])), None);
+        assert_eq!(Bitmap::decode(&png(2, 2, 8, 0, &[], &[0, 0, 0])), None);
+    }
+
+    #[test]
+    fn should_decode_bmp() {
+        let mut bmp = b"BM".to_vec();
+        bmp.resize(54, 0);
+        bmp[10] = 54;
+        bmp[18] = 2;
+        bmp[22] = 2;
+        bmp[28] = 24;
+        //Bottom row first, each padded to four bytes.
+        bmp.extend_from_slice(&[1, 2, 3, 4, 5, 6, 0, 0, 7, 8, 9, 10, 11, 12, 0, 0]);
+        let image = Bitmap::decode(&bmp).unwrap();
+        assert_eq!(image.pixel(0, 0), [9, 8, 7, 255]);
+        assert_eq!(image.pixel(1, 1), [6, 5, 4, 255]);
+        bmp[30] = 1;
+        assert_eq!(Bitmap::decode(&bmp), None);
+    }
+
+    #[test]
+    fn should_fit_bitmap_keeping_aspect_ratio() {
+        let pixels: Vec<u8> = (0..8u8).flat_map(|n| [n, n, n, 255]).collect();
+        let image = Bitmap {
+            width: 4,
+            height: 2,
+            pixels,
+        };
+        let small = image.fit(2, 2);
+        assert_eq!((small.width, small.height), (2, 1));
+        assert_eq!(small.pixel(1, 0), [2, 2, 2, 255]);
+        assert_eq!(small.fit(10, 10).width, 2);
+    }
+}
@@ -0,0 +1,181 @@
+//! Shows HTML definitions in the default browser, terminals can't render them.
+use crate::dictionary::Definition;
+use crate::resource_cache::{sanitize, ResourceCache};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Page definitions are shown in. `{{word}}` is replaced with the headword,
+/// `{{style}}` with the stylesheet of the dictionary and `{{definition}}` with the
+/// definition.
+const PAGE_TEMPLATE: &str = "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{{word}}</title>{{style}}</head>\n<body>\n<h1>{{word}}</h1>\n{{definition}}\n</body>\n</html>\n";
+
+/// How the pages of a dictionary look, from settings. Dictionaries writing their html
+/// for a stylesheet of their own can be given one.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PageStyle {
+    /// CSS file put into the head of every page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stylesheet: Option<PathBuf>,
+    /// Html file used instead of `PAGE_TEMPLATE`, with the same placeholders.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<PathBuf>,
+}
+
+/// Writes `definition` to an .html file in `cache`, or a temporary one without it, and
+/// opens it with the default browser. The file is left in place since the browser
+/// reads it after we return. Fails if the files of `style` can't be read.
+pub fn open_definition(
+    definition: &Definition,
+    style: &PageStyle,
+    cache: Option<&ResourceCache>,
+) -> io::Result<PathBuf> {
+    let template = match &style.template {
+        Some(path) => fs::read_to_string(path)?,
+        None => String::from(PAGE_TEMPLATE),
+    };
+    let stylesheet = match &style.stylesheet {
+        Some(path) => Some(fs::read_to_string(path)?),
+        None => None,
+    };
+    let page = render(definition, &template, stylesheet.as_deref());
+    let path = match cache {
+        Some(cache) => {
+            let name = format!(
+                "{}-{:08x}.html",
+                sanitize(&definition.word),
+                crc32fast::hash(page.as_bytes())
+            );
+            cache.store(&name, page.as_bytes())?
+        }
+        None => {
+            let mut file = tempfile::Builder::new()
+                .prefix("sozluk-")
+                .suffix(".html")
+                .tempfile()?;
+            file.write_all(page.as_bytes())?;
+            file.keep().map_err(|e| e.error)?.1
+        }
+    };
+    debug!("Opening {} in browser.", path.display());
+    open_command(&path).spawn()?;
+    Ok(path)
+}
+
+/// Full html page of `definition` made from `template`, definitions that aren't html
+/// are escaped and shown as preformatted text.
+fn render(definition: &Definition, template: &str, stylesheet: Option<&str>) -> String {
+    let body = if definition.is_html() {
+        definition.definition.clone()
+    } else {
+        format!("<pre>{}</pre>", escape(&definition.definition))
+    };
+    let style = stylesheet.map_or(String::new(), |css| format!("<style>\n{}\n</style>", css));
+    fill(
+        template,
+        &[
+            ("word", &escape(&definition.word)),
+            ("style", &style),
+            ("definition", &body),
+        ],
+    )
+}
+
+/// Replaces the `{{name}}` placeholders of `template` with their values in one pass,
+/// so values holding placeholders are left as they are. Unknown ones are kept.
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    let mut page = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        page.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let value = after.find("}}").and_then(|end| {
+            let name = after[..end].trim();
+            let (_, value) = values.iter().find(|(n, _)| *n == name)?;
+            Some((value, end + 2))
+        });
+        match value {
+            Some((value, length)) => {
+                page.push_str(value);
+                rest = &after[length..];
+            }
+            None => {
+                page.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    page.push_str(rest);
+    page
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(target_os = "macos")]
+fn open_command(path: &std::path::Path) -> Command {
+    let mut command = Command::new("open");
+    command.arg(path);
+    command
+}
+
+#[cfg(target_os = "windows")]
+fn open_command(path: &std::path::Path) -> Command {
+    let mut command = Command::new("cmd");
+    command.args(&["/C", "start", ""]).arg(path);
+    command
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn open_command(path: &std::path::Path) -> Command {
+    let mut command = Command::new("xdg-open");
+    command.arg(path);
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dictionary::SameTypeSequence;
+
+    #[test]
+    fn should_escape_text_definitions() {
+        let definition =
+            Definition::new_from_utf8("a<b", b"x & y".to_vec(), &SameTypeSequence::Meaning);
+        let html = render(&definition, PAGE_TEMPLATE, None);
+        assert!(html.contains("<h1>a&lt;b</h1>"));
+        assert!(html.contains("<pre>x &amp; y</pre>"));
+    }
+
+    #[test]
+    fn should_keep_html_definitions() {
+        let definition =
+            Definition::new_from_utf8("elma", b"<b>apple</b>".to_vec(), &SameTypeSequence::HTML);
+        assert!(render(&definition, PAGE_TEMPLATE, None).contains("\n<b>apple</b>\n"));
+    }
+
+    #[test]
+    fn should_render_with_stylesheet_and_template_of_dictionary() {
+        let definition = Definition::new_from_utf8(
+            "{{style}}",
+            b"<div class=\"trn\">apple</div>".to_vec(),
+            &SameTypeSequence::HTML,
+        );
+        let html = render(&definition, PAGE_TEMPLATE, Some(".trn { color: red; }"));
+        assert!(html.contains("<style>\n.trn { color: red; }\n</style></head>"));
+        assert!(html.contains("<h1>{{style}}</h1>"));
+
+        let template = "<article>{{ definition }}</article><p>{{word}} {{unknown}}</p>";
+        assert_eq!(
+            render(&definition, template, None),
+            "<article><div class=\"trn\">apple</div></article><p>{{style}} {{unknown}}</p>"
+        );
+    }
+}
@@ -0,0 +1,47 @@
+//! Copies text to the system clipboard through the platform's clipboard tool.
+use log::debug;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// Tools tried in order, the first one that runs is used.
+#[cfg(target_os = "macos")]
+const CLIPBOARD_COMMANDS: &[&[&str]] = &[&["pbcopy"]];
+#[cfg(target_os = "windows")]
+const CLIPBOARD_COMMANDS: &[&[&str]] = &[&["clip"]];
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const CLIPBOARD_COMMANDS: &[&[&str]] = &[
+    &["wl-copy"],
+    &["xclip", "-selection", "clipboard"],
+    &["xsel", "--clipboard", "--input"],
+];
+
+/// Puts `text` into the clipboard.
+pub fn copy(text: &str) -> io::Result<()> {
+    for command in CLIPBOARD_COMMANDS {
+        let child = Command::new(command[0])
+            .args(&command[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        debug!("Copying {} bytes with {}.", text.len(), command[0]);
+        child.stdin.take().unwrap().write_all(text.as_bytes())?;
+        let status = child.wait()?;
+        if status.success() {
+            return Ok(());
+        }
+        return Err(io::Error::other(format!(
+            "{} exited with {}",
+            command[0], status
+        )));
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        "no clipboard tool found, install wl-clipboard, xclip or xsel",
+    ))
+}
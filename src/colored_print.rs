@@ -1,33 +1,331 @@
+use log::debug;
 use std::io::Write;
-use termcolor::{BufferWriter, Color, ColorChoice, ColorSpec, WriteColor};
+use std::ops::Range;
+use termcolor::{Ansi, BufferWriter, Color, ColorChoice, ColorSpec, WriteColor};
+use terminal_size::{terminal_size, Width};
+use unicode_width::UnicodeWidthChar;
 
-pub fn print_green(word: &str) {
-    let bufwrt = BufferWriter::stdout(ColorChoice::Always);
-    let mut buffer = bufwrt.buffer();
-    buffer
-        .set_color(
+/// Output of the terminal interface. `Terminal` prints to stdout in color, `Capture`
+/// keeps the text so tests can check what would have been printed.
+pub trait Printer {
+    /// Writes `text` in `spec` without ending the line.
+    fn write(&mut self, text: &str, spec: &ColorSpec);
+
+    fn plain(&mut self, text: &str) {
+        self.write(text, &ColorSpec::new());
+    }
+
+    /// Writes `text` in `spec` and ends the line.
+    fn styled(&mut self, text: &str, spec: &ColorSpec) {
+        self.write(text, spec);
+        self.plain("\n");
+    }
+
+    fn green(&mut self, text: &str) {
+        self.styled(
+            text,
             ColorSpec::new()
                 .set_fg(Some(Color::Green))
                 .set_intense(true),
-        )
-        .unwrap();
-    writeln!(&mut buffer, "{}", &word).unwrap();
-    buffer.set_color(&ColorSpec::new()).unwrap();
-    bufwrt.print(&buffer).unwrap();
-}
-
-pub fn print_yellow(word: &str) {
-    let bufwrt = BufferWriter::stdout(ColorChoice::Always);
+        );
+    }
 
-    let mut buffer = bufwrt.buffer();
-    buffer
-        .set_color(
+    fn yellow(&mut self, text: &str) {
+        self.styled(
+            text,
             ColorSpec::new()
                 .set_fg(Some(Color::Yellow))
                 .set_intense(true),
-        )
-        .unwrap();
-    writeln!(&mut buffer, "{}", &word).unwrap();
-    buffer.set_color(&ColorSpec::new()).unwrap();
-    bufwrt.print(&buffer).unwrap();
+        );
+    }
+
+    /// Writes `text` in `color` with the parts matching `query` highlighted, see
+    /// `match_ranges`. Doesn't end the line.
+    fn highlighted(&mut self, text: &str, query: &str, color: Option<Color>) {
+        let base = ColorSpec::new().set_fg(color).set_intense(true).clone();
+        let highlight = ColorSpec::new()
+            .set_fg(Some(Color::Cyan))
+            .set_intense(true)
+            .set_bold(true)
+            .clone();
+        let mut last = 0;
+        for range in match_ranges(text, query) {
+            self.write(&text[last..range.start], &base);
+            self.write(&text[range.clone()], &highlight);
+            last = range.end;
+        }
+        self.write(&text[last..], &base);
+    }
+}
+
+/// Prints to stdout through termcolor, which turns on ANSI escapes in Windows consoles
+/// that support them, falls back to the console API in those that don't and writes
+/// text that isn't valid UTF-8 for the console lossily.
+pub struct Terminal {
+    writer: BufferWriter,
+}
+
+impl Default for Terminal {
+    fn default() -> Terminal {
+        Terminal {
+            writer: BufferWriter::stdout(ColorChoice::Always),
+        }
+    }
+}
+
+impl Printer for Terminal {
+    fn write(&mut self, text: &str, spec: &ColorSpec) {
+        if text.is_empty() {
+            return;
+        }
+        let mut buffer = self.writer.buffer();
+        let written = if spec.is_none() {
+            buffer.write_all(text.as_bytes())
+        } else {
+            buffer
+                .set_color(spec)
+                .and_then(|_| buffer.write_all(text.as_bytes()))
+                .and_then(|_| buffer.reset())
+        }
+        .and_then(|_| self.writer.print(&buffer));
+        //Output piped to a program that exited isn't worth a panic.
+        if let Err(e) = written {
+            debug!("Can't write to stdout: {}", e);
+        }
+    }
+}
+
+/// Keeps what is printed, without colors.
+#[derive(Debug, Default)]
+pub struct Capture {
+    pub text: String,
+}
+
+impl Printer for Capture {
+    fn write(&mut self, text: &str, _: &ColorSpec) {
+        self.text.push_str(text);
+    }
+}
+
+/// Keeps what is printed with ANSI escapes for its colors, for output shown elsewhere
+/// than stdout.
+pub struct AnsiCapture {
+    writer: Ansi<Vec<u8>>,
+}
+
+impl Default for AnsiCapture {
+    fn default() -> AnsiCapture {
+        AnsiCapture {
+            writer: Ansi::new(Vec::new()),
+        }
+    }
+}
+
+impl AnsiCapture {
+    pub fn into_string(self) -> String {
+        String::from_utf8_lossy(&self.writer.into_inner()).into_owned()
+    }
+}
+
+impl Printer for AnsiCapture {
+    fn write(&mut self, text: &str, spec: &ColorSpec) {
+        //Writing to memory doesn't fail.
+        if spec.is_none() {
+            let _ = self.writer.write_all(text.as_bytes());
+        } else {
+            let _ = self
+                .writer
+                .set_color(spec)
+                .and_then(|_| self.writer.write_all(text.as_bytes()))
+                .and_then(|_| self.writer.reset());
+        }
+    }
+}
+
+/// Byte ranges of `text` matching `query` ignoring case: every occurrence of it, or
+/// when there is none the prefix they share, so fuzzy results and roots found by a
+/// morpher are highlighted too.
+pub fn match_ranges(text: &str, query: &str) -> Vec<Range<usize>> {
+    let eq = |a: char, b: char| a == b || a.to_lowercase().eq(b.to_lowercase());
+    let query: Vec<char> = query.chars().collect();
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let end_of = |i: usize| chars.get(i).map(|c| c.0).unwrap_or_else(|| text.len());
+    let mut ranges = Vec::new();
+    let mut i = 0;
+    while i + query.len() <= chars.len() {
+        if chars[i..i + query.len()]
+            .iter()
+            .zip(&query)
+            .all(|(c, q)| eq(c.1, *q))
+        {
+            ranges.push(chars[i].0..end_of(i + query.len()));
+            i += query.len();
+        } else {
+            i += 1;
+        }
+    }
+    if ranges.is_empty() {
+        let common = chars
+            .iter()
+            .zip(&query)
+            .take_while(|(c, q)| eq(c.1, **q))
+            .count();
+        if common > 0 {
+            ranges.push(0..end_of(common));
+        }
+    }
+    ranges
+}
+
+/// Width output should be wrapped at: the terminal width, capped by `max_width`.
+/// When stdout isn't a terminal only `max_width` applies.
+pub fn output_width(max_width: Option<usize>) -> Option<usize> {
+    let terminal = terminal_size().map(|(Width(w), _)| w as usize);
+    match (terminal, max_width) {
+        (Some(t), Some(m)) => Some(t.min(m)),
+        (t, m) => t.or(m),
+    }
+}
+
+/// Wraps every line of `text` at word boundaries so none takes more than `width`
+/// columns of the terminal, wide characters like CJK ideographs take two. Continuation
+/// lines get `indent` more spaces than the line they come from, words longer than a
+/// line are split.
+pub fn wrap(text: &str, width: usize, indent: usize) -> String {
+    let mut wrapped = String::with_capacity(text.len());
+    for (i, line) in text.lines().enumerate() {
+        if i > 0 {
+            wrapped.push('\n');
+        }
+        let leading = line.len() - line.trim_start().len();
+        let hanging = " ".repeat(leading + indent);
+        let hanging_len = hanging.chars().count();
+        //Leave room for at least one character after the indentation.
+        if width <= hanging_len {
+            wrapped.push_str(line);
+            continue;
+        }
+        wrapped.push_str(&line[..leading]);
+        let mut column = leading;
+        let mut line_start = true;
+        for word in line.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+            let mut word_width = columns(&word);
+            if !line_start && column + 1 + word_width > width {
+                wrapped.push('\n');
+                wrapped.push_str(&hanging);
+                column = hanging_len;
+                line_start = true;
+            }
+            if !line_start {
+                wrapped.push(' ');
+                column += 1;
+            }
+            while column + word_width > width {
+                let mut fits = fitting_chars(&word, width - column);
+                //A character wider than the room of a whole line gets one anyway.
+                if fits == 0 && column == hanging_len {
+                    fits = 1;
+                }
+                let rest = word.split_off(fits);
+                wrapped.extend(&word);
+                if rest.is_empty() {
+                    word_width = columns(&word);
+                    word = rest;
+                    break;
+                }
+                wrapped.push('\n');
+                wrapped.push_str(&hanging);
+                column = hanging_len;
+                word_width = columns(&rest);
+                word = rest;
+            }
+            column += word_width;
+            wrapped.extend(word);
+            line_start = false;
+        }
+    }
+    wrapped
+}
+
+/// Terminal columns `chars` take.
+fn columns(chars: &[char]) -> usize {
+    chars.iter().map(|c| c.width().unwrap_or(0)).sum()
+}
+
+/// How many of the first `chars` fit in `room` columns.
+fn fitting_chars(chars: &[char], room: usize) -> usize {
+    let mut used = 0;
+    chars
+        .iter()
+        .take_while(|c| {
+            used += c.width().unwrap_or(0);
+            used <= room
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_find_query_in_text() {
+        assert_eq!(match_ranges("elma", "elma"), vec![0..4]);
+        assert_eq!(match_ranges("Çiçekçi çiçek", "çiçek"), vec![0..7, 11..18]);
+        assert_eq!(match_ranges("ana kitap", "kitap"), vec![4..9]);
+    }
+
+    #[test]
+    fn should_fall_back_to_common_prefix() {
+        assert_eq!(match_ranges("kitap", "kitaplar"), vec![0..5]);
+        assert_eq!(match_ranges("elmas", "elmo"), vec![0..3]);
+        assert_eq!(match_ranges("armut", "elma"), Vec::<Range<usize>>::new());
+        assert_eq!(match_ranges("armut", ""), Vec::<Range<usize>>::new());
+    }
+
+    #[test]
+    fn should_capture_printed_text() {
+        let mut printer = Capture::default();
+        printer.highlighted("Çiçekçi", "çiçek", None);
+        printer.plain(" ");
+        printer.green("bulundu");
+        assert_eq!(printer.text, "Çiçekçi bulundu\n");
+
+        let mut printer = AnsiCapture::default();
+        printer.plain("elma ");
+        printer.write("armut", ColorSpec::new().set_bold(true));
+        assert_eq!(printer.into_string(), "elma \x1b[0m\x1b[1marmut\x1b[0m");
+    }
+
+    #[test]
+    fn should_wrap_at_word_boundaries() {
+        assert_eq!(wrap("bir iki üç dört beş", 10, 0), "bir iki üç\ndört beş");
+        assert_eq!(wrap("bir iki üç dört beş", 10, 2), "bir iki üç\n  dört beş");
+        assert_eq!(wrap("short", 10, 2), "short");
+    }
+
+    #[test]
+    fn should_keep_lines_and_their_indentation() {
+        assert_eq!(
+            wrap("1. elma armut\n  2. ayva kiraz", 10, 2),
+            "1. elma\n  armut\n  2. ayva\n    kiraz"
+        );
+    }
+
+    #[test]
+    fn should_split_long_words() {
+        assert_eq!(wrap("abcdefghijkl", 5, 1), "abcde\n fghi\n jkl");
+    }
+
+    #[test]
+    fn should_wrap_wide_characters_by_their_width() {
+        assert_eq!(wrap("日本語 の辞書", 7, 0), "日本語\nの辞書");
+        assert_eq!(wrap("日本語の辞書", 5, 0), "日本\n語の\n辞書");
+        assert_eq!(wrap("a 日本", 3, 0), "a\n日\n本");
+        assert_eq!(wrap("日本", 2, 1), "日\n 本");
+    }
 }
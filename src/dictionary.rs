@@ -1,43 +1,579 @@
-use crate::colored_print::print_yellow;
-use bincode::{deserialize, serialize};
+use crate::browser::PageStyle;
+use crate::colored_print::{wrap, AnsiCapture, Capture, Printer};
+use crate::dictzip::DictZip;
+use crate::html;
+use crate::keywords::{contains_phrase, KeywordIndex};
+use crate::matcher::{
+    grapheme_count, matches_pattern, relevance, HeadwordFilter, Lookup, WordBounds,
+};
+use crate::parsed::{self, LineKind, ParsedDefinition};
+use crate::postprocess::{self, Transform};
+use crate::resource_cache::cache_root;
+use crate::resources::{self, ResourceStorage};
+use crate::source::DictSource;
+use crate::stats::IndexStats;
+use bincode::{deserialize, deserialize_from, serialize};
 use byteorder::{BigEndian, ReadBytesExt};
-use log::{debug, error};
+use log::{debug, error, info, warn};
+use rand::seq::index::sample;
 use rand::{thread_rng, Rng};
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use smartstring::{LazyCompact, SmartString};
-use std::borrow::Borrow;
-use std::convert::{AsRef, TryInto};
+use std::cmp;
+use std::collections::HashSet;
+use std::convert::TryInto;
 use std::error::Error;
 use std::fmt::{self};
-use std::fs::{read, write, File};
+use std::fs::{self, read, write, File};
 use std::hash::{Hash, Hasher};
 use std::io::{self, Read, Seek, SeekFrom};
 use std::iter::Iterator;
 use std::mem::size_of;
-use std::path::PathBuf;
-use std::string::FromUtf8Error;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Instant, SystemTime};
+use termcolor::{Color, ColorSpec};
+
+/// Size and CRC32 of a file, to notice when it's truncated or corrupted.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+struct FileChecksum {
+    len: u64,
+    crc: u32,
+}
+
+impl FileChecksum {
+    fn of(path: &Path) -> io::Result<FileChecksum> {
+        let mut file = File::open(path)?;
+        let mut hasher = crc32fast::Hasher::new();
+        let mut buffer = vec![0; 1 << 16];
+        let mut len = 0;
+        loop {
+            let n = file.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+            len += n as u64;
+        }
+        Ok(FileChecksum {
+            len,
+            crc: hasher.finalize(),
+        })
+    }
+}
+
+/// Size and modification time of a file, to notice it changed without reading it.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub(crate) struct FileStamp {
+    len: u64,
+    /// `None` where the file system doesn't keep modification times.
+    modified: Option<SystemTime>,
+}
+
+impl FileStamp {
+    pub(crate) fn of(path: &Path) -> io::Result<FileStamp> {
+        let metadata = fs::metadata(path)?;
+        Ok(FileStamp {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// Tells comparisons of a search whether they may still run, once `deadline` passed
+/// every later one is skipped.
+struct SearchClock {
+    deadline: Option<Instant>,
+    expired: AtomicBool,
+}
+
+impl SearchClock {
+    fn new(deadline: Option<Instant>) -> SearchClock {
+        SearchClock {
+            deadline,
+            expired: AtomicBool::new(false),
+        }
+    }
+
+    fn has_time(&self) -> bool {
+        if self.expired.load(Ordering::Relaxed) {
+            return false;
+        }
+        if self.deadline.is_some_and(|n| Instant::now() >= n) {
+            self.expired.store(true, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+
+    /// Whether comparisons were skipped, the results are partial then.
+    fn expired(&self) -> bool {
+        self.expired.load(Ordering::Relaxed)
+    }
+}
+
+/// Identifies .sozl files in the current format, older caches are rebuilt.
+pub(crate) const CACHE_MAGIC: u64 = 0x736f_7a6c_0000_0007;
+
+/// Contents of a .sozl cache file, `CacheRef` is the same thing for writing it.
+#[derive(Deserialize)]
+struct Cache {
+    magic: u64,
+    /// Checksums of the .idx and .dict files at the time the cache was built.
+    idx: FileChecksum,
+    dict: FileChecksum,
+    /// Stamp of the .idx file, a different one means it may have changed.
+    idx_stamp: FileStamp,
+    /// Whether the indices are in a file per shard instead of this one.
+    sharded: bool,
+    shards: Vec<Shard>,
+    stats: IndexStats,
+    indices: Vec<Index>,
+}
+
+/// Fields of a .sozl file after the magic number, read without the indices to tell
+/// whether it's current.
+#[derive(Deserialize)]
+struct CacheHeader {
+    idx: FileChecksum,
+    _dict: FileChecksum,
+    idx_stamp: FileStamp,
+}
+
+/// Fields of a .sozl file up to the indices, read to load the shards one by one.
+#[derive(Deserialize)]
+struct CacheTable {
+    magic: u64,
+    _idx: FileChecksum,
+    _dict: FileChecksum,
+    idx_stamp: FileStamp,
+    sharded: bool,
+    shards: Vec<Shard>,
+    stats: IndexStats,
+}
+
+#[derive(Serialize)]
+struct CacheRef<'a> {
+    magic: u64,
+    idx: FileChecksum,
+    dict: FileChecksum,
+    idx_stamp: FileStamp,
+    sharded: bool,
+    shards: &'a [Shard],
+    stats: &'a IndexStats,
+    indices: &'a [Index],
+}
+
+/// Cache of the entries of one shard.
+#[derive(Deserialize)]
+struct ShardCache {
+    magic: u64,
+    indices: Vec<Index>,
+}
+
+#[derive(Serialize)]
+struct ShardCacheRef<'a> {
+    magic: u64,
+    indices: &'a [Index],
+}
+
+/// Cache of the anagram table, kept apart so reading the index doesn't read it too.
+#[derive(Deserialize)]
+struct AnagramCache {
+    magic: u64,
+    entries: Vec<AnagramEntry>,
+}
+
+#[derive(Serialize)]
+struct AnagramCacheRef<'a> {
+    magic: u64,
+    entries: &'a [AnagramEntry],
+}
+
+/// Sorted letters of a headword and its position in the index.
+#[derive(Debug, Serialize, Deserialize)]
+struct AnagramEntry {
+    key: String,
+    position: u32,
+}
+
+/// Letters of `word` lower cased and sorted, words with the same key are anagrams.
+/// Anything that isn't a letter is left out.
+pub fn anagram_key(word: &str) -> String {
+    let mut letters: Vec<char> = word
+        .chars()
+        .filter(|c| c.is_alphabetic())
+        .flat_map(char::to_lowercase)
+        .collect();
+    letters.sort_unstable();
+    letters.into_iter().collect()
+}
+
+/// Anagram table of `indices`, sorted by key.
+fn anagrams_of(indices: &[Index]) -> Vec<AnagramEntry> {
+    let mut entries: Vec<AnagramEntry> = indices
+        .iter()
+        .enumerate()
+        .map(|(n, index)| AnagramEntry {
+            key: anagram_key(&index.word),
+            position: n as u32,
+        })
+        .collect();
+    entries.sort_by(|a, b| a.key.cmp(&b.key));
+    entries
+}
+
+/// Cache of the trigram index, kept apart like the anagram table. `entries` is the
+/// length of the index it was built from.
+#[derive(Deserialize)]
+struct TrigramCache {
+    magic: u64,
+    entries: usize,
+    index: TrigramIndex,
+}
+
+#[derive(Serialize)]
+struct TrigramCacheRef<'a> {
+    magic: u64,
+    entries: usize,
+    index: &'a TrigramIndex,
+}
+
+/// Cache of the keyword index, written with the cache of the index so full-text and
+/// reverse searches don't read every definition. `entries` is the length of the index
+/// it was built from.
+#[derive(Deserialize)]
+struct KeywordCache {
+    magic: u64,
+    entries: usize,
+    index: KeywordIndex,
+}
+
+#[derive(Serialize)]
+struct KeywordCacheRef<'a> {
+    magic: u64,
+    entries: usize,
+    index: &'a KeywordIndex,
+}
+
+/// Inverted index from the trigrams of the headwords to their positions in the index,
+/// so the words a few edits away from a word are found without comparing every
+/// headword.
+#[derive(Debug, Serialize, Deserialize)]
+struct TrigramIndex {
+    /// Trigrams packed by `trigrams` in order, with the start of their positions in
+    /// `postings`. Their positions end where the positions of the next one start.
+    grams: Vec<(u64, u32)>,
+    postings: Vec<u32>,
+    /// Positions of the headwords with grapheme clusters of more than one char. Their
+    /// edit distance in grapheme clusters can't be bounded by their trigrams.
+    clusters: Vec<u32>,
+}
+
+/// Distinct trigrams of the chars of `word` padded by two NULs on both ends, each packed
+/// in 63 bits, sorted.
+fn trigrams(word: &str) -> Vec<u64> {
+    let chars: Vec<u64> = ['\0', '\0']
+        .iter()
+        .copied()
+        .chain(word.chars())
+        .chain(['\0', '\0'].iter().copied())
+        .map(|c| c as u64)
+        .collect();
+    let mut grams: Vec<u64> = chars
+        .windows(3)
+        .map(|n| n[0] << 42 | n[1] << 21 | n[2])
+        .collect();
+    grams.sort_unstable();
+    grams.dedup();
+    grams
+}
+
+impl TrigramIndex {
+    fn of(indices: &[Index]) -> TrigramIndex {
+        let mut pairs: Vec<(u64, u32)> = indices
+            .par_iter()
+            .enumerate()
+            .flat_map_iter(|(n, index)| {
+                trigrams(&index.word)
+                    .into_iter()
+                    .map(move |g| (g, n as u32))
+            })
+            .collect();
+        pairs.par_sort_unstable();
+        let mut grams: Vec<(u64, u32)> = Vec::new();
+        let mut postings: Vec<u32> = Vec::with_capacity(pairs.len());
+        for (gram, position) in pairs {
+            if grams.last().map(|n| n.0) != Some(gram) {
+                grams.push((gram, postings.len() as u32));
+            }
+            postings.push(position);
+        }
+        let clusters = indices
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| grapheme_count(&n.word) != n.word.chars().count())
+            .map(|(n, _)| n as u32)
+            .collect();
+        TrigramIndex {
+            grams,
+            postings,
+            clusters,
+        }
+    }
+
+    /// Positions in index order of the headwords that can be at most `edits` edits away
+    /// from `word`. `None` if `word` is too short for its trigrams to rule any headword out.
+    fn candidates(&self, word: &str, edits: usize) -> Option<Vec<u32>> {
+        //An edit changes at most three trigrams, headwords close enough share the rest.
+        let grams = trigrams(word);
+        let threshold = grams.len().checked_sub(3 * edits).filter(|n| *n > 0)?;
+        if grapheme_count(word) != word.chars().count() || threshold > u16::MAX as usize {
+            return None;
+        }
+        let mut shared: Vec<u16> = Vec::new();
+        for gram in grams {
+            let n = self.grams.partition_point(|x| x.0 < gram);
+            if self.grams.get(n).map(|x| x.0) != Some(gram) {
+                continue;
+            }
+            let start = self.grams[n].1 as usize;
+            let end = self
+                .grams
+                .get(n + 1)
+                .map_or(self.postings.len(), |x| x.1 as usize);
+            for position in &self.postings[start..end] {
+                let position = *position as usize;
+                if shared.len() <= position {
+                    shared.resize(position + 1, 0);
+                }
+                shared[position] += 1;
+            }
+        }
+        let mut candidates: Vec<u32> = (0..shared.len())
+            .filter(|n| shared[*n] as usize >= threshold)
+            .map(|n| n as u32)
+            .collect();
+        candidates.extend(&self.clusters);
+        candidates.sort_unstable();
+        candidates.dedup();
+        Some(candidates)
+    }
+}
+
+/// Default of `Dictionary::sharded_cache_entries`.
+pub const SHARDED_CACHE_ENTRIES: usize = 1_000_000;
+
+/// Letters with fewer entries than this share of the index share their shard with the
+/// letters next to them, so an index has about this many shards at most.
+const MAX_SHARDS: usize = 256;
+
+/// Consecutive index entries whose words start with the same letter, ASCII letters
+/// compared case insensitively as .idx files are sorted that way. Letters with few
+/// entries are put together in one shard.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Shard {
+    /// First letter of every entry, `None` if they start with different letters.
+    pub first: Option<char>,
+    /// Shortest and longest words in grapheme clusters.
+    pub min_length: usize,
+    pub max_length: usize,
+    /// Position of the entries in the whole index.
+    pub range: Range<usize>,
+    /// Positions of the entries in the shard ordered by length, then by position.
+    by_length: Vec<u32>,
+    /// Where the entries of each length from `min_length` to `max_length` start in
+    /// `by_length`, followed by its length.
+    length_starts: Vec<u32>,
+    /// Entries read from the shard's own cache file while the whole index isn't read.
+    #[serde(skip)]
+    indices: OnceLock<Vec<Index>>,
+}
+
+impl Shard {
+    /// Whether a word within `bounds` can be in this shard.
+    pub fn can_match(&self, bounds: &WordBounds) -> bool {
+        bounds.min_length <= self.max_length
+            && self.min_length <= bounds.max_length
+            && bounds.first.is_none_or(|c| {
+                self.first
+                    .is_none_or(|first| first == c.to_ascii_lowercase())
+            })
+    }
+
+    /// Positions in the shard of the entries whose length is within `bounds`, ordered
+    /// by length.
+    pub fn positions_within(&self, bounds: &WordBounds) -> &[u32] {
+        let min = bounds.min_length.max(self.min_length);
+        let max = bounds.max_length.min(self.max_length);
+        if min > max || self.length_starts.is_empty() {
+            return &[];
+        }
+        let start = self.length_starts[min - self.min_length] as usize;
+        let end = self.length_starts[max - self.min_length + 1] as usize;
+        &self.by_length[start..end]
+    }
+}
+
+/// Splits `indices` into shards of consecutive entries with the same first letter.
+/// Order of headwords in .idx files, `g_ascii_strcasecmp`: bytes compared with ASCII
+/// letters lowercased. Words equal this way may come in any order.
+pub fn stardict_order(first: &str, second: &str) -> cmp::Ordering {
+    first
+        .bytes()
+        .map(|b| b.to_ascii_lowercase())
+        .cmp(second.bytes().map(|b| b.to_ascii_lowercase()))
+}
+
+/// Positions of the entries of sorted `indices` that can match `word` with `lookup`,
+/// empty for `Lookup::Edits` as its matches aren't next to each other.
+fn lookup_range(indices: &[Index], lookup: Lookup, word: &str) -> Range<usize> {
+    let start = indices.partition_point(|x| stardict_order(&x.word, word) == cmp::Ordering::Less);
+    let length = indices[start..].partition_point(|x| {
+        let headword = x.word.as_bytes();
+        match lookup {
+            Lookup::Exact => headword.eq_ignore_ascii_case(word.as_bytes()),
+            Lookup::Prefix => headword
+                .get(..word.len())
+                .is_some_and(|n| n.eq_ignore_ascii_case(word.as_bytes())),
+            Lookup::Edits(_) => false,
+        }
+    });
+    start..start + length
+}
+
+fn shards_of(indices: &[Index]) -> Vec<Shard> {
+    let mut shards: Vec<Shard> = Vec::new();
+    let lengths: Vec<usize> = indices.iter().map(|n| grapheme_count(&n.word)).collect();
+    for (i, index) in indices.iter().enumerate() {
+        let first = index.word.chars().next().map(|c| c.to_ascii_lowercase());
+        let length = lengths[i];
+        match shards.last_mut() {
+            Some(shard) if shard.first == first => {
+                shard.min_length = shard.min_length.min(length);
+                shard.max_length = shard.max_length.max(length);
+                shard.range.end = i + 1;
+            }
+            _ => shards.push(Shard {
+                first,
+                min_length: length,
+                max_length: length,
+                range: i..i + 1,
+                by_length: Vec::new(),
+                length_starts: Vec::new(),
+                indices: OnceLock::new(),
+            }),
+        }
+    }
+    //Letters with few entries, like the many rare ones of a CJK dictionary, share shards.
+    let rare = indices.len() / MAX_SHARDS;
+    let mut merged: Vec<Shard> = Vec::with_capacity(shards.len());
+    for shard in shards {
+        match merged.last_mut() {
+            Some(last) if last.range.len() < rare && shard.range.len() < rare => {
+                last.first = None;
+                last.min_length = last.min_length.min(shard.min_length);
+                last.max_length = last.max_length.max(shard.max_length);
+                last.range.end = shard.range.end;
+            }
+            _ => merged.push(shard),
+        }
+    }
+    let mut shards = merged;
+    //Counting sort of the entries of each shard by their length.
+    for shard in &mut shards {
+        let lengths = &lengths[shard.range.clone()];
+        let mut starts = vec![0u32; shard.max_length - shard.min_length + 2];
+        for length in lengths {
+            starts[length - shard.min_length + 1] += 1;
+        }
+        for n in 1..starts.len() {
+            starts[n] += starts[n - 1];
+        }
+        let mut next = starts.clone();
+        shard.by_length = vec![0; lengths.len()];
+        for (position, length) in lengths.iter().enumerate() {
+            let slot = &mut next[length - shard.min_length];
+            shard.by_length[*slot as usize] = position as u32;
+            *slot += 1;
+        }
+        shard.length_starts = starts;
+    }
+    shards
+}
 
 /// Holds the location info about a word's corresponding definition entry
 /// in the .dict file.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Index {
     pub word: SmartString<LazyCompact>,
-    offset: u32,
+    /// 64 bits wide for every dictionary, .dict files of the ones with
+    /// `idxoffsetbits=64` in their .ifo file can be larger than 4 GiB.
+    offset: u64,
     size: u32,
 }
 
+impl Index {
+    /// Entry of `word` whose definition is `size` bytes at `offset` of the .dict file.
+    /// Other sources may give them any meaning to find definitions by.
+    pub fn new(word: &str, offset: u64, size: u32) -> Index {
+        Index {
+            word: SmartString::from(word),
+            offset,
+            size,
+        }
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+/// An alternate spelling from the .syn file and the position of the index entry it
+/// stands for.
+#[derive(Debug, Clone)]
+pub struct Synonym {
+    pub word: SmartString<LazyCompact>,
+    pub position: u32,
+}
+
+/// Number of unreadable .idx entries tolerated before the index is considered corrupt.
+const MAX_CORRUPT_ENTRIES: usize = 32;
+
 #[derive(Debug)]
 pub enum DictionaryError {
     IOError,
     PathError,
+    /// Index file had too many unreadable entries, holds the number skipped.
+    CorruptIndex(usize),
+    /// File changed since its checksum was recorded in the cache.
+    ChecksumMismatch(PathBuf),
 }
 
 impl Error for DictionaryError {}
 
 impl fmt::Display for DictionaryError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Error when loading the dictionary!.")
+        match self {
+            DictionaryError::CorruptIndex(n) => write!(
+                f,
+                "Index file is corrupt, gave up after skipping {} entries.",
+                n
+            ),
+            DictionaryError::ChecksumMismatch(path) => write!(
+                f,
+                "{} is corrupt or changed since its cache was built.",
+                path.display()
+            ),
+            _ => write!(f, "Error when loading the dictionary!."),
+        }
     }
 }
 
@@ -54,10 +590,35 @@ pub struct Definition {
     pub word: String,
     pub definition: String,
     definition_type: SameTypeSequence,
+    /// Edit distance to the searched word, set for fuzzy searches.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance: Option<usize>,
+    /// Every field of entries that have more than one, `definition` holds their text.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<DefinitionPart>,
+    /// Senses, examples and part of speech found in `definition`, see `parse`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parsed: Option<ParsedDefinition>,
+    /// Only the start of the entry was read, see `Dictionary::definition_limit`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+/// One typed field of a .dict entry.
+#[derive(Debug, Serialize)]
+pub struct DefinitionPart {
+    definition_type: SameTypeSequence,
+    /// Contents of text fields, `None` for binary ones like pictures and sounds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Contents of binary fields.
+    #[serde(skip)]
+    pub data: Vec<u8>,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Clone, Serialize)]
-enum SameTypeSequence {
+pub(crate) enum SameTypeSequence {
     Meaning,
     Locale,
     Xdfx,
@@ -66,7 +627,75 @@ enum SameTypeSequence {
     WordNet,
     Resource,
     Picture,
-    None,
+    Sound,
+    Phonetic,
+}
+
+/// Names `--type` accepts, in the order of `SameTypeSequence::name`.
+pub const DEFINITION_TYPES: &[&str] = &[
+    "meaning",
+    "locale",
+    "xdfx",
+    "mediawiki",
+    "html",
+    "wordnet",
+    "resource",
+    "picture",
+    "sound",
+    "phonetic",
+];
+
+impl SameTypeSequence {
+    fn name(&self) -> &'static str {
+        match self {
+            SameTypeSequence::Meaning => DEFINITION_TYPES[0],
+            SameTypeSequence::Locale => DEFINITION_TYPES[1],
+            SameTypeSequence::Xdfx => DEFINITION_TYPES[2],
+            SameTypeSequence::MediaWiki => DEFINITION_TYPES[3],
+            SameTypeSequence::HTML => DEFINITION_TYPES[4],
+            SameTypeSequence::WordNet => DEFINITION_TYPES[5],
+            SameTypeSequence::Resource => DEFINITION_TYPES[6],
+            SameTypeSequence::Picture => DEFINITION_TYPES[7],
+            SameTypeSequence::Sound => DEFINITION_TYPES[8],
+            SameTypeSequence::Phonetic => DEFINITION_TYPES[9],
+        }
+    }
+}
+
+/// What `--list-dictionaries` and `GET /dictionaries` show about a dictionary.
+#[derive(Debug, Serialize)]
+pub struct DictionaryInfo {
+    pub bookname: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    pub wordcount: u64,
+    pub cache: CacheStatus,
+    pub ifo_path: PathBuf,
+}
+
+/// State of the .sozl cache of a dictionary.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheStatus {
+    Missing,
+    /// Built from an older .idx file or by an older version, it will be rebuilt.
+    Stale,
+    /// Can't be read, it will be rebuilt.
+    Corrupt,
+    Current,
+}
+
+impl fmt::Display for CacheStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CacheStatus::Missing => write!(f, "missing"),
+            CacheStatus::Stale => write!(f, "stale"),
+            CacheStatus::Corrupt => write!(f, "corrupt"),
+            CacheStatus::Current => write!(f, "current"),
+        }
+    }
 }
 
 /// A struct representing a dictionary file in Strdict format.
@@ -75,16 +704,74 @@ enum SameTypeSequence {
 /// in it's words, reading the definiton of a word and caching.
 #[derive(Debug)]
 pub struct Dictionary {
-    pub indices: Vec<Index>,
+    /// Loaded on first access through `indices()`.
+    indices: OnceLock<Vec<Index>>,
+    /// Loaded on first access through `shards()`.
+    shards: OnceLock<Vec<Shard>>,
+    /// Loaded on first access through `search_anagrams()`.
+    anagrams: OnceLock<Vec<AnagramEntry>>,
+    /// Loaded on the first search within a number of edits, see `search_until()`.
+    trigrams: OnceLock<TrigramIndex>,
+    /// Loaded or built on first access through `search_keywords()`.
+    keywords: OnceLock<KeywordIndex>,
+    /// Positions of the index entries of each length in grapheme clusters, built on
+    /// first access through `search_pattern()`.
+    length_buckets: OnceLock<Vec<Vec<u32>>>,
+    /// Loaded on first access through `synonyms()`.
+    synonyms: OnceLock<Vec<Synonym>>,
+    /// Read along with the indices from the cache, or computed on first access through
+    /// `stats()`.
+    stats: OnceLock<IndexStats>,
+    /// Chunk table of a dictzip compressed .dict.dz file, read on the first
+    /// `read_definition()`.
+    dictzip: OnceLock<DictZip>,
+    /// Opened on the first `read_resource()`, `None` if the dictionary has no resources.
+    resources: OnceLock<Option<ResourceStorage>>,
+    /// Whether the index is in the order of `stardict_order`, checked on first access
+    /// through `is_sorted()`.
+    sorted: OnceLock<bool>,
     pub idx_path: PathBuf,
+    /// Optional file of synonyms, missing in most dictionaries.
+    pub syn_path: PathBuf,
+    /// The .dict file, or the .dict.dz file if there is only that.
     pub dict_path: PathBuf,
     pub ifo_path: PathBuf,
     pub cache_path: PathBuf,
     pub bookname: String,
+    /// Short name set in settings, shown instead of the bookname.
+    pub label: Option<String>,
+    /// Language of the headwords, from the `lang` field of the .ifo file or settings.
+    pub lang: Option<String>,
     pub wordcount: u64,
-    sametype_sequence: SameTypeSequence,
+    /// Width of the offsets in the .idx file, 32 or 64 from the `idxoffsetbits` field.
+    pub idx_offset_bits: u32,
+    /// Type characters of the fields of every entry, empty if each entry gives its own.
+    sametype_sequence: String,
+    /// Matcher to search this dictionary with instead of the default one, from the
+    /// `preferredalgorithm` and `preferreddepth` fields of the .ifo file or settings.
     pub preferred_algorithm: Option<String>,
     pub preferred_depth: Option<u8>,
+    /// Transforms applied to the text of every definition read, from settings.
+    pub postprocess: Vec<Transform>,
+    /// Headwords that don't pass it are skipped by searches.
+    pub headword_filter: HeadwordFilter,
+    /// Most results a fuzzy search gives, the ones answering the query best are kept.
+    /// From settings of the dictionary or its group.
+    pub max_results: Option<usize>,
+    /// Stylesheet and template of the pages definitions are opened in, from settings.
+    pub page_style: PageStyle,
+    /// Bytes `read_definition` reads of an entry at most, the rest is only read by
+    /// `read_whole_definition`. `None` reads every entry whole.
+    pub definition_limit: Option<usize>,
+    /// Backend the headwords and definitions are read from instead of StarDict files,
+    /// see `from_source`.
+    source: Option<Arc<dyn DictSource>>,
+    /// Compare the .idx and .dict files with the checksums in the cache when the
+    /// index is read.
+    pub verify: bool,
+    /// Indices with at least this many entries keep the cache of each shard in its own
+    /// file, so a search only reads the shards it can match in.
+    pub sharded_cache_entries: usize,
 }
 
 impl<'a> Dictionary {
@@ -92,28 +779,225 @@ impl<'a> Dictionary {
     /// generated by modifying this paths extension. This function gives no guarantee
     /// about whether given or other assumed paths exist or whole structure of dictionary
     /// files are usable. Instead use `load_dictionary` method for this guarantees.
-    pub fn new(ifo_path: &PathBuf) -> Dictionary {
+    pub fn new(ifo_path: &Path) -> Dictionary {
         debug!("Creating a dictionary with path {}", &ifo_path.display());
         //If directory name has a "." in it .with_extension() get broken.
+        let mut dict_path = ifo_path.with_extension("dict");
+        let dictzip_path = ifo_path.with_extension("dict.dz");
+        if !dict_path.exists() && dictzip_path.exists() {
+            dict_path = dictzip_path;
+        }
 
         Dictionary {
-            indices: Vec::new(),
-            dict_path: ifo_path.with_extension("dict"),
+            indices: OnceLock::new(),
+            shards: OnceLock::new(),
+            anagrams: OnceLock::new(),
+            trigrams: OnceLock::new(),
+            keywords: OnceLock::new(),
+            length_buckets: OnceLock::new(),
+            synonyms: OnceLock::new(),
+            stats: OnceLock::new(),
+            dictzip: OnceLock::new(),
+            resources: OnceLock::new(),
+            sorted: OnceLock::new(),
+            dict_path,
             idx_path: ifo_path.with_extension("idx"),
-            ifo_path: ifo_path.clone(),
+            syn_path: ifo_path.with_extension("syn"),
+            ifo_path: ifo_path.to_path_buf(),
             cache_path: ifo_path.with_extension("sozl"),
             bookname: String::from("No bookname"),
-            sametype_sequence: SameTypeSequence::None,
+            label: None,
+            lang: None,
+            sametype_sequence: String::new(),
             wordcount: 0,
+            idx_offset_bits: 32,
             preferred_algorithm: None,
             preferred_depth: None,
+            postprocess: Vec::new(),
+            headword_filter: HeadwordFilter::default(),
+            max_results: None,
+            page_style: PageStyle::default(),
+            definition_limit: None,
+            source: None,
+            verify: false,
+            sharded_cache_entries: SHARDED_CACHE_ENTRIES,
+        }
+    }
+
+    /// Dictionary reading its headwords and definitions from `source`. It has no files,
+    /// its indices are kept only in memory.
+    pub fn from_source(source: Arc<dyn DictSource>) -> Dictionary {
+        let mut dictionary = Dictionary::new(Path::new(""));
+        dictionary.bookname = source.bookname().to_string();
+        dictionary.lang = source.lang().map(String::from);
+        dictionary.wordcount = source.wordcount().unwrap_or_else(|e| {
+            warn!("Can't count the headwords of {}: {}", source.bookname(), e);
+            0
+        });
+        dictionary.source = Some(source);
+        dictionary
+    }
+
+    /// Name to show in output, the label if one is set or the bookname.
+    pub fn label(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.bookname)
+    }
+
+    /// Whether the headwords are in language `lang`, compared case insensitively.
+    pub fn has_lang(&self, lang: &str) -> bool {
+        self.lang
+            .as_deref()
+            .is_some_and(|l| l.eq_ignore_ascii_case(lang))
+    }
+
+    /// File `name` of the resource storage, the `res` directory next to the .ifo file.
+    /// Names that would leave the directory aren't resolved. Resources of a res.rdic
+    /// database aren't files, see `read_resource`.
+    pub fn resource_path(&self, name: &str) -> Option<PathBuf> {
+        let path = resources::path_in(&self.ifo_path.parent()?.join("res"), name)?;
+        path.is_file().then_some(path)
+    }
+
+    /// Data of the resource `name` from the res.rdic database or the `res` directory,
+    /// fails with `io::ErrorKind::NotFound` if the dictionary has no such resource.
+    pub fn read_resource(&self, name: &str) -> io::Result<Vec<u8>> {
+        let storage = self.resources.get_or_init(|| {
+            let dir = self.ifo_path.parent()?;
+            ResourceStorage::open(dir).unwrap_or_else(|e| {
+                warn!("Can't open resources of {}: {}", &self.bookname, e);
+                None
+            })
+        });
+        match storage {
+            Some(storage) => storage.read(name),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} has no resources.", self.label()),
+            )),
+        }
+    }
+
+    /// Describes the dictionary for listings.
+    pub fn info(&self) -> DictionaryInfo {
+        DictionaryInfo {
+            bookname: self.bookname.clone(),
+            label: self.label.clone(),
+            lang: self.lang.clone(),
+            wordcount: self.wordcount,
+            cache: self.cache_status(),
+            ifo_path: self.ifo_path.clone(),
+        }
+    }
+
+    /// Whether the cache exists and was built from the current .idx file. The .idx file
+    /// is only read when its size or modification time changed since, see
+    /// `verify_cache` for comparing its checksum anyway.
+    pub fn cache_status(&self) -> CacheStatus {
+        self.header_status(false)
+    }
+
+    fn header_status(&self, checksum: bool) -> CacheStatus {
+        let file = match File::open(&self.cache_path) {
+            Ok(n) => n,
+            Err(_) => return CacheStatus::Missing,
+        };
+        let mut reader = io::BufReader::new(file);
+        match deserialize_from::<_, u64>(&mut reader) {
+            Ok(CACHE_MAGIC) => (),
+            //Caches of other versions have other fields.
+            Ok(_) => return CacheStatus::Stale,
+            Err(_) => return CacheStatus::Corrupt,
+        }
+        let header: CacheHeader = match deserialize_from(&mut reader) {
+            Ok(n) => n,
+            Err(_) => return CacheStatus::Corrupt,
+        };
+        if !checksum && FileStamp::of(&self.idx_path).ok() == Some(header.idx_stamp) {
+            return CacheStatus::Current;
+        }
+        match FileChecksum::of(&self.idx_path) {
+            Ok(idx) if idx == header.idx => CacheStatus::Current,
+            _ => CacheStatus::Stale,
+        }
+    }
+
+    /// Keeps the cache next to the .ifo file if it's current or its directory is
+    /// writable, otherwise uses one in the cache directory of the user so dictionaries
+    /// in read-only system directories are cached too.
+    pub fn locate_cache(&mut self) {
+        if self.cache_status() == CacheStatus::Current {
+            return;
+        }
+        let dir = match self.ifo_path.parent() {
+            Some(n) if n.as_os_str().is_empty() => Path::new("."),
+            Some(n) => n,
+            None => return,
+        };
+        let writable = tempfile::Builder::new()
+            .prefix(".sozluk-")
+            .tempfile_in(dir)
+            .is_ok();
+        if !writable {
+            if let Some(root) = cache_root() {
+                self.use_user_cache(&root.join("index"));
+            }
+        }
+    }
+
+    /// Moves the cache to a directory under `root` named after the checksum of the
+    /// canonical .ifo path.
+    fn use_user_cache(&mut self, root: &Path) {
+        let ifo = fs::canonicalize(&self.ifo_path).unwrap_or_else(|_| self.ifo_path.clone());
+        let dir = root.join(format!(
+            "{:08x}",
+            crc32fast::hash(ifo.to_string_lossy().as_bytes())
+        ));
+        self.cache_path = dir.join(self.ifo_path.with_extension("sozl").file_name().unwrap());
+        debug!(
+            "Directory of {} isn't writable, caching {} in {}.",
+            ifo.display(),
+            &self.bookname,
+            self.cache_path.display()
+        );
+    }
+
+    /// Same as `cache_status` but compares the checksum of the .idx file and reads the
+    /// whole cache of a current one to tell if it's corrupt.
+    pub fn verify_cache(&self) -> CacheStatus {
+        match self.header_status(true) {
+            CacheStatus::Current
+                if self
+                    .load_cache()
+                    .and_then(|c| self.cached_indices(c))
+                    .is_err() =>
+            {
+                CacheStatus::Corrupt
+            }
+            status => status,
         }
     }
 
+    /// Parses the .idx file without touching the cache, returns the number of entries.
+    pub fn check_index(&self) -> Result<usize, DictionaryError> {
+        self.parse_index_file().map(|n| n.len())
+    }
+
     /// Instantiates a dictionary from give directory or .ifo file path.
     /// Does all plumbing necessary to locate .ifo file, parsing .ifo and .idx
     /// files and cache operations. Return `None`on on
-    pub fn load_dictionary(path: &PathBuf) -> Result<Dictionary, DictionaryError> {
+    pub fn load_dictionary(path: &Path) -> Result<Dictionary, DictionaryError> {
+        let mut dictionary = Dictionary::load_metadata(path)?;
+        let indices = dictionary.read_indices()?;
+        dictionary.indices = OnceLock::from(indices);
+        let synonyms = dictionary.read_synonyms()?;
+        dictionary.synonyms = OnceLock::from(synonyms);
+        Ok(dictionary)
+    }
+
+    /// Locates and parses the .ifo file from given directory or .ifo file path
+    /// without touching the index. Index is read from the cache or .idx file the
+    /// first time `indices()` is called.
+    pub fn load_metadata(path: &Path) -> Result<Dictionary, DictionaryError> {
         let ifo_path = if path.is_dir() {
             match Dictionary::find_ifo_in_dir(path) {
                 Some(n) => n,
@@ -129,128 +1013,912 @@ impl<'a> Dictionary {
         );
 
         dictionary.parse_ifo_file()?;
+        dictionary.locate_cache();
+        Ok(dictionary)
+    }
 
-        if let Err(_) = dictionary.load_cache() {
-            debug!("Failed loading the cache from {:?}", &dictionary.cache_path);
-            if let Err(_) = dictionary.parse_index_file() {
-                return Err(DictionaryError::IOError);
-            }
-            if let Err(_) = dictionary.save_cache() {
-                debug!("Error when saving index cache.");
+    /// Returns the index entries, reading them from the cache or .idx file on first call.
+    /// A dictionary whose index can't be read behaves as an empty one, see `try_indices`.
+    pub fn indices(&self) -> &[Index] {
+        match self.try_indices() {
+            Ok(n) => n,
+            Err(e) => {
+                error!("Can't read the index of {}: {}", &self.bookname, e);
+                &[]
             }
         }
+    }
 
-        Ok(dictionary)
+    /// Same as `indices`, but fails if the index can't be read. It's read again on the
+    /// next call then.
+    pub fn try_indices(&self) -> Result<&[Index], DictionaryError> {
+        if let Some(indices) = self.indices.get() {
+            return Ok(indices);
+        }
+        let indices = self.read_indices()?;
+        Ok(self.indices.get_or_init(|| indices))
     }
-    /// Returns the .ifo file in given path. If no .ifo file found or path is not a directory
-    /// returns None. Returned value use for constructing Dictionary structs.
-    fn find_ifo_in_dir(dir: &PathBuf) -> Option<PathBuf> {
-        debug!("Looking for .ifo file in {:?}", &dir);
-        for entry in dir.read_dir().unwrap() {
-            if let Ok(entry) = entry {
-                if let Some(n) = entry.path().extension() {
-                    if n == "ifo" {
-                        return Some(PathBuf::from(entry.path()));
-                    }
-                }
+
+    /// Returns the synonyms of the .syn file, reading them on first call. A dictionary
+    /// without a readable .syn file has none.
+    pub fn synonyms(&self) -> &[Synonym] {
+        self.synonyms.get_or_init(|| match self.read_synonyms() {
+            Ok(n) => n,
+            Err(e) => {
+                error!("Can't read the synonyms of {}: {}", &self.bookname, e);
+                Vec::new()
             }
-        }
-        None
+        })
     }
 
-    pub fn select_random_word(&self) -> &Index {
-        let n: usize = thread_rng()
-            .gen_range(0, self.wordcount)
-            .try_into()
-            .unwrap();
-        &self.indices[n]
+    /// Entry at `position` of the index. Only the shard holding it is read when the
+    /// whole index isn't.
+    pub fn index_at(&self, position: usize) -> Option<&Index> {
+        if let Some(indices) = self.loaded_indices() {
+            return indices.get(position);
+        }
+        let shards = self.shards();
+        let n = shards.partition_point(|s| s.range.end <= position);
+        let shard = shards.get(n)?;
+        self.shard_indices(n)
+            .get(position.checked_sub(shard.range.start)?)
     }
 
-    fn save_cache(&self) -> Result<(), io::Error> {
-        let idx: Vec<u8> = serialize(&self.indices).unwrap();
-        write(&self.cache_path, &idx)?;
-        debug!("Writing cache to {:?}", &self.cache_path);
-        Ok(())
+    /// Whether `indices()` can return without reading anything from disk.
+    pub fn is_index_loaded(&self) -> bool {
+        self.indices.get().is_some()
     }
 
-    fn load_cache(&mut self) -> Result<(), DictionaryError> {
-        debug!("Loading cache from {:?}", &self.cache_path);
-        let idx: Vec<u8> = read(&self.cache_path)?;
-        self.indices = match deserialize(&idx) {
-            Ok(n) => n,
-            Err(_) => return Err(DictionaryError::IOError),
-        };
-        Ok(())
+    /// Index entries if they are already read, without reading them.
+    pub fn loaded_indices(&self) -> Option<&[Index]> {
+        self.indices.get().map(Vec::as_slice)
     }
 
-    fn parse_index_file(&mut self) -> Result<(), io::Error> {
-        let mut index_file = match File::open(&self.idx_path) {
+    /// Returns the shards of the index. Reads only the shard table from a sharded
+    /// cache, otherwise the whole index is read too. A dictionary whose index can't be
+    /// read has none, see `try_shards`.
+    pub fn shards(&self) -> &[Shard] {
+        match self.try_shards() {
             Ok(n) => n,
-            Err(n) => {
-                error!("Error opening index file at: {}", &self.idx_path.display());
-                return Err(n);
+            Err(e) => {
+                error!("Can't read the index of {}: {}", &self.bookname, e);
+                &[]
             }
-        };
-        let mut indices_raw: Vec<u8> = Vec::new();
-        index_file.read_to_end(&mut indices_raw).unwrap();
-        self.indices = self.parse_index(indices_raw).unwrap();
-        Ok(())
+        }
     }
 
-    fn parse_ifo_file(&mut self) -> Result<(), io::Error> {
-        let mut ifo_file = File::open(&self.ifo_path)?;
-        let mut buffer: String = String::new();
-        ifo_file.read_to_string(&mut buffer).ok();
-        self.sametype_sequence = match self.parse_field_from_ifo(&buffer, "sametypesequence") {
-            Some(n) => Definition::match_sametype_sequence(n.as_str()),
-            None => SameTypeSequence::None,
-        };
-        self.wordcount = match self.parse_field_from_ifo(&buffer, "wordcount") {
-            Some(n) => n.parse().unwrap(),
-            None => 0,
-        };
-        self.bookname = match self.parse_field_from_ifo(&buffer, "bookname") {
-            Some(n) => String::from(n),
-            None => {
-                println!("Book doesn't have bookname field");
-                String::from(self.dict_path.to_str().unwrap())
-            }
+    /// Same as `shards`, but fails if neither the shard table nor the index can be read.
+    pub fn try_shards(&self) -> Result<&[Shard], DictionaryError> {
+        if let Some(shards) = self.shards.get() {
+            return Ok(shards);
+        }
+        let shards = match self.loaded_indices() {
+            Some(indices) => shards_of(indices),
+            None => match self.load_cache_table() {
+                Ok(table) if !self.verify => {
+                    let _ = self.stats.set(table.stats);
+                    if !table.sharded {
+                        self.try_indices()?;
+                    }
+                    table.shards
+                }
+                _ => shards_of(self.try_indices()?),
+            },
         };
+        Ok(self.shards.get_or_init(|| shards))
+    }
 
-        Ok(())
+    /// Whether searches can start without reading the whole index, they read the
+    /// shards they reach then.
+    pub fn is_shard_table_loaded(&self) -> bool {
+        self.shards.get().is_some()
     }
 
-    // This function was just a wrapper, now obsolete. 12 July 2020
-    pub fn read_multiple_definitions(
-        &self,
-        indices: &Vec<&Index>,
-    ) -> Result<Vec<Definition>, io::Error> {
-        let mut results = Vec::new();
-        for ind in indices {
-            results.push(self.read_definition(ind)?);
+    /// Returns the entries of the `n`th shard. They are taken from the index if it
+    /// is read, otherwise from the shard's own cache file. Falls back to reading the
+    /// whole index when the dictionary has no sharded cache.
+    pub fn shard_indices(&self, n: usize) -> &[Index] {
+        let shard = &self.shards()[n];
+        if let Some(indices) = self.loaded_indices() {
+            return indices.get(shard.range.clone()).unwrap_or_default();
+        }
+        if let Some(indices) = shard.indices.get() {
+            return indices;
+        }
+        match self.load_shard_cache(n, shard) {
+            Ok(indices) => shard.indices.get_or_init(|| indices),
+            Err(_) => self.indices().get(shard.range.clone()).unwrap_or_default(),
         }
-        Ok(results)
     }
 
-    /// Returns shared references to `Index` entries that mathches given closure.
-    // pub fn fuzzy_search_indices<T: ?Sized+WordMatcher+Sync>(&self, comparator: &T, word: &str) -> Option<Vec<&Index>> {
-    pub fn fuzzy_search_indices<F: Fn(&str, &str) -> bool + Sync>(
-        &self,
-        comparator: F,
-        word: &str,
-    ) -> Option<Vec<&Index>> {
-        debug!("Searching words matching: {} in {}", &word, &self.bookname);
-        let results: Vec<&Index> = self
-            .indices
-            .par_iter()
-            .filter(|x| comparator(&word, &x.word))
-            .collect();
+    /// Estimated bytes the index takes in memory, `None` if it isn't read yet.
+    /// Counts the entries and the heap buffers of words too long to be stored inline.
+    pub fn index_memory(&self) -> Option<usize> {
+        let indices = self.indices.get()?;
+        let words: usize = indices
+            .iter()
+            .filter(|n| !n.word.is_inline())
+            .map(|n| n.word.capacity())
+            .sum();
+        Some(indices.capacity() * size_of::<Index>() + words)
+    }
 
-        if results.is_empty() {
-            None
-        } else {
-            Some(results)
-        }
+    /// Uses `indices` as the index of this dictionary instead of reading it from disk.
+    /// Returns false and changes nothing if the index is already read.
+    pub fn set_indices(&self, indices: Vec<Index>) -> bool {
+        self.indices.set(indices).is_ok()
+    }
+
+    /// Reads indices from the cache file, or parses the .idx file and writes the
+    /// cache if there is no usable one. Failing to write the cache is not an error.
+    fn read_indices(&self) -> Result<Vec<Index>, DictionaryError> {
+        if let Some(source) = &self.source {
+            let mut indices = source.headwords()?;
+            indices.sort_by(|a, b| stardict_order(&a.word, &b.word));
+            return Ok(indices);
+        }
+        match self.load_cache() {
+            //Only read the whole .idx file when it may have changed or is verified.
+            Ok(cache) if !self.verify && FileStamp::of(&self.idx_path)? == cache.idx_stamp => {
+                return self.cached_indices(cache)
+            }
+            Ok(cache) => {
+                if FileChecksum::of(&self.idx_path)? != cache.idx {
+                    warn!(
+                        "{} changed since its cache was built, rebuilding it.",
+                        self.idx_path.display()
+                    );
+                } else if self.verify && FileChecksum::of(&self.dict_path)? != cache.dict {
+                    return Err(DictionaryError::ChecksumMismatch(self.dict_path.clone()));
+                } else {
+                    debug!("Checksums of {} match its cache.", &self.bookname);
+                    return self.cached_indices(cache);
+                }
+            }
+            Err(_) => debug!("Failed loading the cache from {:?}", &self.cache_path),
+        }
+        info!("Building index cache of {}.", &self.bookname);
+        let indices = self.parse_index_file()?;
+        if indices.len() as u64 != self.wordcount {
+            warn!(
+                "{} has {} entries but its .ifo file says {}.",
+                &self.bookname,
+                indices.len(),
+                self.wordcount
+            );
+        }
+        if let Err(e) = self.save_cache(&indices) {
+            warn!(
+                "Can't save the index cache of {} to {}: {}",
+                &self.bookname,
+                self.cache_path.display(),
+                e
+            );
+        }
+        Ok(indices)
+    }
+    /// Returns the .ifo file in given path. If no .ifo file found, path is not a directory
+    /// or can't be read returns None. Returned value use for constructing Dictionary structs.
+    fn find_ifo_in_dir(dir: &Path) -> Option<PathBuf> {
+        debug!("Looking for .ifo file in {:?}", &dir);
+        let entries = match dir.read_dir() {
+            Ok(n) => n,
+            Err(e) => {
+                warn!("Can't read {}: {}", dir.display(), e);
+                return None;
+            }
+        };
+        for entry in entries.flatten() {
+            if let Some(n) = entry.path().extension() {
+                if n == "ifo" {
+                    return Some(entry.path());
+                }
+            }
+        }
+        None
+    }
+
+    /// A random entry of the index, `None` if it's empty. The number of entries that
+    /// were read is used, the `wordcount` of the .ifo file may be wrong.
+    pub fn select_random_word(&self) -> Option<&Index> {
+        let indices = self.indices();
+        if indices.is_empty() {
+            return None;
+        }
+        indices.get(thread_rng().gen_range(0, indices.len()))
+    }
+
+    /// `n` different random entries of the index, all of them in random order if it
+    /// has fewer.
+    pub fn select_random_words(&self, n: usize) -> Vec<&Index> {
+        let indices = self.indices();
+        sample(&mut thread_rng(), indices.len(), n.min(indices.len()))
+            .into_iter()
+            .map(|n| &indices[n])
+            .collect()
+    }
+
+    fn save_cache(&self, indices: &[Index]) -> Result<(), io::Error> {
+        if let Some(dir) = self.cache_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let idx_stamp = FileStamp::of(&self.idx_path)?;
+        let idx = FileChecksum::of(&self.idx_path)?;
+        let shards = shards_of(indices);
+        let stats = self.stats.get_or_init(|| IndexStats::of(indices));
+        let sharded = indices.len() >= self.sharded_cache_entries;
+        if sharded {
+            for (n, shard) in shards.iter().enumerate() {
+                let cache = ShardCacheRef {
+                    magic: CACHE_MAGIC,
+                    indices: &indices[shard.range.clone()],
+                };
+                write(self.shard_cache_path(n), serialize(&cache).unwrap())?;
+            }
+        }
+        //Shards of an earlier cache beyond the ones written now.
+        let written = if sharded { shards.len() } else { 0 };
+        for path in (written..)
+            .map(|n| self.shard_cache_path(n))
+            .take_while(|p| p.exists())
+        {
+            debug!("Removing stale shard cache {:?}", &path);
+            fs::remove_file(&path)?;
+        }
+        let cache = CacheRef {
+            magic: CACHE_MAGIC,
+            idx,
+            dict: FileChecksum::of(&self.dict_path)?,
+            idx_stamp,
+            sharded,
+            shards: &shards,
+            stats,
+            indices: if sharded { &[] } else { indices },
+        };
+        let idx: Vec<u8> = serialize(&cache).unwrap();
+        write(&self.cache_path, &idx)?;
+        debug!("Writing cache to {:?}", &self.cache_path);
+        let anagrams = AnagramCacheRef {
+            magic: CACHE_MAGIC,
+            entries: &anagrams_of(indices),
+        };
+        write(self.anagram_cache_path(), serialize(&anagrams).unwrap())?;
+        let trigrams = TrigramCacheRef {
+            magic: CACHE_MAGIC,
+            entries: indices.len(),
+            index: &TrigramIndex::of(indices),
+        };
+        write(self.trigram_cache_path(), serialize(&trigrams).unwrap())?;
+        //Not kept in memory, most dictionaries are never searched by their definitions.
+        self.save_keyword_cache(indices.len(), &self.keywords_of(indices))?;
+        Ok(())
+    }
+
+    /// Index cache files that exist, the cache itself, the caches of its shards, the
+    /// anagram table, the trigram index and the keyword index.
+    pub fn cache_files(&self) -> Vec<PathBuf> {
+        let mut files = vec![
+            self.cache_path.clone(),
+            self.anagram_cache_path(),
+            self.trigram_cache_path(),
+            self.keyword_cache_path(),
+        ];
+        files.extend(
+            (0..)
+                .map(|n| self.shard_cache_path(n))
+                .take_while(|p| p.exists()),
+        );
+        files.retain(|p| p.exists());
+        files
+    }
+
+    /// Removes every index cache file, returns the number of bytes freed.
+    pub fn remove_cache(&self) -> Result<u64, io::Error> {
+        let mut freed = 0;
+        for path in self.cache_files() {
+            freed += fs::metadata(&path)?.len();
+            debug!("Removing {:?}", &path);
+            fs::remove_file(&path)?;
+        }
+        Ok(freed)
+    }
+
+    /// Cache of the anagram table, next to the cache of the index.
+    fn anagram_cache_path(&self) -> PathBuf {
+        self.cache_path.with_extension("anagrams.sozl")
+    }
+
+    /// Reads the anagram table from its cache if it is of an index of `len` entries.
+    fn load_anagram_cache(&self, len: usize) -> Result<Vec<AnagramEntry>, DictionaryError> {
+        let cache: Vec<u8> = read(self.anagram_cache_path())?;
+        match deserialize::<AnagramCache>(&cache) {
+            Ok(n) if n.magic == CACHE_MAGIC && n.entries.len() == len => Ok(n.entries),
+            _ => Err(DictionaryError::IOError),
+        }
+    }
+
+    /// Cache of the trigram index, next to the cache of the index.
+    fn trigram_cache_path(&self) -> PathBuf {
+        self.cache_path.with_extension("trigrams.sozl")
+    }
+
+    /// Reads the trigram index from its cache if it is of an index of `len` entries.
+    fn load_trigram_cache(&self, len: usize) -> Result<TrigramIndex, DictionaryError> {
+        let cache: Vec<u8> = read(self.trigram_cache_path())?;
+        match deserialize::<TrigramCache>(&cache) {
+            Ok(n) if n.magic == CACHE_MAGIC && n.entries == len => Ok(n.index),
+            _ => Err(DictionaryError::IOError),
+        }
+    }
+
+    /// Cache of the keyword index, next to the cache of the index.
+    fn keyword_cache_path(&self) -> PathBuf {
+        self.cache_path.with_extension("keywords.sozl")
+    }
+
+    /// Reads the keyword index from its cache if it is of an index of `len` entries.
+    fn load_keyword_cache(&self, len: usize) -> Result<KeywordIndex, DictionaryError> {
+        let cache: Vec<u8> = read(self.keyword_cache_path())?;
+        match deserialize::<KeywordCache>(&cache) {
+            Ok(n) if n.magic == CACHE_MAGIC && n.entries == len => Ok(n.index),
+            _ => Err(DictionaryError::IOError),
+        }
+    }
+
+    /// Keyword index of the definitions of `indices`, reads every one of them.
+    fn keywords_of(&self, indices: &[Index]) -> KeywordIndex {
+        info!("Building the keyword index of {}.", &self.bookname);
+        let definitions: Vec<(u32, String)> = indices
+            .par_iter()
+            .enumerate()
+            .filter_map(|(n, index)| {
+                let definition = self.read_whole_definition(index).ok()?;
+                Some((n as u32, definition.readable()))
+            })
+            .collect();
+        KeywordIndex::of(definitions)
+    }
+
+    /// Writes `keywords` of an index of `len` entries to the keyword cache.
+    fn save_keyword_cache(&self, len: usize, keywords: &KeywordIndex) -> Result<(), io::Error> {
+        let cache = KeywordCacheRef {
+            magic: CACHE_MAGIC,
+            entries: len,
+            index: keywords,
+        };
+        write(self.keyword_cache_path(), serialize(&cache).unwrap())
+    }
+
+    /// Builds the keyword index of a dictionary without one and caches it, for caches
+    /// written before the keyword index was written with them.
+    fn build_keyword_index(&self, indices: &[Index]) -> KeywordIndex {
+        let keywords = self.keywords_of(indices);
+        //Other sources have no files to cache next to.
+        if self.source.is_some() {
+            return keywords;
+        }
+        if let Err(e) = self.save_keyword_cache(indices.len(), &keywords) {
+            warn!(
+                "Can't save the keyword index of {} to {}: {}",
+                &self.bookname,
+                self.keyword_cache_path().display(),
+                e
+            );
+        }
+        keywords
+    }
+
+    /// Keyword index of the definitions, loaded from its cache on first use. Built and
+    /// cached then if the cache of the index didn't come with one.
+    pub fn keyword_index(&self) -> &KeywordIndex {
+        self.keywords.get_or_init(|| {
+            let indices = self.indices();
+            match self.load_keyword_cache(indices.len()) {
+                Ok(n) => n,
+                Err(_) => self.build_keyword_index(indices),
+            }
+        })
+    }
+
+    /// Index entries whose definitions have keywords at most `edits` edits away from
+    /// the keywords of `query`, see `KeywordIndex::search`, with their distance in index
+    /// order. The keyword index is read on first use. Headwords `headword_filter`
+    /// rejects are left out.
+    pub fn search_keywords(&self, query: &str, edits: usize) -> Option<Vec<(&Index, usize)>> {
+        let indices = self.indices();
+        let keywords = self.keyword_index();
+        let found: Vec<(&Index, usize)> = keywords
+            .search(query, edits)?
+            .into_iter()
+            .filter_map(|(n, distance)| Some((indices.get(n as usize)?, distance)))
+            .filter(|(n, _)| self.headword_filter.accepts(&n.word))
+            .collect();
+        Some(found).filter(|n| !n.is_empty())
+    }
+
+    /// Index entries that can be at most `edits` edits away from `word`, see
+    /// `TrigramIndex::candidates`.
+    fn trigram_candidates<'b>(
+        &self,
+        indices: &'b [Index],
+        word: &str,
+        edits: usize,
+    ) -> Option<Vec<&'b Index>> {
+        let trigrams = self
+            .trigrams
+            .get_or_init(|| match self.load_trigram_cache(indices.len()) {
+                Ok(n) => n,
+                Err(_) => {
+                    debug!("Building the trigram index of {}.", &self.bookname);
+                    TrigramIndex::of(indices)
+                }
+            });
+        let candidates = trigrams.candidates(word, edits)?;
+        Some(
+            candidates
+                .into_iter()
+                .filter_map(|n| indices.get(n as usize))
+                .collect(),
+        )
+    }
+
+    /// Index entries matching the crossword `pattern`, see `matcher::matches_pattern`,
+    /// in index order. Only the entries as long as the pattern are compared.
+    pub fn search_pattern(&self, pattern: &str) -> Option<Vec<&Index>> {
+        let indices = self.indices();
+        let buckets = self.length_buckets.get_or_init(|| {
+            let mut buckets: Vec<Vec<u32>> = Vec::new();
+            for (n, index) in indices.iter().enumerate() {
+                let length = grapheme_count(&index.word);
+                if buckets.len() <= length {
+                    buckets.resize_with(length + 1, Vec::new);
+                }
+                buckets[length].push(n as u32);
+            }
+            buckets
+        });
+        let found: Vec<&Index> = buckets
+            .get(grapheme_count(pattern))?
+            .iter()
+            .map(|n| &indices[*n as usize])
+            .filter(|n| matches_pattern(pattern, &n.word) && self.headword_filter.accepts(&n.word))
+            .collect();
+        Some(found).filter(|n| !n.is_empty())
+    }
+
+    /// Index entries whose definitions contain `phrase`, see `contains_phrase`, in index
+    /// order. Only the definitions having every keyword of `phrase` in the keyword index
+    /// are read, all of them if it has none. `None` if `phrase` has no words. Headwords
+    /// `headword_filter` rejects are left out.
+    pub fn fulltext_search(&self, phrase: &str) -> Option<Vec<&Index>> {
+        if phrase
+            .split(|c: char| !c.is_alphanumeric())
+            .all(str::is_empty)
+        {
+            return None;
+        }
+        let indices = self.indices();
+        let candidates: Vec<&Index> = match self.keyword_index().search(phrase, 0) {
+            Some(found) => found
+                .into_iter()
+                .filter_map(|(n, _)| indices.get(n as usize))
+                .collect(),
+            None => indices.iter().collect(),
+        };
+        let found: Vec<&Index> = candidates
+            .into_par_iter()
+            .filter(|n| self.headword_filter.accepts(&n.word))
+            .filter(|n| {
+                self.read_whole_definition(n)
+                    .is_ok_and(|d| contains_phrase(&d.readable(), phrase))
+            })
+            .collect();
+        Some(found).filter(|n| !n.is_empty())
+    }
+
+    /// Index entries whose letters are an anagram of the letters of `word`, see
+    /// `anagram_key`, in index order. Headwords `headword_filter` rejects are left out.
+    pub fn search_anagrams(&self, word: &str) -> Option<Vec<&Index>> {
+        let indices = self.indices();
+        let anagrams = self
+            .anagrams
+            .get_or_init(|| match self.load_anagram_cache(indices.len()) {
+                Ok(n) => n,
+                Err(_) => {
+                    debug!("Building the anagram table of {}.", &self.bookname);
+                    anagrams_of(indices)
+                }
+            });
+        let key = anagram_key(word);
+        let start = anagrams.partition_point(|n| n.key < key);
+        let mut positions: Vec<usize> = anagrams[start..]
+            .iter()
+            .take_while(|n| n.key == key)
+            .map(|n| n.position as usize)
+            .collect();
+        positions.sort_unstable();
+        let found: Vec<&Index> = positions
+            .into_iter()
+            .filter_map(|n| indices.get(n))
+            .filter(|n| self.headword_filter.accepts(&n.word))
+            .collect();
+        Some(found).filter(|n| !n.is_empty())
+    }
+
+    fn load_cache(&self) -> Result<Cache, DictionaryError> {
+        debug!("Loading cache from {:?}", &self.cache_path);
+        let idx: Vec<u8> = read(&self.cache_path)?;
+        match deserialize::<Cache>(&idx) {
+            Ok(n) if n.magic == CACHE_MAGIC => Ok(n),
+            _ => Err(DictionaryError::IOError),
+        }
+    }
+
+    /// Reads the cache without its indices, fails like `read_indices` ignores the cache
+    /// when the .idx file may have changed since it was built.
+    fn load_cache_table(&self) -> Result<CacheTable, DictionaryError> {
+        let file = File::open(&self.cache_path)?;
+        match deserialize_from::<_, CacheTable>(io::BufReader::new(file)) {
+            Ok(n) if n.magic == CACHE_MAGIC && FileStamp::of(&self.idx_path)? == n.idx_stamp => {
+                Ok(n)
+            }
+            _ => Err(DictionaryError::IOError),
+        }
+    }
+
+    /// Indices of `cache`, read from the file of each shard if it's sharded.
+    fn cached_indices(&self, cache: Cache) -> Result<Vec<Index>, DictionaryError> {
+        let _ = self.stats.set(cache.stats);
+        if !cache.sharded {
+            return Ok(cache.indices);
+        }
+        let mut indices = Vec::new();
+        for (n, shard) in cache.shards.iter().enumerate() {
+            indices.extend(self.load_shard_cache(n, shard)?);
+        }
+        Ok(indices)
+    }
+
+    /// Cache of the `n`th shard, next to the cache of the whole dictionary.
+    fn shard_cache_path(&self, n: usize) -> PathBuf {
+        self.cache_path.with_extension(format!("{}.sozl", n))
+    }
+
+    fn load_shard_cache(&self, n: usize, shard: &Shard) -> Result<Vec<Index>, DictionaryError> {
+        let path = self.shard_cache_path(n);
+        debug!("Loading shard cache from {:?}", &path);
+        let idx: Vec<u8> = read(&path)?;
+        match deserialize::<ShardCache>(&idx) {
+            Ok(n) if n.magic == CACHE_MAGIC && n.indices.len() == shard.range.len() => {
+                Ok(n.indices)
+            }
+            _ => Err(DictionaryError::IOError),
+        }
+    }
+
+    fn parse_index_file(&self) -> Result<Vec<Index>, DictionaryError> {
+        let mut index_file = match File::open(&self.idx_path) {
+            Ok(n) => n,
+            Err(n) => {
+                error!("Error opening index file at: {}", &self.idx_path.display());
+                return Err(n.into());
+            }
+        };
+        let mut indices_raw: Vec<u8> = Vec::new();
+        index_file.read_to_end(&mut indices_raw)?;
+        self.parse_index(&indices_raw)
+    }
+
+    /// Parses the .syn file, `Ok` with no synonyms if there is none. Each entry is
+    /// a NUL terminated word followed by the big endian position of its index entry.
+    /// Entries that aren't valid UTF-8 are skipped, a truncated last entry is dropped.
+    fn read_synonyms(&self) -> Result<Vec<Synonym>, DictionaryError> {
+        let raw = match read(&self.syn_path) {
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut synonyms = Vec::new();
+        let mut position = 0;
+        while let Some(n) = raw[position..].iter().position(|b| *b == 0) {
+            let word_end = position + n;
+            let mut field = match raw.get(word_end + 1..word_end + 1 + size_of::<u32>()) {
+                Some(n) => n,
+                None => {
+                    warn!("Truncated entry at the end of {}.", self.syn_path.display());
+                    break;
+                }
+            };
+            match std::str::from_utf8(&raw[position..word_end]) {
+                Ok(word) => synonyms.push(Synonym {
+                    word: SmartString::from(word),
+                    position: field.read_u32::<BigEndian>()?,
+                }),
+                Err(_) => warn!(
+                    "Skipping synonym with invalid UTF-8 at byte {} of {}.",
+                    position,
+                    self.syn_path.display()
+                ),
+            }
+            position = word_end + 1 + size_of::<u32>();
+        }
+        debug!("Read {} synonyms of {}.", synonyms.len(), &self.bookname);
+        Ok(synonyms)
+    }
+
+    fn parse_ifo_file(&mut self) -> Result<(), io::Error> {
+        let mut ifo_file = File::open(&self.ifo_path)?;
+        let mut buffer: String = String::new();
+        ifo_file.read_to_string(&mut buffer).ok();
+        self.sametype_sequence = self
+            .parse_field_from_ifo(&buffer, "sametypesequence")
+            .unwrap_or_default();
+        self.idx_offset_bits = match self.parse_field_from_ifo(&buffer, "idxoffsetbits") {
+            Some(n) if n.trim() == "64" => 64,
+            Some(n) if n.trim() != "32" => {
+                warn!(
+                    "Unknown idxoffsetbits {} of {}, using 32.",
+                    n,
+                    &self.ifo_path.display()
+                );
+                32
+            }
+            _ => 32,
+        };
+        self.wordcount = match self.parse_field_from_ifo(&buffer, "wordcount") {
+            Some(n) => n.trim().parse().unwrap_or_else(|_| {
+                warn!(
+                    "Invalid wordcount {} of {}, using 0.",
+                    n,
+                    &self.ifo_path.display()
+                );
+                0
+            }),
+            None => 0,
+        };
+        self.bookname = match self.parse_field_from_ifo(&buffer, "bookname") {
+            Some(n) => n,
+            None => {
+                warn!(
+                    "{} doesn't have a bookname field.",
+                    &self.ifo_path.display()
+                );
+                String::from(self.dict_path.to_str().unwrap())
+            }
+        };
+        self.lang = self.parse_field_from_ifo(&buffer, "lang");
+        self.preferred_algorithm = self.parse_field_from_ifo(&buffer, "preferredalgorithm");
+        self.preferred_depth = self
+            .parse_field_from_ifo(&buffer, "preferreddepth")
+            .and_then(|n| n.parse().ok());
+
+        Ok(())
+    }
+
+    // This function was just a wrapper, now obsolete. 12 July 2020
+    pub fn read_multiple_definitions(
+        &self,
+        indices: &Vec<&Index>,
+    ) -> Result<Vec<Definition>, io::Error> {
+        let mut results = Vec::new();
+        for ind in indices {
+            results.push(self.read_definition(ind)?);
+        }
+        Ok(results)
+    }
+
+    /// Returns shared references to `Index` entries that mathches given closure, at most
+    /// `max_results` of them.
+    // pub fn fuzzy_search_indices<T: ?Sized+WordMatcher+Sync>(&self, comparator: &T, word: &str) -> Option<Vec<&Index>> {
+    pub fn fuzzy_search_indices<F: Fn(&str, &str) -> bool + Sync>(
+        &self,
+        comparator: F,
+        word: &str,
+    ) -> Option<Vec<&Index>> {
+        self.fuzzy_search_indices_until(comparator, word, None).0
+    }
+
+    /// Same as `fuzzy_search_indices` but stops comparing words once `deadline` has passed.
+    /// Second element is `true` if the search was cut short, in that case only the
+    /// matches found until the deadline are returned.
+    pub fn fuzzy_search_indices_until<F: Fn(&str, &str) -> bool + Sync>(
+        &self,
+        comparator: F,
+        word: &str,
+        deadline: Option<Instant>,
+    ) -> (Option<Vec<&Index>>, bool) {
+        self.fuzzy_search_shards_until(comparator, None, word, deadline)
+    }
+
+    /// Same as `fuzzy_search_shards_until`, but with a `lookup` the candidates are found by
+    /// binary search when the index is read and sorted, or through the trigram index for
+    /// `Lookup::Edits`, and only they are compared.
+    pub fn search_until<F: Fn(&str, &str) -> bool + Sync>(
+        &self,
+        comparator: F,
+        lookup: Option<Lookup>,
+        bounds: Option<WordBounds>,
+        word: &str,
+        deadline: Option<Instant>,
+    ) -> (Option<Vec<&Index>>, bool) {
+        //Synonyms can match words the headwords couldn't.
+        if let (Some(lookup), Some(stats)) = (lookup, self.stats.get()) {
+            if !stats.may_match(word, lookup) && self.synonyms().is_empty() {
+                debug!("{} can't have {}, skipping it.", &self.bookname, word);
+                return (None, false);
+            }
+        }
+        let candidates: Vec<&Index> = match (lookup, self.loaded_indices()) {
+            (Some(Lookup::Edits(edits)), Some(indices)) => {
+                match self.trigram_candidates(indices, word, edits) {
+                    Some(n) => n,
+                    None => {
+                        return self.fuzzy_search_shards_until(comparator, bounds, word, deadline)
+                    }
+                }
+            }
+            (Some(lookup), Some(indices)) if self.is_sorted() => indices
+                [lookup_range(indices, lookup, word)]
+            .iter()
+            .collect(),
+            _ => return self.fuzzy_search_shards_until(comparator, bounds, word, deadline),
+        };
+        debug!("Looking up {} in {}", &word, &self.bookname);
+        let clock = SearchClock::new(deadline);
+        let is_match = |headword: &str| clock.has_time() && comparator(word, headword);
+        let results: Vec<&Index> = candidates
+            .into_iter()
+            .filter(|x| is_match(&x.word))
+            .collect();
+        let results = self.keep_best(self.with_synonyms(results, is_match), word);
+        let truncated = clock.expired();
+        if results.is_empty() {
+            (None, truncated)
+        } else {
+            (Some(results), truncated)
+        }
+    }
+
+    /// Statistics of the headwords, see `IndexStats`. Read from the cache with the
+    /// indices, or computed from them if they didn't come from one.
+    pub fn stats(&self) -> &IndexStats {
+        let indices = self.indices();
+        self.stats.get_or_init(|| IndexStats::of(indices))
+    }
+
+    /// Index entries whose headword equals `word` with ASCII letters compared case
+    /// insensitively, in index order.
+    pub fn search_exact(&self, word: &str) -> Vec<&Index> {
+        let indices = self.indices();
+        if self.is_sorted() {
+            return indices[lookup_range(indices, Lookup::Exact, word)]
+                .iter()
+                .collect();
+        }
+        indices
+            .iter()
+            .filter(|n| n.word.as_bytes().eq_ignore_ascii_case(word.as_bytes()))
+            .collect()
+    }
+
+    /// Whether the headwords of the index are in `stardict_order`, as they should be.
+    /// Only sorted indices are searched by binary search.
+    pub fn is_sorted(&self) -> bool {
+        *self.sorted.get_or_init(|| {
+            let sorted = self
+                .indices()
+                .windows(2)
+                .all(|n| stardict_order(&n[0].word, &n[1].word) != cmp::Ordering::Greater);
+            if !sorted {
+                debug!(
+                    "Index of {} isn't sorted, it's always scanned.",
+                    &self.bookname
+                );
+            }
+            sorted
+        })
+    }
+
+    /// Same as `fuzzy_search_indices_until` but only compares the words within `bounds`,
+    /// all of them if it's `None`. Only the shards that can have such words are read,
+    /// and only their entries of a length within `bounds` are compared.
+    pub fn fuzzy_search_shards_until<F: Fn(&str, &str) -> bool + Sync>(
+        &self,
+        comparator: F,
+        bounds: Option<WordBounds>,
+        word: &str,
+        deadline: Option<Instant>,
+    ) -> (Option<Vec<&Index>>, bool) {
+        debug!("Searching words matching: {} in {}", &word, &self.bookname);
+        let clock = SearchClock::new(deadline);
+        let is_match = |headword: &str| clock.has_time() && comparator(word, headword);
+        let results: Vec<&Index> = match bounds {
+            Some(bounds) => {
+                let shards = self.shards();
+                let candidates: Vec<(usize, &[Index], &[u32])> = (0..shards.len())
+                    .filter(|n| shards[*n].can_match(&bounds))
+                    .map(|n| {
+                        (
+                            n,
+                            self.shard_indices(n),
+                            shards[n].positions_within(&bounds),
+                        )
+                    })
+                    .collect();
+                let mut found: Vec<(usize, u32, &Index)> = candidates
+                    .into_par_iter()
+                    .flat_map(|(n, indices, positions)| {
+                        positions
+                            .par_iter()
+                            .map(move |p| (n, *p, &indices[*p as usize]))
+                    })
+                    .filter(|(_, _, x)| is_match(&x.word))
+                    .collect();
+                //Entries were compared in order of length, results are in index order.
+                found.sort_unstable_by_key(|(n, p, _)| (*n, *p));
+                found.into_iter().map(|(_, _, x)| x).collect()
+            }
+            None => self
+                .indices()
+                .par_iter()
+                .filter(|x| is_match(&x.word))
+                .collect(),
+        };
+        let results = self.keep_best(self.with_synonyms(results, is_match), word);
+        let truncated = clock.expired();
+        if results.is_empty() {
+            (None, truncated)
+        } else {
+            (Some(results), truncated)
+        }
+    }
+
+    /// `results` followed by the entries of the synonyms matching with `is_match`.
+    fn with_synonyms<'b, F: Fn(&str) -> bool + Sync>(
+        &'b self,
+        mut results: Vec<&'b Index>,
+        is_match: F,
+    ) -> Vec<&'b Index> {
+        let mut positions: Vec<u32> = self
+            .synonyms()
+            .par_iter()
+            .filter(|n| is_match(&n.word))
+            .map(|n| n.position)
+            .collect();
+        if !positions.is_empty() {
+            //The entries synonyms stand for are given once, after the ones matching themselves.
+            positions.sort_unstable();
+            positions.dedup();
+            let found: HashSet<(u64, u32)> = results.iter().map(|x| (x.offset, x.size)).collect();
+            results.extend(
+                positions
+                    .into_iter()
+                    .filter_map(|n| self.index_at(n as usize))
+                    .filter(|x| !found.contains(&(x.offset, x.size))),
+            );
+        }
+        results
+    }
+
+    /// The `max_results` of `results` answering `word` best, see `relevance`, still in
+    /// index order.
+    fn keep_best<'b>(&self, results: Vec<&'b Index>, word: &str) -> Vec<&'b Index> {
+        let limit = match self.max_results {
+            Some(n) if results.len() > n => n,
+            _ => return results,
+        };
+        debug!(
+            "Keeping {} of {} results from {}.",
+            limit,
+            results.len(),
+            &self.bookname
+        );
+        let lowercase = word.to_lowercase();
+        let mut ranked: Vec<(usize, &Index)> = results.into_iter().enumerate().collect();
+        ranked.sort_by_cached_key(|(_, x)| relevance(word, &lowercase, &x.word, None));
+        ranked.truncate(limit);
+        ranked.sort_unstable_by_key(|(n, _)| *n);
+        ranked.into_iter().map(|(_, x)| x).collect()
     }
 
     fn parse_field_from_ifo(&self, buffer: &'a str, field: &str) -> Option<String> {
@@ -260,127 +1928,537 @@ impl<'a> Dictionary {
                 return Some(line.replace(&pattern, ""));
             }
         }
-        None
+        None
+    }
+
+    /// Whether definitions are read from a dictzip compressed .dict.dz file.
+    pub fn is_compressed(&self) -> bool {
+        self.dict_path.extension().is_some_and(|n| n == "dz")
+    }
+
+    /// Checksum of the .ifo file and the sizes of the .idx and .dict files, the same for
+    /// copies of a dictionary in different directories. Only reads the .ifo file.
+    pub fn fingerprint(&self) -> io::Result<u32> {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&fs::read(&self.ifo_path)?);
+        for path in [&self.idx_path, &self.dict_path] {
+            let len = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+            hasher.update(&len.to_le_bytes());
+        }
+        Ok(hasher.finalize())
+    }
+
+    /// Reads the definition entry from .dict file for a given `Index`. Return
+    /// `io::Error`if failed. From a .dict.dz file only the chunks holding the entry
+    /// are decompressed. Entries longer than `definition_limit` are cut there and
+    /// marked `truncated`.
+    pub fn read_definition(&self, index: &Index) -> Result<Definition, io::Error> {
+        self.read_definition_upto(index, self.definition_limit)
+    }
+
+    /// Reads the definition of `index` like `read_definition`, but never cuts it.
+    pub fn read_whole_definition(&self, index: &Index) -> Result<Definition, io::Error> {
+        self.read_definition_upto(index, None)
+    }
+
+    /// Whether `read_definition` cuts the definition of `index`. Definitions of other
+    /// sources are never cut.
+    pub fn cuts(&self, index: &Index) -> bool {
+        self.source.is_none()
+            && self
+                .definition_limit
+                .is_some_and(|limit| index.size as usize > limit)
+    }
+
+    fn read_definition_upto(
+        &self,
+        index: &Index,
+        limit: Option<usize>,
+    ) -> Result<Definition, io::Error> {
+        let mut definition = match &self.source {
+            Some(source) => source.read_definition(index)?,
+            None => self.read_fields(index, limit)?,
+        };
+        if !self.postprocess.is_empty() {
+            definition.postprocess(&self.postprocess);
+        }
+        definition.parsed = definition.parse();
+        Ok(definition)
+    }
+
+    /// Definition of the fields of the .dict entry of `index`, cut at `limit` bytes.
+    fn read_fields(&self, index: &Index, limit: Option<usize>) -> Result<Definition, io::Error> {
+        let mut file = File::open(&self.dict_path)?;
+        let whole: usize = index.size.try_into().unwrap();
+        let size = limit.map_or(whole, |limit| limit.min(whole));
+        let mut buffer = if self.is_compressed() {
+            let dictzip = match self.dictzip.get() {
+                Some(n) => n,
+                None => {
+                    let _ = self.dictzip.set(DictZip::open(&self.dict_path)?);
+                    self.dictzip.get().unwrap()
+                }
+            };
+            dictzip.read(&mut file, index.offset, size)?
+        } else {
+            file.seek(SeekFrom::Start(index.offset))?;
+            let mut buffer: Vec<u8> = vec![0; size];
+            file.read_exact(&mut buffer)?;
+            buffer
+        };
+
+        if size < whole {
+            //A character split at the limit is left out.
+            if let Err(e) = std::str::from_utf8(&buffer) {
+                if e.error_len().is_none() {
+                    buffer.truncate(e.valid_up_to());
+                }
+            }
+        }
+        let sequence: Vec<char> = self.sametype_sequence.chars().collect();
+        let mut definition = Definition::from_fields(&index.word, split_fields(&buffer, &sequence));
+        definition.truncated = size < whole;
+        Ok(definition)
+    }
+
+    /// Parses raw .idx content into `Index` entries. An entry whose word is not valid
+    /// UTF-8 is skipped by jumping past its NUL terminator and its offset/size fields,
+    /// so the following entries stay aligned. A truncated last entry is dropped.
+    /// Gives up with `DictionaryError::CorruptIndex` when more than
+    /// `MAX_CORRUPT_ENTRIES` entries had to be skipped.
+    fn parse_index(&self, raw_indices: &[u8]) -> Result<Vec<Index>, DictionaryError> {
+        let mut indices = Vec::new();
+        let mut skipped: usize = 0;
+        let mut position: usize = 0;
+        let offset_size = if self.idx_offset_bits == 64 {
+            size_of::<u64>()
+        } else {
+            size_of::<u32>()
+        };
+        while position < raw_indices.len() {
+            let entry_start = position;
+            let word_end = match raw_indices[position..].iter().position(|b| *b == 0) {
+                Some(n) => position + n,
+                None => {
+                    error!(
+                        "Unterminated word at byte {} of {}, ignoring the rest of the file.",
+                        entry_start,
+                        self.idx_path.display()
+                    );
+                    skipped += 1;
+                    break;
+                }
+            };
+            position = word_end + 1 + offset_size + size_of::<u32>();
+            if position > raw_indices.len() {
+                error!(
+                    "Truncated entry at byte {} of {}, ignoring the rest of the file.",
+                    entry_start,
+                    self.idx_path.display()
+                );
+                skipped += 1;
+                break;
+            }
+
+            let mut fields = &raw_indices[word_end + 1..position];
+            match std::str::from_utf8(&raw_indices[entry_start..word_end]) {
+                Ok(word) => indices.push(Index {
+                    word: SmartString::from(word),
+                    offset: if offset_size == size_of::<u64>() {
+                        fields.read_u64::<BigEndian>()?
+                    } else {
+                        fields.read_u32::<BigEndian>()?.into()
+                    },
+                    size: fields.read_u32::<BigEndian>()?,
+                }),
+                Err(_) => {
+                    warn!(
+                        "Skipping entry with invalid UTF-8 word at byte {} of {}.",
+                        entry_start,
+                        self.idx_path.display()
+                    );
+                    skipped += 1;
+                    if skipped > MAX_CORRUPT_ENTRIES {
+                        error!(
+                            "Too many corrupt entries in {}, giving up.",
+                            self.idx_path.display()
+                        );
+                        return Err(DictionaryError::CorruptIndex(skipped));
+                    }
+                }
+            }
+        }
+
+        if skipped > 0 {
+            warn!(
+                "Skipped {} corrupt entries while parsing {}.",
+                skipped,
+                self.idx_path.display()
+            );
+        }
+        if self.wordcount != 0 && self.wordcount != indices.len() as u64 {
+            warn!(
+                "{} declares {} words but {} were parsed from its index.",
+                self.bookname,
+                self.wordcount,
+                indices.len()
+            );
+        }
+        Ok(indices)
+    }
+}
+
+impl Hash for Dictionary {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bookname.hash(state);
+    }
+}
+
+/// Splits a .dict entry into its typed fields. With a sametypesequence the type
+/// characters are left out of the entry, otherwise each field starts with its own.
+/// Text fields, lower case types, end with a NUL and binary fields, upper case types,
+/// start with their big endian 32 bit size. The last field of a sametypesequence
+/// entry has neither and takes up the rest of the entry.
+fn split_fields(mut buffer: &[u8], sequence: &[char]) -> Vec<(char, Vec<u8>)> {
+    let mut fields = Vec::new();
+    let mut n = 0;
+    while !buffer.is_empty() {
+        let type_char = if sequence.is_empty() {
+            let c = buffer[0] as char;
+            buffer = &buffer[1..];
+            c
+        } else {
+            match sequence.get(n) {
+                Some(c) => *c,
+                None => break,
+            }
+        };
+        n += 1;
+        let end = if !sequence.is_empty() && n == sequence.len() {
+            buffer.len()
+        } else if type_char.is_ascii_uppercase() {
+            let mut size = buffer;
+            match size.read_u32::<BigEndian>() {
+                Ok(len) => {
+                    buffer = size;
+                    (len as usize).min(buffer.len())
+                }
+                Err(_) => buffer.len(),
+            }
+        } else {
+            buffer.iter().position(|b| *b == 0).unwrap_or(buffer.len())
+        };
+        fields.push((type_char, buffer[..end].to_vec()));
+        //Skips the NUL after text fields.
+        let next = if type_char.is_ascii_uppercase() {
+            end
+        } else {
+            (end + 1).min(buffer.len())
+        };
+        buffer = &buffer[next..];
+    }
+    fields
+}
+
+impl Definition {
+    /// Definition of `word` that is plain `text`.
+    pub fn from_text(word: &str, text: &str) -> Definition {
+        Definition::from_fields(word, vec![('m', text.as_bytes().to_vec())])
+    }
+
+    /// Definition of a single text field of type `word_type`.
+    #[cfg(test)]
+    pub(crate) fn new_from_utf8(
+        word: &str,
+        buffer: Vec<u8>,
+        word_type: &SameTypeSequence,
+    ) -> Definition {
+        let mut definition = String::from_utf8(buffer).unwrap();
+        if let SameTypeSequence::HTML = word_type {
+            definition = definition.trim().to_string();
+        }
+
+        Definition {
+            word: String::from(word),
+            definition,
+            definition_type: word_type.clone(),
+            distance: None,
+            parts: Vec::new(),
+            parsed: None,
+            truncated: false,
+        }
+    }
+
+    /// Builds a definition from the fields of an entry, the type of the first text field
+    /// is the type of the definition and the text fields are joined into it.
+    fn from_fields(word: &str, fields: Vec<(char, Vec<u8>)>) -> Definition {
+        let mut parts: Vec<DefinitionPart> = fields
+            .into_iter()
+            .map(|(type_char, data)| {
+                let definition_type = Definition::match_sametype_sequence(&type_char.to_string());
+                if type_char.is_ascii_uppercase() {
+                    return DefinitionPart {
+                        definition_type,
+                        text: None,
+                        data,
+                    };
+                }
+                let text = String::from_utf8_lossy(&data).into_owned();
+                DefinitionPart {
+                    text: Some(match definition_type {
+                        SameTypeSequence::HTML => text.trim().to_string(),
+                        _ => text,
+                    }),
+                    definition_type,
+                    data: Vec::new(),
+                }
+            })
+            .collect();
+        let texts: Vec<&DefinitionPart> = parts.iter().filter(|p| p.text.is_some()).collect();
+        let definition_type = texts
+            .first()
+            .or(parts.first().as_ref())
+            .map_or(SameTypeSequence::Meaning, |p| p.definition_type.clone());
+        let definition = texts
+            .iter()
+            .filter_map(|p| p.text.as_deref())
+            .collect::<Vec<_>>()
+            .join("\n");
+        //A single binary field is kept, its data isn't in `definition`.
+        if parts.len() == 1 && parts[0].text.is_some() {
+            parts.clear();
+        }
+        Definition {
+            word: String::from(word),
+            definition,
+            definition_type,
+            distance: None,
+            parts,
+            parsed: None,
+            truncated: false,
+        }
+    }
+
+    /// Contents of the picture fields.
+    pub fn pictures(&self) -> impl Iterator<Item = &[u8]> {
+        self.parts
+            .iter()
+            .filter(|p| p.definition_type == SameTypeSequence::Picture && p.text.is_none())
+            .map(|p| p.data.as_slice())
+    }
+
+    /// Values of the `src` attributes of the `<img>` tags of html definitions.
+    pub fn image_references(&self) -> Vec<&str> {
+        if !self.is_html()
+            && !self
+                .parts
+                .iter()
+                .any(|p| p.definition_type == SameTypeSequence::HTML)
+        {
+            return Vec::new();
+        }
+        //ASCII lower casing keeps the byte positions.
+        let lower = self.definition.to_ascii_lowercase();
+        let mut references = Vec::new();
+        let mut rest = 0;
+        while let Some(start) = lower[rest..].find("<img") {
+            let tag_start = rest + start;
+            let tag_end = lower[tag_start..]
+                .find('>')
+                .map_or(lower.len(), |n| tag_start + n);
+            rest = tag_end;
+            let src = match lower[tag_start..tag_end].find("src=") {
+                Some(n) => tag_start + n + 4,
+                None => continue,
+            };
+            let quote = match lower[src..].chars().next() {
+                Some(c @ ('"' | '\'')) => c,
+                _ => continue,
+            };
+            if let Some(end) = lower[src + 1..tag_end].find(quote) {
+                references.push(&self.definition[src + 1..src + 1 + end]);
+            }
+        }
+        references
     }
 
-    /// Reads the definition entry from .dict file for a given `Index`. Return
-    /// `io::Error`if failed.
-    pub fn read_definition(&self, index: &Index) -> Result<Definition, io::Error> {
-        let mut file = File::open(&self.dict_path)?;
-        file.seek(SeekFrom::Start(index.offset.into())).ok();
+    /// Rewrites the text of the definition and its parts with `transforms`.
+    pub fn postprocess(&mut self, transforms: &[Transform]) {
+        self.definition = postprocess::apply_all(transforms, &self.word, &self.definition);
+        for part in &mut self.parts {
+            if let Some(text) = &part.text {
+                part.text = Some(postprocess::apply_all(transforms, &self.word, text));
+            }
+        }
+    }
+
+    /// Whether the definition or one of its parts has one of `types`, names from
+    /// `DEFINITION_TYPES`.
+    pub fn has_type(&self, types: &[String]) -> bool {
+        let matches = |t: &SameTypeSequence| types.iter().any(|n| n.eq_ignore_ascii_case(t.name()));
+        matches(&self.definition_type) || self.parts.iter().any(|p| matches(&p.definition_type))
+    }
 
-        let mut buffer: Vec<u8> = Vec::new();
-        buffer.resize(index.size.try_into().unwrap(), 0);
-        file.read_exact(&mut buffer).unwrap();
+    /// Name of the definition's type as in `DEFINITION_TYPES`.
+    pub fn type_name(&self) -> &'static str {
+        self.definition_type.name()
+    }
 
-        Ok(Definition::new_from_utf8(
-            &index.word,
-            buffer,
-            &self.sametype_sequence,
-        ))
+    pub fn is_html(&self) -> bool {
+        self.definition_type == SameTypeSequence::HTML
     }
 
-    fn parse_u32<I>(&self, iter: &mut I) -> Result<u32, io::Error>
-    where
-        I: Iterator<Item = &'a u8>,
-    {
-        std::iter::Iterator::take(iter, size_of::<u32>())
-            .map(|x| *x)
-            .collect::<Vec<_>>()
-            .as_slice()
-            .read_u32::<BigEndian>()
+    /// Definition without html tags laid out by `html::render`, without XDXF tags or the
+    /// braces of WordNet links. Other types are returned as they are.
+    pub fn plain_text(&self) -> String {
+        match self.definition_type {
+            SameTypeSequence::Xdfx => return parsed::strip_xdxf_markup(&self.definition),
+            SameTypeSequence::WordNet => return parsed::strip_wordnet_markup(&self.definition),
+            _ if !self.is_html() => return self.definition.clone(),
+            _ => (),
+        }
+        html::render(&self.definition)
+            .iter()
+            .map(html::Line::text)
+            .collect::<Vec<String>>()
+            .join("\n")
     }
 
-    fn parse_word<I>(&self, iter: &mut I) -> Result<Option<String>, FromUtf8Error>
-    where
-        I: Iterator<Item = &'a u8>,
-    {
-        let mut buffer: Vec<u8> = Vec::new();
-        let mut next_byte: u8 = match iter.next() {
-            Some(n) => *n,
-            None => return Ok(None),
+    /// Definition on a single line, cut after the first sentence or `max_chars`
+    /// characters, the whole of it when `max_chars` is `None`.
+    pub fn preview(&self, max_chars: Option<usize>) -> String {
+        let text = self
+            .plain_text()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let max_chars = match max_chars {
+            Some(n) => n,
+            None => return text,
         };
-        while next_byte != 0 {
-            buffer.push(next_byte);
-            next_byte = *iter.next().unwrap();
+        let sentence_end = text
+            .char_indices()
+            .find(|(i, c)| matches!(c, '.' | '!' | '?') && text[i + 1..].starts_with(' '))
+            .map_or(text.len(), |(i, _)| i + 1);
+        let sentence = &text[..sentence_end];
+        if sentence.chars().count() <= max_chars {
+            return sentence.to_string();
         }
-        let res = String::from_utf8(buffer)?;
-        Ok(Some(res))
+        let mut cut: String = sentence.chars().take(max_chars).collect();
+        cut.push('…');
+        cut
     }
 
-    fn parse_index(&self, raw_indices: Vec<u8>) -> Option<Vec<Index>> {
-        let mut indices = Vec::new();
-        let mut iter = raw_indices.iter();
-        loop {
-            let index = Index {
-                word: match self.parse_word(&mut iter) {
-                    Ok(n) => match n {
-                        Some(n) => SmartString::from(n),
-                        None => break,
-                    },
-                    Err(_) => {
-                        error!("Error parsing index file, continuing.");
-                        continue;
-                    }
-                },
-                offset: match self.parse_u32(&mut iter) {
-                    Ok(offset) => offset,
-                    Err(_) => {
-                        error!("Error parsing index file, continuing.");
-                        continue;
-                    }
-                },
-                size: match self.parse_u32(&mut iter) {
-                    Ok(size) => size,
-                    Err(_) => {
-                        error!("Error parsing index file, continuing.");
-                        continue;
-                    }
-                },
-            };
+    /// Structure of text, html, MediaWiki, XDXF and WordNet definitions, `None` for other
+    /// types or when no sense is found.
+    pub fn parse(&self) -> Option<ParsedDefinition> {
+        let parsed = match self.definition_type {
+            SameTypeSequence::Meaning | SameTypeSequence::Locale => {
+                parsed::parse_text(&self.definition)
+            }
+            SameTypeSequence::WordNet => parsed::parse_wordnet(&self.definition),
+            SameTypeSequence::Xdfx => parsed::parse_xdxf(&self.definition),
+            SameTypeSequence::HTML => parsed::parse_text(&self.plain_text()),
+            SameTypeSequence::MediaWiki => parsed::parse_mediawiki(&self.definition),
+            _ => return None,
+        };
+        Some(parsed).filter(|n| !n.senses.is_empty())
+    }
 
-            indices.push(index);
+    /// Whether the definition is markup that reads better in a browser, html or MediaWiki.
+    pub fn is_markup(&self) -> bool {
+        matches!(
+            self.definition_type,
+            SameTypeSequence::HTML | SameTypeSequence::MediaWiki
+        )
+    }
+
+    /// The definition as it's read in the terminal, laid out from `parsed` when it has
+    /// structure, markup removed otherwise.
+    pub fn readable(&self) -> String {
+        match self.parsed.as_ref().filter(|n| n.is_structured()) {
+            Some(n) => n.layout(),
+            None => match self.definition_type {
+                SameTypeSequence::HTML | SameTypeSequence::Xdfx | SameTypeSequence::WordNet => {
+                    self.plain_text()
+                }
+                SameTypeSequence::MediaWiki => parsed::strip_wiki_markup(&self.definition),
+                _ => self.definition.clone(),
+            },
         }
-        Some(indices)
     }
-}
 
-impl Hash for Dictionary {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.bookname.hash(state);
+    /// Prints to `printer` the word with the part matching `query` highlighted and its
+    /// definition, wrapped at `width` if given, see `print_body`.
+    pub fn print_colored(&self, printer: &mut dyn Printer, width: Option<usize>, query: &str) {
+        printer.highlighted(&self.word, query, Some(Color::Yellow));
+        printer.plain("\n");
+        self.print_body(printer, width);
+        printer.plain("\n");
     }
-}
 
-impl Definition {
-    fn new_from_utf8(word: &str, mut buffer: Vec<u8>, word_type: &SameTypeSequence) -> Definition {
-        let word_type = match word_type {
-            SameTypeSequence::None => {
-                let (type_char, temp) = buffer.split_at(1);
-                let r = Box::new(Definition::match_sametype_sequence(
-                    &String::from_utf8_lossy(type_char),
-                ));
-                buffer = temp.to_vec();
-                r
-            }
-            dic_sametype => Box::new(dic_sametype.clone()),
-        };
-        let mut definition = String::from_utf8(buffer).unwrap();
-        if let SameTypeSequence::HTML = word_type.borrow() {
-            definition = definition.trim().to_string();
-        }
-        //TODO Parse definiton according to to word_type.
+    /// Definition laid out like `print_colored` does, without the headword and colors.
+    pub fn to_plain_text(&self, width: Option<usize>) -> String {
+        let mut printer = Capture::default();
+        self.print_body(&mut printer, width);
+        printer.text.trim_end_matches('\n').to_string()
+    }
 
-        Definition {
-            word: String::from(word),
-            definition,
-            definition_type: word_type.as_ref().clone(),
-        }
+    /// Definition laid out like `print_colored` does without the headword, styled with
+    /// ANSI escapes.
+    pub fn to_ansi(&self, width: Option<usize>) -> String {
+        let mut printer = AnsiCapture::default();
+        self.print_body(&mut printer, width);
+        printer.into_string().trim_end_matches('\n').to_string()
     }
 
-    pub fn print_colored(&self) {
-        //TODO Print definition according to definition type.
-        print_yellow(&self.word);
-        println!("{}\n", &self.definition);
+    /// Prints the definition wrapped at `width` if given. Definitions with several
+    /// senses, examples, a part of speech or links to other headwords are laid out from
+    /// `parsed`, parts of speech dim, translations bold, examples italic and links cyan.
+    /// Html is rendered with its bold, italic and underlined text and list bullets, other
+    /// markup is printed as plain text.
+    fn print_body(&self, printer: &mut dyn Printer, width: Option<usize>) {
+        let wrapped = |text: &str| match width {
+            Some(width) => wrap(text, width, 2),
+            None => text.to_string(),
+        };
+        if self.is_html() {
+            for line in html::render(&self.definition) {
+                for spans in line.wrapped(width, 2) {
+                    for span in spans {
+                        printer.write(&span.text, &span.style.spec());
+                    }
+                    printer.plain("\n");
+                }
+            }
+            return;
+        }
+        let parsed = match self.parsed.as_ref().filter(|n| n.is_structured()) {
+            Some(n) => n,
+            None => {
+                printer.plain(&format!("{}\n", wrapped(&self.readable())));
+                return;
+            }
+        };
+        for (kind, line) in parsed.lines() {
+            let mut spec = ColorSpec::new();
+            match kind {
+                LineKind::PartOfSpeech => {
+                    spec.set_dimmed(true);
+                }
+                LineKind::Translation => {
+                    spec.set_bold(true);
+                }
+                LineKind::Example => {
+                    spec.set_italic(true);
+                }
+                LineKind::SeeAlso => {
+                    spec.set_fg(Some(Color::Cyan));
+                }
+                LineKind::Sense | LineKind::Etymology => (),
+            }
+            printer.styled(&wrapped(&line), &spec);
+        }
     }
 
     fn match_sametype_sequence(buffer: &str) -> SameTypeSequence {
@@ -393,6 +2471,9 @@ impl Definition {
             "n" => SameTypeSequence::WordNet,
             "r" => SameTypeSequence::Resource,
             "x" => SameTypeSequence::Xdfx,
+            "t" => SameTypeSequence::Phonetic,
+            "P" => SameTypeSequence::Picture,
+            "W" => SameTypeSequence::Sound,
             n => {
                 error!(
                     "Unknown or unimplemented sametype sequence  {} \n Falling back to meaning",
@@ -407,9 +2488,732 @@ impl Definition {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::{io::Write, time::Instant};
+    use crate::colored_print::Capture;
+    use crate::matcher::{ExactMatcher, LevenshteinMatcher, PrefixMatcher, WordMatcher};
+    use std::io::Write;
+    use std::time::Duration;
     use tempfile::NamedTempFile;
 
+    #[test]
+    fn should_preview_first_sentence() {
+        let definition = |text: &str| {
+            Definition::new_from_utf8("elma", text.as_bytes().to_vec(), &SameTypeSequence::Meaning)
+        };
+        let apple = definition("apple fruit.\n Red or green. 3.5 kg");
+        assert_eq!(apple.preview(Some(40)), "apple fruit.");
+        assert_eq!(apple.preview(Some(5)), "apple…");
+        assert_eq!(apple.preview(None), "apple fruit. Red or green. 3.5 kg");
+        assert_eq!(definition("3.5 kg").preview(Some(40)), "3.5 kg");
+    }
+
+    #[test]
+    fn should_parse_definitions_by_type() {
+        let html = Definition::new_from_utf8(
+            "elma",
+            b"<i>n.</i> <ol><li>fruit</li><li>tree</li></ol>".to_vec(),
+            &SameTypeSequence::HTML,
+        );
+        let parsed = html.parse().unwrap();
+        assert_eq!(parsed.part_of_speech.as_deref(), Some("noun"));
+        assert_eq!(parsed.senses.len(), 2);
+        let picture = Definition::new_from_utf8("elma", b"x".to_vec(), &SameTypeSequence::Picture);
+        assert!(picture.parse().is_none());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &[("elma", "apple fruit")]);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        let definition = dictionary
+            .read_definition(&dictionary.indices()[0])
+            .unwrap();
+        let json = serde_json::to_value(&definition).unwrap();
+        assert_eq!(json["definition"], "apple fruit");
+        assert_eq!(json["parsed"]["senses"][0]["text"], "apple fruit");
+    }
+
+    #[test]
+    fn should_fail_reading_past_end_of_dict_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &[("elma", "apple fruit")]);
+        fs::write(path.join("tr.dict"), "apple").unwrap();
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        assert!(dictionary
+            .read_definition(&dictionary.indices()[0])
+            .is_err());
+    }
+
+    #[test]
+    fn should_print_definition_wrapped() {
+        let html = Definition::new_from_utf8(
+            "elma",
+            b"<b>apple</b> fruit, red or green".to_vec(),
+            &SameTypeSequence::HTML,
+        );
+        let mut printer = Capture::default();
+        html.print_colored(&mut printer, Some(12), "elm");
+        assert_eq!(printer.text, "elma\napple fruit,\n  red or\n  green\n\n");
+    }
+
+    #[test]
+    fn should_format_xdxf_and_wordnet_definitions() {
+        let mut xdxf = Definition::new_from_utf8(
+            "apple",
+            b"<k>apple</k> <abr>n.</abr> fruit, like <kref>pear</kref><ex>an apple a day</ex>"
+                .to_vec(),
+            &SameTypeSequence::Xdfx,
+        );
+        xdxf.parsed = xdxf.parse();
+        assert_eq!(
+            xdxf.to_plain_text(None),
+            "(noun)\nfruit, like pear\n   \u{201c}an apple a day\u{201d}\nSee also: pear"
+        );
+        assert_eq!(
+            xdxf.plain_text(),
+            "apple n. fruit, like pear\n\"an apple a day\""
+        );
+        let mut wordnet = Definition::new_from_utf8(
+            "apple",
+            b"apple\n  n 1: fruit [syn: {Malus}]\n  2: tree".to_vec(),
+            &SameTypeSequence::WordNet,
+        );
+        wordnet.parsed = wordnet.parse();
+        assert_eq!(
+            wordnet.to_plain_text(Some(20)),
+            "(noun)\n1. fruit\n2. tree\nSee also: Malus"
+        );
+        assert!(wordnet.to_ansi(None).contains("\x1b[2m(noun)"));
+    }
+
+    #[test]
+    fn should_split_fields_of_sametypesequence() {
+        let fields = split_fields(b"/elma/\0apple\0<b>fruit</b>", &['t', 'm', 'h']);
+        let types: Vec<char> = fields.iter().map(|(c, _)| *c).collect();
+        assert_eq!(types, vec!['t', 'm', 'h']);
+        let definition = Definition::from_fields("elma", fields);
+        assert_eq!(definition.definition, "/elma/\napple\n<b>fruit</b>");
+        assert_eq!(definition.definition_type, SameTypeSequence::Phonetic);
+        assert_eq!(definition.parts.len(), 3);
+
+        let mut entry = b"mapple\0P".to_vec();
+        entry.extend_from_slice(&3u32.to_be_bytes());
+        entry.extend_from_slice(b"\x89PNhfruit\0");
+        let definition = Definition::from_fields("elma", split_fields(&entry, &[]));
+        assert_eq!(definition.definition, "apple\nfruit");
+        assert_eq!(
+            definition.parts[1].definition_type,
+            SameTypeSequence::Picture
+        );
+        assert_eq!(definition.parts[1].text, None);
+        assert_eq!(definition.pictures().collect::<Vec<_>>(), vec![b"\x89PN"]);
+
+        let single = Definition::from_fields("elma", split_fields(b"apple", &['m']));
+        assert_eq!(single.definition, "apple");
+        assert!(single.parts.is_empty());
+    }
+
+    #[test]
+    fn should_match_definition_types() {
+        let picture = Definition::from_fields(
+            "elma",
+            vec![('m', b"apple".to_vec()), ('P', b"\x89PNG".to_vec())],
+        );
+        assert!(picture.has_type(&[String::from("picture")]));
+        assert!(picture.has_type(&[String::from("html"), String::from("Meaning")]));
+        assert!(!picture.has_type(&[String::from("html")]));
+        assert!(!picture.has_type(&[]));
+    }
+
+    #[test]
+    fn should_find_images_of_definitions() {
+        let html = Definition::new_from_utf8(
+            "elma",
+            b"<IMG SRC=\"Elma.png\"> apple <img alt='x' src='b.jpg'/><img>".to_vec(),
+            &SameTypeSequence::HTML,
+        );
+        assert_eq!(html.image_references(), vec!["Elma.png", "b.jpg"]);
+        let picture = Definition::from_fields("elma", vec![('P', b"\x89PNG".to_vec())]);
+        assert_eq!(picture.pictures().count(), 1);
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("res")).unwrap();
+        std::fs::write(dir.path().join("res/Elma.png"), b"\x89PNG").unwrap();
+        let dictionary = Dictionary::new(&dir.path().join("tr.ifo"));
+        assert_eq!(
+            dictionary.resource_path("Elma.png"),
+            Some(dir.path().join("res/Elma.png"))
+        );
+        assert_eq!(dictionary.resource_path("b.jpg"), None);
+        assert_eq!(dictionary.resource_path("../res/Elma.png"), None);
+        assert_eq!(dictionary.read_resource("Elma.png").unwrap(), b"\x89PNG");
+        assert!(dictionary.read_resource("b.jpg").is_err());
+    }
+
+    #[test]
+    fn should_strip_tags_from_html_definitions() {
+        let html = Definition::new_from_utf8(
+            "elma",
+            b"<b>apple</b><br>a &amp; b<p>fruit</p>".to_vec(),
+            &SameTypeSequence::HTML,
+        );
+        assert_eq!(html.plain_text(), "apple\na & b\nfruit");
+        let meaning =
+            Definition::new_from_utf8("elma", b"<apple>".to_vec(), &SameTypeSequence::Meaning);
+        assert_eq!(meaning.plain_text(), "<apple>");
+    }
+
+    #[test]
+    fn should_detect_corrupt_dict_file_with_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = crate::tests::write_dictionary(
+            dir.path(),
+            "tr",
+            &[("armut", "pear"), ("elma", "apple")],
+        );
+        assert_eq!(Dictionary::load_metadata(&path).unwrap().indices().len(), 2);
+        std::fs::write(path.join("tr.dict"), "pearapplf").unwrap();
+
+        let unverified = Dictionary::load_metadata(&path).unwrap();
+        assert_eq!(unverified.indices().len(), 2);
+        let mut verified = Dictionary::load_metadata(&path).unwrap();
+        verified.verify = true;
+        assert!(matches!(
+            verified.read_indices(),
+            Err(DictionaryError::ChecksumMismatch(_))
+        ));
+        assert_eq!(verified.indices().len(), 0);
+    }
+
+    #[test]
+    fn should_read_definitions_from_dictzip_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let words = [("armut", "pear"), ("elma", "apple"), ("erik", "plum")];
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &words);
+        let dict = std::fs::read(path.join("tr.dict")).unwrap();
+        std::fs::write(path.join("tr.dict.dz"), crate::dictzip::compress(&dict, 3)).unwrap();
+        std::fs::remove_file(path.join("tr.dict")).unwrap();
+
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        assert!(dictionary.is_compressed());
+        for (index, (word, meaning)) in dictionary.indices().iter().zip(words) {
+            let definition = dictionary.read_definition(index).unwrap();
+            assert_eq!(definition.word, word);
+            assert_eq!(definition.plain_text(), meaning);
+        }
+    }
+
+    #[test]
+    fn should_rebuild_cache_when_index_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &[("elma", "apple")]);
+        assert_eq!(Dictionary::load_metadata(&path).unwrap().indices().len(), 1);
+        crate::tests::write_dictionary(dir.path(), "tr", &[("armut", "pear"), ("elma", "apple")]);
+        let mut dictionary = Dictionary::load_metadata(&path).unwrap();
+        dictionary.verify = true;
+        assert_eq!(dictionary.indices().len(), 2);
+    }
+
+    #[test]
+    fn should_rebuild_cache_when_index_or_format_changes_without_verifying() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &[("elma", "apple")]);
+        assert_eq!(Dictionary::load_metadata(&path).unwrap().indices().len(), 1);
+        crate::tests::write_dictionary(dir.path(), "tr", &[("armut", "pear"), ("elma", "apple")]);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        assert_eq!(dictionary.indices().len(), 2);
+        assert_eq!(dictionary.cache_status(), CacheStatus::Current);
+
+        //A cache of an older version starts with a different magic number.
+        let old = serialize(&(
+            CACHE_MAGIC - 1,
+            FileChecksum::of(&dictionary.idx_path).unwrap(),
+        ))
+        .unwrap();
+        std::fs::write(&dictionary.cache_path, old).unwrap();
+        assert_eq!(dictionary.cache_status(), CacheStatus::Stale);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        assert_eq!(dictionary.indices().len(), 2);
+        assert_eq!(dictionary.cache_status(), CacheStatus::Current);
+    }
+
+    #[test]
+    fn should_report_cache_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &[("elma", "apple")]);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        assert_eq!(dictionary.cache_status(), CacheStatus::Missing);
+        dictionary.indices();
+        assert_eq!(dictionary.cache_status(), CacheStatus::Current);
+        crate::tests::write_dictionary(dir.path(), "tr", &[("armut", "pear"), ("elma", "apple")]);
+        assert_eq!(dictionary.cache_status(), CacheStatus::Stale);
+        std::fs::write(&dictionary.cache_path, b"sozl").unwrap();
+        assert_eq!(dictionary.cache_status(), CacheStatus::Corrupt);
+    }
+
+    #[test]
+    fn should_only_checksum_index_when_verifying_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &[("elma", "apple")]);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        dictionary.indices();
+        //Same size and modification time, other content.
+        let modified = fs::metadata(&dictionary.idx_path)
+            .unwrap()
+            .modified()
+            .unwrap();
+        let mut idx = fs::read(&dictionary.idx_path).unwrap();
+        idx[0] = b'a';
+        fs::write(&dictionary.idx_path, idx).unwrap();
+        File::options()
+            .write(true)
+            .open(&dictionary.idx_path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert_eq!(dictionary.cache_status(), CacheStatus::Current);
+        assert_eq!(dictionary.verify_cache(), CacheStatus::Stale);
+    }
+
+    #[test]
+    fn should_search_keywords_of_definitions() {
+        let dir = tempfile::tempdir().unwrap();
+        let words = [
+            ("armut", "a sweet fruit"),
+            ("elma", "the fruit of the apple tree"),
+            ("elmas", "a precious stone"),
+        ];
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &words);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        let found = |dictionary: &Dictionary, query: &str, edits: usize| -> Vec<(String, usize)> {
+            dictionary
+                .search_keywords(query, edits)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(index, distance)| (index.word.to_string(), distance))
+                .collect()
+        };
+        assert_eq!(
+            found(&dictionary, "fruits", 1),
+            vec![(String::from("armut"), 1), (String::from("elma"), 1)]
+        );
+        assert!(found(&dictionary, "fruits", 0).is_empty());
+        assert!(dictionary.keyword_cache_path().exists());
+
+        let cached = Dictionary::load_metadata(&path).unwrap();
+        assert_eq!(found(&cached, "stone", 0), vec![(String::from("elmas"), 0)]);
+    }
+
+    #[test]
+    fn should_store_index_stats_in_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let words = [("elma", "apple"), ("elmas", "diamond")];
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &words);
+        let built = Dictionary::load_metadata(&path).unwrap();
+        assert_eq!(built.stats().entries, 2);
+
+        let cached = Dictionary::load_metadata(&path).unwrap();
+        cached.indices();
+        assert_eq!(cached.stats.get(), Some(built.stats()));
+        assert_eq!(cached.stats().definitions_size, 12);
+        let exact = |word: &str| {
+            cached
+                .search_until(|a, b| a == b, Some(Lookup::Exact), None, word, None)
+                .0
+        };
+        assert_eq!(exact("elma").unwrap().len(), 1);
+        assert!(exact("xyz").is_none());
+    }
+
+    #[test]
+    fn should_cut_definitions_at_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let words = [("çiçek", "güzel çiçek"), ("elma", "apple")];
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &words);
+        let mut dictionary = Dictionary::load_metadata(&path).unwrap();
+        dictionary.definition_limit = Some(8);
+        let indices = dictionary.indices();
+        let cut = dictionary.read_definition(&indices[0]).unwrap();
+        //"ç" takes two bytes, the one split at the limit is left out.
+        assert_eq!(cut.definition, "güzel ");
+        assert!(cut.truncated);
+        assert!(dictionary.cuts(&indices[0]));
+        let whole = dictionary.read_whole_definition(&indices[0]).unwrap();
+        assert_eq!(whole.definition, "güzel çiçek");
+        assert!(!whole.truncated);
+        let short = dictionary.read_definition(&indices[1]).unwrap();
+        assert_eq!(short.definition, "apple");
+        assert!(!short.truncated && !dictionary.cuts(&indices[1]));
+        assert!(!serde_json::to_string(&short).unwrap().contains("truncated"));
+        assert!(serde_json::to_string(&cut)
+            .unwrap()
+            .contains("\"truncated\":true"));
+    }
+
+    #[test]
+    fn should_search_phrases_in_definitions() {
+        let dir = tempfile::tempdir().unwrap();
+        let words = [
+            ("armut", "a sweet fruit of the pear tree"),
+            ("elma", "the fruit of the apple tree"),
+            ("kiraz", "the tree of cherries, apple sized"),
+        ];
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &words);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        let found = |phrase: &str| -> Vec<String> {
+            dictionary
+                .fulltext_search(phrase)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|index| index.word.to_string())
+                .collect()
+        };
+        assert_eq!(found("Apple Tree"), vec!["elma"]);
+        assert_eq!(found("fruit of the"), vec!["armut", "elma"]);
+        assert!(found("tree apple").is_empty());
+        assert!(dictionary.fulltext_search(" - ").is_none());
+    }
+
+    #[test]
+    fn should_save_keyword_index_with_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let words = [("armut", "a sweet pear"), ("elma", "a red apple")];
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &words);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        dictionary.indices();
+        assert!(dictionary.keyword_cache_path().exists());
+        assert!(dictionary.keywords.get().is_none());
+
+        //Rebuilding the cache of a changed dictionary replaces its keyword index.
+        let words = [("armut", "a sweet pear"), ("elma", "a green apple")];
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &words);
+        let rebuilt = Dictionary::load_metadata(&path).unwrap();
+        rebuilt.indices();
+        let cached = rebuilt.load_keyword_cache(2).unwrap();
+        assert_eq!(cached.search("green", 0), Some(vec![(1, 0)]));
+        assert_eq!(cached.search("red", 0), Some(vec![]));
+        let found: Vec<&str> = rebuilt
+            .fulltext_search("green apple")
+            .unwrap_or_default()
+            .into_iter()
+            .map(|index| index.word.as_str())
+            .collect();
+        assert_eq!(found, vec!["elma"]);
+    }
+
+    #[test]
+    fn should_cache_in_user_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &[("elma", "apple")]);
+        let mut dictionary = Dictionary::load_metadata(&path).unwrap();
+        assert_eq!(dictionary.cache_path, path.join("tr.sozl"));
+        let root = dir.path().join("cache");
+        dictionary.use_user_cache(&root);
+        assert!(dictionary.cache_path.starts_with(&root));
+        assert_eq!(dictionary.cache_path.file_name().unwrap(), "tr.sozl");
+        assert_eq!(dictionary.indices().len(), 1);
+        assert_eq!(dictionary.cache_status(), CacheStatus::Current);
+        assert!(!path.join("tr.sozl").exists());
+    }
+
+    #[test]
+    fn should_remove_cache_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let words: Vec<(String, String)> = ["armut", "ayva", "elma", "erik", "kiraz"]
+            .iter()
+            .map(|w| (w.to_string(), w.to_uppercase()))
+            .collect();
+        let words: Vec<(&str, &str)> = words.iter().map(|(w, d)| (&w[..], &d[..])).collect();
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &words);
+        let mut dictionary = Dictionary::load_metadata(&path).unwrap();
+        dictionary.sharded_cache_entries = 4;
+        assert!(dictionary.cache_files().is_empty());
+        dictionary.indices();
+        //The cache of the whole dictionary, its anagram table, its trigram index, its
+        //keyword index and three shards.
+        assert_eq!(dictionary.cache_files().len(), 7);
+        assert!(dictionary.remove_cache().unwrap() > 0);
+        assert!(dictionary.cache_files().is_empty());
+        assert_eq!(dictionary.cache_status(), CacheStatus::Missing);
+    }
+
+    #[test]
+    fn should_estimate_index_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let long = "a".repeat(40);
+        let path =
+            crate::tests::write_dictionary(dir.path(), "tr", &[(&long, "x"), ("elma", "apple")]);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        assert_eq!(dictionary.index_memory(), None);
+        assert_eq!(dictionary.indices().len(), 2);
+        let memory = dictionary.index_memory().unwrap();
+        assert!(memory >= 2 * std::mem::size_of::<Index>() + 40);
+    }
+
+    #[test]
+    fn should_split_index_into_shards() {
+        let indices: Vec<Index> = ["Elma", "elmas", "kitap", "kız"]
+            .iter()
+            .map(|word| Index {
+                word: SmartString::from(*word),
+                offset: 0,
+                size: 0,
+            })
+            .collect();
+        let shards = shards_of(&indices);
+        assert_eq!(shards.len(), 2);
+        assert_eq!(
+            (shards[0].first, shards[0].range.clone()),
+            (Some('e'), 0..2)
+        );
+        assert_eq!((shards[1].min_length, shards[1].max_length), (3, 5));
+        let bounds = ExactMatcher {}.bounds("kız").unwrap();
+        assert!(!shards[0].can_match(&bounds));
+        assert!(shards[1].can_match(&bounds));
+        let bounds = LevenshteinMatcher { level: 1 }.bounds("a").unwrap();
+        assert!(!shards[1].can_match(&bounds));
+        //"kitap" and "kız" ordered by length.
+        assert_eq!(
+            shards[1].positions_within(&WordBounds {
+                min_length: 0,
+                max_length: 9,
+                first: None,
+            }),
+            &[1, 0]
+        );
+        let bounds = LevenshteinMatcher { level: 1 }.bounds("kitab").unwrap();
+        assert_eq!(shards[1].positions_within(&bounds), &[0]);
+        assert!(shards[0]
+            .positions_within(&ExactMatcher {}.bounds("elm").unwrap())
+            .is_empty());
+    }
+
+    #[test]
+    fn should_look_up_words_in_sorted_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let words = [
+            ("Elma", "proper name"),
+            ("elma", "apple"),
+            ("elmas", "diamond"),
+            ("erik", "plum"),
+        ];
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &words);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        dictionary.indices();
+        assert!(dictionary.is_sorted());
+        type Search<'a> = (Box<dyn WordMatcher + Sync>, &'a str, Vec<&'a str>);
+        let searches: Vec<Search> = vec![
+            (Box::new(ExactMatcher {}), "elma", vec!["elma"]),
+            (Box::new(PrefixMatcher {}), "elma", vec!["elma", "elmas"]),
+            (Box::new(PrefixMatcher {}), "E", vec!["Elma"]),
+            (Box::new(ExactMatcher {}), "elm", vec![]),
+        ];
+        for (matcher, word, expected) in searches {
+            let compare = |w1: &str, w2: &str| matcher.compare(w1, w2);
+            let (found, _) = dictionary.search_until(compare, matcher.lookup(), None, word, None);
+            let found: Vec<&str> = found
+                .unwrap_or_default()
+                .iter()
+                .map(|n| n.word.as_str())
+                .collect();
+            assert_eq!(found, expected);
+        }
+
+        let path = crate::tests::write_dictionary(
+            dir.path(),
+            "unsorted",
+            &[("erik", "plum"), ("elma", "apple")],
+        );
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        dictionary.indices();
+        assert!(!dictionary.is_sorted());
+        let (found, _) =
+            dictionary.search_until(|w1, w2| w1 == w2, Some(Lookup::Exact), None, "elma", None);
+        assert_eq!(found.unwrap()[0].word.as_str(), "elma");
+    }
+
+    #[test]
+    fn should_find_words_within_edits_through_trigrams() {
+        let dir = tempfile::tempdir().unwrap();
+        let words = [
+            ("armut", "pear"),
+            ("kitapla", "with the book"),
+            ("kitaplar", "books"),
+            ("kitaplık", "bookcase"),
+            ("ktaplar", "typo"),
+            ("kıtaplar", "typo"),
+        ];
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &words);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        dictionary.indices();
+        assert!(dictionary.trigram_cache_path().exists());
+        for (word, level) in [
+            ("kitaplar", 1),
+            ("kitaplar", 2),
+            ("kitablar", 2),
+            ("armt", 1),
+        ] {
+            let matcher = LevenshteinMatcher { level };
+            let compare = |w1: &str, w2: &str| matcher.compare(w1, w2);
+            let (found, _) = dictionary.search_until(compare, matcher.lookup(), None, word, None);
+            let (scanned, _) = dictionary.fuzzy_search_shards_until(compare, None, word, None);
+            let words = |n: Option<Vec<&Index>>| -> Vec<String> {
+                n.unwrap_or_default()
+                    .iter()
+                    .map(|n| n.word.to_string())
+                    .collect()
+            };
+            assert_eq!(words(found), words(scanned), "{} within {}", word, level);
+        }
+        let trigrams = TrigramIndex::of(dictionary.indices());
+        assert!(!trigrams.candidates("kitaplar", 1).unwrap().contains(&0));
+        assert_eq!(trigrams.candidates("a", 1), None);
+    }
+
+    #[test]
+    fn should_load_only_matching_shards() {
+        let dir = tempfile::tempdir().unwrap();
+        let words = [
+            ("armut", "pear"),
+            ("elma", "apple"),
+            ("elmas", "diamond"),
+            ("kitap", "book"),
+            ("kız", "girl"),
+        ];
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &words);
+        let mut first = Dictionary::load_metadata(&path).unwrap();
+        first.sharded_cache_entries = 4;
+        first.indices();
+        assert!(path.join("tr.2.sozl").exists());
+
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        let matcher = ExactMatcher {};
+        let (found, _) = dictionary.fuzzy_search_shards_until(
+            |w1, w2| matcher.compare(w1, w2),
+            matcher.bounds("kitap"),
+            "kitap",
+            None,
+        );
+        assert_eq!(found.unwrap()[0].word.as_str(), "kitap");
+        assert!(!dictionary.is_index_loaded());
+        assert_eq!(dictionary.indices().len(), 5);
+        assert_eq!(dictionary.shard_indices(1)[1].word.as_str(), "elmas");
+    }
+
+    #[test]
+    fn should_share_shards_between_rare_letters() {
+        let mut words: Vec<String> = (0..600).map(|n| format!("a{:03}", n)).collect();
+        words.extend(["q", "x"].iter().map(|w| w.to_string()));
+        words.extend((0..600).map(|n| format!("z{:03}", n)));
+        let indices: Vec<Index> = words
+            .iter()
+            .enumerate()
+            .map(|(n, w)| Index {
+                word: w.as_str().into(),
+                offset: n as u64,
+                size: 1,
+            })
+            .collect();
+        let shards = shards_of(&indices);
+        let firsts: Vec<Option<char>> = shards.iter().map(|s| s.first).collect();
+        assert_eq!(firsts, vec![Some('a'), None, Some('z')]);
+        assert_eq!(shards[1].range, 600..602);
+        let bounds = ExactMatcher {}.bounds("x").unwrap();
+        assert!(shards[1].can_match(&bounds));
+        assert!(!shards[2].can_match(&bounds));
+    }
+
+    #[test]
+    fn should_remove_stale_shard_caches() {
+        let dir = tempfile::tempdir().unwrap();
+        let words = [
+            ("armut", "pear"),
+            ("elma", "apple"),
+            ("kitap", "book"),
+            ("kız", "girl"),
+        ];
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &words);
+        let mut dictionary = Dictionary::load_metadata(&path).unwrap();
+        dictionary.sharded_cache_entries = 4;
+        dictionary.indices();
+        assert!(path.join("tr.2.sozl").exists());
+
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &words[..3]);
+        let mut dictionary = Dictionary::load_metadata(&path).unwrap();
+        dictionary.sharded_cache_entries = 4;
+        assert_eq!(dictionary.indices().len(), 3);
+        assert!(!path.join("tr.0.sozl").exists());
+        assert!(!path.join("tr.2.sozl").exists());
+    }
+
+    #[test]
+    fn should_ignore_shard_table_of_changed_idx_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let words = [
+            ("armut", "pear"),
+            ("elma", "apple"),
+            ("kitap", "book"),
+            ("kız", "girl"),
+        ];
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &words);
+        let mut dictionary = Dictionary::load_metadata(&path).unwrap();
+        dictionary.sharded_cache_entries = 4;
+        dictionary.indices();
+
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &words[..3]);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        let shards = dictionary.try_shards().unwrap();
+        assert_eq!(shards.last().unwrap().range.end, 3);
+        assert!(dictionary.is_index_loaded());
+    }
+
+    #[test]
+    fn should_search_anagrams() {
+        assert_eq!(anagram_key("Kale-m"), "aeklm");
+        let dir = tempfile::tempdir().unwrap();
+        let words = [
+            ("elma", "apple"),
+            ("kalem", "pen"),
+            ("Kamel", "x"),
+            ("mela", "y"),
+        ];
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &words);
+        Dictionary::load_dictionary(&path).unwrap();
+        assert!(path.join("tr.anagrams.sozl").exists());
+
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        let found: Vec<&str> = dictionary
+            .search_anagrams("melak")
+            .unwrap()
+            .iter()
+            .map(|n| n.word.as_str())
+            .collect();
+        assert_eq!(found, vec!["kalem", "Kamel"]);
+        assert_eq!(dictionary.search_anagrams("aml").map(|n| n.len()), None);
+    }
+
+    #[test]
+    fn should_search_crossword_patterns() {
+        let dir = tempfile::tempdir().unwrap();
+        let words = [
+            ("elma", "apple"),
+            ("elmas", "diamond"),
+            ("erik", "plum"),
+            ("kalem", "pen"),
+        ];
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &words);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        let found = |pattern: &str| -> Vec<String> {
+            dictionary
+                .search_pattern(pattern)
+                .unwrap_or_default()
+                .iter()
+                .map(|n| n.word.to_string())
+                .collect()
+        };
+        assert_eq!(found("e??a"), vec!["elma"]);
+        assert_eq!(found("e???"), vec!["elma", "erik"]);
+        assert_eq!(found("?????"), vec!["elmas", "kalem"]);
+        assert!(found("??????").is_empty());
+    }
+
     #[test]
     fn should_parse_index_file() {
         let mut dic = Dictionary::new(&PathBuf::from("notimportant"));
@@ -418,12 +3222,69 @@ mod tests {
                 .as_bytes();
         println!("index content {:?}", &idx_content);
         let mut idx_file = NamedTempFile::new().unwrap();
-        idx_file.write(idx_content).unwrap();
+        idx_file.write_all(idx_content).unwrap();
         idx_file.flush().unwrap();
-        dic.idx_path = idx_file.path().clone().to_path_buf();
+        dic.idx_path = idx_file.path().to_path_buf();
         println!("{:?}", dic.idx_path);
-        dic.parse_index_file().unwrap();
-        assert_eq!(dic.indices.len(), 2);
+        assert_eq!(dic.parse_index_file().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn should_skip_corrupt_index_entries() {
+        let dic = Dictionary::new(&PathBuf::from("notimportant"));
+        let mut idx_content: Vec<u8> = b"word1\0\x00\x00\x00\x09\x00\x00\x00\x08".to_vec();
+        idx_content.extend_from_slice(b"\xff\xfe\0\x00\x00\x00\x11\x00\x00\x00\x02");
+        idx_content.extend_from_slice(b"word3\0\x00\x00\x00\x13\x00\x00\x00\x04");
+        idx_content.extend_from_slice(b"trunc\0\x00\x00");
+        let indices = dic.parse_index(&idx_content).unwrap();
+        assert_eq!(indices.len(), 2);
+        assert_eq!(indices[1].word.as_str(), "word3");
+        assert_eq!(indices[1].offset, 0x13);
+        assert_eq!(indices[1].size, 4);
+    }
+
+    #[test]
+    fn should_parse_index_with_64_bit_offsets() {
+        let mut dic = Dictionary::new(&PathBuf::from("notimportant"));
+        let ifo = "StarDict's dict ifo file\nidxoffsetbits=64\n";
+        let ifo_file = NamedTempFile::new().unwrap();
+        std::fs::write(ifo_file.path(), ifo).unwrap();
+        dic.ifo_path = ifo_file.path().to_path_buf();
+        dic.parse_ifo_file().unwrap();
+        assert_eq!(dic.idx_offset_bits, 64);
+
+        let mut idx_content: Vec<u8> = b"word1\0".to_vec();
+        idx_content.extend_from_slice(&0x1_0000_0009u64.to_be_bytes());
+        idx_content.extend_from_slice(&8u32.to_be_bytes());
+        idx_content.extend_from_slice(b"word2\0");
+        idx_content.extend_from_slice(&0x13u64.to_be_bytes());
+        idx_content.extend_from_slice(&4u32.to_be_bytes());
+        let indices = dic.parse_index(&idx_content).unwrap();
+        assert_eq!(indices.len(), 2);
+        assert_eq!(indices[0].offset, 0x1_0000_0009);
+        assert_eq!(indices[1].word.as_str(), "word2");
+        assert_eq!(indices[1].size, 4);
+    }
+
+    #[test]
+    fn should_load_dictionary_with_invalid_wordcount() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &[("elma", "apple")]);
+        let ifo = "StarDict's dict ifo file\nversion=2.4.2\nbookname=tr\nwordcount=two\n";
+        fs::write(path.join("tr.ifo"), ifo).unwrap();
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        assert_eq!(dictionary.wordcount, 0);
+        assert_eq!(dictionary.bookname, "tr");
+    }
+
+    #[test]
+    fn should_give_up_on_heavily_corrupt_index() {
+        let dic = Dictionary::new(&PathBuf::from("notimportant"));
+        let idx_content = b"\xff\0\x00\x00\x00\x00\x00\x00\x00\x00".repeat(MAX_CORRUPT_ENTRIES + 1);
+        match dic.parse_index(&idx_content) {
+            Err(DictionaryError::CorruptIndex(n)) => assert_eq!(n, MAX_CORRUPT_ENTRIES + 1),
+            other => panic!("Expected a corrupt index error, got {:?}", other),
+        }
     }
 
     #[test]
@@ -433,17 +3294,18 @@ mod tests {
         let sametypesequence = "m";
         let wordcount = 20000;
         let ifo_content = format!(
-            "sametypesequence={}\nbookname={}\nwordcount={}\n",
+            "sametypesequence={}\nbookname={}\nwordcount={}\nlang=tr\n",
             sametypesequence, bookname, wordcount
         );
         let mut ifo_file = NamedTempFile::new().unwrap();
-        ifo_file.write(ifo_content.as_bytes()).unwrap();
+        ifo_file.write_all(ifo_content.as_bytes()).unwrap();
         ifo_file.flush().unwrap();
         dic.ifo_path = ifo_file.path().to_path_buf();
         dic.parse_ifo_file().unwrap();
         assert_eq!(dic.bookname, bookname);
-        assert_eq!(dic.sametype_sequence, SameTypeSequence::Meaning);
+        assert_eq!(dic.sametype_sequence, "m");
         assert_eq!(dic.wordcount, wordcount);
+        assert_eq!(dic.lang.as_deref(), Some("tr"));
     }
     #[test]
     fn should_read_definition_from_dict_file() {
@@ -451,29 +3313,180 @@ mod tests {
         let dict_content1 = "definition of first word";
         let dict_content2 = "definition of second word";
         let mut dict_file = NamedTempFile::new().unwrap();
-        dict_file.write(dict_content1.as_bytes()).unwrap();
-        dict_file.write(dict_content2.as_bytes()).unwrap();
+        dict_file.write_all(dict_content1.as_bytes()).unwrap();
+        dict_file.write_all(dict_content2.as_bytes()).unwrap();
         let ind2 = Index {
             word: SmartString::from("word2"),
-            offset: dict_content1.len() as u32,
+            offset: dict_content1.len() as u64,
             size: dict_content2.len() as u32,
         };
         dic.dict_path = dict_file.path().to_path_buf();
-        dic.sametype_sequence = SameTypeSequence::Meaning;
+        dic.sametype_sequence = String::from("m");
         let def = dic.read_definition(&ind2).unwrap();
         assert_eq!(def.definition, dict_content2);
     }
 
+    #[test]
+    fn should_truncate_search_after_deadline() {
+        let mut dic = Dictionary::new(&PathBuf::from("notimportant"));
+        let indices: Vec<Index> = ["elma", "armut", "elma"]
+            .iter()
+            .map(|word| Index {
+                word: SmartString::from(*word),
+                offset: 0,
+                size: 0,
+            })
+            .collect();
+        dic.indices = OnceLock::from(indices);
+        let (found, truncated) = dic.fuzzy_search_indices_until(|w1, w2| w1 == w2, "elma", None);
+        assert_eq!(found.unwrap().len(), 2);
+        assert!(!truncated);
+
+        let deadline = Instant::now() - Duration::from_millis(1);
+        let (found, truncated) =
+            dic.fuzzy_search_indices_until(|w1, w2| w1 == w2, "elma", Some(deadline));
+        assert!(found.is_none());
+        assert!(truncated);
+    }
+
+    #[test]
+    fn should_truncate_looked_up_search_after_deadline() {
+        let dir = tempfile::tempdir().unwrap();
+        let words = [("armut", "pear"), ("elma", "apple"), ("elmas", "diamond")];
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &words);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        dictionary.indices();
+        let deadline = Instant::now() - Duration::from_millis(1);
+        for lookup in [Lookup::Exact, Lookup::Edits(1)] {
+            let (found, truncated) =
+                dictionary.search_until(|w1, w2| w1 == w2, Some(lookup), None, "elma", None);
+            assert_eq!(found.unwrap().len(), 1);
+            assert!(!truncated);
+            let (found, truncated) = dictionary.search_until(
+                |w1, w2| w1 == w2,
+                Some(lookup),
+                None,
+                "elma",
+                Some(deadline),
+            );
+            assert!(found.is_none());
+            assert!(truncated);
+        }
+    }
+
+    #[test]
+    fn should_find_entries_by_their_synonyms() {
+        let dir = tempfile::tempdir().unwrap();
+        let words = [
+            ("armut", "pear"),
+            ("ayva", "quince"),
+            ("elma", "apple"),
+            ("erik", "plum"),
+            ("incir", "fig"),
+        ];
+        let path = crate::tests::write_dictionary(dir.path(), "tr", &words);
+        let mut syn = Vec::new();
+        for (word, position) in [("alma", 2u32), ("elma", 2), ("yemiş", 4), ("yok", 99)] {
+            syn.extend_from_slice(word.as_bytes());
+            syn.push(0);
+            syn.extend_from_slice(&position.to_be_bytes());
+        }
+        syn.extend_from_slice(b"kesik\0\0");
+        std::fs::write(path.join("tr.syn"), syn).unwrap();
+
+        let find = |dictionary: &Dictionary, word: &str| -> Vec<String> {
+            dictionary
+                .fuzzy_search_indices(|w1, w2| w1 == w2, word)
+                .unwrap_or_default()
+                .iter()
+                .map(|x| dictionary.read_definition(x).unwrap().definition)
+                .collect()
+        };
+        let mut loaded = Dictionary::load_metadata(&path).unwrap();
+        loaded.sharded_cache_entries = 4;
+        assert_eq!(loaded.synonyms().len(), 4);
+        assert_eq!(find(&loaded, "alma"), vec!["apple"]);
+        assert_eq!(find(&loaded, "elma"), vec!["apple"]);
+        assert!(find(&loaded, "yok").is_empty());
+        //Read from the sharded cache the first load wrote.
+        let sharded = Dictionary::load_metadata(&path).unwrap();
+        assert_eq!(sharded.index_at(4).unwrap().word, "incir");
+        assert!(sharded.index_at(5).is_none());
+        assert!(!sharded.is_index_loaded());
+        assert_eq!(find(&sharded, "yemiş"), vec!["fig"]);
+    }
+
+    #[test]
+    fn should_keep_best_results_up_to_limit() {
+        let mut dic = Dictionary::new(&PathBuf::from("notimportant"));
+        let indices: Vec<Index> = ["almak", "elmas", "Elma", "elma", "elm", "armut"]
+            .iter()
+            .map(|word| Index {
+                word: SmartString::from(*word),
+                offset: 0,
+                size: 0,
+            })
+            .collect();
+        dic.indices = OnceLock::from(indices);
+        dic.max_results = Some(3);
+        let words = |found: Option<Vec<&Index>>| -> Vec<String> {
+            found.unwrap().iter().map(|x| x.word.to_string()).collect()
+        };
+        assert_eq!(
+            words(dic.fuzzy_search_indices(|_, w2| w2 != "armut", "elma")),
+            vec!["elmas", "Elma", "elma"]
+        );
+        dic.max_results = Some(10);
+        assert_eq!(
+            dic.fuzzy_search_indices(|_, w2| w2 != "armut", "elma")
+                .unwrap()
+                .len(),
+            5
+        );
+    }
+
+    #[test]
+    fn should_select_random_words_from_read_entries() {
+        let mut dic = Dictionary::new(&PathBuf::from("notimportant"));
+        dic.wordcount = 10;
+        dic.indices = OnceLock::from(Vec::new());
+        assert!(dic.select_random_word().is_none());
+        assert!(dic.select_random_words(3).is_empty());
+
+        let indices: Vec<Index> = ["armut", "elma", "erik"]
+            .iter()
+            .map(|word| Index {
+                word: SmartString::from(*word),
+                offset: 0,
+                size: 0,
+            })
+            .collect();
+        dic.indices = OnceLock::from(indices);
+        for _ in 0..20 {
+            assert!(dic.select_random_word().is_some());
+        }
+        assert_eq!(dic.select_random_words(2).len(), 2);
+        let mut all: Vec<&str> = dic
+            .select_random_words(5)
+            .iter()
+            .map(|x| x.word.as_str())
+            .collect();
+        all.sort_unstable();
+        assert_eq!(all, vec!["armut", "elma", "erik"]);
+    }
+
     #[test]
     fn test_indexes_sizeof() {
         let _i1 = Index {
             word: SmartString::from("worddfsadfasdf"),
-            offset: 10000_u32,
+            offset: 10000_u64,
             size: 1111_u32,
         };
         println!("Size of the Index: {:?}", size_of::<Index>());
     }
     #[test]
+    #[ignore = "needs the benchmark dictionaries under dic/"]
+    #[allow(unused_variables)]
     fn fuzzy_search() {
     let english= vec![ "suiteth" , "inalterability" , "court-martialled" , "stubbleless" , "returne" , "weak-minded" , "Benin" , "Soton" , "ready-meals" , "outbarks" , "Falcon" , "slaughterhouses" , "Vallone" , "nonweird" , "ball-flower" , "enhardens" , "squirelings" , "tyrannise" , "pennated" , "milting" , "polyed" , "emmarbling" , "secondment" , "suuure" , "degazetting" , "multipoint" , "octaoxygen" , "coaggregate" , "cutinizing" , "poopdecks" , "palaverous" , "quaeritating" , "unguentaria" , "contlines" , "interiorising" , "loanees" , "Utopian" , "metastatic" , "Siu" , "adjuncts" , "disanoint" , "aceprozamine" , "alcoholless" , "911" , "dobupride" , "precognizable" , "anhydrobiosis" , "kegstand" , "orbiculas" , "discocephaline" , ];
 
@@ -511,13 +3524,16 @@ mod tests {
             size: 369,
         };
         dic1.cache_path = NamedTempFile::new().unwrap().path().to_path_buf();
-        dic1.indices.push(i1);
-        dic1.indices.push(i2);
-        dic1.save_cache().unwrap();
+        //Checksums of these are recorded in the cache.
+        let idx_file = NamedTempFile::new().unwrap();
+        let dict_file = NamedTempFile::new().unwrap();
+        dic1.idx_path = idx_file.path().to_path_buf();
+        dic1.dict_path = dict_file.path().to_path_buf();
+        dic1.save_cache(&[i1, i2]).unwrap();
         let mut dic2 = Dictionary::new(&PathBuf::from("notimportant 2"));
         dic2.cache_path = dic1.cache_path;
-        dic2.load_cache().unwrap();
-        assert_eq!(dic2.indices[0].offset, 246);
-        assert_eq!(dic2.indices[1].word.as_str(), "a second word");
+        let indices = dic2.load_cache().unwrap().indices;
+        assert_eq!(indices[0].offset, 246);
+        assert_eq!(indices[1].word.as_str(), "a second word");
     }
 }
@@ -0,0 +1,202 @@
+//! Random access to dictzip compressed .dict.dz files. dictzip writes a gzip file whose
+//! deflate stream is flushed every `chunk_length` bytes and records the compressed size
+//! of each chunk in the `RA` extra field of the header, so a definition is read by
+//! inflating only the chunks it is in.
+use flate2::{Decompress, FlushDecompress};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+
+/// Chunk table of a dictzip file.
+#[derive(Debug)]
+pub struct DictZip {
+    /// Bytes of uncompressed data in every chunk but the last.
+    chunk_length: u64,
+    /// Offsets of the chunks in the file and their compressed sizes.
+    chunks: Vec<(u64, u64)>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn u16_at(bytes: &[u8], position: usize) -> io::Result<u16> {
+    bytes
+        .get(position..position + 2)
+        .map(|n| u16::from_le_bytes([n[0], n[1]]))
+        .ok_or_else(|| invalid("truncated gzip header"))
+}
+
+impl DictZip {
+    /// Reads the chunk table from the gzip header of `path`. Fails with
+    /// `io::ErrorKind::InvalidData` if it's not a gzip file or one without a chunk
+    /// table, which can only be read from the start.
+    pub fn open(path: &Path) -> io::Result<DictZip> {
+        let mut file = File::open(path)?;
+        let mut header = [0; 10];
+        file.read_exact(&mut header)?;
+        if header[..3] != [0x1f, 0x8b, 8] {
+            return Err(invalid("not a gzip file"));
+        }
+        let flags = header[3];
+        if flags & FEXTRA == 0 {
+            return Err(invalid("gzip file has no dictzip chunk table"));
+        }
+        let mut length = [0; 2];
+        file.read_exact(&mut length)?;
+        let mut extra = vec![0; u16::from_le_bytes(length).into()];
+        file.read_exact(&mut extra)?;
+        let mut table = None;
+        let mut position = 0;
+        while position + 4 <= extra.len() {
+            let field_length: usize = u16_at(&extra, position + 2)?.into();
+            let field = extra
+                .get(position + 4..position + 4 + field_length)
+                .ok_or_else(|| invalid("truncated gzip extra field"))?;
+            if extra[position..position + 2] == *b"RA" {
+                table = Some(field);
+            }
+            position += 4 + field_length;
+        }
+        let table = table.ok_or_else(|| invalid("gzip file has no dictzip chunk table"))?;
+        if u16_at(table, 0)? != 1 {
+            return Err(invalid("unknown dictzip version"));
+        }
+        let chunk_length = u16_at(table, 2)?.into();
+        let count: usize = u16_at(table, 4)?.into();
+
+        let mut offset = 10 + 2 + extra.len() as u64;
+        for flag in [FNAME, FCOMMENT] {
+            if flags & flag != 0 {
+                let mut byte = [1];
+                while byte[0] != 0 {
+                    file.read_exact(&mut byte)?;
+                    offset += 1;
+                }
+            }
+        }
+        if flags & FHCRC != 0 {
+            offset += 2;
+        }
+        let mut chunks = Vec::with_capacity(count);
+        for n in 0..count {
+            let size: u64 = u16_at(table, 6 + 2 * n)?.into();
+            chunks.push((offset, size));
+            offset += size;
+        }
+        Ok(DictZip {
+            chunk_length,
+            chunks,
+        })
+    }
+
+    /// Reads `size` uncompressed bytes from `offset` of `file`, the dictzip file the
+    /// table was read from.
+    pub fn read(&self, file: &mut File, offset: u64, size: usize) -> io::Result<Vec<u8>> {
+        if size == 0 {
+            return Ok(Vec::new());
+        }
+        let first = (offset / self.chunk_length) as usize;
+        let last = ((offset + size as u64 - 1) / self.chunk_length) as usize;
+        if last >= self.chunks.len() {
+            return Err(invalid("offset is past the end of the dictzip file"));
+        }
+        let mut data = Vec::with_capacity((last - first + 1) * self.chunk_length as usize);
+        let mut compressed = Vec::new();
+        for &(chunk_offset, chunk_size) in &self.chunks[first..=last] {
+            compressed.resize(chunk_size as usize, 0);
+            file.seek(SeekFrom::Start(chunk_offset))?;
+            file.read_exact(&mut compressed)?;
+            //Every chunk starts after a full flush, so it inflates without the ones before.
+            let mut chunk = Vec::with_capacity(self.chunk_length as usize);
+            Decompress::new(false)
+                .decompress_vec(&compressed, &mut chunk, FlushDecompress::Sync)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            data.extend_from_slice(&chunk);
+        }
+        let start = (offset - first as u64 * self.chunk_length) as usize;
+        data.get(start..start + size)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| invalid("offset is past the end of the dictzip file"))
+    }
+}
+
+/// `data` compressed like dictzip does, in chunks of `chunk_length` bytes.
+#[cfg(test)]
+pub fn compress(data: &[u8], chunk_length: usize) -> Vec<u8> {
+    use flate2::{Compress, Compression, FlushCompress, Status};
+
+    let mut compress = Compress::new(Compression::best(), false);
+    let pieces: Vec<&[u8]> = data.chunks(chunk_length).collect();
+    let mut sizes = Vec::new();
+    let mut body = Vec::new();
+    for (n, piece) in pieces.iter().enumerate() {
+        let flush = if n + 1 == pieces.len() {
+            FlushCompress::Finish
+        } else {
+            FlushCompress::Full
+        };
+        body.reserve(piece.len() * 2 + 64);
+        let before = body.len();
+        let status = compress.compress_vec(piece, &mut body, flush).unwrap();
+        assert_ne!(status, Status::BufError);
+        sizes.push((body.len() - before) as u16);
+    }
+
+    let mut file = vec![0x1f, 0x8b, 8, FEXTRA, 0, 0, 0, 0, 2, 3];
+    let field_length = 6 + 2 * sizes.len() as u16;
+    file.extend_from_slice(&(4 + field_length).to_le_bytes());
+    file.extend_from_slice(b"RA");
+    file.extend_from_slice(&field_length.to_le_bytes());
+    for n in [1, chunk_length as u16, sizes.len() as u16]
+        .iter()
+        .copied()
+        .chain(sizes)
+    {
+        file.extend_from_slice(&n.to_le_bytes());
+    }
+    file.extend_from_slice(&body);
+    file.extend_from_slice(&crc32fast::hash(data).to_le_bytes());
+    file.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+
+    #[test]
+    fn should_read_across_chunks_of_dictzip_file() {
+        let data: Vec<u8> = (0..10_000u32).flat_map(|n| n.to_le_bytes()).collect();
+        let compressed = compress(&data, 4096);
+        let mut decoded = Vec::new();
+        GzDecoder::new(&compressed[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), &compressed).unwrap();
+        let dictzip = DictZip::open(file.path()).unwrap();
+        assert_eq!(dictzip.chunks.len(), 10);
+        let mut file = File::open(file.path()).unwrap();
+        for (offset, size) in [(0, 10), (4090, 20), (8000, 12000), (39_990, 10), (5, 0)] {
+            assert_eq!(
+                dictzip.read(&mut file, offset, size).unwrap(),
+                data[offset as usize..offset as usize + size]
+            );
+        }
+        assert!(dictzip.read(&mut file, 39_995, 10).is_err());
+
+        let plain = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(plain.path(), [&[0x1f, 0x8b, 8, 0][..], &[0; 6]].concat()).unwrap();
+        let error = DictZip::open(plain.path()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,95 @@
+//! Advertises the http server as `_sozluk._tcp` over mDNS, so companion clients
+//! on the local network can find it without being configured.
+use log::{debug, info, warn};
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use std::fs;
+use std::net::SocketAddr;
+
+const SERVICE_TYPE: &str = "_sozluk._tcp.local.";
+
+/// A running advertisement, withdrawn when dropped.
+pub struct Advertisement {
+    daemon: ServiceDaemon,
+    fullname: String,
+}
+
+impl Drop for Advertisement {
+    fn drop(&mut self) {
+        debug!("Withdrawing mDNS advertisement of {}.", &self.fullname);
+        self.daemon.unregister(&self.fullname).ok();
+        self.daemon.shutdown().ok();
+    }
+}
+
+/// Starts advertising a server listening on `addr`. Servers listening only on a
+/// loopback address are not advertised since nobody else could reach them.
+pub fn advertise(addr: SocketAddr) -> Option<Advertisement> {
+    if addr.ip().is_loopback() {
+        debug!("Not advertising server listening on {}.", addr);
+        return None;
+    }
+    let daemon = match ServiceDaemon::new() {
+        Ok(n) => n,
+        Err(e) => {
+            warn!("Can't start mDNS daemon: {}", e);
+            return None;
+        }
+    };
+    let hostname = hostname();
+    let properties = [("version", env!("CARGO_PKG_VERSION"))];
+    let service = if addr.ip().is_unspecified() {
+        ServiceInfo::new(
+            SERVICE_TYPE,
+            &hostname,
+            &format!("{}.local.", &hostname),
+            (),
+            addr.port(),
+            &properties[..],
+        )
+        .map(ServiceInfo::enable_addr_auto)
+    } else {
+        ServiceInfo::new(
+            SERVICE_TYPE,
+            &hostname,
+            &format!("{}.local.", &hostname),
+            addr.ip(),
+            addr.port(),
+            &properties[..],
+        )
+    };
+    let service = match service {
+        Ok(n) => n,
+        Err(e) => {
+            warn!("Can't create mDNS service info: {}", e);
+            return None;
+        }
+    };
+    let fullname = service.get_fullname().to_string();
+    if let Err(e) = daemon.register(service) {
+        warn!("Can't advertise server over mDNS: {}", e);
+        return None;
+    }
+    info!("Advertising {} over mDNS.", &fullname);
+    Some(Advertisement { daemon, fullname })
+}
+
+/// Host name used as the instance name, falls back to "sozluk" where it can't be read.
+fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| fs::read_to_string("/etc/hostname"))
+        .map(|n| n.trim().to_string())
+        .ok()
+        .filter(|n| !n.is_empty())
+        .unwrap_or_else(|| String::from("sozluk"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_not_advertise_loopback_server() {
+        assert!(advertise("127.0.0.1:51881".parse().unwrap()).is_none());
+        assert!(advertise("[::1]:51881".parse().unwrap()).is_none());
+    }
+}
@@ -0,0 +1,307 @@
+//! Health report of the dictionaries in a directory tree for `sozluk doctor`, tells
+//! why a folder's dictionary doesn't load instead of skipping it silently.
+use crate::dictionary::{CacheStatus, Dictionary, DictionaryError};
+use crate::dictzip::DictZip;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Something that stops a dictionary from loading or slows it down.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// Holds the path the .idx file should be at.
+    MissingIdx(PathBuf),
+    MissingDict(PathBuf),
+    /// The .dict.dz file is there instead of a .dict file but it isn't dictzip
+    /// compressed, holds its path.
+    InvalidDictzip(PathBuf),
+    /// An .idx or .dict file without an .ifo file next to it.
+    MissingIfo(PathBuf),
+    UnreadableIfo,
+    MissingBookname,
+    /// The `wordcount` field is missing or not a number.
+    BadWordcount,
+    /// `wordcount` doesn't agree with the .idx file.
+    WordcountMismatch {
+        declared: u64,
+        parsed: usize,
+    },
+    CorruptIndex,
+    StaleCache(PathBuf),
+    CorruptCache(PathBuf),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::MissingIdx(path) => write!(f, "missing {}", path.display()),
+            Problem::MissingDict(path) => write!(f, "missing {}", path.display()),
+            Problem::InvalidDictzip(path) => {
+                write!(
+                    f,
+                    "{} isn't a dictzip file, it can't be read",
+                    path.display()
+                )
+            }
+            Problem::MissingIfo(path) => write!(f, "{} has no .ifo file", path.display()),
+            Problem::UnreadableIfo => write!(f, ".ifo file can't be read"),
+            Problem::MissingBookname => write!(f, ".ifo file has no bookname"),
+            Problem::BadWordcount => write!(f, ".ifo file has no valid wordcount"),
+            Problem::WordcountMismatch { declared, parsed } => write!(
+                f,
+                ".ifo file declares {} words but the index has {}",
+                declared, parsed
+            ),
+            Problem::CorruptIndex => write!(f, ".idx file is corrupt"),
+            Problem::StaleCache(path) => write!(f, "cache {} is stale", path.display()),
+            Problem::CorruptCache(path) => write!(f, "cache {} is corrupt", path.display()),
+        }
+    }
+}
+
+impl Problem {
+    /// What the user can do about it.
+    pub fn fix(&self) -> String {
+        let fix = match self {
+            Problem::MissingIdx(_) | Problem::MissingDict(_) => {
+                "copy it from the dictionary's archive, named like the .ifo file"
+            }
+            Problem::InvalidDictzip(path) => {
+                return format!(
+                    "decompress it with `zcat {} > {}`",
+                    path.display(),
+                    path.with_extension("").display()
+                )
+            }
+            Problem::MissingIfo(_) => "copy the .ifo file from the dictionary's archive",
+            Problem::UnreadableIfo => "check the permissions and encoding of the .ifo file",
+            Problem::MissingBookname => "add a `bookname=` line to the .ifo file",
+            Problem::BadWordcount | Problem::WordcountMismatch { .. } => {
+                "set `wordcount=` in the .ifo file to the number of entries of the index"
+            }
+            Problem::CorruptIndex => "download the dictionary again",
+            Problem::StaleCache(_) => "nothing, it's rebuilt on the next search",
+            Problem::CorruptCache(path) => {
+                return format!("remove {}, it's rebuilt on the next search", path.display())
+            }
+        };
+        String::from(fix)
+    }
+
+    /// Whether the dictionary still loads with it.
+    pub fn is_fatal(&self) -> bool {
+        !matches!(
+            self,
+            Problem::WordcountMismatch { .. } | Problem::StaleCache(_) | Problem::CorruptCache(_)
+        )
+    }
+}
+
+/// Findings of a dictionary, named after its .ifo file or a stray file of it.
+#[derive(Debug)]
+pub struct Report {
+    pub path: PathBuf,
+    pub problems: Vec<Problem>,
+}
+
+impl Report {
+    pub fn is_healthy(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Files of one dictionary in a folder, grouped by the name before their extensions.
+#[derive(Debug, Default)]
+struct Files {
+    ifo: Option<PathBuf>,
+    idx: Option<PathBuf>,
+    dict: Option<PathBuf>,
+    dict_dz: Option<PathBuf>,
+}
+
+/// Examines every folder under `root`, `root` included, that has dictionary files.
+/// Symbolic links aren't followed. Reports are sorted by path.
+pub fn examine_tree(root: &Path) -> Vec<Report> {
+    let mut reports = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(n) => n,
+            Err(_) => continue,
+        };
+        let mut dictionaries: BTreeMap<String, Files> = BTreeMap::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(n) if n.is_dir() => {
+                    dirs.push(path);
+                    continue;
+                }
+                Ok(n) if n.is_file() => (),
+                _ => continue,
+            }
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let (stem, slot): (&str, fn(&mut Files) -> &mut Option<PathBuf>) =
+                if let Some(n) = name.strip_suffix(".ifo") {
+                    (n, |f| &mut f.ifo)
+                } else if let Some(n) = name.strip_suffix(".idx") {
+                    (n, |f| &mut f.idx)
+                } else if let Some(n) = name.strip_suffix(".dict") {
+                    (n, |f| &mut f.dict)
+                } else if let Some(n) = name.strip_suffix(".dict.dz") {
+                    (n, |f| &mut f.dict_dz)
+                } else {
+                    continue;
+                };
+            *slot(dictionaries.entry(stem.to_string()).or_default()) = Some(path);
+        }
+        for (stem, files) in dictionaries {
+            reports.push(examine(&dir, &stem, files));
+        }
+    }
+    reports.sort_by(|a, b| a.path.cmp(&b.path));
+    reports
+}
+
+fn examine(dir: &Path, stem: &str, files: Files) -> Report {
+    let ifo = match files.ifo {
+        Some(n) => n,
+        None => {
+            let path = files.idx.or(files.dict).or(files.dict_dz).unwrap();
+            return Report {
+                problems: vec![Problem::MissingIfo(path.clone())],
+                path,
+            };
+        }
+    };
+    let mut problems = Vec::new();
+    let mut wordcount = None;
+    match fs::read_to_string(&ifo) {
+        Ok(text) => {
+            let field = |name: &str| {
+                text.lines()
+                    .find_map(|l| l.trim().strip_prefix(name)?.strip_prefix('='))
+                    .map(str::trim)
+            };
+            if field("bookname").is_none_or(str::is_empty) {
+                problems.push(Problem::MissingBookname);
+            }
+            wordcount = field("wordcount").and_then(|n| n.parse::<u64>().ok());
+            if wordcount.is_none() {
+                problems.push(Problem::BadWordcount);
+            }
+        }
+        Err(_) => problems.push(Problem::UnreadableIfo),
+    }
+    if files.dict.is_none() {
+        match files.dict_dz {
+            Some(n) if DictZip::open(&n).is_err() => problems.push(Problem::InvalidDictzip(n)),
+            Some(_) => (),
+            None => problems.push(Problem::MissingDict(dir.join(format!("{}.dict", stem)))),
+        }
+    }
+    if files.idx.is_none() {
+        problems.push(Problem::MissingIdx(dir.join(format!("{}.idx", stem))));
+        return Report {
+            path: ifo,
+            problems,
+        };
+    }
+
+    let mut dictionary = Dictionary::new(&ifo);
+    dictionary.locate_cache();
+    match dictionary.check_index() {
+        Ok(parsed) => {
+            if let Some(declared) = wordcount.filter(|n| *n != parsed as u64) {
+                problems.push(Problem::WordcountMismatch { declared, parsed });
+            }
+        }
+        Err(DictionaryError::CorruptIndex(_)) => problems.push(Problem::CorruptIndex),
+        Err(_) => problems.push(Problem::MissingIdx(dictionary.idx_path.clone())),
+    }
+    match dictionary.verify_cache() {
+        CacheStatus::Stale => problems.push(Problem::StaleCache(dictionary.cache_path.clone())),
+        CacheStatus::Corrupt => problems.push(Problem::CorruptCache(dictionary.cache_path.clone())),
+        CacheStatus::Missing | CacheStatus::Current => (),
+    }
+    Report {
+        path: ifo,
+        problems,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::write_dictionary;
+
+    #[test]
+    fn should_report_problems_of_dictionaries_in_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let words = [("elma", "apple"), ("elmas", "diamond")];
+        let healthy = write_dictionary(&dir.path().join("tr"), "ok", &words);
+        let no_idx = write_dictionary(dir.path(), "no_idx", &words);
+        fs::remove_file(no_idx.join("no_idx.idx")).unwrap();
+        let compressed = write_dictionary(dir.path(), "dz", &words);
+        fs::rename(compressed.join("dz.dict"), compressed.join("dz.dict.dz")).unwrap();
+        let dictzip = write_dictionary(dir.path(), "dictzip", &words);
+        let dict = fs::read(dictzip.join("dictzip.dict")).unwrap();
+        fs::write(
+            dictzip.join("dictzip.dict.dz"),
+            crate::dictzip::compress(&dict, 4),
+        )
+        .unwrap();
+        fs::remove_file(dictzip.join("dictzip.dict")).unwrap();
+        let stray = dir.path().join("stray");
+        fs::create_dir(&stray).unwrap();
+        fs::write(stray.join("stray.idx"), b"").unwrap();
+        let cached = write_dictionary(dir.path(), "cached", &words);
+        fs::write(cached.join("cached.sozl"), b"sozl").unwrap();
+        let miscounted = write_dictionary(dir.path(), "count", &words);
+        fs::write(
+            miscounted.join("count.ifo"),
+            "StarDict's dict ifo file\nversion=2.4.2\nwordcount=5\n",
+        )
+        .unwrap();
+
+        let reports = examine_tree(dir.path());
+        let problems = |path: PathBuf| {
+            reports
+                .iter()
+                .find(|r| r.path == path)
+                .unwrap()
+                .problems
+                .clone()
+        };
+        assert_eq!(reports.len(), 7);
+        assert!(problems(healthy.join("ok.ifo")).is_empty());
+        assert!(problems(dictzip.join("dictzip.ifo")).is_empty());
+        assert_eq!(
+            problems(no_idx.join("no_idx.ifo")),
+            vec![Problem::MissingIdx(no_idx.join("no_idx.idx"))]
+        );
+        assert_eq!(
+            problems(compressed.join("dz.ifo")),
+            vec![Problem::InvalidDictzip(compressed.join("dz.dict.dz"))]
+        );
+        assert_eq!(
+            problems(stray.join("stray.idx")),
+            vec![Problem::MissingIfo(stray.join("stray.idx"))]
+        );
+        assert_eq!(
+            problems(cached.join("cached.ifo")),
+            vec![Problem::CorruptCache(cached.join("cached.sozl"))]
+        );
+        assert_eq!(
+            problems(miscounted.join("count.ifo")),
+            vec![
+                Problem::MissingBookname,
+                Problem::WordcountMismatch {
+                    declared: 5,
+                    parsed: 2
+                }
+            ]
+        );
+    }
+}
@@ -0,0 +1,538 @@
+//! Expressions results are kept by with `--filter`, like
+//! `word.len() <= 8 && dict == "gts"`, for curating word lists without piping the
+//! output through other tools.
+//!
+//! An expression compares the fields of a result, `word`, `dict` (its label),
+//! `bookname`, `lang`, `group`, `size` (bytes of the definition) and `distance` (edits
+//! to the searched word), with strings, numbers, `true`, `false` and `null` using
+//! `==`, `!=`, `<`, `<=`, `>` and `>=`, combined with `&&`, `||`, `!` and parentheses.
+//! Strings have the methods `len()`, `lower()`, `upper()`, `contains(s)`,
+//! `starts_with(s)` and `ends_with(s)`. Fields a result doesn't have are `null`,
+//! comparing `null` or values of different types by order is false.
+use crate::IndexDictPair;
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Why an expression can't be parsed, with the char position it was noticed at.
+#[derive(Debug, PartialEq)]
+pub struct FilterError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl Error for FilterError {}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at char {}", self.message, self.position + 1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Str(String),
+    Num(f64),
+    Bool(bool),
+    Null,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Word,
+    Dict,
+    Bookname,
+    Lang,
+    Group,
+    Size,
+    Distance,
+}
+
+const FIELDS: &[(&str, Field)] = &[
+    ("word", Field::Word),
+    ("dict", Field::Dict),
+    ("bookname", Field::Bookname),
+    ("lang", Field::Lang),
+    ("group", Field::Group),
+    ("size", Field::Size),
+    ("distance", Field::Distance),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Method {
+    Len,
+    Lower,
+    Upper,
+    Contains,
+    StartsWith,
+    EndsWith,
+}
+
+/// Names of the methods with the number of arguments they take.
+const METHODS: &[(&str, Method, usize)] = &[
+    ("len", Method::Len, 0),
+    ("lower", Method::Lower, 0),
+    ("upper", Method::Upper, 0),
+    ("contains", Method::Contains, 1),
+    ("starts_with", Method::StartsWith, 1),
+    ("ends_with", Method::EndsWith, 1),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug)]
+enum Expr {
+    Literal(Value),
+    Field(Field),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(Box<Expr>, Operator, Box<Expr>),
+    Call(Box<Expr>, Method, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(f64),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "(", ")", ".", ",",
+];
+
+/// Tokens of `text` with the char positions they start at.
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, FilterError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((Token::Ident(chars[start..i].iter().collect()), start));
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let number: String = chars[start..i].iter().collect();
+            let number = number.parse().map_err(|_| FilterError {
+                position: start,
+                message: format!("invalid number {}", number),
+            })?;
+            tokens.push((Token::Num(number), start));
+        } else if c == '"' || c == '\'' {
+            let mut string = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => {
+                        return Err(FilterError {
+                            position: start,
+                            message: String::from("unterminated string"),
+                        })
+                    }
+                    Some(n) if *n == c => break,
+                    Some('\\') if i + 1 < chars.len() => {
+                        string.push(chars[i + 1]);
+                        i += 1;
+                    }
+                    Some(n) => string.push(*n),
+                }
+                i += 1;
+            }
+            i += 1;
+            tokens.push((Token::Str(string), start));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let symbol = SYMBOLS
+                .iter()
+                .find(|s| rest.starts_with(**s))
+                .ok_or_else(|| FilterError {
+                    position: start,
+                    message: format!("unexpected {:?}", c),
+                })?;
+            i += symbol.chars().count();
+            tokens.push((Token::Symbol(symbol), start));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+    /// Position reported for errors at the end of the expression.
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|t| &t.0)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.next).map_or(self.end, |t| t.1)
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, FilterError> {
+        let message = match self.peek() {
+            Some(_) => message.to_string(),
+            None => format!("{}, found the end", message),
+        };
+        Err(FilterError {
+            position: self.position(),
+            message,
+        })
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol_of(symbol))) {
+            self.next += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), FilterError> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            self.error(&format!("expected `{}`", symbol))
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.and()?;
+        while self.eat("||") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.unary()?;
+        while self.eat("&&") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, FilterError> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        let left = self.call()?;
+        let operator = match self.peek() {
+            Some(Token::Symbol("==")) => Operator::Eq,
+            Some(Token::Symbol("!=")) => Operator::Ne,
+            Some(Token::Symbol("<")) => Operator::Lt,
+            Some(Token::Symbol("<=")) => Operator::Le,
+            Some(Token::Symbol(">")) => Operator::Gt,
+            Some(Token::Symbol(">=")) => Operator::Ge,
+            _ => return Ok(left),
+        };
+        self.next += 1;
+        let right = self.call()?;
+        Ok(Expr::Compare(Box::new(left), operator, Box::new(right)))
+    }
+
+    fn call(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.primary()?;
+        while self.eat(".") {
+            let position = self.position();
+            let name = match self.peek() {
+                Some(Token::Ident(n)) => n.clone(),
+                _ => return self.error("expected a method name"),
+            };
+            let (_, method, arity) =
+                METHODS
+                    .iter()
+                    .find(|m| m.0 == name)
+                    .ok_or_else(|| FilterError {
+                        position,
+                        message: format!("unknown method {}", name),
+                    })?;
+            self.next += 1;
+            self.expect("(")?;
+            let mut arguments = Vec::new();
+            if !self.eat(")") {
+                loop {
+                    arguments.push(self.or()?);
+                    if self.eat(")") {
+                        break;
+                    }
+                    self.expect(",")?;
+                }
+            }
+            if arguments.len() != *arity {
+                return Err(FilterError {
+                    position,
+                    message: format!("{} takes {} arguments", name, arity),
+                });
+            }
+            expr = Expr::Call(Box::new(expr), *method, arguments);
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, FilterError> {
+        let position = self.position();
+        let token = match self.peek() {
+            Some(n) => n.clone(),
+            None => return self.error("expected a value"),
+        };
+        let expr = match token {
+            Token::Str(n) => Expr::Literal(Value::Str(n)),
+            Token::Num(n) => Expr::Literal(Value::Num(n)),
+            Token::Ident(n) => match n.as_str() {
+                "true" => Expr::Literal(Value::Bool(true)),
+                "false" => Expr::Literal(Value::Bool(false)),
+                "null" => Expr::Literal(Value::Null),
+                name => match FIELDS.iter().find(|f| f.0 == name) {
+                    Some((_, field)) => Expr::Field(*field),
+                    None => {
+                        return Err(FilterError {
+                            position,
+                            message: format!(
+                                "unknown field {}, expected one of {}",
+                                name,
+                                FIELDS.iter().map(|f| f.0).collect::<Vec<_>>().join(", ")
+                            ),
+                        })
+                    }
+                },
+            },
+            Token::Symbol("(") => {
+                self.next += 1;
+                let expr = self.or()?;
+                self.expect(")")?;
+                return Ok(expr);
+            }
+            Token::Symbol(_) => return self.error("expected a value"),
+        };
+        self.next += 1;
+        Ok(expr)
+    }
+}
+
+/// The `&'static str` of `SYMBOLS` equal to `symbol`.
+fn symbol_of(symbol: &str) -> &'static str {
+    SYMBOLS.iter().find(|s| **s == symbol).unwrap()
+}
+
+/// A parsed `--filter` expression.
+#[derive(Debug)]
+pub struct Filter {
+    expr: Expr,
+}
+
+impl FromStr for Filter {
+    type Err = FilterError;
+
+    fn from_str(text: &str) -> Result<Filter, FilterError> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            next: 0,
+            end: text.chars().count(),
+        };
+        let expr = parser.or()?;
+        if parser.peek().is_some() {
+            return parser.error("expected `&&`, `||` or the end");
+        }
+        Ok(Filter { expr })
+    }
+}
+
+impl Filter {
+    /// Whether the `n`th index of `pair` passes the filter.
+    pub fn matches(&self, pair: &IndexDictPair, n: usize) -> bool {
+        evaluate(&self.expr, pair, n) == Value::Bool(true)
+    }
+}
+
+fn field(field: Field, pair: &IndexDictPair, n: usize) -> Value {
+    let string = |s: Option<&str>| s.map_or(Value::Null, |s| Value::Str(s.to_string()));
+    match field {
+        Field::Word => Value::Str(pair.index[n].word.to_string()),
+        Field::Dict => Value::Str(pair.dict.label().to_string()),
+        Field::Bookname => Value::Str(pair.dict.bookname.clone()),
+        Field::Lang => string(pair.dict.lang.as_deref()),
+        Field::Group => string(pair.group.as_deref()),
+        Field::Size => Value::Num(f64::from(pair.index[n].size())),
+        Field::Distance => pair
+            .distances
+            .get(n)
+            .copied()
+            .flatten()
+            .map_or(Value::Null, |d| Value::Num(d as f64)),
+    }
+}
+
+fn evaluate(expr: &Expr, pair: &IndexDictPair, n: usize) -> Value {
+    match expr {
+        Expr::Literal(value) => value.clone(),
+        Expr::Field(f) => field(*f, pair, n),
+        Expr::Not(expr) => match evaluate(expr, pair, n) {
+            Value::Bool(b) => Value::Bool(!b),
+            _ => Value::Null,
+        },
+        Expr::And(left, right) => Value::Bool(
+            evaluate(left, pair, n) == Value::Bool(true)
+                && evaluate(right, pair, n) == Value::Bool(true),
+        ),
+        Expr::Or(left, right) => Value::Bool(
+            evaluate(left, pair, n) == Value::Bool(true)
+                || evaluate(right, pair, n) == Value::Bool(true),
+        ),
+        Expr::Compare(left, operator, right) => {
+            let (left, right) = (evaluate(left, pair, n), evaluate(right, pair, n));
+            let ordering = match (&left, &right) {
+                (Value::Num(a), Value::Num(b)) => a.partial_cmp(b),
+                (Value::Str(a), Value::Str(b)) => Some(a.cmp(b)),
+                _ => None,
+            };
+            Value::Bool(match operator {
+                Operator::Eq => left == right,
+                Operator::Ne => left != right,
+                Operator::Lt => ordering == Some(Ordering::Less),
+                Operator::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                Operator::Gt => ordering == Some(Ordering::Greater),
+                Operator::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+            })
+        }
+        Expr::Call(receiver, method, arguments) => {
+            let text = match evaluate(receiver, pair, n) {
+                Value::Str(n) => n,
+                _ => return Value::Null,
+            };
+            let argument = match arguments.first().map(|a| evaluate(a, pair, n)) {
+                Some(Value::Str(n)) => Some(n),
+                Some(_) => return Value::Null,
+                None => None,
+            };
+            let argument = argument.as_deref().unwrap_or_default();
+            match method {
+                Method::Len => Value::Num(text.chars().count() as f64),
+                Method::Lower => Value::Str(text.to_lowercase()),
+                Method::Upper => Value::Str(text.to_uppercase()),
+                Method::Contains => Value::Bool(text.contains(argument)),
+                Method::StartsWith => Value::Bool(text.starts_with(argument)),
+                Method::EndsWith => Value::Bool(text.ends_with(argument)),
+            }
+        }
+    }
+}
+
+/// Keeps only the indices passing `filter` and drops the pairs left without any.
+pub fn retain_matching(pairs: &mut Vec<IndexDictPair>, filter: &Filter) {
+    for pair in pairs.iter_mut() {
+        let keep: Vec<bool> = (0..pair.index.len())
+            .map(|n| filter.matches(pair, n))
+            .collect();
+        let mut keep_index = keep.iter();
+        pair.index.retain(|_| *keep_index.next().unwrap());
+        let mut keep_distance = keep.iter();
+        pair.distances.retain(|_| *keep_distance.next().unwrap());
+    }
+    pairs.retain(|pair| !pair.index.is_empty() || pair.truncated);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dictionary::Dictionary;
+    use crate::search_in_dicts;
+    use crate::tests::write_dictionary;
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_keep_results_passing_filter() {
+        let dir = tempdir().unwrap();
+        let path = write_dictionary(
+            dir.path(),
+            "gts",
+            &[
+                ("elma", "apple"),
+                ("elmas", "diamond"),
+                ("elmacık", "cheekbone"),
+            ],
+        );
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        let comp = crate::build_matcher("prefix", 0);
+        let search = || {
+            search_in_dicts(
+                &mut std::iter::once(&dictionary),
+                comp.as_ref(),
+                "elm",
+                None,
+            )
+        };
+        let words = |filter: &str| {
+            let mut pairs = search();
+            retain_matching(&mut pairs, &filter.parse().unwrap());
+            pairs
+                .iter()
+                .flat_map(|p| p.index.iter().map(|n| n.word.to_string()))
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            words("word.len() <= 5 && dict == \"gts\""),
+            vec!["elma", "elmas"]
+        );
+        assert_eq!(
+            words("size > 5 || word.ends_with('a')"),
+            vec!["elma", "elmas", "elmacık"]
+        );
+        assert_eq!(
+            words("!(word.upper().contains(\"MAS\"))"),
+            vec!["elma", "elmacık"]
+        );
+        assert_eq!(
+            words("lang == null && distance == null"),
+            vec!["elma", "elmas", "elmacık"]
+        );
+        assert!(words("dict == 'tr'").is_empty());
+        assert!(words("word > 3").is_empty());
+    }
+
+    #[test]
+    fn should_report_where_expressions_are_wrong() {
+        let error = |text: &str| Filter::from_str(text).unwrap_err().to_string();
+        assert_eq!(
+            error("wrod == 'a'"),
+            "unknown field wrod, expected one of word, dict, bookname, lang, group, size, \
+             distance at char 1"
+        );
+        assert_eq!(error("word.len(1) > 2"), "len takes 0 arguments at char 6");
+        assert_eq!(error("word == 'a"), "unterminated string at char 9");
+        assert_eq!(
+            error("word == "),
+            "expected a value, found the end at char 9"
+        );
+        assert_eq!(
+            error("word 'a'"),
+            "expected `&&`, `||` or the end at char 6"
+        );
+        assert_eq!(error("size = 3"), "unexpected '=' at char 6");
+    }
+}
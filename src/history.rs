@@ -0,0 +1,186 @@
+//! Words searched in the interactive loop and words bookmarked in it, kept in files next
+//! to the settings file so they are suggested again in later runs. With `--study` found
+//! words are also queued in the `study` file to learn later.
+use crate::performance_log::{Operation, TimeLog, TIMELOG_FILE};
+use log::debug;
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Number of searched words the history file keeps.
+pub const HISTORY_SIZE: usize = 200;
+
+/// Number of words suggested for an empty prompt.
+pub const SUGGESTION_COUNT: usize = 20;
+
+/// Words stored in a file one per line, the most recently added last.
+#[derive(Debug)]
+pub struct WordStore {
+    path: PathBuf,
+    words: Vec<String>,
+    /// Words beyond this many are dropped, oldest first.
+    capacity: usize,
+}
+
+impl WordStore {
+    /// Reads the words in `path`, a missing or unreadable file is an empty store.
+    pub fn load(path: &Path, capacity: usize) -> WordStore {
+        let words = match fs::read_to_string(path) {
+            Ok(n) => n
+                .lines()
+                .map(str::trim)
+                .filter(|w| !w.is_empty())
+                .map(String::from)
+                .collect(),
+            Err(e) => {
+                debug!("Can't read {}: {}", path.display(), e);
+                Vec::new()
+            }
+        };
+        WordStore {
+            path: path.to_path_buf(),
+            words,
+            capacity,
+        }
+    }
+
+    /// Adds `word` as the most recent one, moving it if it's already stored, and writes
+    /// the file.
+    pub fn add(&mut self, word: &str) -> io::Result<()> {
+        let word = word.trim();
+        if word.is_empty() {
+            return Ok(());
+        }
+        self.words.retain(|w| w != word);
+        self.words.push(word.to_string());
+        if self.words.len() > self.capacity {
+            self.words.drain(..self.words.len() - self.capacity);
+        }
+        self.save()
+    }
+
+    /// Adds `word` as the most recent one unless it's already stored, where it keeps its
+    /// place, and writes the file. Returns whether it was added.
+    pub fn push_new(&mut self, word: &str) -> io::Result<bool> {
+        let word = word.trim();
+        if word.is_empty() || self.words.iter().any(|w| w == word) {
+            return Ok(false);
+        }
+        self.add(word)?;
+        Ok(true)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut contents = self.words.join("\n");
+        contents.push('\n');
+        fs::write(&self.path, contents)
+    }
+
+    /// Stored words, the most recent first.
+    pub fn recent(&self) -> impl Iterator<Item = &str> {
+        self.words.iter().rev().map(String::as_str)
+    }
+}
+
+/// Recently searched words then bookmarked ones starting with `prefix`, compared case
+/// insensitively, without duplicates and at most `SUGGESTION_COUNT` of them.
+pub fn suggestions(prefix: &str, history: &WordStore, bookmarks: &WordStore) -> Vec<String> {
+    let prefix = prefix.to_lowercase();
+    let mut words: Vec<String> = Vec::new();
+    for word in history.recent().chain(bookmarks.recent()) {
+        if words.len() == SUGGESTION_COUNT {
+            break;
+        }
+        if word.to_lowercase().starts_with(&prefix) && !words.iter().any(|w| w == word) {
+            words.push(word.to_string());
+        }
+    }
+    words
+}
+
+/// Completes the interactive prompt with `suggestions` when Tab is pressed.
+pub struct Suggester {
+    pub history: WordStore,
+    pub bookmarks: WordStore,
+}
+
+impl Completer for Suggester {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let prefix = &line[..pos];
+        let start_time = Instant::now();
+        let words = suggestions(prefix, &self.history, &self.bookmarks);
+        TimeLog::write(&TIMELOG_FILE, || {
+            TimeLog::new()
+                .clock(start_time.elapsed())
+                .operation(Operation::Complete)
+                .prefix_length(prefix.chars().count())
+                .candidates(words.len())
+        });
+        Ok((0, words))
+    }
+}
+
+impl Hinter for Suggester {
+    type Hint = String;
+}
+
+impl Highlighter for Suggester {}
+impl Validator for Suggester {}
+impl Helper for Suggester {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_suggest_recent_then_bookmarked_words() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = WordStore::load(&dir.path().join("sozluk/history"), 2);
+        history.add("elma").unwrap();
+        history.add("kitap").unwrap();
+        history.add("Elmas").unwrap();
+        history.add("kitap").unwrap();
+        let mut bookmarks = WordStore::load(&dir.path().join("sozluk/bookmarks"), 10);
+        bookmarks.add("elma").unwrap();
+        bookmarks.add("kitap").unwrap();
+
+        let history = WordStore::load(&dir.path().join("sozluk/history"), 2);
+        assert_eq!(history.recent().collect::<Vec<_>>(), vec!["kitap", "Elmas"]);
+        assert_eq!(
+            suggestions("", &history, &bookmarks),
+            vec!["kitap", "Elmas", "elma"]
+        );
+        assert_eq!(
+            suggestions("el", &history, &bookmarks),
+            vec!["Elmas", "elma"]
+        );
+    }
+
+    #[test]
+    fn should_queue_words_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sozluk/study");
+        let mut study = WordStore::load(&path, usize::MAX);
+        assert!(study.push_new("elma").unwrap());
+        assert!(study.push_new("kitap").unwrap());
+        assert!(!study.push_new("elma ").unwrap());
+        assert!(!study.push_new("").unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "elma\nkitap\n");
+    }
+}
@@ -0,0 +1,415 @@
+//! Html definitions laid out as terminal text: tags become line breaks, list bullets and
+//! bold, italic or underlined spans, scripts and stylesheets are left out and
+//! whitespace is collapsed the way browsers do.
+use termcolor::ColorSpec;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Tags ending the line before and after them.
+const BLOCK_TAGS: &[&str] = &[
+    "p",
+    "div",
+    "li",
+    "ul",
+    "ol",
+    "dl",
+    "dt",
+    "dd",
+    "tr",
+    "table",
+    "blockquote",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "pre",
+];
+
+/// Tags whose content isn't text to show.
+const HIDDEN_TAGS: &[&str] = &["script", "style", "head", "title"];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Style {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl Style {
+    pub fn spec(&self) -> ColorSpec {
+        ColorSpec::new()
+            .set_bold(self.bold)
+            .set_italic(self.italic)
+            .set_underline(self.underline)
+            .clone()
+    }
+}
+
+/// Text in a single style.
+#[derive(Debug, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+/// A line of text, `prefix` is the indentation and bullet of a list item.
+#[derive(Debug, Default, PartialEq)]
+pub struct Line {
+    pub prefix: String,
+    pub spans: Vec<Span>,
+}
+
+impl Line {
+    pub fn text(&self) -> String {
+        let mut text = self.prefix.clone();
+        text.extend(self.spans.iter().map(|s| s.text.as_str()));
+        text
+    }
+
+    fn push(&mut self, c: char, style: Style) {
+        match self.spans.last_mut() {
+            Some(span) if span.style == style => span.text.push(c),
+            _ => self.spans.push(Span {
+                text: c.to_string(),
+                style,
+            }),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.prefix.is_empty() && self.spans.is_empty()
+    }
+
+    /// The line broken at spaces so none takes more than `width` columns, continuation
+    /// lines indented `indent` more than the text after the prefix. Words longer than a
+    /// line aren't split. The prefix is a plain span of the first line.
+    pub fn wrapped(&self, width: Option<usize>, indent: usize) -> Vec<Vec<Span>> {
+        let hanging = self.prefix.width() + indent;
+        let mut lines: Vec<Vec<Span>> = Vec::new();
+        let mut current = Line::default();
+        if !self.prefix.is_empty() {
+            current.spans.push(Span {
+                text: self.prefix.clone(),
+                style: Style::default(),
+            });
+        }
+        let mut column = self.prefix.width();
+        let mut words: Vec<Vec<(char, Style)>> = vec![Vec::new()];
+        for span in &self.spans {
+            for c in span.text.chars() {
+                if c == ' ' {
+                    words.push(Vec::new());
+                } else {
+                    words.last_mut().unwrap().push((c, span.style));
+                }
+            }
+        }
+        for (i, word) in words.iter().enumerate() {
+            let word_width: usize = word.iter().map(|(c, _)| c.width().unwrap_or(0)).sum();
+            if i > 0 {
+                if width.is_some_and(|w| column + 1 + word_width > w) && column > hanging {
+                    lines.push(std::mem::take(&mut current.spans));
+                    current.push_str(&" ".repeat(hanging), Style::default());
+                    column = hanging;
+                } else {
+                    current.push(' ', Style::default());
+                    column += 1;
+                }
+            }
+            for (c, style) in word {
+                current.push(*c, *style);
+            }
+            column += word_width;
+        }
+        lines.push(current.spans);
+        lines
+    }
+
+    fn push_str(&mut self, text: &str, style: Style) {
+        for c in text.chars() {
+            self.push(c, style);
+        }
+    }
+}
+
+/// Lines of `html` as they should be shown, without leading and trailing empty lines
+/// and at most one empty line in a row.
+pub fn render(html: &str) -> Vec<Line> {
+    let mut renderer = Renderer::default();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        renderer.text(&decode_entities(&rest[..start]));
+        match rest[start..].find('>') {
+            Some(end) => {
+                renderer.tag(&rest[start + 1..start + end]);
+                rest = &rest[start + end + 1..];
+            }
+            None => {
+                //A lone '<' is text.
+                renderer.text(&decode_entities(&rest[start..]));
+                rest = "";
+            }
+        }
+    }
+    renderer.text(&decode_entities(rest));
+    renderer.finish()
+}
+
+#[derive(Default)]
+struct Renderer {
+    lines: Vec<Line>,
+    line: Line,
+    bold: usize,
+    italic: usize,
+    underline: usize,
+    /// Open lists, `Some` with the number of the last item for ordered ones.
+    lists: Vec<Option<usize>>,
+    /// Open tag whose content is left out.
+    hidden: Option<String>,
+    /// Whether a space goes before the next char of the line.
+    space: bool,
+}
+
+impl Renderer {
+    fn style(&self) -> Style {
+        Style {
+            bold: self.bold > 0,
+            italic: self.italic > 0,
+            underline: self.underline > 0,
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.hidden.is_some() {
+            return;
+        }
+        for c in text.chars() {
+            if c.is_whitespace() && c != '\u{a0}' {
+                self.space = !self.line.spans.is_empty();
+                continue;
+            }
+            if self.space {
+                self.line.push(' ', Style::default());
+                self.space = false;
+            }
+            self.line.push(c, self.style());
+        }
+    }
+
+    /// Ends the line, or adds an empty one with `force`.
+    fn break_line(&mut self, force: bool) {
+        self.space = false;
+        if self.line.spans.is_empty() && !force {
+            self.line.prefix.clear();
+            return;
+        }
+        let line = std::mem::take(&mut self.line);
+        let blank = line.is_empty();
+        if blank && self.lines.last().is_none_or(|l| l.is_empty()) {
+            return;
+        }
+        self.lines.push(line);
+    }
+
+    fn tag(&mut self, tag: &str) {
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .trim_end_matches('/')
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if let Some(hidden) = &self.hidden {
+            if closing && *hidden == name {
+                self.hidden = None;
+            }
+            return;
+        }
+        if HIDDEN_TAGS.contains(&name.as_str()) {
+            //Self closing tags have no content to hide.
+            if !closing && !tag.ends_with('/') {
+                self.hidden = Some(name);
+            }
+            return;
+        }
+        let counter = match name.as_str() {
+            "b" | "strong" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "dt" => Some(&mut self.bold),
+            "i" | "em" | "cite" | "var" => Some(&mut self.italic),
+            "u" | "ins" => Some(&mut self.underline),
+            _ => None,
+        };
+        if let Some(counter) = counter {
+            *counter = if closing {
+                counter.saturating_sub(1)
+            } else {
+                *counter + 1
+            };
+        }
+        if name == "br" {
+            self.break_line(true);
+        } else if BLOCK_TAGS.contains(&name.as_str()) {
+            self.break_line(false);
+        }
+        match (name.as_str(), closing) {
+            ("ul", false) => self.lists.push(None),
+            ("ol", false) => self.lists.push(Some(0)),
+            ("ul", true) | ("ol", true) => {
+                self.lists.pop();
+            }
+            ("li", false) => {
+                let depth = self.lists.len().max(1);
+                let bullet = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", n)
+                    }
+                    _ => String::from("• "),
+                };
+                self.line.prefix = format!("{}{}", "  ".repeat(depth - 1), bullet);
+            }
+            ("dd", false) => self.line.prefix = String::from("  "),
+            _ => (),
+        }
+    }
+
+    fn finish(mut self) -> Vec<Line> {
+        self.break_line(false);
+        while self.lines.last().is_some_and(|l| l.is_empty()) {
+            self.lines.pop();
+        }
+        self.lines
+    }
+}
+
+/// `text` with the character references and the common named entities replaced by
+/// what they stand for, unknown ones are kept as they are.
+pub fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..]
+            .find(';')
+            .filter(|n| *n <= 10)
+            .and_then(|n| Some((entity(&rest[1..n + 1])?, n + 2)));
+        match entity {
+            Some((c, length)) => {
+                decoded.push(c);
+                rest = &rest[length..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn entity(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number
+            .strip_prefix('x')
+            .or_else(|| number.strip_prefix('X'))
+        {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    Some(match name {
+        "lt" => '<',
+        "gt" => '>',
+        "amp" => '&',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "middot" => '·',
+        "mdash" => '—',
+        "ndash" => '–',
+        "hellip" => '…',
+        "laquo" => '«',
+        "raquo" => '»',
+        "copy" => '©',
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(html: &str) -> Vec<String> {
+        render(html).iter().map(Line::text).collect()
+    }
+
+    #[test]
+    fn should_lay_out_blocks_and_lists() {
+        assert_eq!(
+            texts("<h3>elma</h3>\n<ol><li>apple</li><li>apple  tree<ul><li>wood</li></ul></li></ol><p>&lt;fruit&gt;</p>"),
+            vec!["elma", "1. apple", "2. apple tree", "  • wood", "<fruit>"]
+        );
+        assert_eq!(texts("a<br><br><br>b<br>"), vec!["a", "", "b"]);
+        assert_eq!(
+            texts("x &amp y &#231;&#x131; &nosuch;"),
+            vec!["x &amp y çı &nosuch;"]
+        );
+    }
+
+    #[test]
+    fn should_leave_out_scripts_and_styles() {
+        assert_eq!(
+            texts("<style>b { color: red }</style>elma<script>alert('<b>')</script> <i>armut</i>"),
+            vec!["elma armut"]
+        );
+    }
+
+    #[test]
+    fn should_keep_styles_of_tags() {
+        let lines = render("<b>el<i>ma</i></b> tree");
+        let bold = Style {
+            bold: true,
+            ..Style::default()
+        };
+        let bold_italic = Style {
+            italic: true,
+            ..bold
+        };
+        assert_eq!(
+            lines[0].spans,
+            vec![
+                Span {
+                    text: String::from("el"),
+                    style: bold
+                },
+                Span {
+                    text: String::from("ma"),
+                    style: bold_italic
+                },
+                Span {
+                    text: String::from(" tree"),
+                    style: Style::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn should_wrap_under_list_bullets() {
+        let lines = render("<ul><li>bir iki üç dört</li></ul>");
+        let wrapped: Vec<String> = lines[0]
+            .wrapped(Some(10), 0)
+            .iter()
+            .map(|l| l.iter().map(|s| s.text.as_str()).collect())
+            .collect();
+        assert_eq!(wrapped, vec!["• bir iki", "  üç dört"]);
+        assert_eq!(lines[0].wrapped(None, 2).len(), 1);
+        let lines = render("<p>日本語 の辞書</p>");
+        assert_eq!(lines[0].wrapped(Some(7), 0).len(), 2);
+    }
+}
@@ -0,0 +1,207 @@
+//! Messages of the terminal interface in English and Turkish. The language is picked
+//! with `--ui-lang` or the `ui_lang` setting, otherwise from the locale of the
+//! environment. Log messages stay in English.
+use std::env;
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Language {
+    English,
+    Turkish,
+}
+
+/// Names of the languages for `--ui-lang`.
+pub const LANGUAGES: &[&str] = &["en", "tr"];
+
+static LANGUAGE: AtomicU8 = AtomicU8::new(0);
+
+impl Language {
+    /// Language named `name`, one of `LANGUAGES`.
+    pub fn from_name(name: &str) -> Option<Language> {
+        match name {
+            "en" => Some(Language::English),
+            "tr" => Some(Language::Turkish),
+            _ => None,
+        }
+    }
+
+    /// Language of a locale like `tr_TR.UTF-8`, English unless it's Turkish.
+    pub fn from_locale(locale: &str) -> Language {
+        if locale.starts_with("tr") {
+            Language::Turkish
+        } else {
+            Language::English
+        }
+    }
+
+    /// Language of the first locale set of `LC_ALL`, `LC_MESSAGES` and `LANG`.
+    pub fn from_env() -> Language {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|n| env::var(n).ok())
+            .find(|n| !n.is_empty())
+            .map_or(Language::English, |n| Language::from_locale(&n))
+    }
+}
+
+/// Sets the language messages are shown in.
+pub fn set_language(language: Language) {
+    LANGUAGE.store(language as u8, Ordering::Relaxed);
+}
+
+pub fn language() -> Language {
+    match LANGUAGE.load(Ordering::Relaxed) {
+        1 => Language::Turkish,
+        _ => Language::English,
+    }
+}
+
+/// A message of the terminal interface, shown in the language set with `set_language`
+/// when displayed.
+#[derive(Debug)]
+pub enum Message<'a> {
+    NoResult,
+    SearchPrompt,
+    Bookmarked(&'a str),
+    DictionaryPrompt,
+    SearchingOnlyIn(&'a str),
+    SearchingInAll,
+    TimedOut,
+    /// Dictionary and the number of definitions found in it.
+    FoundResults(&'a str, usize),
+    /// Dictionary results listed below are from.
+    From(&'a str),
+    /// Page shown and the number of pages.
+    Page(usize, usize),
+    ListPrompt,
+    NoMatchingResult(&'a str),
+    NoMorePages,
+    /// Number of listed results.
+    InvalidChoice(usize),
+    OpenedInBrowser(&'a str),
+    Copied,
+    /// Bytes of the definition that are shown.
+    DefinitionCut(usize),
+    NothingCut,
+}
+
+impl Message<'_> {
+    /// Text of the message in `language`.
+    pub fn text(&self, language: Language) -> String {
+        use Language::*;
+        use Message::*;
+        match (self, language) {
+            (NoResult, English) => String::from("Found no result!"),
+            (NoResult, Turkish) => String::from("Sonuç bulunamadı!"),
+            (SearchPrompt, English) => String::from(
+                "Enter a word to search, Tab for suggestions, + to bookmark it, d to pick a \
+                 dictionary or z to exit.",
+            ),
+            (SearchPrompt, Turkish) => String::from(
+                "Aranacak kelimeyi girin, öneriler için Tab, yer imlerine eklemek için +, \
+                 sözlük seçmek için d, çıkmak için z.",
+            ),
+            (Bookmarked(word), English) => format!("Bookmarked {}.", word),
+            (Bookmarked(word), Turkish) => format!("{} yer imlerine eklendi.", word),
+            (DictionaryPrompt, English) => String::from(
+                "Enter the number of a dictionary to search only in it, or nothing to search \
+                 in all.",
+            ),
+            (DictionaryPrompt, Turkish) => String::from(
+                "Yalnızca bir sözlükte aramak için numarasını girin, hepsinde aramak için boş \
+                 bırakın.",
+            ),
+            (SearchingOnlyIn(label), English) => format!("Searching only in {}.", label),
+            (SearchingOnlyIn(label), Turkish) => format!("Yalnızca {} içinde aranıyor.", label),
+            (SearchingInAll, English) => String::from("Searching in all dictionaries."),
+            (SearchingInAll, Turkish) => String::from("Bütün sözlüklerde aranıyor."),
+            (TimedOut, English) => String::from("Search timed out, results are incomplete."),
+            (TimedOut, Turkish) => String::from("Arama zaman aşımına uğradı, sonuçlar eksik."),
+            (FoundResults(label, count), English) => {
+                format!("From dictionary {} found {} results. \n", label, count)
+            }
+            (FoundResults(label, count), Turkish) => {
+                format!("{} sözlüğünde {} sonuç bulundu. \n", label, count)
+            }
+            (From(label), English) => format!("From {:?}", label),
+            (From(label), Turkish) => format!("{:?} sözlüğünden", label),
+            (Page(page, pages), English) => {
+                format!("Page {}/{}, n/p for next/previous page.", page, pages)
+            }
+            (Page(page, pages), Turkish) => {
+                format!("Sayfa {}/{}, sonraki/önceki sayfa için n/p.", page, pages)
+            }
+            (ListPrompt, English) => String::from(
+                "Enter a number or range (3-7) to see definitions, o <number> to open one in \
+                 the browser, c <number> to copy it, /pattern to filter them, b for the list \
+                 or z to exit.",
+            ),
+            (ListPrompt, Turkish) => String::from(
+                "Tanımları görmek için bir numara ya da aralık (3-7), tarayıcıda açmak için \
+                 o <numara>, kopyalamak için c <numara>, süzmek için /kalıp, liste için b, \
+                 çıkmak için z girin.",
+            ),
+            (NoMatchingResult(pattern), English) => {
+                format!("No result contains {:?}.", pattern)
+            }
+            (NoMatchingResult(pattern), Turkish) => {
+                format!("Hiçbir sonuçta {:?} geçmiyor.", pattern)
+            }
+            (NoMorePages, English) => String::from("No more pages."),
+            (NoMorePages, Turkish) => String::from("Başka sayfa yok."),
+            (InvalidChoice(count), English) => {
+                format!("Enter a number between 1 and {} or a range of them.", count)
+            }
+            (InvalidChoice(count), Turkish) => format!(
+                "1 ile {} arasında bir numara ya da bir aralık girin.",
+                count
+            ),
+            (OpenedInBrowser(path), English) => format!("Opened {} in the browser.", path),
+            (OpenedInBrowser(path), Turkish) => format!("{} tarayıcıda açıldı.", path),
+            (Copied, English) => String::from("Copied to the clipboard."),
+            (Copied, Turkish) => String::from("Panoya kopyalandı."),
+            (DefinitionCut(bytes), English) => format!(
+                "Only the first {} bytes of this definition are shown, enter m to see all of it.",
+                bytes
+            ),
+            (DefinitionCut(bytes), Turkish) => format!(
+                "Bu tanımın yalnızca ilk {} baytı gösteriliyor, tamamı için m girin.",
+                bytes
+            ),
+            (NothingCut, English) => String::from("No definition was cut."),
+            (NothingCut, Turkish) => String::from("Kesilen tanım yok."),
+        }
+    }
+}
+
+impl fmt::Display for Message<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text(language()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_pick_language_and_translate_messages() {
+        assert_eq!(Language::from_locale("tr_TR.UTF-8"), Language::Turkish);
+        assert_eq!(Language::from_locale("en_US.UTF-8"), Language::English);
+        assert_eq!(Language::from_locale("C"), Language::English);
+        assert_eq!(Language::from_name("tr"), Some(Language::Turkish));
+        assert_eq!(Language::from_name("de"), None);
+
+        let message = Message::Bookmarked("elma");
+        assert_eq!(message.text(Language::English), "Bookmarked elma.");
+        assert_eq!(
+            message.text(Language::Turkish),
+            "elma yer imlerine eklendi."
+        );
+        assert_eq!(
+            Message::Page(2, 5).text(Language::Turkish),
+            "Sayfa 2/5, sonraki/önceki sayfa için n/p."
+        );
+    }
+}
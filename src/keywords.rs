@@ -0,0 +1,157 @@
+//! Keywords of definitions for `--reverse`, finding the headwords whose definitions talk
+//! about a word. Misspelled or inflected queries still match keywords a few edits away.
+//! `--fulltext` narrows its phrase down to the entries having all of its keywords.
+use serde::{Deserialize, Serialize};
+use strsim::levenshtein;
+
+/// Words too common in definitions to tell them apart, English and Turkish.
+const STOPWORDS: &[&str] = &[
+    "a", "about", "an", "and", "any", "are", "as", "at", "be", "been", "being", "by", "can", "for",
+    "from", "has", "have", "he", "her", "his", "in", "into", "is", "it", "its", "not", "of", "on",
+    "one", "or", "she", "so", "some", "such", "than", "that", "the", "their", "them", "there",
+    "these", "they", "this", "those", "to", "used", "was", "were", "which", "who", "with", "ama",
+    "bir", "biri", "bu", "da", "de", "den", "diye", "gibi", "için", "ile", "ise", "kadar", "ki",
+    "mi", "ne", "olan", "olarak", "veya", "ve", "ya", "yani", "şu", "o",
+];
+
+/// Keywords shorter than this many chars are left out, they are rarely salient.
+const MIN_KEYWORD_LENGTH: usize = 3;
+
+/// Distinct lower cased words of `text` that aren't stopwords, sorted.
+pub fn keywords(text: &str) -> Vec<String> {
+    let mut words: Vec<String> = words(text)
+        .filter(|w| w.chars().count() >= MIN_KEYWORD_LENGTH)
+        .filter(|w| !STOPWORDS.contains(&w.as_str()))
+        .collect();
+    words.sort_unstable();
+    words.dedup();
+    words
+}
+
+/// Lower cased words of `text`, split at anything that isn't a letter or a digit.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Whether the words of `phrase` follow each other in `text`, ignoring case, punctuation
+/// and whitespace. False if `phrase` has no words.
+pub fn contains_phrase(text: &str, phrase: &str) -> bool {
+    let phrase: Vec<String> = words(phrase).collect();
+    if phrase.is_empty() {
+        return false;
+    }
+    let text: Vec<String> = words(text).collect();
+    text.windows(phrase.len()).any(|w| w == phrase.as_slice())
+}
+
+/// Inverted index from the keywords of definitions to the positions of their entries in
+/// the index.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct KeywordIndex {
+    /// Keywords in order, with the start of their positions in `postings`. Their
+    /// positions end where the positions of the next one start.
+    words: Vec<(String, u32)>,
+    postings: Vec<u32>,
+}
+
+impl KeywordIndex {
+    /// Index of the keywords of each entry, given as its position and definition text.
+    pub fn of<I: IntoIterator<Item = (u32, String)>>(definitions: I) -> KeywordIndex {
+        let mut pairs: Vec<(String, u32)> = definitions
+            .into_iter()
+            .flat_map(|(n, text)| keywords(&text).into_iter().map(move |w| (w, n)))
+            .collect();
+        pairs.sort_unstable();
+        let mut index = KeywordIndex::default();
+        for (word, position) in pairs {
+            if index.words.last().map(|n| &n.0) != Some(&word) {
+                index.words.push((word, index.postings.len() as u32));
+            }
+            index.postings.push(position);
+        }
+        index
+    }
+
+    /// Positions of the entries having a keyword at most `edits` edits away from every
+    /// keyword of `query`, with the sum of their distances, in index order. `None` if
+    /// `query` has no keywords.
+    pub fn search(&self, query: &str, edits: usize) -> Option<Vec<(u32, usize)>> {
+        let mut found: Option<Vec<(u32, usize)>> = None;
+        for word in keywords(query) {
+            let length = word.chars().count();
+            let mut matches: Vec<(u32, usize)> = Vec::new();
+            for (n, (keyword, _)) in self.words.iter().enumerate() {
+                if keyword.chars().count().abs_diff(length) > edits {
+                    continue;
+                }
+                let distance = levenshtein(&word, keyword);
+                if distance <= edits {
+                    matches.extend(self.positions(n).iter().map(|p| (*p, distance)));
+                }
+            }
+            //An entry matching a word through several keywords keeps the closest.
+            matches.sort_unstable();
+            matches.dedup_by_key(|m| m.0);
+            found = Some(match found {
+                None => matches,
+                Some(found) => found
+                    .into_iter()
+                    .filter_map(|(position, total)| {
+                        let n = matches.binary_search_by_key(&position, |m| m.0).ok()?;
+                        Some((position, total + matches[n].1))
+                    })
+                    .collect(),
+            });
+        }
+        found
+    }
+
+    fn positions(&self, n: usize) -> &[u32] {
+        let start = self.words[n].1 as usize;
+        let end = self
+            .words
+            .get(n + 1)
+            .map_or(self.postings.len(), |x| x.1 as usize);
+        &self.postings[start..end]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_extract_keywords_without_stopwords() {
+        assert_eq!(
+            keywords("The fruit of the apple tree, a round FRUIT."),
+            vec!["apple", "fruit", "round", "tree"]
+        );
+        assert_eq!(keywords("bir meyve ve ağaç"), vec!["ağaç", "meyve"]);
+    }
+
+    #[test]
+    fn should_find_entries_by_close_keywords() {
+        let index = KeywordIndex::of(vec![
+            (0, String::from("the fruit of the apple tree")),
+            (1, String::from("a yellow fruit")),
+            (2, String::from("a precious stone")),
+        ]);
+        assert_eq!(index.search("fruit", 0), Some(vec![(0, 0), (1, 0)]));
+        assert_eq!(index.search("frut", 1), Some(vec![(0, 1), (1, 1)]));
+        assert_eq!(index.search("frut", 0), Some(vec![]));
+        assert_eq!(index.search("yelow fruit", 1), Some(vec![(1, 1)]));
+        assert_eq!(index.search("of the", 1), None);
+    }
+
+    #[test]
+    fn should_find_phrases_in_text() {
+        let text = "The fruit of the apple-tree;  a ROUND fruit.";
+        assert!(contains_phrase(text, "fruit of the apple"));
+        assert!(contains_phrase(text, "apple tree, a round"));
+        assert!(!contains_phrase(text, "fruit of apple"));
+        assert!(!contains_phrase(text, "app"));
+        assert!(!contains_phrase(text, " ,"));
+    }
+}
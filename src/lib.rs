@@ -1,116 +1,1043 @@
+pub mod bitmap;
+pub mod browser;
+pub mod clipboard;
 pub mod colored_print;
 pub mod dictionary;
+pub mod dictzip;
+pub mod discovery;
+pub mod doctor;
+pub mod filter;
+pub mod history;
+pub mod html;
+pub mod i18n;
+pub mod keywords;
 pub mod matcher;
 pub mod morpher;
+pub mod normalization;
+pub mod parsed;
 pub mod performance_log;
+pub mod postprocess;
+pub mod query_cache;
+pub mod registry;
+pub mod resource_cache;
+pub mod resources;
 pub mod server;
+pub mod session_log;
 pub mod settings;
+pub mod setup;
+pub mod snapshot;
+pub mod source;
+pub mod stats;
+pub mod stdio;
+pub mod systemd;
+pub mod terminal_image;
+pub mod transliteration;
+pub mod wordlist;
 
 use dictionary::{Definition, Dictionary, Index};
-use log::{debug, error, info};
-use matcher::{ExactMatcher, LevenshteinMatcher, WordMatcher};
+use log::{debug, info, warn};
+use matcher::{
+    relevance, ContainsMatcher, ExactMatcher, LevenshteinMatcher, PrefixMatcher, RegexMatcher,
+    WordMatcher,
+};
 use morpher::{EnglishMorpher, Morpher, NoMorpher, TurkishMorpher};
+use normalization::{NormalizingMatcher, Pipeline, Step, VariantsMatcher};
 use performance_log::{Operation, TimeLog, TIMELOG_FILE};
 use rayon::prelude::*;
-use std::collections::HashMap;
-use std::fs::{self, File};
-use std::io::{self};
-use std::path::PathBuf;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use registry::Registry;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Instant;
 
 pub struct IndexDictPair<'a> {
     pub index: Vec<&'a Index>,
     pub dict: &'a Dictionary,
+    /// Search in `dict` hit the timeout, `index` holds only the matches found until then.
+    pub truncated: bool,
+    /// Distance of each index to the searched word, same order as `index`.
+    pub distances: Vec<Option<usize>>,
+    /// Group `dict` was searched in, only set when several groups are searched at once.
+    pub group: Option<String>,
+}
+
+impl<'a> IndexDictPair<'a> {
+    /// Reads the definition of the `n`th index with its distance to the searched word.
+    pub fn read_definition(&self, n: usize) -> Result<Definition, std::io::Error> {
+        let mut definition = self.dict.read_definition(self.index[n])?;
+        definition.distance = self.distances.get(n).copied().flatten();
+        Ok(definition)
+    }
+
+    /// Reads the definition of the `n`th index like `read_definition`, without cutting it
+    /// at the definition limit of the dictionary.
+    pub fn read_whole_definition(&self, n: usize) -> Result<Definition, std::io::Error> {
+        let mut definition = self.dict.read_whole_definition(self.index[n])?;
+        definition.distance = self.distances.get(n).copied().flatten();
+        Ok(definition)
+    }
+
+    /// Name to show the results under, the dictionary label prefixed with its group.
+    pub fn label(&self) -> String {
+        match &self.group {
+            Some(group) => format!("{}/{}", group, self.dict.label()),
+            None => self.dict.label().to_string(),
+        }
+    }
+}
+
+/// Output format of `indices_to_json`, details of the search next to the results so a
+/// dictionary's label can't be mistaken for one of them.
+#[derive(Serialize)]
+struct JsonOutput<'a> {
+    /// Definitions keyed by dictionary label.
+    results: HashMap<String, Vec<Definition>>,
+    /// Booknames of dictionaries whose search was cut short by the timeout.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    truncated: Vec<String>,
+    /// Normalization steps of the group that was searched.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    normalization: Vec<Step>,
+    /// Normalization steps of each group, when several were searched.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    group_normalization: BTreeMap<String, Vec<Step>>,
+    /// Configured synonyms of the query that were searched along with it.
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    expansions: &'a [String],
 }
 
 pub fn build_matcher(algorithm: &str, depth: usize) -> Box<dyn WordMatcher + Sync> {
     let comp: Box<dyn WordMatcher + Sync> = match algorithm {
         "levenshtein" => Box::from(LevenshteinMatcher { level: depth }),
+        "prefix" => Box::from(PrefixMatcher {}),
+        "contains" => Box::from(ContainsMatcher {}),
+        "regex" => Box::from(RegexMatcher::default()),
         _ => Box::from(ExactMatcher {}),
     };
     comp
 }
 
+pub fn build_morpher(name: &str) -> Box<dyn Morpher> {
+    match name {
+        "tr" => Box::new(TurkishMorpher {}),
+        "en" => Box::new(EnglishMorpher {}),
+        _ => Box::new(NoMorpher {}),
+    }
+}
+
+/// Matcher a dictionary asks for through its preferred algorithm and depth, `None` if it
+/// has no preference. A depth alone means levenshtein, an algorithm alone depth 2.
+pub fn preferred_matcher(dictionary: &Dictionary) -> Option<Box<dyn WordMatcher + Sync>> {
+    match (&dictionary.preferred_algorithm, dictionary.preferred_depth) {
+        (None, None) => None,
+        (algorithm, depth) => Some(build_matcher(
+            algorithm.as_deref().unwrap_or("levenshtein"),
+            depth.map_or(2, usize::from),
+        )),
+    }
+}
+
+/// Searches `word` in `dicts` with `comp`, or the preferred matcher of a dictionary
+/// when it has one, cutting searches still going at `deadline` short. Results are
+/// ordered by `rank_pairs`.
 pub fn search_in_dicts<'a, D, M: ?Sized + WordMatcher + Sync>(
     dicts: &mut D,
     comp: &M,
     word: &str,
+    deadline: Option<Instant>,
+) -> Vec<IndexDictPair<'a>>
+where
+    D: Iterator<Item = &'a Dictionary>,
+{
+    let forms = [word.to_string()];
+    let mut pairs = search_normalized_in_dicts(dicts, comp, &forms, None, deadline);
+    rank_pairs(&mut pairs, word, comp);
+    pairs
+}
+
+/// Same as `search_in_dicts` but searches every one of `forms` and compares headwords
+/// normalized by `pipeline`.
+fn search_normalized_in_dicts<'a, D, M: ?Sized + WordMatcher + Sync>(
+    dicts: &mut D,
+    comp: &M,
+    forms: &[String],
+    pipeline: Option<&Pipeline>,
+    deadline: Option<Instant>,
 ) -> Vec<IndexDictPair<'a>>
 where
     D: Iterator<Item = &'a Dictionary>,
 {
+    let dicts: Vec<&Dictionary> = dicts.collect();
+    let dicts = load_indices(&dicts);
     let mut indices_to_list: Vec<IndexDictPair> = Vec::new();
+    for dic in dicts {
+        let preferred = preferred_matcher(dic);
+        let pair = match (&preferred, pipeline) {
+            (Some(own), Some(pipeline)) => {
+                let inner = own.as_ref();
+                let normalizing = NormalizingMatcher { inner, pipeline };
+                search_forms(dic, &normalizing, forms, deadline)
+            }
+            (Some(own), None) => search_forms(dic, own.as_ref(), forms, deadline),
+            (None, Some(pipeline)) => {
+                let inner = comp;
+                let normalizing = NormalizingMatcher { inner, pipeline };
+                search_forms(dic, &normalizing, forms, deadline)
+            }
+            (None, None) => search_forms(dic, comp, forms, deadline),
+        };
+        indices_to_list.extend(pair);
+    }
+    indices_to_list
+}
+
+/// Searches every one of `forms` in `dic`. Matchers with a lookup find the candidates of
+/// each form without reading the whole index, the others compare all forms with each
+/// headword in a single pass.
+fn search_forms<'a, M: ?Sized + WordMatcher + Sync>(
+    dic: &'a Dictionary,
+    comp: &M,
+    forms: &[String],
+    deadline: Option<Instant>,
+) -> Option<IndexDictPair<'a>> {
+    match forms {
+        [] => None,
+        [word] => search_dictionary(dic, comp, word, deadline),
+        forms if comp.lookup().is_some() => {
+            let pairs = forms
+                .iter()
+                .filter_map(|form| search_dictionary(dic, comp, form, deadline))
+                .collect();
+            merge_pairs(pairs).pop()
+        }
+        forms => {
+            let inner = comp;
+            search_dictionary(dic, &VariantsMatcher { inner, forms }, &forms[0], deadline)
+        }
+    }
+}
+
+fn search_dictionary<'a, M: ?Sized + WordMatcher + Sync>(
+    dic: &'a Dictionary,
+    comp: &M,
+    word: &str,
+    deadline: Option<Instant>,
+) -> Option<IndexDictPair<'a>> {
+    let start_time = Instant::now();
+    let filter = &dic.headword_filter;
+    let (indices, truncated) = if filter.is_empty() {
+        dic.search_until(
+            |w1, w2| comp.compare(w1, w2),
+            comp.lookup(),
+            comp.bounds(word),
+            word,
+            deadline,
+        )
+    } else {
+        dic.search_until(
+            |w1, w2| filter.accepts(w2) && comp.compare(w1, w2),
+            comp.lookup(),
+            filter.narrow(comp.bounds(word)),
+            word,
+            deadline,
+        )
+    };
+    TimeLog::write(&TIMELOG_FILE, || {
+        TimeLog::new()
+            .clock(start_time.elapsed())
+            .dictionary(&dic.bookname)
+            .word(&word)
+            .operation(Operation::Search)
+            .matcher(&comp.name())
+    });
+
+    if truncated {
+        warn!("Search for {} in {} timed out.", word, &dic.bookname);
+    }
+    debug!(
+        "Searched {} with {} in {:?}.",
+        word,
+        comp.name(),
+        start_time.elapsed()
+    );
+    if indices.is_some() || truncated {
+        let index = indices.unwrap_or_default();
+        let distances = index
+            .iter()
+            .map(|ind| comp.distance(word, &ind.word))
+            .collect();
+        Some(IndexDictPair {
+            index,
+            dict: dic,
+            truncated,
+            distances,
+            group: None,
+        })
+    } else {
+        debug!("Found no result in {}", &dic.bookname);
+        None
+    }
+}
+
+/// Searches the headwords of `dicts` that are anagrams of `word`, see
+/// `Dictionary::search_anagrams`.
+pub fn search_anagrams_in_dicts<'a>(
+    dicts: &[&'a Dictionary],
+    word: &str,
+) -> Vec<IndexDictPair<'a>> {
+    search_each_dict(dicts, word, "Anagram", |dic| dic.search_anagrams(word))
+}
+
+/// Searches the headwords of `dicts` matching the crossword `pattern`, see
+/// `Dictionary::search_pattern`.
+pub fn search_pattern_in_dicts<'a>(
+    dicts: &[&'a Dictionary],
+    pattern: &str,
+) -> Vec<IndexDictPair<'a>> {
+    search_each_dict(dicts, pattern, "Crossword", |dic| {
+        dic.search_pattern(pattern)
+    })
+}
+
+/// Searches the headwords of `dicts` whose definitions contain `phrase`, see
+/// `Dictionary::fulltext_search`.
+pub fn search_fulltext_in_dicts<'a>(
+    dicts: &[&'a Dictionary],
+    phrase: &str,
+) -> Vec<IndexDictPair<'a>> {
+    search_each_dict(dicts, phrase, "Fulltext", |dic| dic.fulltext_search(phrase))
+}
+
+/// Searches the headwords of `dicts` whose definitions have keywords at most `edits`
+/// edits away from the keywords of `query`, see `Dictionary::search_keywords`. The
+/// closest come first.
+pub fn search_keywords_in_dicts<'a>(
+    dicts: &[&'a Dictionary],
+    query: &str,
+    edits: usize,
+) -> Vec<IndexDictPair<'a>> {
+    let dicts = load_indices(dicts);
+    let mut pairs = Vec::new();
     for dic in dicts {
         let start_time = Instant::now();
-        let indices = dic.fuzzy_search_indices(|w1, w2| comp.compare(w1, w2), word);
+        let found = dic.search_keywords(query, edits);
         TimeLog::write(&TIMELOG_FILE, || {
             TimeLog::new()
                 .clock(start_time.elapsed())
                 .dictionary(&dic.bookname)
-                .word(&word)
+                .word(&query)
                 .operation(Operation::Search)
-                .matcher(&comp.name())
+                .matcher(&"Keyword")
         });
+        if let Some(mut found) = found {
+            //Stable, entries as close stay in index order.
+            found.sort_by_key(|(_, distance)| *distance);
+            pairs.push(IndexDictPair {
+                distances: found.iter().map(|(_, d)| Some(*d)).collect(),
+                index: found.into_iter().map(|(index, _)| index).collect(),
+                dict: dic,
+                truncated: false,
+                group: None,
+            });
+        }
+    }
+    pairs
+}
 
-        if let Some(indices) = indices {
-            indices_to_list.push(IndexDictPair {
-                index: indices,
-                dict: &dic,
+/// Pairs of the dictionaries `search` finds something for `word` in, `name` is logged
+/// as the matcher.
+fn search_each_dict<'a, F>(
+    dicts: &[&'a Dictionary],
+    word: &str,
+    name: &str,
+    search: F,
+) -> Vec<IndexDictPair<'a>>
+where
+    F: Fn(&'a Dictionary) -> Option<Vec<&'a Index>>,
+{
+    let dicts = load_indices(dicts);
+    let mut pairs = Vec::new();
+    for dic in dicts {
+        let start_time = Instant::now();
+        let found = search(dic);
+        TimeLog::write(&TIMELOG_FILE, || {
+            TimeLog::new()
+                .clock(start_time.elapsed())
+                .dictionary(&dic.bookname)
+                .word(&word)
+                .operation(Operation::Search)
+                .matcher(&name)
+        });
+        if let Some(index) = found {
+            pairs.push(IndexDictPair {
+                distances: vec![None; index.len()],
+                index,
+                dict: dic,
+                truncated: false,
+                group: None,
             });
-        } else {
-            debug!("Found no result in {}", &dic.bookname);
         }
+    }
+    rank_pairs(&mut pairs, word, &ExactMatcher {});
+    pairs
+}
 
-        debug!(
-            "Searched {} with {} in {:?}.",
-            word,
-            comp.name(),
-            start_time.elapsed()
-        );
+/// Keeps only the indices whose definitions have one of `types`, see
+/// `Definition::has_type`, and drops the pairs left without any. Does nothing if
+/// `types` is empty.
+pub fn retain_types(pairs: &mut Vec<IndexDictPair>, types: &[String]) {
+    if types.is_empty() {
+        return;
     }
-    indices_to_list
+    for pair in pairs.iter_mut() {
+        let keep: Vec<bool> = (0..pair.index.len())
+            .map(|n| pair.read_definition(n).is_ok_and(|d| d.has_type(types)))
+            .collect();
+        let mut keep_index = keep.iter();
+        pair.index.retain(|_| *keep_index.next().unwrap());
+        let mut keep_distance = keep.iter();
+        pair.distances.retain(|_| *keep_distance.next().unwrap());
+    }
+    pairs.retain(|pair| !pair.index.is_empty() || pair.truncated);
+}
+
+/// Combines pairs of the same dictionary, from searching several spellings or roots
+/// of a word, into one. An index found more than once keeps its smallest distance.
+pub fn merge_pairs(pairs: Vec<IndexDictPair>) -> Vec<IndexDictPair> {
+    let mut merged: Vec<IndexDictPair> = Vec::new();
+    //Positions in `merged` and in its `index` by address, entries of a dictionary are
+    //only found at one place in memory.
+    let mut dictionaries: HashMap<*const Dictionary, usize> = HashMap::new();
+    let mut entries: HashMap<(usize, *const Index), usize> = HashMap::new();
+    for pair in pairs {
+        let n = *dictionaries
+            .entry(pair.dict as *const Dictionary)
+            .or_insert(merged.len());
+        if n == merged.len() {
+            for (position, index) in pair.index.iter().enumerate() {
+                entries
+                    .entry((n, *index as *const Index))
+                    .or_insert(position);
+            }
+            merged.push(pair);
+            continue;
+        }
+        let target = &mut merged[n];
+        target.truncated |= pair.truncated;
+        for (index, distance) in pair.index.into_iter().zip(pair.distances) {
+            match entries.get(&(n, index as *const Index)) {
+                Some(&position) => {
+                    target.distances[position] = match (target.distances[position], distance) {
+                        (Some(a), Some(b)) => Some(a.min(b)),
+                        (a, b) => a.or(b),
+                    }
+                }
+                None => {
+                    entries.insert((n, index as *const Index), target.index.len());
+                    target.index.push(index);
+                    target.distances.push(distance);
+                }
+            }
+        }
+    }
+    merged
 }
-pub fn indices_to_json(pairs: &Vec<IndexDictPair>) -> String {
-    let mut output: HashMap<String, Vec<Definition>> = HashMap::new();
+
+/// Orders the results of each pair deterministically: the headword equal to `word`,
+/// then equal ignoring case, then the ones starting with it, each of them by the score
+/// of the dictionary's matcher, `comp` if it has none of its own, then by edit
+/// distance, length and alphabetically. Distances matchers didn't give are computed
+/// for it.
+pub fn rank_pairs<M: ?Sized + WordMatcher>(pairs: &mut [IndexDictPair], word: &str, comp: &M) {
+    let lowercase = word.to_lowercase();
+    for pair in pairs {
+        let preferred = preferred_matcher(pair.dict);
+        let score = |headword: &str| match &preferred {
+            Some(own) => own.score(word, headword),
+            None => comp.score(word, headword),
+        };
+        let mut ranked: Vec<(&Index, Option<usize>)> =
+            pair.index.drain(..).zip(pair.distances.drain(..)).collect();
+        ranked.sort_by_cached_key(|(index, distance)| {
+            let (class, distance, length, lower, headword) =
+                relevance(word, &lowercase, &index.word, *distance);
+            //Scores are at least 0, their bits are ordered like them.
+            let score = score(&index.word).map(|n| n.max(0.0).to_bits());
+            (class, Reverse(score), distance, length, lower, headword)
+        });
+        for (index, distance) in ranked {
+            pair.index.push(index);
+            pair.distances.push(distance);
+        }
+    }
+}
+
+/// Searches every form of `word` the group's normalization `pipeline` produces, see
+/// `Pipeline::query_forms`, and merges the results.
+pub fn search_variants_in_dicts<'a, M: ?Sized + WordMatcher + Sync>(
+    dicts: &[&'a Dictionary],
+    comp: &M,
+    word: &str,
+    pipeline: &Pipeline,
+    deadline: Option<Instant>,
+) -> Vec<IndexDictPair<'a>> {
+    let forms = pipeline.query_forms(word);
+    let pipeline = Some(pipeline).filter(|p| p.changes_headwords());
+    let mut pairs =
+        search_normalized_in_dicts(&mut dicts.iter().copied(), comp, &forms, pipeline, deadline);
+    rank_pairs(&mut pairs, word, comp);
+    pairs
+}
+
+/// Searches every possible root `morpher` gives for `word` and for its `expansions` like
+/// `search_variants_in_dicts`, merges the results of each dictionary and orders them by
+/// how well they answer `word` itself.
+pub fn search_roots_in_dicts<'a, M: ?Sized + WordMatcher + Sync>(
+    dicts: &[&'a Dictionary],
+    comp: &M,
+    morpher: &dyn Morpher,
+    word: &str,
+    expansions: &[String],
+    pipeline: &Pipeline,
+    deadline: Option<Instant>,
+) -> Vec<IndexDictPair<'a>> {
+    let mut roots = morpher.possible_roots(word);
+    for expansion in expansions {
+        roots.extend(morpher.possible_roots(expansion));
+    }
+    let mut seen = HashSet::new();
+    roots.retain(|root| seen.insert(root.clone()));
+    let pairs = roots
+        .iter()
+        .flat_map(|root| search_variants_in_dicts(dicts, comp, root, pipeline, deadline))
+        .collect();
+    let mut pairs = merge_pairs(pairs);
+    rank_pairs(&mut pairs, word, comp);
+    pairs
+}
+
+/// `normalization` of the searched group, or `group_normalization` of each group when
+/// several were searched, and the `expansions` of the query are recorded in the output
+/// so the search can be reproduced. Fails if a definition can't be read.
+pub fn indices_to_json(
+    pairs: &[IndexDictPair],
+    normalization: &[Step],
+    group_normalization: &BTreeMap<String, Vec<Step>>,
+    expansions: &[String],
+) -> Result<String, std::io::Error> {
+    let mut output = JsonOutput {
+        results: HashMap::new(),
+        truncated: Vec::new(),
+        normalization: normalization.to_vec(),
+        group_normalization: group_normalization.clone(),
+        expansions,
+    };
     for pair in pairs {
         let mut words = Vec::new();
-        for index in &pair.index {
-            words.push(pair.dict.read_definition(index).unwrap());
+        for n in 0..pair.index.len() {
+            words.push(pair.read_definition(n)?);
+        }
+        output.results.insert(pair.label(), words);
+        if pair.truncated {
+            output.truncated.push(pair.label());
         }
-        output.insert(pair.dict.bookname.clone(), words);
     }
-    serde_json::to_string_pretty(&output).unwrap()
+    Ok(serde_json::to_string_pretty(&output).unwrap())
 }
 
-pub fn load_dicts_from_paths_and_subpaths(paths: &Vec<PathBuf>) -> Vec<Dictionary> {
-    let mut dicts: Vec<Dictionary> = Vec::new();
+/// Fields `--fields` can pick for each result, `score` is the edit distance to the query.
+pub const OUTPUT_FIELDS: &[&str] = &["word", "dictionary", "definition", "type", "score"];
+
+/// Results as a flat JSON array with only `fields`, from `OUTPUT_FIELDS`, of each.
+/// Definitions are only read when they or their type are asked for.
+pub fn indices_to_json_fields(pairs: &[IndexDictPair], fields: &[String]) -> String {
+    let wants = |field: &str| fields.iter().any(|f| f == field);
+    let mut records = Vec::new();
+    for pair in pairs {
+        for n in 0..pair.index.len() {
+            let definition = if wants("definition") || wants("type") {
+                pair.read_definition(n).ok()
+            } else {
+                None
+            };
+            let mut record = serde_json::Map::new();
+            for field in OUTPUT_FIELDS.iter().filter(|f| wants(f)) {
+                let value = match *field {
+                    "word" => serde_json::json!(pair.index[n].word.as_str()),
+                    "dictionary" => serde_json::json!(pair.label()),
+                    "definition" => serde_json::json!(definition.as_ref().map(|d| &d.definition)),
+                    "type" => serde_json::json!(definition.as_ref().map(|d| d.type_name())),
+                    _ => serde_json::json!(pair.distances.get(n).copied().flatten()),
+                };
+                record.insert(field.to_string(), value);
+            }
+            records.push(serde_json::Value::Object(record));
+        }
+    }
+    serde_json::to_string_pretty(&records).unwrap()
+}
+
+/// Dictionaries read at the same time by default, see `set_io_parallelism`.
+pub const DEFAULT_IO_PARALLELISM: usize = 4;
+
+static IO_PARALLELISM: AtomicUsize = AtomicUsize::new(DEFAULT_IO_PARALLELISM);
+
+/// Sets the upper bound of threads reading .ifo files, parsing .idx files and writing
+/// their caches at the same time, 0 uses every thread of the global pool.
+pub fn set_io_parallelism(threads: usize) {
+    IO_PARALLELISM.store(threads, Ordering::Relaxed);
+}
+
+/// Pool dictionaries are read on, built for each load since they're rare.
+fn io_pool() -> ThreadPool {
+    let threads = match IO_PARALLELISM.load(Ordering::Relaxed) {
+        0 => rayon::current_num_threads(),
+        n => n,
+    };
+    ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .unwrap()
+}
+
+/// Loads the metadata of dictionaries in `paths` and their subdirectories down to
+/// `depth` levels, or of the .ifo files given directly. Symbolic links are followed,
+/// a directory reached a second time is skipped so link cycles end.
+pub fn load_dicts_from_paths_and_subpaths(paths: &[PathBuf], depth: usize) -> Vec<Dictionary> {
+    let mut candidates: Vec<PathBuf> = Vec::new();
+    let mut visited: HashSet<PathBuf> = HashSet::new();
     for path in paths {
         debug!("Trying to load from {:?} ", &path);
         if path.as_path().is_dir() {
-            //Try to load sub-directories.
-            let sub_paths: Vec<PathBuf> = fs::read_dir(&path)
-                .unwrap()
+            collect_dirs(path, depth, &mut visited, &mut candidates);
+        //TODO If .gz or some sort of default_compressed dictionary file.
+        } else if path.extension().is_some_and(|e| e == "ifo") {
+            candidates.push(path.clone());
+        } else {
+            warn!(
+                "{} is neither a directory nor an .ifo file.",
+                path.display()
+            );
+        }
+    }
+
+    let dicts: Vec<Dictionary> = io_pool().install(|| {
+        candidates
+            .par_iter()
+            .filter_map(|x| Dictionary::load_metadata(x).ok())
+            .collect()
+    });
+    //An .ifo file given directly may also be in one of the directories, or a copy of a
+    //dictionary in another one.
+    let mut loaded: HashSet<PathBuf> = HashSet::new();
+    let mut fingerprints: HashSet<u32> = HashSet::new();
+    dicts
+        .into_iter()
+        .filter(|d| {
+            if !loaded.insert(Registry::id(d)) {
+                return false;
+            }
+            let copy = d.fingerprint().is_ok_and(|n| !fingerprints.insert(n));
+            if copy {
+                info!(
+                    "Skipping {}, a copy of an already loaded dictionary.",
+                    d.ifo_path.display()
+                );
+            }
+            !copy
+        })
+        .collect()
+}
+
+/// Pushes subdirectories of `dir` down to `depth` levels then `dir` itself to
+/// `candidates`, unless they are in `visited`.
+fn collect_dirs(
+    dir: &Path,
+    depth: usize,
+    visited: &mut HashSet<PathBuf>,
+    candidates: &mut Vec<PathBuf>,
+) {
+    match fs::canonicalize(dir) {
+        Ok(n) => {
+            if !visited.insert(n) {
+                debug!("Already visited {:?}, skipping.", dir);
+                return;
+            }
+        }
+        Err(e) => {
+            warn!("Can't resolve {}: {}", dir.display(), e);
+            return;
+        }
+    }
+    if depth > 0 {
+        if let Ok(entries) = fs::read_dir(dir) {
+            let mut subdirs: Vec<PathBuf> = entries
                 .filter_map(|x| x.ok())
                 .map(|x| x.path())
                 .filter(|x| x.is_dir())
                 .collect();
+            subdirs.sort();
+            for subdir in subdirs {
+                collect_dirs(&subdir, depth - 1, visited, candidates);
+            }
+        }
+    }
+    candidates.push(dir.to_path_buf());
+}
 
-            dicts.append(
-                &mut sub_paths
-                    .par_iter()
-                    .filter_map(|x| Dictionary::load_dictionary(x).ok())
-                    .collect(),
-            );
+/// Reads what searches of dictionaries that haven't been searched yet need first, at
+/// most `set_io_parallelism` of them at the same time since building a missing cache
+/// means parsing the whole .idx file. That's the shard table of a sharded cache, whose
+/// shards are read when a search reaches them, and the whole index otherwise, see
+/// `Dictionary::try_shards`. Returns the dictionaries that can be searched, the others
+/// are skipped with a warning.
+pub fn load_indices<'a>(dicts: &[&'a Dictionary]) -> Vec<&'a Dictionary> {
+    let ready = |d: &Dictionary| d.is_index_loaded() || d.is_shard_table_loaded();
+    let pending: Vec<&&Dictionary> = dicts.iter().filter(|d| !ready(d)).collect();
+    if !pending.is_empty() {
+        let start_time = Instant::now();
+        io_pool().install(|| {
+            pending.par_iter().for_each(|d| {
+                if let Err(e) = d.try_shards() {
+                    warn!("Skipping {}, its index can't be read: {}", d.label(), e);
+                }
+            })
+        });
+        TimeLog::write(&TIMELOG_FILE, || {
+            TimeLog::new()
+                .clock(start_time.elapsed())
+                .operation(Operation::LoadDictionary)
+                .comment(&format!("Loaded indices of {} dictionaries", pending.len()))
+        });
+    }
+    dicts.iter().copied().filter(|d| ready(d)).collect()
+}
 
-            //Try to load this directory itself.
-            if let Ok(n) = Dictionary::load_dictionary(&path) {
-                dicts.push(n)
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use tempfile::tempdir;
 
-        //TODO If .gz or some sort of default_compressed dictionary file.
-        } else {
-            // dicts.push(Dictionary::load_dictionary(path).unwrap());
+    /// Writes a dictionary named `name` with `sametypesequence=m` under `dir/name/`.
+    pub(crate) fn write_dictionary(dir: &Path, name: &str, words: &[(&str, &str)]) -> PathBuf {
+        let dict_dir = dir.join(name);
+        fs::create_dir_all(&dict_dir).unwrap();
+        let mut idx: Vec<u8> = Vec::new();
+        let mut dict: Vec<u8> = Vec::new();
+        for (word, definition) in words {
+            idx.extend_from_slice(word.as_bytes());
+            idx.push(0);
+            idx.extend_from_slice(&(dict.len() as u32).to_be_bytes());
+            idx.extend_from_slice(&(definition.len() as u32).to_be_bytes());
+            dict.extend_from_slice(definition.as_bytes());
         }
+        let ifo = format!(
+            "StarDict's dict ifo file\nversion=2.4.2\nbookname={}\nwordcount={}\nsametypesequence=m\n",
+            name,
+            words.len()
+        );
+        fs::write(dict_dir.join(format!("{}.ifo", name)), ifo).unwrap();
+        fs::write(dict_dir.join(format!("{}.idx", name)), idx).unwrap();
+        fs::write(dict_dir.join(format!("{}.dict", name)), dict).unwrap();
+        dict_dir
+    }
+
+    #[test]
+    fn should_output_only_requested_fields() {
+        let dir = tempdir().unwrap();
+        let path = write_dictionary(dir.path(), "tr", &[("elma", "apple"), ("elmas", "diamond")]);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        let comp = build_matcher("levenshtein", 1);
+        let pairs = search_in_dicts(
+            &mut std::iter::once(&dictionary),
+            comp.as_ref(),
+            "elma",
+            None,
+        );
+        let fields = vec![String::from("score"), String::from("word")];
+        let output: serde_json::Value =
+            serde_json::from_str(&indices_to_json_fields(&pairs, &fields)).unwrap();
+        assert_eq!(
+            output,
+            serde_json::json!([{"word": "elma", "score": 0}, {"word": "elmas", "score": 1}])
+        );
+    }
+
+    #[test]
+    fn should_keep_results_apart_from_details_of_search() {
+        let dir = tempdir().unwrap();
+        let path = write_dictionary(dir.path(), "truncated", &[("elma", "apple")]);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        let mut pairs = search_in_dicts(
+            &mut std::iter::once(&dictionary),
+            &ExactMatcher {},
+            "elma",
+            None,
+        );
+        pairs[0].truncated = true;
+        let output = indices_to_json(&pairs, &[], &BTreeMap::new(), &[]).unwrap();
+        let output: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(output["results"]["truncated"][0]["word"], "elma");
+        assert_eq!(output["truncated"], serde_json::json!(["truncated"]));
+    }
+
+    #[test]
+    fn should_skip_dictionaries_with_unreadable_index() {
+        let dir = tempdir().unwrap();
+        let path = write_dictionary(dir.path(), "tr", &[("elma", "apple")]);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        let idx = fs::read(&dictionary.idx_path).unwrap();
+        fs::remove_file(&dictionary.idx_path).unwrap();
+        let search = || {
+            search_in_dicts(
+                &mut std::iter::once(&dictionary),
+                &ExactMatcher {},
+                "elma",
+                None,
+            )
+        };
+        assert!(search().is_empty());
+        assert!(!dictionary.is_index_loaded());
+        //Read again once it can be.
+        fs::write(&dictionary.idx_path, idx).unwrap();
+        assert_eq!(search()[0].index[0].word, "elma");
+    }
+
+    #[test]
+    fn should_search_with_preferred_matcher_of_dictionary() {
+        let dir = tempdir().unwrap();
+        let words = [("elma", "apple"), ("elmas", "diamond")];
+        let exact = write_dictionary(dir.path(), "exact", &words);
+        let ifo = exact.join("exact.ifo");
+        let mut fields = fs::read_to_string(&ifo).unwrap();
+        fields.push_str("preferredalgorithm=exact\n");
+        fs::write(&ifo, fields).unwrap();
+        write_dictionary(dir.path(), "fuzzy", &words);
+        let dicts = load_dicts_from_paths_and_subpaths(&[dir.path().to_path_buf()], 1);
+        let comp = build_matcher("levenshtein", 1);
+        let pairs = search_in_dicts(&mut dicts.iter(), comp.as_ref(), "elma", None);
+        let found = |bookname: &str| {
+            let pair = pairs.iter().find(|p| p.dict.bookname == bookname).unwrap();
+            pair.index.len()
+        };
+        assert_eq!(found("exact"), 1);
+        assert_eq!(found("fuzzy"), 2);
+    }
+
+    #[test]
+    fn should_search_transliterated_spellings() {
+        let dir = tempdir().unwrap();
+        let path = write_dictionary(dir.path(), "tr", &[("çiçek", "flower"), ("cicek", "typo")]);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        let comp = build_matcher("levenshtein", 2);
+        let pipeline = Pipeline::new(
+            vec![Step::Transliterate],
+            transliteration::builtin_table("tr").unwrap(),
+        );
+        let pairs =
+            search_variants_in_dicts(&[&dictionary], comp.as_ref(), "cicek", &pipeline, None);
+        assert_eq!(pairs.len(), 1);
+        let mut words: Vec<&str> = pairs[0].index.iter().map(|i| i.word.as_str()).collect();
+        words.sort();
+        assert_eq!(words, vec!["cicek", "çiçek"]);
+        //Found as "cicek" at distance 2 and "çiçek" at distance 0.
+        let flower = pairs[0]
+            .index
+            .iter()
+            .position(|i| i.word == "çiçek")
+            .unwrap();
+        assert_eq!(pairs[0].distances[flower], Some(0));
+    }
+
+    #[test]
+    fn should_compare_every_spelling_in_one_pass() {
+        let dir = tempdir().unwrap();
+        let words = [("çiçekçi", "florist"), ("cicekci", "typo"), ("kedi", "cat")];
+        let path = write_dictionary(dir.path(), "tr", &words);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        let comp = build_matcher("contains", 0);
+        assert_eq!(comp.lookup(), None);
+        let pipeline = Pipeline::new(
+            vec![Step::Transliterate],
+            transliteration::builtin_table("tr").unwrap(),
+        );
+        let pairs =
+            search_variants_in_dicts(&[&dictionary], comp.as_ref(), "cicek", &pipeline, None);
+        assert_eq!(pairs.len(), 1);
+        let mut words: Vec<&str> = pairs[0].index.iter().map(|i| i.word.as_str()).collect();
+        words.sort();
+        assert_eq!(words, vec!["cicekci", "çiçekçi"]);
+    }
+
+    #[test]
+    fn should_search_roots_and_expansions() {
+        let dir = tempdir().unwrap();
+        let words = [("apple", "elma"), ("pear", "armut"), ("quince", "ayva")];
+        let path = write_dictionary(dir.path(), "en", &words);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        let comp = build_matcher("exact", 0);
+        let pairs = search_roots_in_dicts(
+            &[&dictionary],
+            comp.as_ref(),
+            &EnglishMorpher {},
+            "apples",
+            &[String::from("pears")],
+            &Pipeline::default(),
+            None,
+        );
+        assert_eq!(pairs.len(), 1);
+        let words: Vec<&str> = pairs[0].index.iter().map(|i| i.word.as_str()).collect();
+        assert_eq!(words, vec!["apple", "pear"]);
+    }
+
+    #[test]
+    fn should_merge_pairs_of_same_dictionary() {
+        let dir = tempdir().unwrap();
+        let path = write_dictionary(dir.path(), "tr", &[("elma", "apple"), ("elmas", "x")]);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        let indices = dictionary.indices();
+        let pair = |index: Vec<&'static str>, distance| IndexDictPair {
+            index: index
+                .iter()
+                .map(|w| indices.iter().find(|i| i.word == *w).unwrap())
+                .collect(),
+            dict: &dictionary,
+            truncated: false,
+            distances: vec![Some(distance); index.len()],
+            group: None,
+        };
+        let merged = merge_pairs(vec![pair(vec!["elmas"], 2), pair(vec!["elma", "elmas"], 1)]);
+        assert_eq!(merged.len(), 1);
+        let words: Vec<&str> = merged[0].index.iter().map(|i| i.word.as_str()).collect();
+        assert_eq!(words, vec!["elmas", "elma"]);
+        assert_eq!(merged[0].distances, vec![Some(1), Some(1)]);
+    }
+
+    #[test]
+    fn should_read_indices_only_of_searched_dictionaries() {
+        let dir = tempdir().unwrap();
+        write_dictionary(dir.path(), "first", &[("elma", "apple")]);
+        write_dictionary(
+            dir.path(),
+            "second",
+            &[("armut", "pear"), ("ayva", "quince")],
+        );
+        let dicts = load_dicts_from_paths_and_subpaths(&[dir.path().to_path_buf()], 1);
+        assert_eq!(dicts.len(), 2);
+        assert!(dicts.iter().all(|d| !d.is_index_loaded()));
+
+        let second = dicts.iter().find(|d| d.bookname == "second").unwrap();
+        let found = search_in_dicts(&mut std::iter::once(second), &ExactMatcher {}, "ayva", None);
+        assert_eq!(found.len(), 1);
+        assert!(second.cache_path.exists());
+        let first = dicts.iter().find(|d| d.bookname == "first").unwrap();
+        assert!(!first.is_index_loaded());
+        assert!(!first.cache_path.exists());
+
+        let reloaded = load_dicts_from_paths_and_subpaths(&[dir.path().to_path_buf()], 1);
+        let second = reloaded.iter().find(|d| d.bookname == "second").unwrap();
+        assert_eq!(second.indices().len(), 2);
+    }
+
+    #[test]
+    fn should_search_sharded_caches_without_reading_whole_index() {
+        let dir = tempdir().unwrap();
+        let path = write_dictionary(
+            dir.path(),
+            "tr",
+            &[
+                ("armut", "pear"),
+                ("elma", "apple"),
+                ("kitap", "book"),
+                ("kız", "girl"),
+            ],
+        );
+        let mut first = Dictionary::load_metadata(&path).unwrap();
+        first.sharded_cache_entries = 4;
+        first.indices();
+
+        let sharded = Dictionary::load_metadata(&path).unwrap();
+        assert_eq!(load_indices(&[&sharded]).len(), 1);
+        assert!(sharded.is_shard_table_loaded());
+        assert!(!sharded.is_index_loaded());
+        let found = search_in_dicts(
+            &mut std::iter::once(&sharded),
+            &ExactMatcher {},
+            "kız",
+            None,
+        );
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].index[0].word, "kız");
+        assert!(!sharded.is_index_loaded());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn should_discover_nested_linked_and_given_dictionaries() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("root");
+        write_dictionary(&root, "shallow", &[("elma", "apple")]);
+        write_dictionary(&root.join("a/b"), "deep", &[("armut", "pear")]);
+        let outside = write_dictionary(dir.path(), "linked", &[("ayva", "quince")]);
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+        std::os::unix::fs::symlink(&root, root.join("a/cycle")).unwrap();
+        let given = write_dictionary(dir.path(), "given", &[("kiraz", "cherry")]);
+
+        let names = |paths: &[PathBuf], depth| {
+            let mut names: Vec<String> = load_dicts_from_paths_and_subpaths(paths, depth)
+                .into_iter()
+                .map(|d| d.bookname)
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            names(std::slice::from_ref(&root), 1),
+            vec!["linked", "shallow"]
+        );
+        assert_eq!(
+            names(std::slice::from_ref(&root), 10),
+            vec!["deep", "linked", "shallow"]
+        );
+        assert_eq!(
+            names(
+                &[
+                    root.clone(),
+                    given.join("given.ifo"),
+                    root.join("link/linked.ifo")
+                ],
+                1
+            ),
+            vec!["given", "linked", "shallow"]
+        );
+    }
+
+    #[test]
+    fn should_rank_exact_then_prefix_then_close_results() {
+        let dir = tempdir().unwrap();
+        let words = ["Elma", "almanak", "elmacık", "elma", "elmas", "alma", "elm"];
+        let entries: Vec<(&str, &str)> = words.iter().map(|w| (*w, *w)).collect();
+        let path = write_dictionary(dir.path(), "tr", &entries);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        let mut pairs = vec![IndexDictPair {
+            index: dictionary.indices().iter().collect(),
+            dict: &dictionary,
+            truncated: false,
+            distances: vec![None; words.len()],
+            group: None,
+        }];
+        rank_pairs(&mut pairs, "elma", &LevenshteinMatcher { level: 2 });
+        let ranked: Vec<&str> = pairs[0].index.iter().map(|i| i.word.as_str()).collect();
+        assert_eq!(
+            ranked,
+            vec!["elma", "Elma", "elmas", "elmacık", "elm", "alma", "almanak"]
+        );
+        //Distances computed for ranking aren't reported as the matcher's.
+        assert!(pairs[0].distances.iter().all(Option::is_none));
     }
-    dicts
 }
@@ -1,45 +1,123 @@
-use ctrlc::set_handler;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+use rayon::ThreadPoolBuilder;
+use regex::Regex;
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::Editor;
+use serde::Serialize;
 use simplelog::{Config, LevelFilter, TermLogger, TerminalMode};
-use sozluk::colored_print::{print_green, print_yellow};
-use sozluk::dictionary::{Definition, Dictionary, Index};
-use sozluk::load_dicts_from_paths_and_subpaths;
-use sozluk::morpher::{EnglishMorpher, Morpher, NoMorpher, TurkishMorpher};
+use sozluk::browser::open_definition;
+use sozluk::clipboard;
+use sozluk::colored_print::{output_width, Printer, Terminal};
+use sozluk::dictionary::{CacheStatus, Definition, Dictionary, DictionaryInfo};
+use sozluk::doctor::{self, Report};
+use sozluk::filter::retain_matching;
+use sozluk::history::{Suggester, WordStore, HISTORY_SIZE};
+use sozluk::i18n::{self, Message};
+use sozluk::matcher::WordMatcher;
+use sozluk::morpher::Morpher;
+use sozluk::normalization::{clean_query, Pipeline};
 use sozluk::performance_log::{Operation, TimeLog, TIMELOG_FILE};
-use sozluk::server::serve_http;
-use sozluk::settings::Opt;
-use sozluk::{build_matcher, indices_to_json, search_in_dicts, IndexDictPair};
+use sozluk::query_cache::QueryCache;
+use sozluk::registry::Registry;
+use sozluk::resource_cache::{self, ResourceCache};
+use sozluk::server::{serve_http, serve_http_sharing};
+use sozluk::session_log::SessionLog;
+use sozluk::settings::{CacheCommand, Command, ConfigCommand, InfoCommand, Opt};
+use sozluk::setup;
+use sozluk::stats::IndexStats;
+use sozluk::stdio::serve_stdio;
+use sozluk::terminal_image;
+use sozluk::wordlist;
+use sozluk::{
+    build_matcher, build_morpher, indices_to_json, indices_to_json_fields, retain_types,
+    search_anagrams_in_dicts, search_fulltext_in_dicts, search_keywords_in_dicts,
+    search_pattern_in_dicts, search_roots_in_dicts, set_io_parallelism, IndexDictPair,
+};
+use sozluk::{load_dicts_from_paths_and_subpaths, load_indices};
+use std::collections::{BTreeMap, HashSet};
 use std::fs::{self, OpenOptions};
-use std::io::{self};
-use std::path::PathBuf;
+use std::io::{self, IsTerminal, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::thread;
 use std::time::Instant;
+use structopt::clap::{Error, ErrorKind};
 use structopt::StructOpt;
 
 fn main() -> std::io::Result<()> {
+    let matches = Opt::clap().get_matches();
+    let mut opt = Opt::from_clap(&matches);
+    //Standard output only carries responses in --stdio mode.
+    let (debug_mode, mode) = if opt.stdio {
+        (TerminalMode::Stderr, TerminalMode::Stderr)
+    } else {
+        (TerminalMode::Stdout, TerminalMode::Mixed)
+    };
     if cfg!(debug_assertions) {
-        TermLogger::init(LevelFilter::Trace, Config::default(), TerminalMode::Stdout).unwrap();
+        TermLogger::init(LevelFilter::Trace, Config::default(), debug_mode).unwrap();
     }
-    let mut opt = Opt::from_args();
-    opt.apply_settings_file(Opt::clap());
 
-    if !opt.verbose && !cfg!(debug_assertions) {
-        TermLogger::init(LevelFilter::Info, Config::default(), TerminalMode::Mixed).unwrap();
+    if !cfg!(debug_assertions) {
+        let level = if opt.verbose {
+            LevelFilter::Debug
+        } else {
+            LevelFilter::Info
+        };
+        TermLogger::init(level, Config::default(), mode).unwrap();
     }
-    *TIMELOG_FILE.lock().unwrap() = if opt.timelog || cfg!(debug_assertions) {
-        Some(
-            OpenOptions::new()
-                .append(true)
-                .create(true)
-                .open(&opt.timelog_file)
-                .expect("Cannot open log file"),
+    if let Some(Command::Config(ConfigCommand::Check)) = opt.command {
+        std::process::exit(check_settings_file(&opt));
+    }
+    match opt.check_settings_file() {
+        Ok(problems) => {
+            for problem in problems {
+                warn!("{}: {}", opt.settings_path.display(), problem);
+            }
+        }
+        Err(e) => {
+            error!(
+                "Can't read settings file {}: {}",
+                opt.settings_path.display(),
+                e
+            );
+            std::process::exit(1);
+        }
+    }
+    opt.apply_settings_file(&matches);
+    if let Some(profile) = opt.profile.as_deref().filter(|n| !opt.has_profile(n)) {
+        Error::with_description(
+            &format!(
+                "No profile named {} in {}.",
+                profile,
+                opt.settings_path.display()
+            ),
+            ErrorKind::InvalidValue,
         )
-    } else {
-        None
-    };
+        .exit()
+    }
+    if let Some(path) = opt.timelog_path() {
+        if let Some(parent) = path.parent().filter(|n| !n.as_os_str().is_empty()) {
+            let _ = fs::create_dir_all(parent);
+        }
+        match OpenOptions::new().append(true).create(true).open(&path) {
+            Ok(file) => *TIMELOG_FILE.lock().unwrap() = Some(file),
+            Err(e) => warn!("Can't open the time log {}: {}", path.display(), e),
+        }
+    }
 
     debug!("{:#?}", &opt);
+    if let Some(threads) = opt.threads {
+        if let Err(e) = ThreadPoolBuilder::new().num_threads(threads).build_global() {
+            warn!("Can't use {} threads: {}", threads, e);
+        }
+    }
+    set_io_parallelism(opt.io_parallelism);
+    i18n::set_language(opt.ui_language());
     let running = Arc::new(AtomicBool::new(false));
     let r = running.clone();
 
@@ -48,35 +126,183 @@ fn main() -> std::io::Result<()> {
     })
     .expect("Error setting Ctrl-C handler");
 
-    if opt.list_dictionaries {
-        print_dictionaries(opt.paths.unwrap());
-        panic!()
+    if let Some(Command::Serve) = opt.command {
+        serve_http(&opt, &matches, &running);
+        return Ok(());
+    }
+
+    if let Some(Command::Grep {
+        pattern,
+        with_dictionary,
+    }) = &opt.command
+    {
+        let mut out = io::BufWriter::new(io::stdout().lock());
+        std::process::exit(grep_headwords(&opt, pattern, *with_dictionary, &mut out));
+    }
+
+    if let Some(Command::Random {
+        count,
+        no_spaces,
+        json,
+    }) = &opt.command
+    {
+        print_random_words(&opt, *count, *no_spaces, *json || opt.json_output);
+        return Ok(());
+    }
+
+    if let Some(Command::Wordlist {
+        dict,
+        out,
+        frequencies,
+    }) = &opt.command
+    {
+        std::process::exit(write_wordlist(
+            &opt,
+            dict.as_deref(),
+            out.as_deref(),
+            frequencies.as_deref(),
+        ));
+    }
+
+    if let Some(Command::Index { keywords }) = &opt.command {
+        std::process::exit(build_caches(&opt, *keywords));
+    }
+
+    if let Some(Command::Cache(command)) = &opt.command {
+        std::process::exit(match command {
+            CacheCommand::Build => build_caches(&opt, false),
+            CacheCommand::Clear => clear_caches(&opt),
+            CacheCommand::Status => print_cache_status(&opt),
+        });
+    }
+
+    if let Some(Command::Doctor { path }) = &opt.command {
+        std::process::exit(print_health_report(path));
+    }
+
+    if let Some(Command::Info(InfoCommand { memory: true, .. })) = opt.command {
+        print_memory(&opt);
+        return Ok(());
+    }
+
+    if let Some(Command::Info(InfoCommand { stats: true, .. })) = opt.command {
+        std::process::exit(print_stats(&opt));
+    }
+
+    if opt.list_dictionaries || matches!(opt.command, Some(Command::Info(_) | Command::List)) {
+        std::process::exit(print_dictionaries(&opt));
     };
 
-    let start_time = Instant::now();
-    let mut dicts: Vec<Dictionary> = Vec::new();
-    if let Some(ref key) = &opt.group {
-        if let Some(group) = opt.groups.get(key) {
-            let d = load_dicts_from_paths_and_subpaths(&group.paths);
-            if d.len() >= 1 {
-                dicts = d;
+    let mut word = match opt.take_word() {
+        //Words are read from the requests.
+        _ if opt.stdio => String::new(),
+        Some(n) => match clean_query(&n) {
+            Some(n) => n,
+            None => {
+                Error::with_description("The word to search can't be empty.", ErrorKind::EmptyValue)
+                    .exit()
             }
-        } else {
+        },
+        None => Error::with_description(
+            "A word to search is required when no subcommand is given.",
+            ErrorKind::MissingRequiredArgument,
+        )
+        .exit(),
+    };
+
+    let start_time = Instant::now();
+    let selected = opt.selected_groups();
+    //Options of a group win over the defaults, but not over the command line.
+    let group_matcher = matches.occurrences_of("search-algorithm") == 0
+        && matches.occurrences_of("search-depth") == 0;
+    let group_morpher = matches.occurrences_of("morpher") == 0;
+    let mut searches: Vec<GroupSearch> = Vec::new();
+    //Results of every group are printed together, a dictionary in several of them is
+    //only searched in the first.
+    let mut registry = Registry::default();
+    for (name, group) in &selected {
+        let dicts: Vec<Arc<Dictionary>> =
+            load_dicts_from_paths_and_subpaths(&group.paths, opt.scan_depth)
+                .into_iter()
+                .filter_map(|mut d| {
+                    if registry.contains(&d) {
+                        debug!("{} is already searched in another group.", d.label());
+                        return None;
+                    }
+                    group.configure_dictionary(&mut d);
+                    opt.configure_dictionary(&mut d);
+                    Some(registry.register(d))
+                })
+                .collect();
+        if dicts.is_empty() {
+            warn!("Group {} has no dictionaries.", name);
+            continue;
+        }
+        searches.push(GroupSearch {
+            //Results are only labeled with their group when there is more than one.
+            name: Some(name.to_string()).filter(|_| selected.len() > 1),
+            dicts,
+            matcher: if group_matcher {
+                build_matcher(&group.matcher_type, group.matcher_depth)
+            } else {
+                build_matcher(&opt.search_algorithm, opt.search_depth)
+            },
+            morpher: build_morpher(if group_morpher {
+                &group.morpher
+            } else {
+                &opt.morpher
+            }),
+            pipeline: group.pipeline(),
+            mode: SearchMode::of(&opt),
+        });
+    }
+    if searches.is_empty() {
+        if opt.group.is_some() {
             error!(
                 "No dictionary file (dict.dz) or dictionary directory found in given group paths!"
             );
             info!("Falling back to default paths.");
         }
-    }
-    if dicts.len() == 0 {
         //This unwrap is safe because at this point opt.paths at least have default OS specific paths.
-        let d = load_dicts_from_paths_and_subpaths(&opt.paths.as_ref().unwrap());
-        if d.len() >= 1 {
-            dicts = d
-        } else {
+        let mut dicts: Vec<Dictionary> =
+            load_dicts_from_paths_and_subpaths(opt.paths.as_ref().unwrap(), opt.scan_depth);
+        if dicts.is_empty() && !opt.settings_file().exists() && io::stdin().is_terminal() {
+            let candidates = setup::candidate_directories(opt.scan_depth);
+            if setup::run(io::stdin().lock(), io::stdout(), &opt, &candidates)? {
+                opt.apply_settings_file(&matches);
+                dicts =
+                    load_dicts_from_paths_and_subpaths(opt.paths.as_ref().unwrap(), opt.scan_depth);
+            }
+        }
+        let dicts: Vec<Arc<Dictionary>> = dicts
+            .into_iter()
+            .map(|mut d| {
+                opt.configure_dictionary(&mut d);
+                registry.register(d)
+            })
+            .collect();
+        if dicts.is_empty() {
             error!("No dictionary file (dict.dz) or dictionary directory found in given paths!");
             return Ok(());
         }
+        searches.push(GroupSearch {
+            name: None,
+            dicts,
+            matcher: build_matcher(&opt.search_algorithm, opt.search_depth),
+            morpher: build_morpher(&opt.morpher),
+            pipeline: Pipeline::default(),
+            mode: SearchMode::of(&opt),
+        });
+    }
+
+    if let Some(lang) = &opt.lang {
+        for search in &mut searches {
+            search.dicts.retain(|d| d.has_lang(lang));
+        }
+        if searches.iter().all(|s| s.dicts.is_empty()) {
+            error!("No dictionary in language {} found in given paths!", lang);
+            return Ok(());
+        }
     }
 
     TimeLog::write(&TIMELOG_FILE, || {
@@ -85,148 +311,1285 @@ fn main() -> std::io::Result<()> {
             .operation(Operation::LoadDictionary)
     });
 
-    let morpher: &dyn Morpher = match opt.morpher.as_ref() {
-        "tr" => &TurkishMorpher {},
-        "en" => &EnglishMorpher {},
-        "none" | _ => &NoMorpher {},
+    if opt.stdio {
+        let dicts: Vec<&Dictionary> = searches
+            .iter()
+            .flat_map(|s| s.dicts.iter().map(|d| d.as_ref()))
+            .collect();
+        return serve_stdio(io::stdin().lock(), io::stdout().lock(), &dicts, |word| {
+            let deadline = opt.search_deadline();
+            let mut results: Vec<IndexDictPair> = searches
+                .iter()
+                .flat_map(|search| search.search(word, opt.expansions(word), deadline, None))
+                .collect();
+            retain_types(&mut results, &opt.types);
+            if let Some(filter) = &opt.filter {
+                retain_matching(&mut results, filter);
+            }
+            results
+        });
+    }
+
+    //The server answers requests on its own thread while the terminal searches go on, with
+    //the dictionaries loaded for them.
+    let server = if opt.background {
+        let mut server_opt = Opt::from_clap(&matches);
+        server_opt.apply_settings_file(&matches);
+        let matches = matches.clone();
+        let stop = running.clone();
+        let dictionaries = registry.clone();
+        Some(thread::spawn(move || {
+            serve_http_sharing(&server_opt, &matches, &stop, dictionaries)
+        }))
+    } else {
+        None
     };
 
-    let default_comp = build_matcher(&opt.search_algorithm, opt.search_depth);
+    let (normalization, group_normalization) = match searches.as_slice() {
+        [search] => (search.pipeline.steps.clone(), BTreeMap::new()),
+        searches => (
+            Vec::new(),
+            searches
+                .iter()
+                .filter_map(|s| Some((s.name.clone()?, s.pipeline.steps.clone())))
+                .collect(),
+        ),
+    };
 
-    //TODO Move all this logic to seperate function. Webserver logic should be completely seperate from
-    //other parts of the app.
-    if opt.background {
-        serve_http(&opt);
+    let matchers: Vec<String> = searches.iter().map(|s| s.matcher.name()).collect();
+    //Group and dictionary positions in `searches` picked with `d`, searches are only done in it.
+    let mut only: Option<(usize, usize)> = None;
+    let interactive = !opt.exit && !opt.json_output && !opt.words_only;
+    let mut prompt: Editor<Suggester, DefaultHistory> = Editor::new().unwrap();
+    if interactive {
+        prompt.set_helper(Some(Suggester {
+            history: WordStore::load(&opt.data_file("history"), HISTORY_SIZE),
+            bookmarks: WordStore::load(&opt.data_file("bookmarks"), usize::MAX),
+        }));
     }
+    let mut study = opt
+        .study
+        .then(|| WordStore::load(&opt.data_file("study"), usize::MAX));
+    let mut cache: QueryCache<QueryKey, Vec<IndexDictPair>> = QueryCache::new(RESULT_CACHE_SIZE);
+    let mut printer = Terminal::default();
+    let mut session = match &opt.session_log {
+        Some(path) => match SessionLog::open(path) {
+            Ok(n) => Some(n),
+            Err(e) => {
+                error!("Can't open the session log {}: {}", path.display(), e);
+                None
+            }
+        },
+        None => None,
+    };
+    'search: loop {
+        if let Some(suggester) = prompt.helper_mut() {
+            if let Err(e) = suggester.history.add(&word) {
+                debug!("Can't save the search history: {}", e);
+            }
+        }
+        let expansions = opt.expansions(&word);
+        if !expansions.is_empty() {
+            debug!("Searching {} also as {}.", &word, expansions.join(", "));
+        }
+        let key = (word.clone(), matchers.clone(), only);
+        let uncached;
+        let indices_to_list: &[IndexDictPair] = if cache.contains(&key) {
+            debug!("Showing cached results of {}.", &word);
+            cache.get(&key).unwrap()
+        } else {
+            let deadline = opt.search_deadline();
+            let mut results: Vec<IndexDictPair> = searches
+                .iter()
+                .enumerate()
+                .filter(|(n, _)| only.is_none_or(|(group, _)| group == *n))
+                .flat_map(|(_, search)| {
+                    search.search(&word, expansions, deadline, only.map(|(_, n)| n))
+                })
+                .collect();
+            retain_types(&mut results, &opt.types);
+            if let Some(filter) = &opt.filter {
+                retain_matching(&mut results, filter);
+            }
+            //Searching a timed out word again may find more.
+            if results.iter().any(|pair| pair.truncated) {
+                uncached = results;
+                &uncached
+            } else {
+                cache.insert(key, results)
+            }
+        };
 
-    loop {
-        let possible_roots = morpher.possible_roots(&opt.word);
-        let indices_to_list: Vec<IndexDictPair> = possible_roots
-            .iter()
-            .map(|word| search_in_dicts(&mut dicts.iter(), default_comp.as_ref(), &word))
-            .flatten()
-            .collect();
-
-        if indices_to_list.len() == 0 {
-            if !opt.json_output {
-                println!("Found no result!")
+        if let Some(study) = study.as_mut() {
+            let headwords = indices_to_list
+                .iter()
+                .flat_map(|pair| &pair.index)
+                .map(|index| index.word.as_str());
+            for headword in study_words(&word, headwords) {
+                match study.push_new(headword) {
+                    Ok(true) => debug!("Queued {} to study.", headword),
+                    Ok(false) => (),
+                    Err(e) => error!("Can't save the study queue: {}", e),
+                }
             }
         }
 
-        if opt.json_output {
-            println!("{}", &indices_to_json(&indices_to_list));
+        if opt.words_only {
+            print_words(indices_to_list);
             break;
-        } else if opt.list {
-            listed_interface(&indices_to_list);
+        }
+
+        if indices_to_list.is_empty() && !opt.json_output {
+            printer.plain(&format!("{}\n", Message::NoResult))
+        }
+
+        if opt.json_output && !opt.fields.is_empty() {
+            println!("{}", indices_to_json_fields(indices_to_list, &opt.fields));
+            break;
+        } else if opt.json_output {
+            println!(
+                "{}",
+                indices_to_json(
+                    indices_to_list,
+                    &normalization,
+                    &group_normalization,
+                    expansions
+                )?
+            );
+            break;
+        }
+        if let Some(log) = session.as_mut() {
+            let results = indices_to_list.iter().map(|pair| pair.index.len()).sum();
+            log.query(&word, results);
+        }
+        if opt.list {
+            let preview = Some(PREVIEW_LENGTH).filter(|_| !opt.full);
+            listed_interface(
+                &mut printer,
+                session.as_mut(),
+                indices_to_list,
+                output_width(opt.max_width),
+                &word,
+                preview,
+            );
         } else {
-            print_defs(&indices_to_list.as_slice());
+            print_defs(
+                &mut printer,
+                session.as_mut(),
+                indices_to_list,
+                output_width(opt.max_width),
+                &word,
+                &opt,
+            );
         }
 
         if opt.exit {
             break;
-        } else {
+        }
+        loop {
             if running.load(Ordering::SeqCst) {
-                break;
+                break 'search;
             }
-            print_yellow("Enter a word to search or z to exit.");
-            let mut buffer = String::new();
-            io::stdin().read_line(&mut buffer)?;
+            printer.yellow(&Message::SearchPrompt.to_string());
+            let buffer = match prompt.readline("") {
+                Ok(n) => n,
+                Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break 'search,
+                Err(e) => return Err(io::Error::other(e)),
+            };
             if running.load(Ordering::SeqCst) {
-                break;
+                break 'search;
             }
             if buffer.trim().eq_ignore_ascii_case("z") {
+                break 'search;
+            } else if buffer.trim().eq_ignore_ascii_case("d") {
+                only = choose_dictionary(&mut printer, &searches)?;
+            } else if buffer.trim().eq_ignore_ascii_case("m") {
+                print_whole_definitions(
+                    &mut printer,
+                    indices_to_list,
+                    output_width(opt.max_width),
+                    &word,
+                );
+            } else if buffer.trim() == "+" {
+                if let Some(suggester) = prompt.helper_mut() {
+                    match suggester.bookmarks.add(&word) {
+                        Ok(()) => printer.green(&Message::Bookmarked(&word).to_string()),
+                        Err(e) => error!("Can't save the bookmarks: {}", e),
+                    }
+                }
+            } else if let Some(n) = clean_query(&buffer) {
+                word = n;
                 break;
-            } else {
-                opt.word = buffer.trim().to_string();
             };
+            //Empty input shows the prompt again instead of searching.
         }
     }
 
+    if let Some(server) = server {
+        if interactive {
+            running.store(true, Ordering::SeqCst);
+        } else {
+            info!("Serving until interrupted.");
+        }
+        if server.join().is_err() {
+            error!("The server stopped with a panic.");
+        }
+    }
     Ok(())
 }
 
-fn print_dictionaries(paths: Vec<PathBuf>) {
-    let mut dicts: Vec<Dictionary> = Vec::new();
-    for path in &paths {
-        for entry in fs::read_dir(path).unwrap() {
-            if let Ok(dictionary) = Dictionary::load_dictionary(&entry.as_ref().unwrap().path()) {
-                dicts.push(dictionary);
+/// Lists the dictionaries of `searches` and reads the one to search only in, `None`
+/// to search in all of them again.
+fn choose_dictionary(
+    printer: &mut dyn Printer,
+    searches: &[GroupSearch],
+) -> io::Result<Option<(usize, usize)>> {
+    let dicts: Vec<(usize, usize)> = searches
+        .iter()
+        .enumerate()
+        .flat_map(|(group, search)| (0..search.dicts.len()).map(move |n| (group, n)))
+        .collect();
+    for (number, (group, n)) in dicts.iter().enumerate() {
+        let search = &searches[*group];
+        match &search.name {
+            Some(name) => printer.plain(&format!(
+                "{}:   {}/{}\n",
+                number + 1,
+                name,
+                search.dicts[*n].label()
+            )),
+            None => printer.plain(&format!("{}:   {}\n", number + 1, search.dicts[*n].label())),
+        }
+    }
+    printer.yellow(&Message::DictionaryPrompt.to_string());
+    let mut buffer = String::new();
+    io::stdin().read_line(&mut buffer)?;
+    let choice = parse_dictionary_choice(&buffer, dicts.len()).map(|n| dicts[n]);
+    match choice {
+        Some((group, n)) => {
+            printer.green(&Message::SearchingOnlyIn(searches[group].dicts[n].label()).to_string())
+        }
+        None => printer.green(&Message::SearchingInAll.to_string()),
+    }
+    Ok(choice)
+}
+
+/// Position of the dictionary numbered in `input` among `count` listed ones, `None`
+/// when it isn't one of them.
+fn parse_dictionary_choice(input: &str, count: usize) -> Option<usize> {
+    match input.trim().parse::<usize>() {
+        Ok(n) if 1 <= n && n <= count => Some(n - 1),
+        _ => None,
+    }
+}
+
+/// Dictionaries of a group and how they are searched, the dictionaries of default paths
+/// when no group is selected.
+struct GroupSearch {
+    /// Name results are labeled with, only set when several groups are searched.
+    name: Option<String>,
+    dicts: Vec<Arc<Dictionary>>,
+    matcher: Box<dyn WordMatcher + Sync>,
+    morpher: Box<dyn Morpher>,
+    pipeline: Pipeline,
+    mode: SearchMode,
+}
+
+/// How words are looked up, matched with the matcher of the group by default.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SearchMode {
+    Match,
+    /// Headwords made of the same letters, for `--anagram`.
+    Anagram,
+    /// Headwords fitting a pattern with unknown letters, for `--crossword`.
+    Crossword,
+    /// Headwords whose definitions have words at most this many edits away from the
+    /// words of the query, for `--reverse`.
+    Reverse(usize),
+    /// Headwords whose definitions contain the query as a phrase, for `--fulltext`.
+    FullText,
+}
+
+impl SearchMode {
+    fn of(opt: &Opt) -> SearchMode {
+        if opt.anagram {
+            SearchMode::Anagram
+        } else if opt.crossword {
+            SearchMode::Crossword
+        } else if opt.reverse {
+            //Other matchers compare whole words, only levenshtein allows edits.
+            let edits = if opt.search_algorithm == "levenshtein" {
+                opt.search_depth
+            } else {
+                0
+            };
+            SearchMode::Reverse(edits)
+        } else if opt.fulltext {
+            SearchMode::FullText
+        } else {
+            SearchMode::Match
+        }
+    }
+}
+
+impl GroupSearch {
+    /// Searches every possible root of `word` and of its configured `expansions` until
+    /// `deadline`, and merges the results of each dictionary, or only of the `only`th one.
+    fn search(
+        &self,
+        word: &str,
+        expansions: &[String],
+        deadline: Option<Instant>,
+        only: Option<usize>,
+    ) -> Vec<IndexDictPair<'_>> {
+        let dict_refs: Vec<&Dictionary> = self
+            .dicts
+            .iter()
+            .enumerate()
+            .filter(|(n, _)| only.is_none_or(|only| only == *n))
+            .map(|(_, d)| d.as_ref())
+            .collect();
+        if self.mode != SearchMode::Match {
+            let mut pairs = match self.mode {
+                SearchMode::Anagram => search_anagrams_in_dicts(&dict_refs, word),
+                SearchMode::Reverse(edits) => search_keywords_in_dicts(&dict_refs, word, edits),
+                SearchMode::FullText => search_fulltext_in_dicts(&dict_refs, word),
+                _ => search_pattern_in_dicts(&dict_refs, word),
+            };
+            for pair in &mut pairs {
+                pair.group = self.name.clone();
+            }
+            return pairs;
+        }
+        let mut pairs = search_roots_in_dicts(
+            &dict_refs,
+            self.matcher.as_ref(),
+            self.morpher.as_ref(),
+            word,
+            expansions,
+            &self.pipeline,
+            deadline,
+        );
+        for pair in &mut pairs {
+            pair.group = self.name.clone();
+        }
+        pairs
+    }
+}
+
+/// Prints the problems of the settings file for `sozluk config check`, returns the exit code.
+fn check_settings_file(opt: &Opt) -> i32 {
+    let path = opt.settings_path.display();
+    if !opt.settings_path.exists() {
+        println!("There is no settings file at {}, defaults are used.", path);
+        return 0;
+    }
+    match opt.check_settings_file() {
+        Ok(problems) if problems.is_empty() => {
+            println!("{} is valid.", path);
+            0
+        }
+        Ok(problems) => {
+            for problem in &problems {
+                println!("{}: {}", path, problem);
+            }
+            println!("Found {} problems in {}.", problems.len(), path);
+            1
+        }
+        Err(e) => {
+            println!("{} is not valid json: {}", path, e);
+            1
+        }
+    }
+}
+
+/// Lists the dictionaries in the default paths, one per line with the name, word count
+/// and .ifo path separated by tabs, as JSON with `--json`. Returns the exit code, 1 if
+/// there is none.
+fn print_dictionaries(opt: &Opt) -> i32 {
+    let dicts = path_dictionaries(opt);
+    let infos: Vec<DictionaryInfo> = dicts.iter().map(|d| d.info()).collect();
+    if infos.is_empty() {
+        error!("No dictionary file (dict.dz) or dictionary directory found in given paths!");
+        return 1;
+    }
+    if opt.json_output {
+        println!("{}", serde_json::to_string_pretty(&infos).unwrap());
+        return 0;
+    }
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    for info in &infos {
+        //Output is piped into a program that stopped reading.
+        if writeln!(out, "{}", dictionary_line(info)).is_err() {
+            return 0;
+        }
+    }
+    out.flush().ok();
+    0
+}
+
+/// Line of `info` in the list of dictionaries, its fields separated by tabs.
+fn dictionary_line(info: &DictionaryInfo) -> String {
+    format!(
+        "{}\t{}\t{}",
+        info.bookname,
+        info.wordcount,
+        info.ifo_path.display()
+    )
+}
+
+/// Dictionaries in `paths` with their options from the settings applied.
+fn load_configured(opt: &Opt, paths: &[PathBuf]) -> Vec<Dictionary> {
+    let mut dicts = load_dicts_from_paths_and_subpaths(paths, opt.scan_depth);
+    dicts.iter_mut().for_each(|d| opt.configure_dictionary(d));
+    dicts
+}
+
+/// Dictionaries in the paths, for the commands that don't look at groups.
+fn path_dictionaries(opt: &Opt) -> Vec<Dictionary> {
+    //This unwrap is safe because at this point opt.paths at least have default OS specific paths.
+    load_configured(opt, opt.paths.as_ref().unwrap())
+}
+
+/// Dictionaries in the paths and in every group of the settings, for `sozluk cache`.
+fn configured_dictionaries(opt: &Opt) -> Vec<Dictionary> {
+    let paths: Vec<PathBuf> = opt
+        .paths
+        .iter()
+        .flatten()
+        .chain(opt.groups.values().flat_map(|g| &g.paths))
+        .cloned()
+        .collect();
+    load_configured(opt, &paths)
+}
+
+/// Random headwords of a dictionary for `sozluk random`.
+#[derive(Serialize)]
+struct RandomWords<'a> {
+    dictionary: &'a str,
+    words: Vec<&'a str>,
+}
+
+/// Prints `count` random headwords of each dictionary that its headword filter accepts,
+/// only the ones without whitespace if `no_spaces` is set.
+fn print_random_words(opt: &Opt, count: usize, no_spaces: bool, json: bool) {
+    let dicts = configured_dictionaries(opt);
+    let mut all = Vec::new();
+    for dictionary in &dicts {
+        let filter = &dictionary.headword_filter;
+        let words: Vec<&str> = if filter.is_empty() && !no_spaces {
+            dictionary
+                .select_random_words(count)
+                .into_iter()
+                .map(|x| x.word.as_str())
+                .collect()
+        } else {
+            let pool: Vec<&str> = dictionary
+                .indices()
+                .iter()
+                .map(|x| x.word.as_str())
+                .filter(|w| filter.accepts(w) && !(no_spaces && w.contains(char::is_whitespace)))
+                .collect();
+            pool.choose_multiple(&mut thread_rng(), count)
+                .copied()
+                .collect()
+        };
+        all.push(RandomWords {
+            dictionary: dictionary.label(),
+            words,
+        });
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&all).unwrap());
+        return;
+    }
+    for word in all.iter().flat_map(|n| &n.words) {
+        println!("{}", word);
+    }
+}
+
+/// Writes the headwords of the dictionary named `name`, or of every dictionary, to
+/// `out` or the standard output, with the counts of the `frequencies` file if given.
+fn write_wordlist(
+    opt: &Opt,
+    name: Option<&str>,
+    out: Option<&Path>,
+    frequencies: Option<&Path>,
+) -> i32 {
+    let dicts = configured_dictionaries(opt);
+    let selected: Vec<&Dictionary> = dicts
+        .iter()
+        .filter(|d| name.is_none_or(|n| d.bookname == n || d.label() == n))
+        .collect();
+    if selected.is_empty() {
+        error!("No dictionary named {} found.", name.unwrap_or_default());
+        return 1;
+    }
+    let frequencies = match frequencies.map(fs::File::open) {
+        Some(Ok(file)) => match wordlist::read_frequencies(io::BufReader::new(file)) {
+            Ok(n) => Some(n),
+            Err(e) => {
+                error!("Can't read the frequency list: {}", e);
+                return 1;
+            }
+        },
+        Some(Err(e)) => {
+            error!("Can't open the frequency list: {}", e);
+            return 1;
+        }
+        None => None,
+    };
+    let words = wordlist::headwords(&selected);
+    let written = match out {
+        Some(path) => fs::File::create(path).and_then(|file| {
+            wordlist::write_wordlist(&mut io::BufWriter::new(file), &words, frequencies.as_ref())
+        }),
+        None => wordlist::write_wordlist(
+            &mut io::BufWriter::new(io::stdout().lock()),
+            &words,
+            frequencies.as_ref(),
+        ),
+    };
+    match written {
+        Ok(()) => {
+            info!("Wrote {} words.", words.len());
+            0
+        }
+        //Output is piped into a program that stopped reading.
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => 0,
+        Err(e) => {
+            error!("Can't write the word list: {}", e);
+            1
+        }
+    }
+}
+
+/// What `sozluk cache status` shows about a dictionary.
+#[derive(Serialize)]
+struct CacheInfo {
+    bookname: String,
+    cache: CacheStatus,
+    /// Bytes of every cache file of the index.
+    size: u64,
+    cache_path: PathBuf,
+}
+
+fn cache_info(dictionary: &Dictionary) -> CacheInfo {
+    CacheInfo {
+        bookname: dictionary.label().to_owned(),
+        cache: dictionary.cache_status(),
+        size: dictionary
+            .cache_files()
+            .iter()
+            .filter_map(|p| fs::metadata(p).ok())
+            .map(|m| m.len())
+            .sum(),
+        cache_path: dictionary.cache_path.clone(),
+    }
+}
+
+fn print_cache_infos(opt: &Opt, infos: &[CacheInfo]) {
+    if opt.json_output {
+        println!("{}", serde_json::to_string_pretty(infos).unwrap());
+        return;
+    }
+    for (i, info) in infos.iter().enumerate() {
+        println!(
+            "{}:   {}	 cache {}	 {} bytes	 {}",
+            i + 1,
+            info.bookname,
+            info.cache,
+            info.size,
+            info.cache_path.display()
+        );
+    }
+}
+
+/// Builds the index caches for `sozluk index` and `sozluk cache build`, and the keyword
+/// indices with `keywords`, returns the exit code.
+fn build_caches(opt: &Opt, keywords: bool) -> i32 {
+    let dicts = configured_dictionaries(opt);
+    let pending: Vec<&Dictionary> = dicts
+        .iter()
+        .filter(|d| d.cache_status() != CacheStatus::Current)
+        .collect();
+    load_indices(&pending);
+    if keywords {
+        for dictionary in &dicts {
+            dictionary.keyword_index();
+        }
+    }
+    let infos: Vec<CacheInfo> = dicts.iter().map(cache_info).collect();
+    print_cache_infos(opt, &infos);
+    if infos.iter().all(|i| i.cache == CacheStatus::Current) {
+        0
+    } else {
+        1
+    }
+}
+
+/// Shows the freshness of index caches for `sozluk cache status`, returns the exit code.
+fn print_cache_status(opt: &Opt) -> i32 {
+    let infos: Vec<CacheInfo> = configured_dictionaries(opt)
+        .iter()
+        .map(cache_info)
+        .collect();
+    print_cache_infos(opt, &infos);
+    0
+}
+
+/// Removes the index and resource caches for `sozluk cache clear`, returns the exit code.
+fn clear_caches(opt: &Opt) -> i32 {
+    let mut code = 0;
+    let mut freed = 0;
+    let dicts = configured_dictionaries(opt);
+    for dictionary in &dicts {
+        match dictionary.remove_cache() {
+            Ok(n) => freed += n,
+            Err(e) => {
+                error!("Can't remove the cache of {}: {}", dictionary.label(), e);
+                code = 1;
+            }
+        }
+    }
+    println!(
+        "Removed {} bytes of index caches of {} dictionaries.",
+        freed,
+        dicts.len()
+    );
+    let root = match resource_cache::resources_root() {
+        Some(n) => n,
+        None => {
+            error!("Can't find the cache directory.");
+            return 1;
+        }
+    };
+    match resource_cache::clear(&root) {
+        Ok(freed) => println!("Removed {} bytes from {}.", freed, root.display()),
+        Err(e) => {
+            error!("Can't clear {}: {}", root.display(), e);
+            code = 1;
+        }
+    }
+    code
+}
+
+/// Prints the findings of `sozluk doctor` for the tree under `path`, returns the exit
+/// code.
+fn print_health_report(path: &Path) -> i32 {
+    let reports = doctor::examine_tree(path);
+    if reports.is_empty() {
+        println!("No dictionary files under {}.", path.display());
+        return 1;
+    }
+    for report in &reports {
+        if report.is_healthy() {
+            println!("ok      {}", report.path.display());
+            continue;
+        }
+        let state = if report.problems.iter().any(|p| p.is_fatal()) {
+            "broken"
+        } else {
+            "warning"
+        };
+        println!("{:7} {}", state, report.path.display());
+        for problem in &report.problems {
+            println!("        {}", problem);
+            println!("          fix: {}", problem.fix());
+        }
+    }
+    if reports.iter().all(Report::is_healthy) {
+        0
+    } else {
+        1
+    }
+}
+
+/// Writes the headwords of every dictionary matching `pattern` to `out` for `sozluk grep`,
+/// returns the exit code.
+fn grep_headwords<W: Write>(opt: &Opt, pattern: &str, with_dictionary: bool, out: &mut W) -> i32 {
+    let regex = match Regex::new(pattern) {
+        Ok(n) => n,
+        Err(e) => {
+            error!("Invalid regular expression: {}", e);
+            return 2;
+        }
+    };
+    let dicts = path_dictionaries(opt);
+    let mut found = false;
+    for dictionary in &dicts {
+        for index in dictionary.indices() {
+            if !regex.is_match(&index.word) {
+                continue;
+            }
+            found = true;
+            let written = if with_dictionary {
+                writeln!(out, "{}\t{}", dictionary.label(), index.word)
+            } else {
+                writeln!(out, "{}", index.word)
+            };
+            //Output is piped into a program that stopped reading.
+            if written.is_err() {
+                return 0;
             }
         }
     }
-    for (i, entry) in dicts.iter().enumerate() {
+    if out.flush().is_err() {
+        return 0;
+    }
+    if found {
+        0
+    } else {
+        1
+    }
+}
+
+/// Prints the headwords of `pairs` for `--words-only`, each once in the order they are
+/// ranked. Only the index is read.
+fn print_words(pairs: &[IndexDictPair]) {
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    let mut seen = HashSet::new();
+    for index in pairs.iter().flat_map(|pair| &pair.index) {
+        //Output is piped into a program that stopped reading.
+        if seen.insert(index.word.as_str()) && writeln!(out, "{}", index.word).is_err() {
+            return;
+        }
+    }
+    out.flush().ok();
+}
+
+/// Headwords of the results spelled like the searched `word` apart from case, each once,
+/// to queue for `--study`. Similar words a fuzzy search found aren't studied.
+fn study_words<'a>(word: &str, headwords: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let word = word.trim().to_lowercase();
+    let mut words: Vec<&str> = Vec::new();
+    for headword in headwords {
+        if headword.to_lowercase() == word && !words.contains(&headword) {
+            words.push(headword);
+        }
+    }
+    words
+}
+
+/// What `info --memory` reports about the index of a dictionary.
+#[derive(Debug, Serialize)]
+struct MemoryInfo {
+    bookname: String,
+    entries: usize,
+    /// Estimated bytes of the index in memory.
+    memory: usize,
+    /// Bytes of the .sozl file, `None` if there is none.
+    cache_size: Option<u64>,
+    load_millis: u128,
+}
+
+fn print_memory(opt: &Opt) {
+    let dicts = path_dictionaries(opt);
+    let infos: Vec<MemoryInfo> = dicts
+        .iter()
+        .map(|d| {
+            let start = Instant::now();
+            let entries = d.indices().len();
+            let load_millis = start.elapsed().as_millis();
+            MemoryInfo {
+                bookname: d.label().to_owned(),
+                entries,
+                memory: d.index_memory().unwrap_or(0),
+                cache_size: fs::metadata(&d.cache_path).ok().map(|m| m.len()),
+                load_millis,
+            }
+        })
+        .collect();
+    if opt.json_output {
+        println!("{}", serde_json::to_string_pretty(&infos).unwrap());
+        return;
+    }
+    for (i, info) in infos.iter().enumerate() {
         println!(
-            "{}:   {}\t {}",
+            "{}:   {}\t {} entries\t {} bytes in memory\t cache {}\t loaded in {} ms",
             i + 1,
-            entry.bookname,
-            entry.idx_path.display()
+            info.bookname,
+            info.entries,
+            info.memory,
+            info.cache_size
+                .map_or(String::from("missing"), |n| format!("{} bytes", n)),
+            info.load_millis
         );
     }
+    let total: usize = infos.iter().map(|n| n.memory).sum();
+    println!("total: {} bytes in memory", total);
 }
 
-fn listed_interface(pairs: &Vec<IndexDictPair>) {
-    let mut index_count = 0;
-    for pair in pairs {
-        print_green(format!("From {:?}", pair.dict.bookname).as_ref());
-        for ind in &pair.index {
-            println!("{}:   {:?}", index_count, &ind.word);
-            index_count += 1;
+/// What `info --stats` reports about a dictionary.
+#[derive(Serialize)]
+struct StatsInfo<'a> {
+    bookname: &'a str,
+    /// Entry count the .ifo file gives.
+    wordcount: u64,
+    #[serde(flatten)]
+    stats: &'a IndexStats,
+}
+
+/// Prints the index statistics of every dictionary, returns 1 if the entry count of one
+/// isn't the one its .ifo file gives.
+fn print_stats(opt: &Opt) -> i32 {
+    let dicts = configured_dictionaries(opt);
+    let infos: Vec<StatsInfo> = dicts
+        .iter()
+        .map(|d| StatsInfo {
+            bookname: d.label(),
+            wordcount: d.wordcount,
+            stats: d.stats(),
+        })
+        .collect();
+    if opt.json_output {
+        println!("{}", serde_json::to_string_pretty(&infos).unwrap());
+    } else {
+        for (i, info) in infos.iter().enumerate() {
+            let letters: String = info.stats.top_letters(10).iter().map(|(c, _)| *c).collect();
+            println!(
+                "{}:   {}\t {} entries\t {}-{} chars, {:.1} on average\t {} definition bytes\t {}",
+                i + 1,
+                info.bookname,
+                info.stats.entries,
+                info.stats.min_word_len,
+                info.stats.max_word_len,
+                info.stats.mean_word_len,
+                info.stats.definitions_size,
+                letters
+            );
         }
-        println!()
     }
+    let mut code = 0;
+    for info in infos.iter().filter(|i| i.stats.entries != i.wordcount) {
+        warn!(
+            "{} has {} entries but its .ifo file says {}.",
+            info.bookname, info.stats.entries, info.wordcount
+        );
+        code = 1;
+    }
+    code
+}
+
+/// Number of words `listed_interface` shows at once.
+const PAGE_SIZE: usize = 20;
+
+/// Something the user asked for in `listed_interface`.
+#[derive(Debug, PartialEq)]
+enum ListCommand {
+    /// Show the definitions of these entries, 0 based.
+    Show(Range<usize>),
+    /// Open the definition of this entry in the browser, 0 based.
+    Open(usize),
+    /// Copy the definition of this entry to the clipboard, 0 based.
+    Copy(usize),
+    /// Keep only the results containing this, all of them if it's empty.
+    Filter(String),
+    NextPage,
+    PreviousPage,
+    /// Show the current page of the list again.
+    Back,
+    Exit,
+    Invalid,
+}
+
+/// Parses a number, a range like `3-7`, `o <number>`, `c <number>`, `/pattern` or
+/// one of `n`, `p`, `b`, `z`.
+/// Numbers are 1 based like the listed ones and must be at most `count`.
+fn parse_list_command(input: &str, count: usize) -> ListCommand {
+    let input = input.trim().to_lowercase();
+    if let Some(pattern) = input.strip_prefix('/') {
+        return ListCommand::Filter(pattern.trim().to_string());
+    }
+    match input.as_str() {
+        "n" => return ListCommand::NextPage,
+        "p" => return ListCommand::PreviousPage,
+        "b" => return ListCommand::Back,
+        "z" => return ListCommand::Exit,
+        _ => (),
+    }
+    let entry = |number: &str| match number.trim().parse::<usize>() {
+        Ok(n) if 1 <= n && n <= count => Some(n - 1),
+        _ => None,
+    };
+    if let Some(number) = input.strip_prefix('o') {
+        return entry(number).map_or(ListCommand::Invalid, ListCommand::Open);
+    }
+    if let Some(number) = input.strip_prefix('c') {
+        return entry(number).map_or(ListCommand::Invalid, ListCommand::Copy);
+    }
+    let (start, end) = match input.split_once('-') {
+        Some((start, end)) => (start.trim().parse::<usize>(), end.trim().parse::<usize>()),
+        None => (input.parse::<usize>(), input.parse::<usize>()),
+    };
+    match (start, end) {
+        (Ok(start), Ok(end)) if 1 <= start && start <= end && end <= count => {
+            ListCommand::Show(start - 1..end)
+        }
+        _ => ListCommand::Invalid,
+    }
+}
+
+/// Number of queries whose results the interactive loop keeps.
+const RESULT_CACHE_SIZE: usize = 16;
+
+/// Word, matcher names and the dictionary searches were restricted to.
+type QueryKey = (String, Vec<String>, Option<(usize, usize)>);
+
+/// Characters of a definition `listed_interface` shows next to its headword.
+const PREVIEW_LENGTH: usize = 60;
+
+/// `preview` is the length of definition previews, `None` to show them whole.
+fn print_list_page(
+    printer: &mut dyn Printer,
+    entries: &[(&IndexDictPair, usize)],
+    page: usize,
+    query: &str,
+    preview: Option<usize>,
+) {
+    let pages = entries.len().div_ceil(PAGE_SIZE);
+    let start = page * PAGE_SIZE;
+    let mut previous: Option<&IndexDictPair> = None;
+    for (number, (pair, n)) in entries.iter().enumerate().skip(start).take(PAGE_SIZE) {
+        if !previous.is_some_and(|p| std::ptr::eq(p, *pair)) {
+            printer.green(&Message::From(&pair.label()).to_string());
+            if pair.truncated {
+                printer.yellow(&Message::TimedOut.to_string());
+            }
+            previous = Some(pair);
+        }
+        printer.plain(&format!("{}:   ", number + 1));
+        printer.highlighted(&pair.index[*n].word, query, None);
+        if let Some(distance) = pair.distances.get(*n).copied().flatten() {
+            printer.plain(&format!("   ({})", distance));
+        }
+        match pair.read_definition(*n) {
+            Ok(definition) => printer.plain(&format!("   {}\n", definition.preview(preview))),
+            Err(_) => printer.plain("\n"),
+        }
+    }
+    printer.plain("\n");
+    if pages > 1 {
+        printer.green(&Message::Page(page + 1, pages).to_string());
+    }
+}
+
+fn listed_interface(
+    printer: &mut dyn Printer,
+    mut session: Option<&mut SessionLog>,
+    pairs: &[IndexDictPair],
+    width: Option<usize>,
+    query: &str,
+    preview: Option<usize>,
+) {
+    let all_entries: Vec<(&IndexDictPair, usize)> = pairs
+        .iter()
+        .flat_map(|pair| (0..pair.index.len()).map(move |n| (pair, n)))
+        .collect();
+    if all_entries.is_empty() {
+        return;
+    }
+    let mut entries = all_entries.clone();
+    let mut pages = entries.len().div_ceil(PAGE_SIZE);
+    let mut page = 0;
+    print_list_page(printer, &entries, page, query, preview);
     loop {
-        print_green("Enter the number of word you want to see.");
+        printer.green(&Message::ListPrompt.to_string());
         let mut buffer = String::new();
-        io::stdin().read_line(&mut buffer).unwrap();
-        if buffer.trim().eq_ignore_ascii_case("z") {
-            break;
-        } else {
-            match usize::from_str_radix(&buffer.trim(), 10) {
-                Ok(n) => {
-                    if n < index_count + 1 {
-                        let mut previous_lenght: usize = 0;
-                        for sub_group in pairs {
-                            if previous_lenght < n
-                                && n - 1 < previous_lenght + sub_group.index.len()
-                            {
-                                debug!("Found index corresponding to entered number {}, previous length: {}, sub_group.len: {}, n: {}  ", sub_group.dict.bookname, previous_lenght, sub_group.index.len(), n);
-                                let index: &Index =
-                                    sub_group.index.get(n - previous_lenght - 1).unwrap();
-                                sub_group
-                                    .dict
-                                    .read_definition(index)
-                                    .unwrap()
-                                    .print_colored();
+        match io::stdin().read_line(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => (),
+        }
+        match parse_list_command(&buffer, entries.len()) {
+            ListCommand::Show(range) => {
+                for (pair, n) in &entries[range] {
+                    debug!("Showing entry {} of {}.", n, pair.dict.bookname);
+                    //Only previews are cut, a chosen entry is shown whole.
+                    match pair.read_whole_definition(*n) {
+                        Ok(definition) => {
+                            definition.print_colored(printer, width, query);
+                            if let Some(log) = session.as_deref_mut() {
+                                log.definition(&definition, &pair.label());
                             }
-                            previous_lenght += sub_group.index.len();
                         }
-                    } else {
-                        print_green("Enter a valid number or enter z to exit.");
+                        Err(e) => error!("Can't read definition: {}", e),
+                    }
+                }
+            }
+            ListCommand::Open(n) => {
+                let (pair, n) = entries[n];
+                match pair.read_whole_definition(n) {
+                    Ok(definition) => {
+                        open_in_browser(printer, &definition, pair.dict);
+                        if let Some(log) = session.as_deref_mut() {
+                            log.definition(&definition, &pair.label());
+                        }
                     }
+                    Err(e) => error!("Can't read definition: {}", e),
                 }
-                Err(_) => print_green("Enter a valid number or enter z to exit."),
+            }
+            ListCommand::Copy(n) => {
+                let (pair, n) = entries[n];
+                match pair.read_whole_definition(n) {
+                    Ok(definition) => copy_to_clipboard(printer, &[definition]),
+                    Err(e) => error!("Can't read definition: {}", e),
+                }
+            }
+            ListCommand::Filter(pattern) => {
+                let filtered: Vec<(&IndexDictPair, usize)> = all_entries
+                    .iter()
+                    .filter(|(pair, n)| pair.index[*n].word.to_lowercase().contains(&pattern))
+                    .copied()
+                    .collect();
+                if filtered.is_empty() {
+                    printer.green(&Message::NoMatchingResult(&pattern).to_string());
+                } else {
+                    entries = filtered;
+                    pages = entries.len().div_ceil(PAGE_SIZE);
+                    page = 0;
+                    print_list_page(printer, &entries, page, query, preview);
+                }
+            }
+            ListCommand::NextPage if page + 1 < pages => {
+                page += 1;
+                print_list_page(printer, &entries, page, query, preview);
+            }
+            ListCommand::PreviousPage if page > 0 => {
+                page -= 1;
+                print_list_page(printer, &entries, page, query, preview);
+            }
+            ListCommand::NextPage | ListCommand::PreviousPage => {
+                printer.green(&Message::NoMorePages.to_string());
+            }
+            ListCommand::Back => print_list_page(printer, &entries, page, query, preview),
+            ListCommand::Exit => break,
+            ListCommand::Invalid => {
+                printer.green(&Message::InvalidChoice(entries.len()).to_string())
             }
         }
     }
 }
 
-fn print_defs(pairs: &[IndexDictPair]) {
+fn open_in_browser(printer: &mut dyn Printer, definition: &Definition, dictionary: &Dictionary) {
+    match open_definition(
+        definition,
+        &dictionary.page_style,
+        ResourceCache::of(dictionary).as_ref(),
+    ) {
+        Ok(path) => {
+            printer.green(&Message::OpenedInBrowser(&path.display().to_string()).to_string())
+        }
+        Err(e) => error!("Can't open definition in the browser: {}", e),
+    }
+}
+
+fn copy_to_clipboard(printer: &mut dyn Printer, definitions: &[Definition]) {
+    let text = definitions
+        .iter()
+        .map(|d| format!("{}\n{}", d.word, d.plain_text()))
+        .collect::<Vec<String>>()
+        .join("\n\n");
+    match clipboard::copy(&text) {
+        Ok(_) => printer.green(&Message::Copied.to_string()),
+        Err(e) => error!("Can't copy to the clipboard: {}", e),
+    }
+}
+
+fn print_defs(
+    printer: &mut dyn Printer,
+    mut session: Option<&mut SessionLog>,
+    pairs: &[IndexDictPair],
+    width: Option<usize>,
+    query: &str,
+    opt: &Opt,
+) {
+    let mut printed = Vec::new();
     for pair in pairs {
-        let defs: Vec<Definition> = pair
-            .index
-            .iter()
-            .filter_map(|ind| pair.dict.read_definition(ind).ok())
+        let defs: Vec<Definition> = (0..pair.index.len())
+            .filter_map(|n| pair.read_definition(n).ok())
             .collect();
-        print_green(
-            format!(
-                "From dictionary {} found {} results. \n",
-                &pair.dict.bookname,
-                &defs.len()
-            )
-            .as_ref(),
-        );
+        printer.green(&Message::FoundResults(&pair.label(), defs.len()).to_string());
+        if pair.truncated {
+            printer.yellow(&Message::TimedOut.to_string());
+        }
         for d in &defs {
-            d.print_colored();
+            d.print_colored(printer, width, query);
+            if d.truncated && !opt.exit {
+                let limit = pair.dict.definition_limit.unwrap_or_default();
+                printer.yellow(&Message::DefinitionCut(limit).to_string());
+            }
+            if let Some(log) = session.as_deref_mut() {
+                log.definition(d, &pair.label());
+            }
+            show_images(d, pair.dict);
+            if opt.open && d.is_markup() {
+                open_in_browser(printer, d, pair.dict);
+            }
+        }
+        printed.extend(defs);
+    }
+    if opt.copy && !printed.is_empty() {
+        copy_to_clipboard(printer, &printed);
+    }
+}
+
+/// Prints whole the definitions of `pairs` that `print_defs` cut, for `m` at the prompt.
+fn print_whole_definitions(
+    printer: &mut dyn Printer,
+    pairs: &[IndexDictPair],
+    width: Option<usize>,
+    query: &str,
+) {
+    let mut printed = false;
+    for pair in pairs {
+        for n in 0..pair.index.len() {
+            if !pair.dict.cuts(pair.index[n]) {
+                continue;
+            }
+            match pair.read_whole_definition(n) {
+                Ok(definition) => definition.print_colored(printer, width, query),
+                Err(e) => error!("Can't read definition: {}", e),
+            }
+            printed = true;
         }
     }
+    if !printed {
+        printer.green(&Message::NothingCut.to_string());
+    }
+}
+
+/// Draws the pictures of `definition` and the images it refers to in the resource
+/// storage of `dictionary`.
+fn show_images(definition: &Definition, dictionary: &Dictionary) {
+    let cache = ResourceCache::of_or_temporary(dictionary);
+    for data in definition.pictures() {
+        if let Err(e) = terminal_image::show(data, &definition.word, None, &cache) {
+            error!("Can't show a picture of {}: {}", &definition.word, e);
+        }
+    }
+    for name in definition.image_references() {
+        let data = match dictionary.read_resource(name) {
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                debug!("{} has no resource named {}.", dictionary.label(), name);
+                continue;
+            }
+            Err(e) => {
+                error!(
+                    "Can't read resource {} of {}: {}",
+                    name,
+                    dictionary.label(),
+                    e
+                );
+                continue;
+            }
+        };
+        //Files of the res directory are shown from where they are, resources of a
+        //database are written to the cache first.
+        let path = dictionary.resource_path(name);
+        if let Err(e) = terminal_image::show(&data, &definition.word, path.as_deref(), &cache) {
+            error!("Can't show {}: {}", name, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_list_commands() {
+        assert_eq!(parse_list_command("3\n", 10), ListCommand::Show(2..3));
+        assert_eq!(parse_list_command(" 3 - 7 ", 10), ListCommand::Show(2..7));
+        assert_eq!(parse_list_command("N", 10), ListCommand::NextPage);
+        assert_eq!(parse_list_command("p", 10), ListCommand::PreviousPage);
+        assert_eq!(parse_list_command("b", 10), ListCommand::Back);
+        assert_eq!(parse_list_command("z", 10), ListCommand::Exit);
+        assert_eq!(parse_list_command("o 4", 10), ListCommand::Open(3));
+        assert_eq!(parse_list_command("o4", 10), ListCommand::Open(3));
+        assert_eq!(parse_list_command("c 10", 10), ListCommand::Copy(9));
+        assert_eq!(
+            parse_list_command("/Lar\n", 10),
+            ListCommand::Filter(String::from("lar"))
+        );
+        assert_eq!(
+            parse_list_command("/", 10),
+            ListCommand::Filter(String::new())
+        );
+    }
+
+    #[test]
+    fn should_reject_numbers_out_of_list() {
+        assert_eq!(parse_list_command("0", 10), ListCommand::Invalid);
+        assert_eq!(parse_list_command("11", 10), ListCommand::Invalid);
+        assert_eq!(parse_list_command("7-3", 10), ListCommand::Invalid);
+        assert_eq!(parse_list_command("3-", 10), ListCommand::Invalid);
+        assert_eq!(parse_list_command("", 10), ListCommand::Invalid);
+        assert_eq!(parse_list_command("elma", 10), ListCommand::Invalid);
+        assert_eq!(parse_list_command("o 11", 10), ListCommand::Invalid);
+        assert_eq!(parse_list_command("o", 10), ListCommand::Invalid);
+    }
+
+    #[test]
+    fn should_separate_dictionary_fields_by_tabs() {
+        let mut dictionary = Dictionary::new(Path::new("/usr/share/stardict/tdk/tdk.ifo"));
+        dictionary.bookname = String::from("TDK Güncel Sözlük");
+        dictionary.wordcount = 92411;
+        assert_eq!(
+            dictionary_line(&dictionary.info()),
+            "TDK Güncel Sözlük\t92411\t/usr/share/stardict/tdk/tdk.ifo"
+        );
+    }
+
+    #[test]
+    fn should_study_found_headwords_only() {
+        let headwords = ["Elma", "elmas", "Elma", "elma"];
+        assert_eq!(
+            study_words(" elma", headwords.iter().copied()),
+            vec!["Elma", "elma"]
+        );
+        assert!(study_words("elmaa", headwords.iter().copied()).is_empty());
+    }
+
+    #[test]
+    fn should_grep_headwords() {
+        let dir = tempfile::tempdir().unwrap();
+        let dict_dir = dir.path().join("tdk");
+        fs::create_dir(&dict_dir).unwrap();
+        let mut idx: Vec<u8> = Vec::new();
+        for (n, word) in ["armut", "elma", "elmas"].iter().enumerate() {
+            idx.extend_from_slice(word.as_bytes());
+            idx.push(0);
+            idx.extend_from_slice(&(n as u32).to_be_bytes());
+            idx.extend_from_slice(&1u32.to_be_bytes());
+        }
+        fs::write(
+            dict_dir.join("tdk.ifo"),
+            "StarDict's dict ifo file\nversion=2.4.2\nbookname=tdk\nwordcount=3\nsametypesequence=m\n",
+        )
+        .unwrap();
+        fs::write(dict_dir.join("tdk.idx"), idx).unwrap();
+        fs::write(dict_dir.join("tdk.dict"), "abc").unwrap();
+        let mut opt = Opt::new();
+        opt.paths = Some(vec![dir.path().to_path_buf()]);
+
+        let mut out: Vec<u8> = Vec::new();
+        assert_eq!(grep_headwords(&opt, "^elma", true, &mut out), 0);
+        assert_eq!(String::from_utf8(out).unwrap(), "tdk\telma\ntdk\telmas\n");
+        let mut out: Vec<u8> = Vec::new();
+        assert_eq!(grep_headwords(&opt, "t$", false, &mut out), 0);
+        assert_eq!(String::from_utf8(out).unwrap(), "armut\n");
+        let mut out: Vec<u8> = Vec::new();
+        assert_eq!(grep_headwords(&opt, "^kiraz", true, &mut out), 1);
+        assert!(out.is_empty());
+        assert_eq!(grep_headwords(&opt, "(", false, &mut Vec::new()), 2);
+    }
+
+    #[test]
+    fn should_parse_dictionary_choice() {
+        assert_eq!(parse_dictionary_choice("2\n", 3), Some(1));
+        assert_eq!(parse_dictionary_choice("0", 3), None);
+        assert_eq!(parse_dictionary_choice("4", 3), None);
+        assert_eq!(parse_dictionary_choice("\n", 3), None);
+    }
 }
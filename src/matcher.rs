@@ -1,13 +1,151 @@
-use std::convert::TryFrom;
+use log::error;
+use regex::Regex;
 use std::fmt::{Debug, Formatter, Result, Write};
-use strsim::normalized_levenshtein;
+use std::sync::RwLock;
+use strsim::{generic_levenshtein, levenshtein};
+use unicode_segmentation::UnicodeSegmentation;
 
 pub trait WordMatcher {
     fn compare(&self, first: &str, second: &str) -> bool;
     fn name(&self) -> String;
+    /// How far apart two matching words are, for matchers that can tell.
+    fn distance(&self, _first: &str, _second: &str) -> Option<usize> {
+        None
+    }
+    /// How similar two matching words are from 0 to 1, 1 for the same word. Results
+    /// are ordered by it, see `rank_pairs`. Matchers with a distance give one minus the
+    /// distance per letter of the longer word.
+    fn score(&self, first: &str, second: &str) -> Option<f64> {
+        let distance = self.distance(first, second)?;
+        let length = grapheme_count(first).max(grapheme_count(second)).max(1);
+        Some(1.0 - (distance.min(length) as f64 / length as f64))
+    }
+    /// Lengths and first letter a word must have to match `word`, `None` if this
+    /// matcher can't tell. Lets a search skip the shards of an index that can't match.
+    fn bounds(&self, _word: &str) -> Option<WordBounds> {
+        None
+    }
+    /// How the candidates for the matches of a word can be found in the index, `None` if
+    /// every headword has to be compared.
+    fn lookup(&self) -> Option<Lookup> {
+        None
+    }
     // fn best_matches(&self, pool: &Vec<&str>, word: &str, number: usize) -> Vec<Index>;
 }
 
+/// Words a matcher can match, see `WordMatcher::bounds`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WordBounds {
+    /// Length range in grapheme clusters, both inclusive.
+    pub min_length: usize,
+    pub max_length: usize,
+    /// First character of every match, `None` if it can be anything.
+    pub first: Option<char>,
+}
+
+/// Where the candidates for the matches of a word are found without comparing every
+/// headword, see `WordMatcher::lookup`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lookup {
+    /// Headwords equal to the word ignoring ASCII case, next to each other in a sorted
+    /// index.
+    Exact,
+    /// Headwords starting with the word ignoring ASCII case, next to each other in a
+    /// sorted index.
+    Prefix,
+    /// Headwords at most this many edits away, sharing enough trigrams with the word.
+    Edits(usize),
+}
+
+/// Stands for any one letter in crossword patterns.
+pub const UNKNOWN_LETTER: &str = "?";
+
+/// Whether `word` has as many letters as `pattern` and the same letter wherever the
+/// pattern doesn't have an `UNKNOWN_LETTER`, ignoring case.
+pub fn matches_pattern(pattern: &str, word: &str) -> bool {
+    let mut word = word.graphemes(true);
+    for letter in pattern.graphemes(true) {
+        match word.next() {
+            Some(_) if letter == UNKNOWN_LETTER => (),
+            Some(n) if n == letter || n.to_lowercase() == letter.to_lowercase() => (),
+            _ => return false,
+        }
+    }
+    word.next().is_none()
+}
+
+/// Characters headwords may have, for `--charset`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Charset {
+    /// Only letters.
+    Alpha,
+    /// Anything but whitespace.
+    NoSpaces,
+}
+
+pub const CHARSETS: &[&str] = &["alpha", "no-spaces"];
+
+impl Charset {
+    pub fn from_name(name: &str) -> Option<Charset> {
+        match name {
+            "alpha" => Some(Charset::Alpha),
+            "no-spaces" => Some(Charset::NoSpaces),
+            _ => None,
+        }
+    }
+
+    fn accepts(self, word: &str) -> bool {
+        match self {
+            Charset::Alpha => word.chars().all(char::is_alphabetic),
+            Charset::NoSpaces => !word.chars().any(char::is_whitespace),
+        }
+    }
+}
+
+/// Conditions a headword has to meet before it's compared with the query at all.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct HeadwordFilter {
+    /// Lengths in grapheme clusters, both inclusive.
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    pub charset: Option<Charset>,
+}
+
+impl HeadwordFilter {
+    pub fn is_empty(&self) -> bool {
+        *self == HeadwordFilter::default()
+    }
+
+    pub fn accepts(&self, word: &str) -> bool {
+        if self.min_length.is_some() || self.max_length.is_some() {
+            let length = grapheme_count(word);
+            if self.min_length.is_some_and(|n| length < n)
+                || self.max_length.is_some_and(|n| length > n)
+            {
+                return false;
+            }
+        }
+        self.charset.is_none_or(|c| c.accepts(word))
+    }
+
+    /// `bounds` of a matcher narrowed to the lengths this filter accepts.
+    pub fn narrow(&self, bounds: Option<WordBounds>) -> Option<WordBounds> {
+        if self.min_length.is_none() && self.max_length.is_none() {
+            return bounds;
+        }
+        let bounds = bounds.unwrap_or(WordBounds {
+            min_length: 0,
+            max_length: usize::MAX,
+            first: None,
+        });
+        Some(WordBounds {
+            min_length: bounds.min_length.max(self.min_length.unwrap_or(0)),
+            max_length: bounds.max_length.min(self.max_length.unwrap_or(usize::MAX)),
+            first: bounds.first,
+        })
+    }
+}
+
 impl Debug for dyn WordMatcher {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         f.debug_tuple("").field(&self.name()).finish()
@@ -20,25 +158,278 @@ impl WordMatcher for ExactMatcher {
         first == second
     }
 
+    fn score(&self, first: &str, second: &str) -> Option<f64> {
+        Some(if first == second { 1.0 } else { 0.0 })
+    }
+
+    fn bounds(&self, word: &str) -> Option<WordBounds> {
+        let length = grapheme_count(word);
+        Some(WordBounds {
+            min_length: length,
+            max_length: length,
+            first: word.chars().next(),
+        })
+    }
+
+    fn lookup(&self) -> Option<Lookup> {
+        Some(Lookup::Exact)
+    }
+
     fn name(&self) -> String {
         String::from("Exact Matcher")
     }
 }
 
+/// Share of the letters of `second` that `first` has, at most 1.
+fn length_ratio(first: &str, second: &str) -> f64 {
+    let length = grapheme_count(second).max(1);
+    grapheme_count(first).min(length) as f64 / length as f64
+}
+
+/// Matches the headwords starting with the query, for completing a word.
+pub struct PrefixMatcher {}
+
+impl WordMatcher for PrefixMatcher {
+    fn compare(&self, first: &str, second: &str) -> bool {
+        second.starts_with(first)
+    }
+
+    fn score(&self, first: &str, second: &str) -> Option<f64> {
+        Some(length_ratio(first, second))
+    }
+
+    fn bounds(&self, word: &str) -> Option<WordBounds> {
+        Some(WordBounds {
+            min_length: grapheme_count(word),
+            max_length: usize::MAX,
+            first: word.chars().next(),
+        })
+    }
+
+    fn lookup(&self) -> Option<Lookup> {
+        Some(Lookup::Prefix)
+    }
+
+    fn name(&self) -> String {
+        String::from("Prefix Matcher")
+    }
+}
+
+/// Matches the headwords the query is a part of.
+pub struct ContainsMatcher {}
+
+impl WordMatcher for ContainsMatcher {
+    fn compare(&self, first: &str, second: &str) -> bool {
+        second.contains(first)
+    }
+
+    fn score(&self, first: &str, second: &str) -> Option<f64> {
+        Some(length_ratio(first, second))
+    }
+
+    fn bounds(&self, word: &str) -> Option<WordBounds> {
+        Some(WordBounds {
+            min_length: grapheme_count(word),
+            max_length: usize::MAX,
+            first: None,
+        })
+    }
+
+    fn name(&self) -> String {
+        String::from("Contains Matcher")
+    }
+}
+
+/// Matches the headwords a regular expression, the query, finds a match in. The
+/// expression is compiled on the first comparison with it and reused by the next ones,
+/// those of other dictionaries too.
+#[derive(Default)]
+pub struct RegexMatcher {
+    /// The last query and its expression, `None` if it isn't valid.
+    compiled: RwLock<Option<(String, Option<Regex>)>>,
+}
+
+impl RegexMatcher {
+    fn compile(&self, pattern: &str) -> Option<Regex> {
+        if let Some((last, regex)) = self.compiled.read().unwrap().as_ref() {
+            if last == pattern {
+                return regex.clone();
+            }
+        }
+        let regex = match Regex::new(pattern) {
+            Ok(n) => Some(n),
+            Err(e) => {
+                error!("Invalid regular expression: {}", e);
+                None
+            }
+        };
+        *self.compiled.write().unwrap() = Some((pattern.to_string(), regex.clone()));
+        regex
+    }
+}
+
+impl WordMatcher for RegexMatcher {
+    fn compare(&self, first: &str, second: &str) -> bool {
+        if let Some((last, regex)) = self.compiled.read().unwrap().as_ref() {
+            if last == first {
+                return regex.as_ref().is_some_and(|r| r.is_match(second));
+            }
+        }
+        self.compile(first).is_some_and(|r| r.is_match(second))
+    }
+
+    fn name(&self) -> String {
+        String::from("Regex Matcher")
+    }
+}
+
 pub struct LevenshteinMatcher {
     pub level: usize,
 }
 
+/// Longest word `myers_distance` can take as its pattern, a letter for each bit of a `u64`.
+const MYERS_MAX_LENGTH: usize = 64;
+
+/// Edit distance of a pattern of `pattern_length` letters and `text` with Myers'
+/// bit-parallel algorithm, as formulated by Hyyrö for the distance of whole words.
+/// `peq` gives the bits of the positions a letter of the text has in the pattern.
+fn myers_distance<T>(
+    pattern_length: usize,
+    text: impl Iterator<Item = T>,
+    peq: impl Fn(T) -> u64,
+) -> usize {
+    debug_assert!(pattern_length <= MYERS_MAX_LENGTH);
+    if pattern_length == 0 {
+        return text.count();
+    }
+    let last = 1u64 << (pattern_length - 1);
+    let mut positive: u64 = !0;
+    let mut negative: u64 = 0;
+    let mut distance = pattern_length;
+    for letter in text {
+        let eq = peq(letter);
+        let xv = eq | negative;
+        let xh = (((eq & positive).wrapping_add(positive)) ^ positive) | eq;
+        let mut horizontal_positive = negative | !(xh | positive);
+        let mut horizontal_negative = positive & xh;
+        if horizontal_positive & last != 0 {
+            distance += 1;
+        } else if horizontal_negative & last != 0 {
+            distance -= 1;
+        }
+        horizontal_positive = (horizontal_positive << 1) | 1;
+        horizontal_negative <<= 1;
+        positive = horizontal_negative | !(xv | horizontal_positive);
+        negative = horizontal_positive & xv;
+    }
+    distance
+}
+
+/// Edit distance counting grapheme clusters, so a letter written with a combining
+/// accent is one edit away from another letter like a precomposed one is. Uses
+/// `myers_distance` when the shorter word has at most `MYERS_MAX_LENGTH` letters.
+fn grapheme_levenshtein(first: &str, second: &str) -> usize {
+    if first.is_ascii() && second.is_ascii() {
+        let (pattern, text) = if first.len() <= second.len() {
+            (first, second)
+        } else {
+            (second, first)
+        };
+        if pattern.len() > MYERS_MAX_LENGTH {
+            return levenshtein(first, second);
+        }
+        let mut peq = [0u64; 128];
+        for (i, byte) in pattern.bytes().enumerate() {
+            peq[byte as usize] |= 1 << i;
+        }
+        return myers_distance(pattern.len(), text.bytes(), |b| peq[b as usize]);
+    }
+    let first: Vec<&str> = first.graphemes(true).collect();
+    let second: Vec<&str> = second.graphemes(true).collect();
+    let (pattern, text) = if first.len() <= second.len() {
+        (&first, &second)
+    } else {
+        (&second, &first)
+    };
+    if pattern.len() > MYERS_MAX_LENGTH {
+        return generic_levenshtein(&first, &second);
+    }
+    let mut peq: Vec<(&str, u64)> = Vec::new();
+    for (i, letter) in pattern.iter().enumerate() {
+        match peq.iter_mut().find(|(l, _)| l == letter) {
+            Some(n) => n.1 |= 1 << i,
+            None => peq.push((letter, 1 << i)),
+        }
+    }
+    myers_distance(pattern.len(), text.iter(), |letter| {
+        peq.iter().find(|(l, _)| l == letter).map_or(0, |n| n.1)
+    })
+}
+
+/// Number of grapheme clusters in `word`.
+pub(crate) fn grapheme_count(word: &str) -> usize {
+    if word.is_ascii() {
+        word.len()
+    } else {
+        word.graphemes(true).count()
+    }
+}
+
+/// Sort key ordering `headword` by how well it answers `word`, best first: `word`
+/// itself, the same word in another case, the words starting with it, then the rest.
+/// Ties go to the closer, the shorter, then the alphabetically first headword.
+/// `lowercase` is `word` in lower case, `distance` their edit distance if it's known.
+pub fn relevance(
+    word: &str,
+    lowercase: &str,
+    headword: &str,
+    distance: Option<usize>,
+) -> (u8, usize, usize, String, String) {
+    let lower = headword.to_lowercase();
+    let class = if headword == word {
+        0
+    } else if lower == lowercase {
+        1
+    } else if lower.starts_with(lowercase) {
+        2
+    } else {
+        3
+    };
+    let distance = distance.unwrap_or_else(|| grapheme_levenshtein(word, headword));
+    (
+        class,
+        distance,
+        grapheme_count(headword),
+        lower,
+        headword.to_string(),
+    )
+}
+
 impl WordMatcher for LevenshteinMatcher {
     fn compare(&self, first: &str, second: &str) -> bool {
-        let delta = i8::try_from(first.chars().count()).unwrap()
-            - i8::try_from(second.chars().count()).unwrap();
-        if delta > i8::try_from(self.level).unwrap()
-            || delta < i8::try_from(self.level).unwrap() * -1
-        {
+        //Words whose lengths differ more than the level can't match, skip computing the distance.
+        if grapheme_count(first).abs_diff(grapheme_count(second)) > self.level {
             return false;
         }
-        normalized_levenshtein(first, second) > 0.89 - 0.05 * f64::from(self.level as u32)
+        grapheme_levenshtein(first, second) <= self.level
+    }
+
+    fn distance(&self, first: &str, second: &str) -> Option<usize> {
+        Some(grapheme_levenshtein(first, second))
+    }
+
+    fn bounds(&self, word: &str) -> Option<WordBounds> {
+        let length = grapheme_count(word);
+        Some(WordBounds {
+            min_length: length.saturating_sub(self.level),
+            max_length: length + self.level,
+            //Any edit can change the first letter, without edits it stays.
+            first: word.chars().next().filter(|_| self.level == 0),
+        })
+    }
+
+    fn lookup(&self) -> Option<Lookup> {
+        Some(Lookup::Edits(self.level))
     }
 
     fn name(&self) -> String {
@@ -51,6 +442,7 @@ impl WordMatcher for LevenshteinMatcher {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn should_plain_matcher_match_same() {
@@ -64,6 +456,47 @@ mod tests {
         assert!(!matcher.compare("elmalar", "elma"));
     }
 
+    #[test]
+    fn should_prefix_and_contains_matchers_match_parts() {
+        let prefix = PrefixMatcher {};
+        assert!(prefix.compare("elm", "elma"));
+        assert!(prefix.compare("elma", "elma"));
+        assert!(!prefix.compare("lma", "elma"));
+        let contains = ContainsMatcher {};
+        assert!(contains.compare("lma", "elma"));
+        assert!(!contains.compare("elmas", "elma"));
+        assert_eq!(
+            prefix.bounds("çi"),
+            Some(WordBounds {
+                min_length: 2,
+                max_length: usize::MAX,
+                first: Some('ç')
+            })
+        );
+        assert_eq!(contains.bounds("çi").unwrap().first, None);
+    }
+
+    #[test]
+    fn should_regex_matcher_reuse_compiled_pattern() {
+        let matcher = RegexMatcher::default();
+        assert!(matcher.compare("^über.*ung$", "überraschung"));
+        assert!(!matcher.compare("^über.*ung$", "überrasch"));
+        assert_eq!(
+            matcher.compiled.read().unwrap().as_ref().unwrap().0,
+            "^über.*ung$"
+        );
+        assert!(matcher.compare("ma$", "elma"));
+        assert!(!matcher.compare("(", "("));
+        assert!(matcher
+            .compiled
+            .read()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .1
+            .is_none());
+    }
+
     #[test]
     fn should_levenshtein_matcher_match_same() {
         let matcher = LevenshteinMatcher { level: 3 };
@@ -71,4 +504,115 @@ mod tests {
         assert!(matcher.compare("armut", "erm"));
         assert!(matcher.compare("Armut", "armutar"));
     }
+
+    #[test]
+    fn should_only_levenshtein_matcher_give_distance() {
+        assert_eq!(
+            LevenshteinMatcher { level: 2 }.distance("elma", "elmas"),
+            Some(1)
+        );
+        assert_eq!(ExactMatcher {}.distance("elma", "elma"), None);
+    }
+
+    #[test]
+    fn should_score_similar_words_higher() {
+        let levenshtein = LevenshteinMatcher { level: 2 };
+        assert_eq!(levenshtein.score("elma", "elma"), Some(1.0));
+        assert_eq!(levenshtein.score("elma", "elmas"), Some(0.8));
+        assert_eq!(levenshtein.score("", ""), Some(1.0));
+        assert_eq!(ExactMatcher {}.score("elma", "alma"), Some(0.0));
+        assert_eq!(PrefixMatcher {}.score("elm", "elmas"), Some(0.6));
+        assert_eq!(ContainsMatcher {}.score("", "elma"), Some(0.0));
+        assert_eq!(RegexMatcher::default().score("^el", "elma"), None);
+    }
+
+    #[test]
+    fn should_compare_long_and_combining_words() {
+        let matcher = LevenshteinMatcher { level: 1 };
+        let long = "a".repeat(300);
+        assert!(matcher.compare(&long, &format!("{}b", long)));
+        assert!(!matcher.compare(&long, "a"));
+        //"ç" written as "c" and a combining cedilla is one grapheme.
+        assert!(matcher.compare("c\u{327}icek", "cicek"));
+        assert_eq!(matcher.distance("c\u{327}icek", "çiçek"), Some(2));
+    }
+
+    #[test]
+    fn should_bound_matching_words() {
+        let exact = ExactMatcher {}.bounds("çiçek").unwrap();
+        assert_eq!((exact.min_length, exact.max_length), (5, 5));
+        assert_eq!(exact.first, Some('ç'));
+        let levenshtein = LevenshteinMatcher { level: 2 }.bounds("elma").unwrap();
+        assert_eq!((levenshtein.min_length, levenshtein.max_length), (2, 6));
+        assert_eq!(levenshtein.first, None);
+    }
+
+    #[test]
+    fn should_match_crossword_patterns() {
+        assert!(matches_pattern("s??lük", "sözlük"));
+        assert!(matches_pattern("s??luk", "Sunluk"));
+        assert!(!matches_pattern("s??lük", "sözlükler"));
+        assert!(!matches_pattern("s??luk", "sluk"));
+        assert!(matches_pattern("??", "c\u{327}a"));
+        assert!(matches_pattern("", ""));
+    }
+
+    #[test]
+    fn should_filter_headwords() {
+        let filter = HeadwordFilter {
+            min_length: Some(4),
+            max_length: Some(5),
+            charset: Charset::from_name("alpha"),
+        };
+        assert!(filter.accepts("elma"));
+        assert!(filter.accepts("çiçek"));
+        assert!(!filter.accepts("kız"));
+        assert!(!filter.accepts("elmalar"));
+        assert!(!filter.accepts("e-ma"));
+        assert!(!Charset::NoSpaces.accepts("elma ağacı"));
+        assert!(HeadwordFilter::default().accepts("elma ağacı"));
+
+        let bounds = filter.narrow(LevenshteinMatcher { level: 2 }.bounds("elma"));
+        assert_eq!(bounds.map(|b| (b.min_length, b.max_length)), Some((4, 5)));
+        assert_eq!(filter.narrow(None).map(|b| b.min_length), Some(4));
+        let first = |level| LevenshteinMatcher { level }.bounds("elma").unwrap().first;
+        assert_eq!((first(0), first(1)), (Some('e'), None));
+        assert_eq!(HeadwordFilter::default().narrow(None), None);
+    }
+
+    proptest! {
+        #[test]
+        fn levenshtein_compare_agrees_with_distance(
+            first in "\\PC{0,200}",
+            second in "\\PC{0,200}",
+            level in 0usize..300,
+        ) {
+            let matcher = LevenshteinMatcher { level };
+            let distance = matcher.distance(&first, &second).unwrap();
+            prop_assert_eq!(matcher.compare(&first, &second), distance <= level);
+            prop_assert_eq!(matcher.compare(&second, &first), distance <= level);
+        }
+
+        #[test]
+        fn myers_distance_agrees_with_strsim(
+            first in "[a-cçğ]{0,70}",
+            second in "[a-cçğ]{0,70}",
+        ) {
+            let graphemes: Vec<Vec<&str>> =
+                [&first, &second].iter().map(|w| w.graphemes(true).collect()).collect();
+            prop_assert_eq!(
+                grapheme_levenshtein(&first, &second),
+                generic_levenshtein(&graphemes[0], &graphemes[1])
+            );
+            let ascii = |w: &str| w.replace('ç', "d").replace('ğ', "e");
+            let (first, second) = (ascii(&first), ascii(&second));
+            prop_assert_eq!(grapheme_levenshtein(&first, &second), levenshtein(&first, &second));
+        }
+
+        #[test]
+        fn levenshtein_matches_same_word(word in "\\PC*", level in 0usize..4) {
+            let matcher = LevenshteinMatcher { level };
+            prop_assert!(matcher.compare(&word, &word));
+        }
+    }
 }
@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 pub trait Morpher {
     fn possible_roots(&self, word: &str) -> Vec<String>;
 }
@@ -9,21 +11,217 @@ impl Morpher for NoMorpher {
     }
 }
 
+/// Strips English inflections: plurals, past and present participles and comparatives.
+/// Roots that aren't words are returned too, they just find nothing.
 pub struct EnglishMorpher {}
 
+/// Stems left after a suffix that may have doubled the last consonant or dropped a
+/// final e of the root, "running" → "runn", "run" and "runne".
+fn english_stems(stem: &str, roots: &mut Vec<String>) {
+    let mut chars = stem.chars().rev();
+    roots.push(stem.to_string());
+    roots.push(format!("{}e", stem));
+    if let (Some(last), Some(before)) = (chars.next(), chars.next()) {
+        if last == before && !"aeiouls".contains(last) {
+            roots.push(stem[..stem.len() - last.len_utf8()].to_string());
+        }
+    }
+}
+
 impl Morpher for EnglishMorpher {
     fn possible_roots(&self, word: &str) -> Vec<String> {
-        vec![String::from(word)]
+        let mut roots = vec![String::from(word)];
+        //The root keeps at least two letters, "is" and "bed" aren't inflected.
+        let strip = |suffix: &str| {
+            word.strip_suffix(suffix)
+                .filter(|stem| stem.chars().count() >= 2)
+        };
+        for suffix in ["ies", "ied", "ier", "iest"] {
+            if let Some(stem) = strip(suffix) {
+                roots.push(format!("{}y", stem));
+            }
+        }
+        if let Some(stem) = strip("es") {
+            roots.push(stem.to_string());
+        }
+        if let Some(stem) = strip("s").filter(|n| !n.ends_with('s')) {
+            roots.push(stem.to_string());
+        }
+        for suffix in ["ed", "ing", "er", "est"] {
+            if let Some(stem) = strip(suffix) {
+                english_stems(stem, &mut roots);
+            }
+        }
+        let mut seen = HashSet::new();
+        roots.retain(|n| seen.insert(n.clone()));
+        roots
     }
 }
+/// Strips the Turkish suffix chain of nouns, root + plural + possessive + case, keeping
+/// the ones in harmony with the last vowel of what's left. A final b, c, d or ğ softened
+/// before a vowel is hardened back, "kitabı" → "kitap".
 pub struct TurkishMorpher {}
 
+const TURKISH_VOWELS: &str = "aeıioöuü";
+
+/// Suffixes of each slot of the chain from the end of a word, longer ones first.
+const CASE_SUFFIXES: &[&str] = &[
+    "nın", "nin", "nun", "nün", "dan", "den", "tan", "ten", "da", "de", "ta", "te", "ya", "ye",
+    "yı", "yi", "yu", "yü", "na", "ne", "nı", "ni", "nu", "nü", "ın", "in", "un", "ün", "a", "e",
+    "ı", "i", "u", "ü",
+];
+const POSSESSIVE_SUFFIXES: &[&str] = &[
+    "ımız", "imiz", "umuz", "ümüz", "ınız", "iniz", "unuz", "ünüz", "ları", "leri", "sı", "si",
+    "su", "sü", "ım", "im", "um", "üm", "ın", "in", "un", "ün", "m", "n", "ı", "i", "u", "ü",
+];
+const PLURAL_SUFFIXES: &[&str] = &["lar", "ler"];
+
+fn is_turkish_vowel(c: char) -> bool {
+    TURKISH_VOWELS.contains(c)
+}
+
+/// Whether `suffix` can follow `stem`: its vowels harmonize with the last vowel of the
+/// stem, a buffer consonant comes after a vowel and a vowel or t after a consonant,
+/// a voiceless one for t.
+fn can_follow(stem: &str, suffix: &str) -> bool {
+    let last = match stem.chars().last() {
+        Some(n) => n,
+        None => return false,
+    };
+    let vowel = match stem.chars().rev().find(|c| is_turkish_vowel(*c)) {
+        Some(n) => n,
+        None => return false,
+    };
+    let back = "aıou".contains(vowel);
+    let rounded = "oöuü".contains(vowel);
+    let harmonizes = suffix
+        .chars()
+        .filter(|c| is_turkish_vowel(*c))
+        .all(|c| match c {
+            'a' | 'e' => (c == 'a') == back,
+            'ı' | 'i' | 'u' | 'ü' => {
+                c == match (back, rounded) {
+                    (true, false) => 'ı',
+                    (false, false) => 'i',
+                    (true, true) => 'u',
+                    (false, true) => 'ü',
+                }
+            }
+            _ => false,
+        });
+    let first = suffix.chars().next().unwrap();
+    let joins = match first {
+        'y' | 's' | 'n' | 'm' => is_turkish_vowel(last),
+        't' => "fstkçşhp".contains(last),
+        c if is_turkish_vowel(c) => !is_turkish_vowel(last),
+        _ => true,
+    };
+    harmonizes && joins
+}
+
+/// Adds to `forms` the stems left by removing one of `suffixes` from each of them, with
+/// the first letter of the suffix that was removed.
+fn strip_slot(forms: &mut Vec<(String, Option<char>)>, suffixes: &[&str]) {
+    let mut stripped = Vec::new();
+    for (form, _) in forms.iter() {
+        for suffix in suffixes {
+            if let Some(stem) = form.strip_suffix(suffix) {
+                let long_enough = stem.chars().count() >= 2;
+                if long_enough && can_follow(stem, suffix) {
+                    stripped.push((stem.to_string(), suffix.chars().next()));
+                }
+            }
+        }
+    }
+    forms.extend(stripped);
+}
+
 impl Morpher for TurkishMorpher {
     fn possible_roots(&self, word: &str) -> Vec<String> {
-        let mut roots = vec![String::from(word)];
-        if word.ends_with("ler") {
-            roots.push(word.strip_suffix("ler").unwrap().to_string())
+        let mut forms = vec![(String::from(word), None)];
+        strip_slot(&mut forms, CASE_SUFFIXES);
+        strip_slot(&mut forms, POSSESSIVE_SUFFIXES);
+        strip_slot(&mut forms, PLURAL_SUFFIXES);
+        let mut roots = Vec::new();
+        for (stem, next) in forms {
+            if next.is_some_and(is_turkish_vowel) {
+                let mut chars = stem.chars();
+                let last = chars.next_back();
+                let before = chars.as_str();
+                let hardened = match last {
+                    Some('b') => Some('p'),
+                    Some('c') => Some('ç'),
+                    Some('d') => Some('t'),
+                    Some('g') if before.ends_with('n') => Some('k'),
+                    Some('ğ') => Some('k'),
+                    _ => None,
+                };
+                if let Some(n) = hardened {
+                    roots.push(format!("{}{}", before, n));
+                }
+            }
+            roots.push(stem);
         }
+        let mut seen = HashSet::new();
+        roots.retain(|n| seen.insert(n.clone()));
         roots
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_strip_turkish_suffix_chain() {
+        let roots = |word| TurkishMorpher {}.possible_roots(word);
+        for (word, root) in [
+            ("evler", "ev"),
+            ("kitabı", "kitap"),
+            ("evlerimizden", "ev"),
+            ("kitaplarım", "kitap"),
+            ("evde", "ev"),
+            ("kitapta", "kitap"),
+            ("annem", "anne"),
+            ("arabaya", "araba"),
+            ("köpeği", "köpek"),
+            ("ağacı", "ağaç"),
+            ("çocuğun", "çocuk"),
+            ("rengi", "renk"),
+            ("okuldan", "okul"),
+            ("gözlerini", "göz"),
+        ] {
+            assert!(roots(word).contains(&root.to_string()), "{}", word);
+        }
+        assert_eq!(roots("evler")[0], "evler");
+        //The vowels of suffixes have to agree with the root.
+        assert!(!roots("evlar").contains(&"ev".to_string()));
+        assert!(!roots("kitapde").contains(&"kitap".to_string()));
+        assert_eq!(roots("ev"), vec!["ev"]);
+    }
+
+    #[test]
+    fn should_strip_english_inflections() {
+        let roots = |word| EnglishMorpher {}.possible_roots(word);
+        for (word, root) in [
+            ("running", "run"),
+            ("stopped", "stop"),
+            ("loved", "love"),
+            ("making", "make"),
+            ("walked", "walk"),
+            ("flies", "fly"),
+            ("tried", "try"),
+            ("boxes", "box"),
+            ("cats", "cat"),
+            ("bigger", "big"),
+            ("happiest", "happy"),
+            ("larger", "large"),
+            ("calling", "call"),
+        ] {
+            assert!(roots(word).contains(&root.to_string()), "{}", word);
+        }
+        assert_eq!(roots("running")[0], "running");
+        assert_eq!(roots("glass"), vec!["glass"]);
+        assert_eq!(roots("bed"), vec!["bed"]);
+    }
+}
@@ -0,0 +1,274 @@
+//! Ordered steps applied to queries and headwords before they are compared, so
+//! "Çiçek!" can match "cicek" in groups configured to ignore case and accents.
+use crate::matcher::{WordBounds, WordMatcher};
+use crate::transliteration;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    CaseFold,
+    /// Removes diacritics, "çiçek" becomes "cicek".
+    AccentFold,
+    /// Also searches the spellings the group's transliteration table allows, only
+    /// applies to queries.
+    Transliterate,
+    StripPunctuation,
+}
+
+pub const STEP_NAMES: &[&str] = &[
+    "case_fold",
+    "accent_fold",
+    "transliterate",
+    "strip_punctuation",
+];
+
+impl Step {
+    fn apply(self, word: &str) -> String {
+        match self {
+            Step::CaseFold => word.to_lowercase(),
+            Step::AccentFold => word
+                .nfd()
+                .filter(|c| !is_combining_mark(*c))
+                .map(|c| match c {
+                    //Letters that have no decomposition.
+                    'ı' => 'i',
+                    'ł' => 'l',
+                    'Ł' => 'L',
+                    'ø' => 'o',
+                    'Ø' => 'O',
+                    'đ' => 'd',
+                    'Đ' => 'D',
+                    c => c,
+                })
+                .collect(),
+            Step::Transliterate => word.to_string(),
+            Step::StripPunctuation => word
+                .chars()
+                .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+                .collect(),
+        }
+    }
+}
+
+/// `word` as it's searched, with any whitespace at its ends removed and whitespace in it
+/// collapsed to single spaces. Invisible characters like zero width spaces pasted along
+/// with a word are dropped. `None` if nothing is left, there is nothing to search.
+pub fn clean_query(word: &str) -> Option<String> {
+    let word: String = word
+        .chars()
+        .filter(|c| !matches!(c, '\u{200b}'..='\u{200d}' | '\u{2060}' | '\u{feff}'))
+        .collect();
+    let word = word.split_whitespace().collect::<Vec<&str>>().join(" ");
+    Some(word).filter(|w| !w.is_empty())
+}
+
+/// Normalization steps of a group with the transliteration table they use.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Pipeline {
+    pub steps: Vec<Step>,
+    pub transliteration: HashMap<String, String>,
+}
+
+impl Pipeline {
+    pub fn new(steps: Vec<Step>, transliteration: HashMap<String, String>) -> Pipeline {
+        Pipeline {
+            steps,
+            transliteration,
+        }
+    }
+
+    /// Forms of `word` to search, it's normalized and every spelling the
+    /// transliteration step allows.
+    pub fn query_forms(&self, word: &str) -> Vec<String> {
+        let mut forms = vec![word.to_string()];
+        for step in &self.steps {
+            forms = match step {
+                Step::Transliterate => forms
+                    .iter()
+                    .flat_map(|f| transliteration::variants(f, &self.transliteration))
+                    .collect(),
+                step => forms.iter().map(|f| step.apply(f)).collect(),
+            };
+            let mut seen = Vec::with_capacity(forms.len());
+            forms.retain(|f| {
+                let new = !seen.contains(f);
+                if new {
+                    seen.push(f.clone());
+                }
+                new
+            });
+        }
+        forms
+    }
+
+    /// Headword as it should be compared with normalized queries.
+    pub fn normalize_headword(&self, word: &str) -> String {
+        let mut word = word.to_string();
+        for step in &self.steps {
+            word = step.apply(&word);
+        }
+        word
+    }
+
+    /// Whether headwords are changed at all, comparing them as they are is cheaper.
+    pub fn changes_headwords(&self) -> bool {
+        self.steps.iter().any(|s| *s != Step::Transliterate)
+    }
+}
+
+/// Compares normalized queries with headwords normalized by `pipeline`.
+pub struct NormalizingMatcher<'a, M: ?Sized + WordMatcher + Sync> {
+    pub inner: &'a M,
+    pub pipeline: &'a Pipeline,
+}
+
+impl<M: ?Sized + WordMatcher + Sync> WordMatcher for NormalizingMatcher<'_, M> {
+    fn compare(&self, first: &str, second: &str) -> bool {
+        self.inner
+            .compare(first, &self.pipeline.normalize_headword(second))
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "{} with normalization {:?}",
+            self.inner.name(),
+            self.pipeline.steps
+        )
+    }
+
+    fn distance(&self, first: &str, second: &str) -> Option<usize> {
+        self.inner
+            .distance(first, &self.pipeline.normalize_headword(second))
+    }
+
+    fn score(&self, first: &str, second: &str) -> Option<f64> {
+        self.inner
+            .score(first, &self.pipeline.normalize_headword(second))
+    }
+}
+
+/// Matches headwords any of `forms` matches with `inner`, so every spelling of a query
+/// is compared in one pass over the index. The word it's given is ignored.
+pub struct VariantsMatcher<'a, M: ?Sized + WordMatcher + Sync> {
+    pub inner: &'a M,
+    pub forms: &'a [String],
+}
+
+impl<M: ?Sized + WordMatcher + Sync> WordMatcher for VariantsMatcher<'_, M> {
+    fn compare(&self, _first: &str, second: &str) -> bool {
+        self.forms.iter().any(|f| self.inner.compare(f, second))
+    }
+
+    fn name(&self) -> String {
+        format!("{} of {} spellings", self.inner.name(), self.forms.len())
+    }
+
+    fn distance(&self, _first: &str, second: &str) -> Option<usize> {
+        self.forms
+            .iter()
+            .filter(|f| self.inner.compare(f, second))
+            .filter_map(|f| self.inner.distance(f, second))
+            .min()
+    }
+
+    fn score(&self, _first: &str, second: &str) -> Option<f64> {
+        self.forms
+            .iter()
+            .filter(|f| self.inner.compare(f, second))
+            .filter_map(|f| self.inner.score(f, second))
+            .reduce(f64::max)
+    }
+
+    fn bounds(&self, _word: &str) -> Option<WordBounds> {
+        let mut bounds = self.forms.iter().map(|f| self.inner.bounds(f));
+        let first = bounds.next()??;
+        bounds.try_fold(first, |all, form| {
+            let form = form?;
+            Some(WordBounds {
+                min_length: all.min_length.min(form.min_length),
+                max_length: all.max_length.max(form.max_length),
+                first: all.first.filter(|&c| form.first == Some(c)),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::ExactMatcher;
+
+    #[test]
+    fn should_clean_whitespace_of_queries() {
+        assert_eq!(
+            clean_query("  ice \t\u{a0}cream\n").as_deref(),
+            Some("ice cream")
+        );
+        assert_eq!(clean_query("\u{feff}elma\u{200b}").as_deref(), Some("elma"));
+        assert_eq!(clean_query(""), None);
+        assert_eq!(clean_query(" \u{3000}\u{2028}\r\n"), None);
+        assert_eq!(clean_query("\u{200b}"), None);
+    }
+
+    #[test]
+    fn should_apply_steps_in_order() {
+        let pipeline = Pipeline::new(
+            vec![Step::CaseFold, Step::AccentFold, Step::StripPunctuation],
+            HashMap::new(),
+        );
+        assert_eq!(pipeline.query_forms("Çiçek!"), vec!["cicek"]);
+        assert_eq!(pipeline.normalize_headword("Işık"), "isik");
+        assert_eq!(pipeline.normalize_headword("e-posta"), "eposta");
+    }
+
+    #[test]
+    fn should_transliterate_only_queries() {
+        let pipeline = Pipeline::new(
+            vec![Step::CaseFold, Step::Transliterate],
+            transliteration::builtin_table("tr").unwrap(),
+        );
+        assert!(pipeline
+            .query_forms("Cicek")
+            .contains(&String::from("çiçek")));
+        assert_eq!(pipeline.normalize_headword("Cicek"), "cicek");
+        assert!(pipeline.changes_headwords());
+        assert!(!Pipeline::new(vec![Step::Transliterate], HashMap::new()).changes_headwords());
+    }
+
+    #[test]
+    fn should_match_normalized_headwords() {
+        let pipeline = Pipeline::new(vec![Step::CaseFold, Step::AccentFold], HashMap::new());
+        let matcher = NormalizingMatcher {
+            inner: &ExactMatcher {},
+            pipeline: &pipeline,
+        };
+        assert!(matcher.compare("cicek", "Çiçek"));
+        assert!(!matcher.compare("cicek", "Çiçekçi"));
+    }
+
+    #[test]
+    fn should_match_any_spelling() {
+        let forms = [String::from("cicek"), String::from("çiçekçi")];
+        let matcher = VariantsMatcher {
+            inner: &ExactMatcher {},
+            forms: &forms,
+        };
+        assert!(matcher.compare("", "çiçekçi"));
+        assert!(!matcher.compare("cicek", "çiçek"));
+        let bounds = matcher.bounds("").unwrap();
+        assert_eq!((bounds.min_length, bounds.max_length), (5, 7));
+        assert_eq!(bounds.first, None);
+    }
+
+    #[test]
+    fn should_name_steps_like_settings() {
+        for name in STEP_NAMES {
+            let step: Step = serde_json::from_value(serde_json::json!(name)).unwrap();
+            assert_eq!(serde_json::to_value(step).unwrap(), serde_json::json!(name));
+        }
+    }
+}
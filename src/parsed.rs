@@ -0,0 +1,696 @@
+//! Definitions broken into senses with their examples, the part of speech and the
+//! etymology, as far as the format or the layout of the text shows them. The raw text
+//! stays in `Definition::definition`, this is for the terminal layout and for clients
+//! that want the structure.
+use crate::html::decode_entities;
+use serde::Serialize;
+
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct ParsedDefinition {
+    /// Full name of the part of speech, "noun" for "n.".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub part_of_speech: Option<String>,
+    pub senses: Vec<Sense>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etymology: Option<String>,
+    /// Headwords the definition refers to, like the `<kref>` links of XDXF or the
+    /// `{synonyms}` of WordNet.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub see_also: Vec<String>,
+}
+
+/// One meaning of a word with the sentences showing it in use.
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct Sense {
+    pub text: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<String>,
+}
+
+/// Abbreviations and names of parts of speech in English and Turkish dictionaries with
+/// the names they are given as.
+const PARTS_OF_SPEECH: &[(&str, &str)] = &[
+    ("n", "noun"),
+    ("noun", "noun"),
+    ("v", "verb"),
+    ("vt", "verb"),
+    ("vi", "verb"),
+    ("verb", "verb"),
+    ("adj", "adjective"),
+    ("adjective", "adjective"),
+    ("adv", "adverb"),
+    ("adverb", "adverb"),
+    ("prep", "preposition"),
+    ("preposition", "preposition"),
+    ("pron", "pronoun"),
+    ("pronoun", "pronoun"),
+    ("conj", "conjunction"),
+    ("conjunction", "conjunction"),
+    ("interj", "interjection"),
+    ("interjection", "interjection"),
+    ("isim", "isim"),
+    ("sıfat", "sıfat"),
+    ("fiil", "fiil"),
+    ("zarf", "zarf"),
+    ("zamir", "zamir"),
+    ("edat", "edat"),
+    ("bağlaç", "bağlaç"),
+    ("ünlem", "ünlem"),
+];
+
+/// Starts of lines that give the origin of the word.
+const ETYMOLOGY_PREFIXES: &[&str] = &["etymology:", "etymology", "etym.", "köken:", "kökeni:"];
+
+/// Starts of lines that are examples of the sense before them.
+const EXAMPLE_PREFIXES: &[&str] = &["e.g.", "ex:", "example:", "örnek:", "›"];
+
+fn part_of_speech(token: &str) -> Option<&'static str> {
+    let token = token
+        .trim_matches(|c| matches!(c, '(' | ')' | '[' | ']' | ','))
+        .trim_end_matches('.')
+        .to_lowercase();
+    PARTS_OF_SPEECH
+        .iter()
+        .find(|(name, _)| *name == token)
+        .map(|(_, full)| *full)
+}
+
+/// `line` without a leading `prefix` of `prefixes`, ignoring case.
+fn strip_any_prefix<'a>(line: &'a str, prefixes: &[&str]) -> Option<&'a str> {
+    let lower = line.to_lowercase();
+    prefixes
+        .iter()
+        .find(|p| lower.starts_with(*p))
+        .and_then(|p| line.get(p.len()..))
+        .map(|n| n.trim_start_matches([':', ' ']).trim())
+}
+
+/// Length of a sense number like "2.", "2)", "2:" or "b)" followed by a space at the
+/// start of `line`.
+fn sense_marker(line: &str) -> Option<usize> {
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    let number = if digits > 0 {
+        digits
+    } else if line.starts_with(|c: char| c.is_ascii_lowercase()) && line[1..].starts_with(')') {
+        1
+    } else {
+        return None;
+    };
+    let rest = &line[number..];
+    if rest.starts_with(['.', ')', ':']) && rest[1..].starts_with(' ') {
+        Some(number + 2)
+    } else {
+        None
+    }
+}
+
+/// Splits a line numbered inline, "1. first 2. second", at each next number.
+fn split_inline_numbers(line: &str) -> Vec<&str> {
+    if !line.starts_with("1") || sense_marker(line).is_none() {
+        return vec![line];
+    }
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut next = 2;
+    loop {
+        let marker = format!(" {}. ", next);
+        let alternative = format!(" {}) ", next);
+        let found = line[start + 1..]
+            .find(&marker)
+            .or_else(|| line[start + 1..].find(&alternative));
+        match found {
+            Some(n) => {
+                let end = start + 1 + n;
+                pieces.push(line[start..end].trim());
+                start = end + 1;
+                next += 1;
+            }
+            None => break,
+        }
+    }
+    pieces.push(line[start..].trim());
+    pieces
+}
+
+fn is_quoted(text: &str) -> bool {
+    let quoted =
+        |open: char, close: char| text.len() > 1 && text.starts_with(open) && text.ends_with(close);
+    quoted('"', '"') || quoted('“', '”')
+}
+
+fn unquote(text: &str) -> &str {
+    text.trim_matches(['"', '“', '”']).trim()
+}
+
+/// Moves the quoted sentences after a colon or semicolon of `sense` to its examples,
+/// "to run: "he runs"". Quoted words inside the text stay.
+fn take_quoted_examples(sense: &mut Sense) {
+    let mut text = String::new();
+    let mut rest = sense.text.as_str();
+    let mut taken = 0;
+    while let Some(start) = rest.find(['"', '“']) {
+        let close = if rest[start..].starts_with('"') {
+            '"'
+        } else {
+            '”'
+        };
+        let open_len = rest[start..].chars().next().unwrap().len_utf8();
+        let end = match rest[start + open_len..].find(close) {
+            Some(n) => start + open_len + n,
+            None => break,
+        };
+        let before = rest[..start].trim();
+        let introduced = before.ends_with([':', ';'])
+            || (before.is_empty() && text.trim().is_empty())
+            || (taken > 0 && matches!(before, "" | ","));
+        let example = rest[start + open_len..end].trim();
+        if introduced && !example.is_empty() {
+            sense.examples.push(example.to_string());
+            taken += 1;
+            text.push_str(&rest[..start]);
+        } else {
+            text.push_str(&rest[..end + close.len_utf8()]);
+        }
+        rest = &rest[end + close.len_utf8()..];
+    }
+    text.push_str(rest);
+    if text.len() != sense.text.len() {
+        sense.text = text
+            .trim_end_matches([':', ';', ',', ' '])
+            .trim()
+            .to_string();
+    }
+}
+
+/// Parses plain text definitions, senses are numbered lines or every line if none is
+/// numbered. A leading part of speech, example and etymology lines are recognized.
+pub fn parse_text(text: &str) -> ParsedDefinition {
+    let mut parsed = ParsedDefinition::default();
+    let mut lines: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .collect();
+    if let Some(first) = lines.first_mut() {
+        let token = first.split_whitespace().next().unwrap_or_default();
+        if let Some(name) = part_of_speech(token) {
+            parsed.part_of_speech = Some(name.to_string());
+            *first = first[token.len()..].trim();
+        }
+    }
+    let lines: Vec<&str> = lines.into_iter().flat_map(split_inline_numbers).collect();
+    let numbered = lines.iter().any(|n| sense_marker(n).is_some());
+    for line in lines {
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(etymology) = strip_any_prefix(line, ETYMOLOGY_PREFIXES) {
+            parsed.etymology = Some(etymology.to_string());
+            continue;
+        }
+        let example = strip_any_prefix(line, EXAMPLE_PREFIXES)
+            .or_else(|| Some(line).filter(|n| is_quoted(n)));
+        if let (Some(example), Some(sense)) = (example, parsed.senses.last_mut()) {
+            sense.examples.push(unquote(example).to_string());
+            continue;
+        }
+        match sense_marker(line) {
+            Some(n) => parsed.senses.push(Sense {
+                text: line[n..].trim().to_string(),
+                examples: Vec::new(),
+            }),
+            //Lines after a numbered sense continue it.
+            None if numbered && !parsed.senses.is_empty() => {
+                let sense = parsed.senses.last_mut().unwrap();
+                sense.text.push(' ');
+                sense.text.push_str(line);
+            }
+            None => parsed.senses.push(Sense {
+                text: line.to_string(),
+                examples: Vec::new(),
+            }),
+        }
+    }
+    parsed.senses.iter_mut().for_each(take_quoted_examples);
+    parsed.senses.retain(|n| !n.text.is_empty());
+    parsed
+}
+
+/// `text` without MediaWiki markup: links are replaced by their labels, templates and
+/// bold or italic quotes are removed.
+pub fn strip_wiki_markup(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut rest = text;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("{{") {
+            rest = after.find("}}").map_or("", |n| &after[n + 2..]);
+        } else if let Some(after) = rest.strip_prefix("[[") {
+            let end = after.find("]]").unwrap_or(after.len());
+            let link = &after[..end];
+            plain.push_str(link.rsplit('|').next().unwrap_or(link));
+            rest = after.get(end + 2..).unwrap_or("");
+        } else {
+            let c = rest.chars().next().unwrap();
+            plain.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    plain
+        .replace("'''", "")
+        .replace("''", "")
+        .lines()
+        .map(|n| n.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Parses Wiktionary style MediaWiki definitions: `# ` lines are senses, `#:` and `#*`
+/// lines their examples, headings give the part of speech and the etymology. Text
+/// without sense lines is parsed as plain text.
+pub fn parse_mediawiki(text: &str) -> ParsedDefinition {
+    #[derive(PartialEq)]
+    enum Section {
+        Etymology,
+        Other,
+    }
+    let mut parsed = ParsedDefinition::default();
+    let mut section = Section::Other;
+    let mut etymology: Vec<String> = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('=') {
+            let heading = line.trim_matches(|c| c == '=' || c == ' ');
+            section = Section::Other;
+            if heading.to_lowercase().starts_with("etymology") {
+                section = Section::Etymology;
+            } else if let Some(name) = part_of_speech(heading) {
+                parsed
+                    .part_of_speech
+                    .get_or_insert_with(|| name.to_string());
+            }
+            continue;
+        }
+        if let Some(example) = line.strip_prefix("#:").or_else(|| line.strip_prefix("#*")) {
+            if let Some(sense) = parsed.senses.last_mut() {
+                let example = strip_wiki_markup(example.trim_start_matches([':', '*']));
+                if !example.is_empty() {
+                    sense.examples.push(example);
+                }
+            }
+        } else if let Some(sense) = line.strip_prefix('#') {
+            let text = strip_wiki_markup(sense.trim_start_matches('#'));
+            if !text.is_empty() {
+                parsed.senses.push(Sense {
+                    text,
+                    examples: Vec::new(),
+                });
+            }
+        } else if section == Section::Etymology && !line.is_empty() {
+            etymology.push(strip_wiki_markup(line));
+        }
+    }
+    if !etymology.is_empty() {
+        parsed.etymology = Some(etymology.join(" "));
+    }
+    if parsed.senses.is_empty() {
+        let mut plain = parse_text(&strip_wiki_markup(text));
+        plain.part_of_speech = plain.part_of_speech.or(parsed.part_of_speech);
+        plain.etymology = plain.etymology.or(parsed.etymology);
+        return plain;
+    }
+    parsed
+}
+
+/// Plain text of XDXF markup: `<def>` and `<br>` end lines, examples in `<ex>` are put
+/// on lines of their own in quotes and the words of `<kref>` links are added to
+/// `references`. With `headword` the `<k>` headword and `<tr>` transcription are kept.
+fn xdxf_text(text: &str, headword: bool, references: &mut Vec<String>) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut skipping = false;
+    let mut reference_start = None;
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        if !skipping {
+            plain.push_str(&decode_entities(&rest[..start]));
+        }
+        let end = match rest[start..].find('>') {
+            Some(n) => start + n,
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        };
+        let tag = &rest[start + 1..end];
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match (name.as_str(), closing) {
+            ("k", _) | ("tr", _) if !headword => skipping = !closing,
+            ("tr", false) => plain.push('['),
+            ("tr", true) => plain.push(']'),
+            ("ex", false) => plain.push_str("\n\""),
+            ("ex", true) => plain.push_str("\"\n"),
+            ("def", _) | ("br", _) => plain.push('\n'),
+            ("kref", false) => reference_start = Some(plain.len()),
+            ("kref", true) => {
+                if let Some(n) = reference_start.take() {
+                    let word = plain[n..].trim();
+                    if !word.is_empty() && !references.iter().any(|r| r == word) {
+                        references.push(word.to_string());
+                    }
+                }
+            }
+            _ => (),
+        }
+        rest = &rest[end + 1..];
+    }
+    if !skipping {
+        plain.push_str(&decode_entities(rest));
+    }
+    plain
+}
+
+/// `text` without XDXF tags, headwords and transcriptions kept.
+pub fn strip_xdxf_markup(text: &str) -> String {
+    xdxf_text(text, true, &mut Vec::new())
+        .lines()
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Parses XDXF definitions like plain text after their markup is removed, the part of
+/// speech in `<abr>` or `<gr>` leads, `<def>` elements are senses and `<ex>` their
+/// examples. The repeated headword and its transcription are left out.
+pub fn parse_xdxf(text: &str) -> ParsedDefinition {
+    let mut see_also = Vec::new();
+    let plain = xdxf_text(text, false, &mut see_also);
+    let mut parsed = parse_text(&plain);
+    parsed.see_also = see_also;
+    parsed
+}
+
+/// Moves the `{words}` of WordNet definitions to `references`, the words stay in the
+/// text. `[syn: ...]`, `[ant: ...]` and `[also: ...]` lists are left out of the text.
+fn take_wordnet_references(text: &str, references: &mut Vec<String>) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(['{', '[']) {
+        plain.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let close = if rest.starts_with('{') { '}' } else { ']' };
+        let end = match rest.find(close) {
+            Some(n) => n,
+            None => break,
+        };
+        let inner = &rest[1..end];
+        let is_list = close == ']'
+            && inner
+                .split_once(':')
+                .is_some_and(|(label, _)| label.chars().all(|c| c.is_ascii_alphabetic()));
+        if close == ']' && !is_list {
+            plain.push('[');
+            rest = &rest[1..];
+            continue;
+        }
+        for word in inner
+            .split(['{', '}'])
+            .skip(usize::from(is_list))
+            .step_by(2)
+        {
+            let word = word.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !word.is_empty() && !references.contains(&word) {
+                references.push(word);
+            }
+        }
+        if !is_list {
+            plain.push_str(inner);
+        }
+        rest = &rest[end + 1..];
+    }
+    plain.push_str(rest);
+    plain
+}
+
+/// Parses WordNet definitions in the layout of its dict files: senses numbered under
+/// the part of speech, "n 1: ...", followed by "2: ...", with quoted examples after a
+/// semicolon and the words they link to in braces. Lines before the first part of
+/// speech, the headword, are left out. Senses of a later part of speech start with its
+/// name.
+pub fn parse_wordnet(text: &str) -> ParsedDefinition {
+    let mut first_part_of_speech = None;
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines().map(str::trim) {
+        let token = line.split_whitespace().next().unwrap_or_default();
+        let rest = line[token.len()..].trim_start();
+        let name = match (part_of_speech(token), sense_marker(rest)) {
+            (Some(name), Some(marker)) => Some((name, marker)),
+            _ => None,
+        };
+        match (name, first_part_of_speech) {
+            (Some((name, _)), None) => {
+                first_part_of_speech = Some(name);
+                lines.clear();
+                lines.push(rest.to_string());
+            }
+            (Some((name, marker)), Some(_)) => {
+                lines.push(format!("{}({}) {}", &rest[..marker], name, &rest[marker..]))
+            }
+            (None, _) => lines.push(line.to_string()),
+        }
+    }
+    let mut see_also = Vec::new();
+    let plain = take_wordnet_references(&lines.join("\n"), &mut see_also);
+    let mut parsed = parse_text(&plain);
+    if let Some(name) = first_part_of_speech {
+        parsed.part_of_speech = Some(name.to_string());
+    }
+    parsed.see_also = see_also;
+    parsed
+}
+
+/// `text` of a WordNet definition with the braces around linked words removed.
+pub fn strip_wordnet_markup(text: &str) -> String {
+    text.replace(['{', '}'], "")
+}
+
+impl ParsedDefinition {
+    /// Whether there is more to show than a single sense, the raw text reads as well then.
+    pub fn is_structured(&self) -> bool {
+        self.senses.len() > 1
+            || self.part_of_speech.is_some()
+            || self.etymology.is_some()
+            || self.senses.iter().any(|n| !n.examples.is_empty())
+            || !self.see_also.is_empty()
+    }
+
+    /// Lines for the terminal: the part of speech, numbered senses with their examples
+    /// indented under them, the etymology and the words it refers to, with what each of
+    /// them shows.
+    pub fn lines(&self) -> Vec<(LineKind, String)> {
+        let mut lines = Vec::new();
+        if let Some(name) = &self.part_of_speech {
+            lines.push((LineKind::PartOfSpeech, format!("({})", name)));
+        }
+        for (n, sense) in self.senses.iter().enumerate() {
+            let kind = if is_translation(&sense.text) {
+                LineKind::Translation
+            } else {
+                LineKind::Sense
+            };
+            if self.senses.len() > 1 {
+                lines.push((kind, format!("{}. {}", n + 1, sense.text)));
+            } else {
+                lines.push((kind, sense.text.clone()));
+            }
+            for example in &sense.examples {
+                lines.push((LineKind::Example, format!("   “{}”", example)));
+            }
+        }
+        if let Some(etymology) = &self.etymology {
+            lines.push((LineKind::Etymology, format!("Etymology: {}", etymology)));
+        }
+        if !self.see_also.is_empty() {
+            lines.push((
+                LineKind::SeeAlso,
+                format!("See also: {}", self.see_also.join(", ")),
+            ));
+        }
+        lines
+    }
+
+    /// `lines` as one text.
+    pub fn layout(&self) -> String {
+        self.lines()
+            .into_iter()
+            .map(|(_, line)| line)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// What a line of `ParsedDefinition::lines` shows, terminal output styles each kind.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineKind {
+    PartOfSpeech,
+    /// A sense explained in a sentence.
+    Sense,
+    /// A sense given as words of another language or synonyms, "elma, elma ağacı".
+    Translation,
+    Example,
+    Etymology,
+    SeeAlso,
+}
+
+/// Whether `sense` is a list of short glosses instead of an explanation, every comma or
+/// semicolon separated piece is at most three words and it isn't a sentence.
+fn is_translation(sense: &str) -> bool {
+    !sense.ends_with('.')
+        && sense
+            .split([',', ';'])
+            .all(|n| (1..=3).contains(&n.split_whitespace().count()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sense(text: &str, examples: &[&str]) -> Sense {
+        Sense {
+            text: text.to_string(),
+            examples: examples.iter().map(|n| n.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn should_parse_numbered_text_definitions() {
+        let parsed = parse_text(
+            "n. 1. a round fruit: \"an apple a day\"\n2) the tree it grows on\n   in orchards\n\
+             e.g. apple blossom\nEtymology: Old English æppel",
+        );
+        assert_eq!(parsed.part_of_speech.as_deref(), Some("noun"));
+        assert_eq!(
+            parsed.senses,
+            vec![
+                sense("a round fruit", &["an apple a day"]),
+                sense("the tree it grows on in orchards", &["apple blossom"]),
+            ]
+        );
+        assert_eq!(parsed.etymology.as_deref(), Some("Old English æppel"));
+        assert!(parsed.is_structured());
+        assert_eq!(
+            parsed.layout(),
+            "(noun)\n1. a round fruit\n   “an apple a day”\n2. the tree it grows on in orchards\n   \
+             “apple blossom”\nEtymology: Old English æppel"
+        );
+
+        let inline = parse_text("isim 1. meyve 2. ağaç 3. renk");
+        assert_eq!(inline.part_of_speech.as_deref(), Some("isim"));
+        assert_eq!(
+            inline.senses,
+            vec![sense("meyve", &[]), sense("ağaç", &[]), sense("renk", &[])]
+        );
+        assert_eq!(
+            parsed.lines()[3],
+            (
+                LineKind::Sense,
+                String::from("2. the tree it grows on in orchards")
+            )
+        );
+        assert_eq!(
+            parse_text("elma, elma ağacı").lines(),
+            vec![(LineKind::Translation, String::from("elma, elma ağacı"))]
+        );
+        let plain = parse_text("apple fruit. Red or green.");
+        assert_eq!(plain.senses, vec![sense("apple fruit. Red or green.", &[])]);
+        assert!(!plain.is_structured());
+        assert_eq!(parse_text("1.5 kg of apples").senses.len(), 1);
+        let quoted = parse_text("the word \"elma\" in Turkish; “elma yedim”, “elma al”");
+        assert_eq!(
+            quoted.senses,
+            vec![sense(
+                "the word \"elma\" in Turkish",
+                &["elma yedim", "elma al"]
+            )]
+        );
+    }
+
+    #[test]
+    fn should_parse_wiktionary_definitions() {
+        let parsed = parse_mediawiki(
+            "===Etymology===\nFrom {{inh|en|ang|æppel}} Old English.\n===Noun===\n\
+             {{en-noun}}\n# A [[fruit|round fruit]] of the '''apple''' tree.\n\
+             #: ''She ate an apple.''\n# {{lb|en|informal}} [[New York City]]",
+        );
+        assert_eq!(parsed.part_of_speech.as_deref(), Some("noun"));
+        assert_eq!(
+            parsed.senses,
+            vec![
+                sense("A round fruit of the apple tree.", &["She ate an apple."]),
+                sense("New York City", &[]),
+            ]
+        );
+        assert_eq!(parsed.etymology.as_deref(), Some("From Old English."));
+        let plain = parse_mediawiki("''elma'' meyvesi");
+        assert_eq!(plain.senses, vec![sense("elma meyvesi", &[])]);
+    }
+
+    #[test]
+    fn should_parse_xdxf_definitions() {
+        let text = "<k>apple</k>\n<tr>ˈæpl</tr>\n<abr>n.</abr>\n<def>1. a round \
+                    <c c=\"red\">fruit</c><ex>an apple &amp; a pear</ex></def>\n\
+                    <def>2. the tree, see <kref>apple tree</kref></def>";
+        let parsed = parse_xdxf(text);
+        assert_eq!(parsed.part_of_speech.as_deref(), Some("noun"));
+        assert_eq!(
+            parsed.senses,
+            vec![
+                sense("a round fruit", &["an apple & a pear"]),
+                sense("the tree, see apple tree", &[]),
+            ]
+        );
+        assert_eq!(parsed.see_also, vec!["apple tree"]);
+        assert_eq!(
+            parsed.lines().last().unwrap(),
+            &(LineKind::SeeAlso, String::from("See also: apple tree"))
+        );
+        assert_eq!(
+            strip_xdxf_markup("<k>elma</k>\n<tr>el'ma</tr> <dtrn>apple</dtrn>"),
+            "elma\n[el'ma] apple"
+        );
+    }
+
+    #[test]
+    fn should_parse_wordnet_definitions() {
+        let text = "apple\n     n 1: fruit with red or yellow or green skin; \"an apple a \
+                    day\"\n     2: native Eurasian tree widely cultivated [syn: {orchard \
+                    apple tree},\n        {Malus pumila}]\n     v 1: to pick {apples}";
+        let parsed = parse_wordnet(text);
+        assert_eq!(parsed.part_of_speech.as_deref(), Some("noun"));
+        assert_eq!(
+            parsed.senses,
+            vec![
+                sense(
+                    "fruit with red or yellow or green skin",
+                    &["an apple a day"]
+                ),
+                sense("native Eurasian tree widely cultivated", &[]),
+                sense("(verb) to pick apples", &[]),
+            ]
+        );
+        assert_eq!(
+            parsed.see_also,
+            vec!["orchard apple tree", "Malus pumila", "apples"]
+        );
+        assert_eq!(strip_wordnet_markup("see {apple}"), "see apple");
+        let plain = parse_wordnet("an [old] word");
+        assert_eq!(plain.senses, vec![sense("an [old] word", &[])]);
+        assert!(plain.see_also.is_empty());
+    }
+}
@@ -16,6 +16,8 @@ pub enum Operation {
     ReadDefinition,
     LoadDictionary,
     BulkSearch,
+    /// Suggesting words for a prefix typed in the interactive prompt.
+    Complete,
     Other,
 }
 
@@ -34,10 +36,14 @@ pub struct TimeLog {
     pub word: Option<String>,
     pub operation: Operation,
     pub comment: Option<String>,
+    /// Characters of the completed prefix, for `Operation::Complete`.
+    pub prefix_length: Option<usize>,
+    /// Number of suggestions found, for `Operation::Complete`.
+    pub candidates: Option<usize>,
     build: Build,
 }
 
-impl<'a> TimeLog {
+impl TimeLog {
     pub fn new() -> Self {
         const BUILD_TYPE: Build = if cfg!(debug_assertions) {
             Build::Debug
@@ -53,6 +59,8 @@ impl<'a> TimeLog {
             word: Default::default(),
             operation: Operation::Other,
             comment: Default::default(),
+            prefix_length: Default::default(),
+            candidates: Default::default(),
             build: BUILD_TYPE,
         }
     }
@@ -98,6 +106,16 @@ impl<'a> TimeLog {
         self
     }
 
+    pub fn prefix_length(mut self, prefix_length: usize) -> Self {
+        self.prefix_length = Some(prefix_length);
+        self
+    }
+
+    pub fn candidates(mut self, candidates: usize) -> Self {
+        self.candidates = Some(candidates);
+        self
+    }
+
     pub fn serialize(&self) -> String {
         serde_json::to_string_pretty(&self).unwrap()
     }
@@ -110,7 +128,42 @@ impl<'a> TimeLog {
     }
 }
 
+impl Default for TimeLog {
+    fn default() -> Self {
+        TimeLog::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test]
+    fn should_write_only_when_writer_is_set() {
+        let writer: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+        TimeLog::write(&writer, || TimeLog::new().word(&"elma"));
+        assert!(writer.lock().unwrap().is_none());
+
+        *writer.lock().unwrap() = Some(Vec::new());
+        TimeLog::write(&writer, || {
+            TimeLog::new().word(&"elma").operation(Operation::Search)
+        });
+        let written = String::from_utf8(writer.lock().unwrap().take().unwrap()).unwrap();
+        assert!(written.contains("\"elma\""));
+        assert!(written.contains("Search"));
+    }
+
+    #[test]
+    fn should_write_completion_details() {
+        let writer: Mutex<Option<Vec<u8>>> = Mutex::new(Some(Vec::new()));
+        TimeLog::write(&writer, || {
+            TimeLog::new()
+                .operation(Operation::Complete)
+                .prefix_length(2)
+                .candidates(5)
+        });
+        let written = String::from_utf8(writer.lock().unwrap().take().unwrap()).unwrap();
+        assert!(written.contains("\"prefix_length\": 2"));
+        assert!(written.contains("\"candidates\": 5"));
+    }
 }
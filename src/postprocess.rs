@@ -0,0 +1,100 @@
+//! Named transforms that rewrite the text of definitions before they are shown, for
+//! dictionaries whose converters left boilerplate or escaped characters in them.
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transform {
+    /// Drops a first line that only repeats the headword.
+    StripHeadword,
+    /// Turns `\n` and `\t` written as two characters into a new line and a tab.
+    UnescapeNewlines,
+    /// Removes HTML tags, keeping their text.
+    StripTags,
+    /// Trims every line and drops blank lines at the start, the end and repeated ones.
+    CollapseBlankLines,
+}
+
+pub const TRANSFORM_NAMES: &[&str] = &[
+    "strip_headword",
+    "unescape_newlines",
+    "strip_tags",
+    "collapse_blank_lines",
+];
+
+impl Transform {
+    /// Rewrites `text`, the definition of `word`.
+    pub fn apply(self, word: &str, text: &str) -> String {
+        match self {
+            Transform::StripHeadword => {
+                let is_headword =
+                    |line: &str| line.trim().to_lowercase() == word.trim().to_lowercase();
+                match text.trim_start().split_once('\n') {
+                    Some((first, rest)) if is_headword(first) => rest.to_string(),
+                    None if is_headword(text) => String::new(),
+                    _ => text.to_string(),
+                }
+            }
+            Transform::UnescapeNewlines => text.replace("\\n", "\n").replace("\\t", "\t"),
+            Transform::StripTags => {
+                let mut stripped = String::with_capacity(text.len());
+                let mut in_tag = false;
+                for c in text.chars() {
+                    match c {
+                        '<' => in_tag = true,
+                        '>' if in_tag => in_tag = false,
+                        c if !in_tag => stripped.push(c),
+                        _ => (),
+                    }
+                }
+                stripped
+            }
+            Transform::CollapseBlankLines => {
+                let mut lines: Vec<&str> = Vec::new();
+                for line in text.lines().map(str::trim) {
+                    if !line.is_empty() || lines.last().is_some_and(|l| !l.is_empty()) {
+                        lines.push(line);
+                    }
+                }
+                while lines.last() == Some(&"") {
+                    lines.pop();
+                }
+                lines.join("\n")
+            }
+        }
+    }
+}
+
+/// Applies `transforms` to `text` in order.
+pub fn apply_all(transforms: &[Transform], word: &str, text: &str) -> String {
+    transforms
+        .iter()
+        .fold(text.to_string(), |text, t| t.apply(word, &text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_apply_transforms_in_order() {
+        let text = "Elma\\n<b>apple</b>\\n\\n\\n  fruit  \\n";
+        let transforms = [
+            Transform::UnescapeNewlines,
+            Transform::StripHeadword,
+            Transform::StripTags,
+            Transform::CollapseBlankLines,
+        ];
+        assert_eq!(apply_all(&transforms, "elma", text), "apple\n\nfruit");
+        assert_eq!(apply_all(&[], "elma", text), text);
+        assert_eq!(Transform::StripHeadword.apply("elma", "apple"), "apple");
+    }
+
+    #[test]
+    fn should_name_transforms_like_settings() {
+        for name in TRANSFORM_NAMES {
+            let transform: Transform = serde_json::from_value(serde_json::json!(name)).unwrap();
+            assert_eq!(serde_json::to_value(transform).unwrap(), *name);
+        }
+    }
+}
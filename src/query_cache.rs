@@ -0,0 +1,60 @@
+//! Results of the last few queries, so searching a word again in the interactive
+//! loop doesn't scan every dictionary again.
+use std::collections::VecDeque;
+
+/// Holds at most `capacity` values, the least recently used one is dropped first.
+pub struct QueryCache<K, V> {
+    capacity: usize,
+    /// Most recently used first.
+    entries: VecDeque<(K, V)>,
+}
+
+impl<K: PartialEq, V> QueryCache<K, V> {
+    pub fn new(capacity: usize) -> QueryCache<K, V> {
+        QueryCache {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.entries.iter().any(|(k, _)| k == key)
+    }
+
+    /// Returns the value of `key` and marks it as the most recently used.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let position = self.entries.iter().position(|(k, _)| k == key)?;
+        let entry = self.entries.remove(position)?;
+        self.entries.push_front(entry);
+        self.entries.front().map(|(_, v)| v)
+    }
+
+    /// Adds `value`, replacing the old value of `key` if there is one.
+    pub fn insert(&mut self, key: K, value: V) -> &V {
+        self.entries.retain(|(k, _)| *k != key);
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_back();
+        }
+        self.entries.push_front((key, value));
+        &self.entries[0].1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_drop_least_recently_used() {
+        let mut cache = QueryCache::new(2);
+        cache.insert("elma", 1);
+        cache.insert("armut", 2);
+        assert_eq!(cache.get(&"elma"), Some(&1));
+        cache.insert("kitap", 3);
+        assert!(cache.contains(&"elma"));
+        assert!(!cache.contains(&"armut"));
+        assert_eq!(cache.insert("elma", 4), &4);
+        assert_eq!(cache.get(&"kitap"), Some(&3));
+        assert_eq!(cache.get(&"elma"), Some(&4));
+    }
+}
@@ -0,0 +1,123 @@
+//! Dictionaries loaded once and shared by every group they're configured in, whichever
+//! path leads to them.
+use crate::dictionary::Dictionary;
+use log::info;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Every loaded dictionary, keyed by the canonical path of its .ifo file. A copy of a
+/// registered dictionary in another directory, one with the same fingerprint, isn't
+/// registered again.
+#[derive(Clone, Default)]
+pub struct Registry {
+    dicts: HashMap<PathBuf, Arc<Dictionary>>,
+    /// IDs of the registered dictionaries by their fingerprint.
+    fingerprints: HashMap<u32, PathBuf>,
+}
+
+impl Registry {
+    /// Stable ID of a dictionary, the same for every path it can be reached from.
+    pub fn id(dictionary: &Dictionary) -> PathBuf {
+        fs::canonicalize(&dictionary.ifo_path).unwrap_or_else(|_| dictionary.ifo_path.clone())
+    }
+
+    /// The registered dictionary with the ID or the fingerprint of `dictionary`.
+    pub fn get(&self, dictionary: &Dictionary) -> Option<&Arc<Dictionary>> {
+        self.dicts.get(&Registry::id(dictionary)).or_else(|| {
+            let id = self.fingerprints.get(&dictionary.fingerprint().ok()?)?;
+            self.dicts.get(id)
+        })
+    }
+
+    pub fn contains(&self, dictionary: &Dictionary) -> bool {
+        self.get(dictionary).is_some()
+    }
+
+    /// Adds `dictionary`, or returns the already registered one it's the same as.
+    pub fn register(&mut self, dictionary: Dictionary) -> Arc<Dictionary> {
+        self.insert(Arc::new(dictionary))
+    }
+
+    /// Adds a dictionary that may be shared already, or returns the registered one it's
+    /// the same as.
+    pub fn insert(&mut self, dictionary: Arc<Dictionary>) -> Arc<Dictionary> {
+        if let Some(registered) = self.get(&dictionary) {
+            if registered.ifo_path != dictionary.ifo_path {
+                info!(
+                    "{} is the same dictionary as {}, loading it once.",
+                    dictionary.ifo_path.display(),
+                    registered.ifo_path.display()
+                );
+            }
+            return registered.clone();
+        }
+        let id = Registry::id(&dictionary);
+        if let Ok(fingerprint) = dictionary.fingerprint() {
+            self.fingerprints.insert(fingerprint, id.clone());
+        }
+        self.dicts.insert(id, dictionary.clone());
+        dictionary
+    }
+
+    pub fn has_bookname(&self, bookname: &str) -> bool {
+        self.dicts.values().any(|d| d.bookname == bookname)
+    }
+
+    /// Removes every dictionary named `bookname`.
+    pub fn remove_bookname(&mut self, bookname: &str) -> Vec<Arc<Dictionary>> {
+        let ids: Vec<PathBuf> = self
+            .dicts
+            .iter()
+            .filter(|(_, d)| d.bookname == bookname)
+            .map(|(id, _)| id.clone())
+            .collect();
+        self.fingerprints.retain(|_, id| !ids.contains(id));
+        ids.iter().filter_map(|id| self.dicts.remove(id)).collect()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Dictionary> {
+        self.dicts.values().map(|d| d.as_ref())
+    }
+
+    /// The registered dictionaries as they are shared with groups.
+    pub fn shared(&self) -> impl Iterator<Item = &Arc<Dictionary>> {
+        self.dicts.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.dicts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dicts.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::write_dictionary;
+
+    #[test]
+    fn should_register_copies_of_dictionary_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let words = [("elma", "apple")];
+        let first = write_dictionary(&dir.path().join("a"), "first", &words);
+        let copy = write_dictionary(&dir.path().join("b"), "first", &words);
+        let other = write_dictionary(dir.path(), "other", &words);
+        let mut registry = Registry::default();
+        let dictionary = registry.register(Dictionary::load_metadata(&first).unwrap());
+        let again = registry.register(Dictionary::load_metadata(&first.join("first.ifo")).unwrap());
+        assert!(Arc::ptr_eq(&dictionary, &again));
+        let copied = registry.register(Dictionary::load_metadata(&copy).unwrap());
+        assert!(Arc::ptr_eq(&dictionary, &copied));
+        registry.register(Dictionary::load_metadata(&other).unwrap());
+        assert_eq!(registry.len(), 2);
+
+        assert_eq!(registry.remove_bookname("first").len(), 1);
+        assert!(!registry.contains(&Dictionary::load_metadata(&copy).unwrap()));
+        assert_eq!(registry.len(), 1);
+    }
+}
@@ -0,0 +1,195 @@
+//! Files made from dictionaries to be read by other programs, pictures extracted from
+//! definitions and definitions rendered to HTML. Each dictionary has its own directory
+//! under `resources_root()`, the oldest files are removed once they grow past
+//! `SIZE_LIMIT`.
+use crate::dictionary::Dictionary;
+use log::{debug, warn};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Bytes all resource caches may take together.
+pub const SIZE_LIMIT: u64 = 256 * 1024 * 1024;
+
+/// `$XDG_CACHE_HOME/sozluk`, or `~/.cache/sozluk` when it isn't set.
+pub fn cache_root() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CACHE_HOME").filter(|n| !n.is_empty()) {
+        Some(n) => PathBuf::from(n),
+        None => env::home_dir()?.join(".cache"),
+    };
+    Some(base.join("sozluk"))
+}
+
+/// Directory the resource caches of every dictionary are in, index caches that can't be
+/// written next to their dictionaries are in the `index` directory next to it.
+pub fn resources_root() -> Option<PathBuf> {
+    cache_root().map(|n| n.join("resources"))
+}
+
+/// Keeps only the letters and digits of `name`, so it can be part of a file name.
+pub fn sanitize(name: &str) -> String {
+    name.chars().filter(|c| c.is_alphanumeric()).collect()
+}
+
+/// Resource cache directory of a single dictionary.
+#[derive(Debug)]
+pub struct ResourceCache {
+    root: PathBuf,
+    pub dir: PathBuf,
+}
+
+impl ResourceCache {
+    /// Cache of `dictionary` under `resources_root()`.
+    pub fn of(dictionary: &Dictionary) -> Option<ResourceCache> {
+        resources_root().map(|root| ResourceCache::in_root(&root, dictionary))
+    }
+
+    /// Cache of `dictionary` under `resources_root()`, or under the temporary directory
+    /// when there's no home directory for it, so its files are still cleaned up.
+    pub fn of_or_temporary(dictionary: &Dictionary) -> ResourceCache {
+        ResourceCache::of(dictionary).unwrap_or_else(|| {
+            ResourceCache::in_root(&env::temp_dir().join("sozluk-resources"), dictionary)
+        })
+    }
+
+    /// Cache of `dictionary` under `root`. Its directory is named after the bookname
+    /// and the checksum of the .ifo path, dictionaries with the same bookname don't
+    /// share it.
+    pub fn in_root(root: &Path, dictionary: &Dictionary) -> ResourceCache {
+        let ifo =
+            fs::canonicalize(&dictionary.ifo_path).unwrap_or_else(|_| dictionary.ifo_path.clone());
+        let checksum = crc32fast::hash(ifo.to_string_lossy().as_bytes());
+        ResourceCache {
+            root: root.to_path_buf(),
+            dir: root.join(format!(
+                "{}-{:08x}",
+                sanitize(&dictionary.bookname),
+                checksum
+            )),
+        }
+    }
+
+    /// Writes `data` to the read-only file `name` of the cache unless it already holds
+    /// it, then removes the oldest files of every cache if they take more than
+    /// `SIZE_LIMIT`. Files are read-only so programs opening them don't change them.
+    pub fn store(&self, name: &str, data: &[u8]) -> io::Result<PathBuf> {
+        let path = self.dir.join(sanitize_file_name(name));
+        if fs::metadata(&path).is_ok_and(|m| m.len() == data.len() as u64) {
+            debug!("{} is already cached.", path.display());
+            return Ok(path);
+        }
+        fs::create_dir_all(&self.dir)?;
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        fs::write(&path, data)?;
+        let mut permissions = fs::metadata(&path)?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&path, permissions)?;
+        if let Err(e) = collect_garbage(&self.root, SIZE_LIMIT, &path) {
+            warn!("Can't clean up {}: {}", self.root.display(), e);
+        }
+        Ok(path)
+    }
+}
+
+/// `name` without path separators, keeping the dots of its extension.
+fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, '.' | '-' | '_'))
+        .collect::<String>()
+        .trim_start_matches('.')
+        .to_string()
+}
+
+/// Files under `dir` with their sizes and modification times.
+fn files_in(dir: &Path) -> io::Result<Vec<(PathBuf, u64, SystemTime)>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)?.flatten() {
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+            } else {
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                files.push((entry.path(), metadata.len(), modified));
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Removes the least recently written files under `root`, except `keep`, until the
+/// rest take at most `limit` bytes. Returns the number of bytes freed.
+pub fn collect_garbage(root: &Path, limit: u64, keep: &Path) -> io::Result<u64> {
+    let mut files = files_in(root)?;
+    let mut total: u64 = files.iter().map(|f| f.1).sum();
+    files.sort_by_key(|f| f.2);
+    let mut freed = 0;
+    for (path, size, _) in files {
+        if total <= limit {
+            break;
+        }
+        if path == keep {
+            continue;
+        }
+        debug!("Removing {} from the resource cache.", path.display());
+        fs::remove_file(&path)?;
+        total -= size;
+        freed += size;
+    }
+    Ok(freed)
+}
+
+/// Removes every resource cache under `root`, returns the number of bytes freed.
+pub fn clear(root: &Path) -> io::Result<u64> {
+    let freed = match files_in(root) {
+        Ok(n) => n.iter().map(|f| f.1).sum(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    fs::remove_dir_all(root)?;
+    Ok(freed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::write_dictionary;
+    use std::time::Duration;
+
+    #[test]
+    fn should_store_resources_and_collect_garbage() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_dictionary(dir.path(), "Türkçe Sözlük", &[("elma", "apple")]);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        let root = dir.path().join("cache");
+        let cache = ResourceCache::in_root(&root, &dictionary);
+        assert!(cache
+            .dir
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("TürkçeSözlük-"));
+
+        let old = cache.store("../elma.png", b"old").unwrap();
+        assert_eq!(old, cache.dir.join("elma.png"));
+        let modified = SystemTime::now() - Duration::from_secs(60);
+        assert!(fs::metadata(&old).unwrap().permissions().readonly());
+        fs::File::open(&old)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let new = cache.store("armut.png", b"new").unwrap();
+        assert_eq!(collect_garbage(&root, 4, &new).unwrap(), 3);
+        assert!(!old.exists());
+        assert_eq!(fs::read(&new).unwrap(), b"new");
+
+        assert_eq!(clear(&root).unwrap(), 3);
+        assert!(!root.exists());
+        assert_eq!(clear(&root).unwrap(), 0);
+    }
+}
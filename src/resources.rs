@@ -0,0 +1,309 @@
+//! Resource storage of StarDict dictionaries, the pictures and sounds their definitions
+//! refer to by file name. Resources are either files of the `res` directory next to the
+//! .ifo file or entries of a database of `res.rifo`, `res.ridx` and `res.rdic` files in
+//! the same place, the database is preferred when both are there.
+use crate::dictzip::DictZip;
+use byteorder::{BigEndian, ReadBytesExt};
+use log::{debug, warn};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::mem::size_of;
+use std::path::{Component, Path, PathBuf};
+
+/// First line of every res.rifo file.
+const RIFO_MAGIC: &str = "StarDict's storage ifo file";
+
+#[derive(Debug)]
+pub enum ResourceStorage {
+    /// Files of the `res` directory.
+    Directory(PathBuf),
+    Database(ResourceDatabase),
+}
+
+/// Resources packed into res.rdic, or res.rdic.dz, and found through res.ridx.
+#[derive(Debug)]
+pub struct ResourceDatabase {
+    /// Names of the resources with the offset and size of their data, sorted by name.
+    entries: Vec<(String, u64, u32)>,
+    rdic_path: PathBuf,
+    /// Chunk table of a dictzip compressed res.rdic.dz file.
+    dictzip: Option<DictZip>,
+}
+
+/// `dir` joined with `name`, `None` if `name` would leave `dir`.
+pub fn path_in(dir: &Path, name: &str) -> Option<PathBuf> {
+    let name = Path::new(name);
+    if name
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+    Some(dir.join(name))
+}
+
+fn not_found(name: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("No resource named {}.", name),
+    )
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl ResourceStorage {
+    /// Storage of the dictionary whose .ifo file is in `dir`, `Ok(None)` if it has none.
+    pub fn open(dir: &Path) -> io::Result<Option<ResourceStorage>> {
+        let rifo = dir.join("res.rifo");
+        if rifo.is_file() {
+            return ResourceDatabase::open(&rifo).map(|n| Some(ResourceStorage::Database(n)));
+        }
+        let res = dir.join("res");
+        Ok(res.is_dir().then_some(ResourceStorage::Directory(res)))
+    }
+
+    /// Data of the resource `name`, fails with `io::ErrorKind::NotFound` if there is
+    /// none.
+    pub fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        match self {
+            ResourceStorage::Directory(dir) => {
+                let path = path_in(dir, name).ok_or_else(|| not_found(name))?;
+                fs::read(path)
+            }
+            ResourceStorage::Database(database) => database.read(name),
+        }
+    }
+
+    /// Number of resources in the storage.
+    pub fn len(&self) -> usize {
+        match self {
+            ResourceStorage::Directory(dir) => fs::read_dir(dir).map_or(0, |n| n.count()),
+            ResourceStorage::Database(database) => database.entries.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl ResourceDatabase {
+    /// Reads the entries of the res.ridx file next to `rifo`. The res.rdic file, or
+    /// res.rdic.dz if there is only that, is read by `read()`.
+    pub fn open(rifo: &Path) -> io::Result<ResourceDatabase> {
+        let info = fs::read_to_string(rifo)?;
+        let mut lines = info.lines();
+        if lines.next().map(str::trim) != Some(RIFO_MAGIC) {
+            return Err(invalid_data(format!(
+                "{} isn't a resource storage info file.",
+                rifo.display()
+            )));
+        }
+        let mut offset_bits = 32;
+        let mut filecount: Option<usize> = None;
+        for (key, value) in lines.filter_map(|l| l.split_once('=')) {
+            match key.trim() {
+                "ridxoffsetbits" if value.trim() == "64" => offset_bits = 64,
+                "filecount" => filecount = value.trim().parse().ok(),
+                _ => (),
+            }
+        }
+
+        let ridx_path = rifo.with_extension("ridx");
+        let entries = parse_ridx(&fs::read(&ridx_path)?, offset_bits, &ridx_path)?;
+        if filecount.is_some_and(|n| n != entries.len()) {
+            warn!(
+                "{} has {} resources but {} says {}.",
+                ridx_path.display(),
+                entries.len(),
+                rifo.display(),
+                filecount.unwrap()
+            );
+        }
+
+        let mut rdic_path = rifo.with_extension("rdic");
+        let dictzip_path = rifo.with_extension("rdic.dz");
+        if !rdic_path.exists() && dictzip_path.exists() {
+            rdic_path = dictzip_path;
+        }
+        let dictzip = if rdic_path.extension().is_some_and(|n| n == "dz") {
+            Some(DictZip::open(&rdic_path)?)
+        } else {
+            None
+        };
+        debug!(
+            "Read {} resources of {}.",
+            entries.len(),
+            ridx_path.display()
+        );
+        Ok(ResourceDatabase {
+            entries,
+            rdic_path,
+            dictzip,
+        })
+    }
+
+    fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        let n = self
+            .entries
+            .binary_search_by(|e| e.0.as_str().cmp(name))
+            .map_err(|_| not_found(name))?;
+        let (_, offset, size) = &self.entries[n];
+        let mut file = File::open(&self.rdic_path)?;
+        match &self.dictzip {
+            Some(dictzip) => dictzip.read(&mut file, *offset, *size as usize),
+            None => {
+                file.seek(SeekFrom::Start(*offset))?;
+                let mut buffer = vec![0; *size as usize];
+                file.read_exact(&mut buffer)?;
+                Ok(buffer)
+            }
+        }
+    }
+}
+
+/// Entries of a res.ridx file, each a NUL terminated name followed by the big endian
+/// offset and size of its data, sorted by name. Names that aren't valid UTF-8 are
+/// skipped, a truncated last entry is dropped.
+fn parse_ridx(raw: &[u8], offset_bits: u32, path: &Path) -> io::Result<Vec<(String, u64, u32)>> {
+    let offset_size = if offset_bits == 64 {
+        size_of::<u64>()
+    } else {
+        size_of::<u32>()
+    };
+    let mut entries = Vec::new();
+    let mut position = 0;
+    while let Some(n) = raw[position..].iter().position(|b| *b == 0) {
+        let name_end = position + n;
+        let end = name_end + 1 + offset_size + size_of::<u32>();
+        let mut fields = match raw.get(name_end + 1..end) {
+            Some(n) => n,
+            None => {
+                warn!("Truncated entry at the end of {}.", path.display());
+                break;
+            }
+        };
+        let offset = if offset_size == size_of::<u64>() {
+            fields.read_u64::<BigEndian>()?
+        } else {
+            fields.read_u32::<BigEndian>()?.into()
+        };
+        let size = fields.read_u32::<BigEndian>()?;
+        match std::str::from_utf8(&raw[position..name_end]) {
+            Ok(name) => entries.push((name.to_string(), offset, size)),
+            Err(_) => warn!(
+                "Skipping resource with invalid UTF-8 name at byte {} of {}.",
+                position,
+                path.display()
+            ),
+        }
+        position = end;
+    }
+    entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    Ok(entries)
+}
+
+/// Content type of a resource by the extension of its name.
+pub fn content_type(name: &str) -> &'static str {
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|n| n.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("bmp") => "image/bmp",
+        Some("svg") => "image/svg+xml",
+        Some("webp") => "image/webp",
+        Some("wav") => "audio/wav",
+        Some("mp3") => "audio/mpeg",
+        Some("ogg") | Some("oga") => "audio/ogg",
+        Some("spx") => "audio/speex",
+        Some("css") => "text/css",
+        Some("js") => "text/javascript",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dictzip::compress;
+    use byteorder::WriteBytesExt;
+    use tempfile::tempdir;
+
+    /// Writes a resource database of `resources` into `dir`, compressed with dictzip if
+    /// `dz` is set.
+    fn write_database(dir: &Path, resources: &[(&str, &[u8])], dz: bool) {
+        let mut ridx = Vec::new();
+        let mut rdic = Vec::new();
+        for (name, data) in resources {
+            ridx.extend_from_slice(name.as_bytes());
+            ridx.push(0);
+            ridx.write_u32::<BigEndian>(rdic.len() as u32).unwrap();
+            ridx.write_u32::<BigEndian>(data.len() as u32).unwrap();
+            rdic.extend_from_slice(data);
+        }
+        let rifo = format!(
+            "{}\nversion=3.0.0\nfilecount={}\nridxfilesize={}\n",
+            RIFO_MAGIC,
+            resources.len(),
+            ridx.len()
+        );
+        fs::write(dir.join("res.rifo"), rifo).unwrap();
+        fs::write(dir.join("res.ridx"), ridx).unwrap();
+        if dz {
+            fs::write(dir.join("res.rdic.dz"), compress(&rdic, 4)).unwrap();
+        } else {
+            fs::write(dir.join("res.rdic"), rdic).unwrap();
+        }
+    }
+
+    #[test]
+    fn should_read_resources_of_database() {
+        for dz in [false, true] {
+            let dir = tempdir().unwrap();
+            write_database(
+                dir.path(),
+                &[("sounds/elma.ogg", b"OggS..."), ("Elma.png", b"\x89PNG")],
+                dz,
+            );
+            let storage = ResourceStorage::open(dir.path()).unwrap().unwrap();
+            assert_eq!(storage.len(), 2);
+            assert_eq!(storage.read("Elma.png").unwrap(), b"\x89PNG");
+            assert_eq!(storage.read("sounds/elma.ogg").unwrap(), b"OggS...");
+            let error = storage.read("elma.png").unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        }
+    }
+
+    #[test]
+    fn should_read_resources_of_directory() {
+        let dir = tempdir().unwrap();
+        assert!(ResourceStorage::open(dir.path()).unwrap().is_none());
+        fs::create_dir(dir.path().join("res")).unwrap();
+        fs::write(dir.path().join("res/Elma.png"), b"\x89PNG").unwrap();
+        let storage = ResourceStorage::open(dir.path()).unwrap().unwrap();
+        assert_eq!(storage.read("Elma.png").unwrap(), b"\x89PNG");
+        assert!(storage.read("../res/Elma.png").is_err());
+        assert!(storage.read("b.jpg").is_err());
+    }
+
+    #[test]
+    fn should_reject_other_info_files() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("res.rifo"), "StarDict's dict ifo file\n").unwrap();
+        let error = ResourceStorage::open(dir.path()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn should_guess_content_types() {
+        assert_eq!(content_type("Elma.PNG"), "image/png");
+        assert_eq!(content_type("sounds/elma.mp3"), "audio/mpeg");
+        assert_eq!(content_type("elma"), "application/octet-stream");
+    }
+}
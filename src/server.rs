@@ -1,81 +1,1229 @@
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::io::Read;
 
-use log::{debug, error};
+use log::{debug, error, info, warn};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use serde::Deserialize;
-use tiny_http::Response;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use structopt::clap::ArgMatches;
+use structopt::StructOpt;
+use tiny_http::{Header, Method, Request, Response, ResponseBox};
 
 use crate::{
-    build_matcher, dictionary::Dictionary, indices_to_json, load_dicts_from_paths_and_subpaths,
-    matcher::WordMatcher, morpher::Morpher, morpher::NoMorpher, search_in_dicts, settings::Opt,
+    build_matcher, build_morpher,
+    dictionary::{Dictionary, DictionaryInfo, DEFINITION_TYPES},
+    discovery, indices_to_json, load_dicts_from_paths_and_subpaths,
+    matcher::WordMatcher,
+    morpher::Morpher,
+    normalization::{clean_query, Pipeline, Step},
+    registry::Registry,
+    resources, retain_types, search_fulltext_in_dicts, search_in_dicts, search_roots_in_dicts,
+    set_io_parallelism,
+    settings::{check_settings, Opt},
+    snapshot, systemd,
 };
-#[derive(Deserialize)]
+/// Dictionaries, matcher, morpher and normalization pipeline of a group.
+type GroupEntry = (
+    Vec<Arc<Dictionary>>,
+    Box<dyn WordMatcher + Sync>,
+    Box<dyn Morpher>,
+    Pipeline,
+);
+
+/// Loads the dictionaries of every group in the settings into one registry.
+fn load_groups(opt: &Opt, mut registry: Registry) -> (Registry, HashMap<String, GroupEntry>) {
+    let mut groups: HashMap<String, GroupEntry> = HashMap::new();
+    for (name, group) in &opt.groups {
+        let dicts: Vec<Arc<Dictionary>> =
+            load_dicts_from_paths_and_subpaths(&group.paths, opt.scan_depth)
+                .into_iter()
+                .map(|mut d| {
+                    group.configure_dictionary(&mut d);
+                    opt.configure_dictionary(&mut d);
+                    registry.register(d)
+                })
+                .collect();
+        debug!("Group {} has {} dictionaries.", name, dicts.len());
+        let matcher: Box<dyn WordMatcher + Sync> =
+            build_matcher(&group.matcher_type, group.matcher_depth);
+        let morpher = build_morpher(&group.morpher);
+        groups.insert(name.clone(), (dicts, matcher, morpher, group.pipeline()));
+    }
+    (registry, groups)
+}
+
+#[derive(Deserialize, Hash)]
 struct RequestBody {
     word: String,
     group: Option<String>,
+    /// Only searches dictionaries in this language.
+    #[serde(default)]
+    lang: Option<String>,
+    /// Only returns definitions of these types, see `DEFINITION_TYPES`.
+    #[serde(default)]
+    types: Vec<String>,
+    /// Searches definitions containing the word as a phrase instead of headwords.
+    #[serde(default)]
+    fulltext: bool,
+}
+
+/// Body of `POST /admin/dictionaries`.
+#[derive(Deserialize)]
+struct AddDictionaryBody {
+    path: PathBuf,
+    group: Option<String>,
+    #[serde(default)]
+    persist: bool,
+}
+
+/// Body of `DELETE /admin/dictionaries`.
+#[derive(Deserialize)]
+struct RemoveDictionaryBody {
+    bookname: String,
+    #[serde(default)]
+    persist: bool,
 }
 
-pub fn serve_http(opt: &Opt) {
-    let server = tiny_http::Server::http("127.0.0.1:51881").unwrap();
-    let default_comp = build_matcher(&opt.search_algorithm, opt.search_depth);
-
-    let mut all_dicts: HashMap<String, Dictionary> = HashMap::new();
-    let mut groups: HashMap<String, (Vec<String>, Box<dyn WordMatcher + Sync>, Box<dyn Morpher>)> =
-        HashMap::new();
-    for g in &opt.groups {
-        // load_dicts_from_paths_and_subpaths(&g.1.paths)
-        //     .drain(..)
-        //     .for_each(|d| {dicts.entry(d.bookname.clone()).or_insert(d); ()});
-        //A dictionary that repeated in multiple groups is still loaded. Purpose of the global all_dicts is to save memory.
-        let mut group_d = load_dicts_from_paths_and_subpaths(&g.1.paths);
-        let mut dict_keys = Vec::new();
-        for d in 0..group_d.len() {
-            //Load and insert groups dictionaries to global dictionary hashmap.
-            all_dicts
-                .entry(group_d[d].bookname.clone())
-                .or_insert(group_d.remove(d));
-            dict_keys.push(group_d[d].bookname.clone());
+/// How long the server waits for a request before checking whether the settings file changed.
+const SETTINGS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long dictionary directories have to stay unchanged before they are scanned for
+/// new dictionaries, so files still being copied aren't loaded.
+const DICTIONARY_SETTLE_TIME: Duration = Duration::from_secs(1);
+
+/// Runs the http server until `stop` is set. `matches` are the command line arguments
+/// `opt` was parsed from, they are applied again over the settings file when it changes.
+pub fn serve_http(opt: &Opt, matches: &ArgMatches, stop: &AtomicBool) {
+    serve_http_sharing(opt, matches, stop, Registry::default())
+}
+
+/// Like `serve_http`, the groups take the dictionaries already in `registry` instead of
+/// loading them again, so a server running next to terminal searches shares them.
+pub fn serve_http_sharing(opt: &Opt, matches: &ArgMatches, stop: &AtomicBool, registry: Registry) {
+    let mut default_comp = build_matcher(&opt.search_algorithm, opt.search_depth);
+
+    let (mut all_dicts, mut groups) = load_groups(opt, registry);
+    if let Some(path) = &opt.snapshot {
+        match snapshot::restore(path, all_dicts.values()) {
+            Ok(n) => info!(
+                "Restored indices of {} dictionaries from {}.",
+                n,
+                path.display()
+            ),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => warn!("Can't restore snapshot {}: {}", path.display(), e),
         }
-        let matcher: Box<dyn WordMatcher + Sync> =
-            build_matcher(&g.1.matcher_type, g.1.matcher_depth);
-        let morpher = Box::new(NoMorpher {});
-        groups.insert(g.0.clone(), (dict_keys, matcher, morpher));
     }
+    let mut reloaded: Option<Opt> = None;
+    let settings_watch = watch_settings(opt);
+    let mut dictionaries_watch = watch_dictionaries(opt);
+    //When dictionary directories last changed, cleared once they are scanned.
+    let mut dictionaries_changed: Option<Instant> = None;
+
+    //Created after loading dictionaries, with socket activation systemd queues
+    //connections until then.
+    let server = match systemd::listen_fds() {
+        Some(listener) => {
+            info!("Listening on the socket passed by systemd.");
+            tiny_http::Server::from_listener(listener, None)
+        }
+        None => {
+            info!("Listening on {}.", opt.listen);
+            tiny_http::Server::http(opt.listen)
+        }
+    };
+    let server = match server {
+        Ok(n) => n,
+        Err(e) => {
+            error!("Can't start the server: {}", e);
+            return;
+        }
+    };
+    let mut dicts_version = dictionary_set_version(&all_dicts);
+    //Bumped on every reload of the settings, matchers and normalization change results
+    //without changing the dictionaries.
+    let mut settings_generation: u64 = 0;
+    let _advertisement = server.server_addr().to_ip().and_then(discovery::advertise);
+    systemd::notify_ready();
 
     loop {
-        let mut request = match server.recv() {
-            Ok(rq) => rq,
+        if stop.load(Ordering::SeqCst) {
+            info!("Shutting down.");
+            systemd::notify_stopping();
+            let opt = reloaded.as_ref().unwrap_or(opt);
+            if let Some(path) = &opt.snapshot {
+                match snapshot::save(path, all_dicts.values()) {
+                    Ok(n) => info!("Saved indices of {} dictionaries to {}.", n, path.display()),
+                    Err(e) => error!("Can't save snapshot {}: {}", path.display(), e),
+                }
+            }
+            return;
+        }
+        if let Some((_, events)) = &settings_watch {
+            if has_events(events) {
+                let current = reloaded.as_ref().unwrap_or(opt);
+                let new_opt = changed_settings(current, matches);
+                let changes = new_opt
+                    .as_ref()
+                    .map_or_else(Vec::new, |n| settings_changes(current, n));
+                match new_opt {
+                    Some(_) if changes.is_empty() => {
+                        debug!("Settings file changed but nothing the server uses did.")
+                    }
+                    None => (),
+                    Some(new_opt) => {
+                        for change in &changes {
+                            info!("Settings reloaded: {}", change);
+                        }
+                        let (mut new_dicts, new_groups) =
+                            load_groups(&new_opt, Registry::default());
+                        //Dictionaries added through the admin endpoint without a group
+                        //aren't in the settings, keep them.
+                        for d in all_dicts.shared() {
+                            if !groups
+                                .values()
+                                .any(|g| g.0.iter().any(|gd| Arc::ptr_eq(gd, d)))
+                            {
+                                new_dicts.insert(d.clone());
+                            }
+                        }
+                        all_dicts = new_dicts;
+                        groups = new_groups;
+                        default_comp =
+                            build_matcher(&new_opt.search_algorithm, new_opt.search_depth);
+                        dicts_version = dictionary_set_version(&all_dicts);
+                        settings_generation += 1;
+                        dictionaries_watch = watch_dictionaries(&new_opt);
+                        set_io_parallelism(new_opt.io_parallelism);
+                        reloaded = Some(new_opt);
+                    }
+                }
+            }
+        }
+        let opt = reloaded.as_ref().unwrap_or(opt);
+        if let Some((_, events)) = &dictionaries_watch {
+            if has_events(events) {
+                dictionaries_changed = Some(Instant::now());
+            }
+        }
+        if dictionaries_changed.is_some_and(|t| t.elapsed() >= DICTIONARY_SETTLE_TIME) {
+            dictionaries_changed = None;
+            if !add_new_dictionaries(opt, &mut all_dicts, &mut groups).is_empty() {
+                dicts_version = dictionary_set_version(&all_dicts);
+            }
+        }
+
+        let mut request = match server.recv_timeout(SETTINGS_POLL_INTERVAL) {
+            Ok(Some(rq)) => rq,
+            Ok(None) => continue,
             Err(er) => {
-                println!("Error in incoming request: {}", er);
+                error!("Error in incoming request: {}", er);
                 continue;
             }
         };
-        debug!("Request came from {}", &request.remote_addr());
-        let req_body: RequestBody = match serde_json::from_reader(request.as_reader()) {
-            Ok(n) => n,
-            Err(e) => {
-                request.respond(Response::empty(404)).unwrap();
-                error!("Error reading request {}.", e);
+        debug!("Request came from {:?}", &request.remote_addr());
+        if request.url().split('?').next() == Some("/admin/dictionaries") {
+            let response = admin_dictionaries(&mut request, opt, &mut all_dicts, &mut groups);
+            dicts_version = dictionary_set_version(&all_dicts);
+            respond(request, response);
+            continue;
+        }
+
+        if request.url().split('?').next() == Some("/dictionaries") {
+            let mut infos: Vec<DictionaryInfo> = all_dicts.values().map(|d| d.info()).collect();
+            infos.sort_by(|a, b| a.bookname.cmp(&b.bookname));
+            let body = serde_json::to_string_pretty(&infos).unwrap();
+            respond(
+                request,
+                Response::from_string(body).with_header(json_header()),
+            );
+            continue;
+        }
+
+        if let Some(rest) = request.url().strip_prefix("/res/") {
+            let response = match resource_route(request.method(), rest) {
+                Some((bookname, name)) => resource_response(&all_dicts, &bookname, &name),
+                None => json_response(404, "Expected GET /res/<dictionary>/<name>."),
+            };
+            respond(request, response);
+            continue;
+        }
+
+        let path = request.url().split('?').next().unwrap_or_default();
+        //A POST to /search is read from its body like one to any other url.
+        let get_search = path == "/search" && *request.method() == Method::Get;
+        let mut req_body: RequestBody = if path.starts_with("/g/") || get_search {
+            match search_route(request.method(), request.url()) {
+                Some(n) => n,
+                None if get_search => {
+                    respond(request, json_response(400, "Expected ?word=<word>."));
+                    continue;
+                }
+                None => {
+                    respond(request, json_response(404, "No such route."));
+                    continue;
+                }
+            }
+        } else {
+            match serde_json::from_reader(request.as_reader()) {
+                Ok(n) => n,
+                Err(e) => {
+                    debug!("Can't read request body: {}", e);
+                    let message = format!("Invalid request body: {}", e);
+                    respond(request, json_response(400, &message));
+                    continue;
+                }
+            }
+        };
+
+        if let Some(t) = req_body
+            .types
+            .iter()
+            .find(|t| !DEFINITION_TYPES.contains(&t.to_lowercase().as_str()))
+        {
+            let message = format!(
+                "Unknown definition type {}, expected one of {}.",
+                t,
+                DEFINITION_TYPES.join(", ")
+            );
+            respond(request, json_response(400, &message));
+            continue;
+        }
+
+        req_body.word = match clean_query(&req_body.word) {
+            Some(n) => n,
+            None => {
+                respond(
+                    request,
+                    json_response(400, "The word to search can't be empty."),
+                );
                 continue;
             }
         };
 
-        let indices_to_list = if let Some(group) = req_body.group {
-            let group = groups.get(&group).unwrap();
-            search_in_dicts(
-                &mut group.0.iter().map(|key| all_dicts.get(key).unwrap()),
-                group.1.as_ref(),
-                &req_body.word,
-            )
+        let etag = etag(dicts_version, settings_generation, &req_body);
+        if if_none_match(&request, &etag) {
+            debug!("Client already has the results for {}.", &req_body.word);
+            respond(
+                request,
+                Response::empty(304).with_header(etag_header(&etag)),
+            );
+            continue;
+        }
+
+        let mut normalization: &[Step] = &[];
+        let mut expansions: &[String] = &[];
+        let mut indices_to_list = if let Some(group) = &req_body.group {
+            let group = match groups.get(group) {
+                Some(n) => n,
+                None => {
+                    respond(request, json_response(404, "No such group."));
+                    continue;
+                }
+            };
+            let dicts: Vec<&Dictionary> = group
+                .0
+                .iter()
+                .map(|d| d.as_ref())
+                .filter(|d| in_lang(d, &req_body.lang))
+                .collect();
+            normalization = &group.3.steps;
+            if req_body.fulltext {
+                search_fulltext_in_dicts(&dicts, &req_body.word)
+            } else {
+                expansions = opt.expansions(&req_body.word);
+                search_roots_in_dicts(
+                    &dicts,
+                    group.1.as_ref(),
+                    group.2.as_ref(),
+                    &req_body.word,
+                    expansions,
+                    &group.3,
+                    opt.search_deadline(),
+                )
+            }
+        } else if req_body.fulltext {
+            let dicts: Vec<&Dictionary> = all_dicts
+                .values()
+                .filter(|d| in_lang(d, &req_body.lang))
+                .collect();
+            search_fulltext_in_dicts(&dicts, &req_body.word)
         } else {
             search_in_dicts(
-                &mut all_dicts.values(),
+                &mut all_dicts.values().filter(|d| in_lang(d, &req_body.lang)),
                 default_comp.as_ref(),
                 &req_body.word,
+                opt.search_deadline(),
             )
         };
 
-        request
-            .respond(Response::from_string(indices_to_json(&indices_to_list)))
+        retain_types(&mut indices_to_list, &req_body.types);
+
+        let body = match indices_to_json(
+            &indices_to_list,
+            normalization,
+            &BTreeMap::new(),
+            expansions,
+        ) {
+            Ok(n) => n,
+            Err(e) => {
+                error!("Can't read definitions of {}: {}", &req_body.word, e);
+                respond(request, json_response(500, "Can't read definitions."));
+                continue;
+            }
+        };
+        let mut response = Response::from_string(body).with_header(json_header());
+        //Partial results of a timed out search shouldn't be reused by clients.
+        if !indices_to_list.iter().any(|pair| pair.truncated) {
+            response = response.with_header(etag_header(&etag));
+        }
+        respond(request, response);
+    }
+}
+
+/// Sends `response`, a client that went away is only logged.
+fn respond<R: Read>(request: Request, response: Response<R>) {
+    if let Err(e) = request.respond(response) {
+        error!("Error sending response {}.", e);
+    }
+}
+
+/// Whether `dictionary` should be searched by a request asking for `lang`.
+fn in_lang(dictionary: &Dictionary, lang: &Option<String>) -> bool {
+    lang.as_ref().is_none_or(|lang| dictionary.has_lang(lang))
+}
+
+/// Parses `GET /g/<group>/search?word=...&lang=...` or `GET /search?word=...&group=...`,
+/// the same query as a body of `{"word": ..., "group": ..., "lang": ...}` but addressable
+/// by url. `fulltext=true` or `fulltext=1` searches definitions.
+fn search_route(method: &Method, url: &str) -> Option<RequestBody> {
+    if *method != Method::Get {
+        return None;
+    }
+    let (path, query) = match url.split_once('?') {
+        Some((path, query)) => (path, query),
+        None => (url, ""),
+    };
+    let group = if path == "/search" {
+        None
+    } else {
+        let group = path.strip_prefix("/g/")?.strip_suffix("/search")?;
+        if group.is_empty() || group.contains('/') {
+            return None;
+        }
+        Some(percent_decode(group)?)
+    };
+    let param = |name: &str| {
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .and_then(|(_, value)| decode_query_value(value))
+    };
+    Some(RequestBody {
+        word: param("word")?,
+        group: group.or_else(|| param("group")),
+        lang: param("lang"),
+        types: param("type")
+            .map(|t| t.split(',').map(String::from).collect())
+            .unwrap_or_default(),
+        fulltext: param("fulltext").is_some_and(|f| f == "1" || f == "true"),
+    })
+}
+
+/// Parses the bookname and resource name of `GET /res/<bookname>/<name>`, given what
+/// follows `/res/`. Names can have directories like `sounds/elma.ogg`.
+fn resource_route(method: &Method, rest: &str) -> Option<(String, String)> {
+    if *method != Method::Get {
+        return None;
+    }
+    let path = rest.split('?').next()?;
+    let (bookname, name) = path.split_once('/')?;
+    if bookname.is_empty() || name.is_empty() {
+        return None;
+    }
+    Some((percent_decode(bookname)?, percent_decode(name)?))
+}
+
+/// Data of the resource `name` of the dictionary named `bookname`, with its content type.
+fn resource_response(all_dicts: &Registry, bookname: &str, name: &str) -> ResponseBox {
+    let dictionary = match all_dicts.values().find(|d| d.bookname == bookname) {
+        Some(n) => n,
+        None => return json_response(404, "No such dictionary."),
+    };
+    match dictionary.read_resource(name) {
+        Ok(data) => {
+            let content_type = resources::content_type(name);
+            Response::from_data(data)
+                .with_header(Header::from_bytes(&b"Content-Type"[..], content_type).unwrap())
+                .boxed()
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            json_response(404, "No such resource.")
+        }
+        Err(e) => {
+            error!("Can't read resource {} of {}: {}", name, bookname, e);
+            json_response(500, "Can't read resource.")
+        }
+    }
+}
+
+/// Decodes a value of a query string, where `+` stands for a space unlike in paths.
+fn decode_query_value(s: &str) -> Option<String> {
+    percent_decode(&s.replace('+', " "))
+}
+
+/// Decodes `%XX` escapes, None if the result isn't valid UTF-8.
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(b) = iter.next() {
+        match b {
+            b'%' => {
+                let hex = [iter.next()?, iter.next()?];
+                let hex = std::str::from_utf8(&hex).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            }
+            b => bytes.push(b),
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Starts watching the settings file. The directory is watched instead of the
+/// file itself since editors replace files on save.
+fn watch_settings(opt: &Opt) -> Option<(RecommendedWatcher, Receiver<PathBuf>)> {
+    let path = opt.settings_file();
+    let dir = match path.parent() {
+        Some(n) if n.as_os_str().is_empty() => Path::new("."),
+        Some(n) => n,
+        None => return None,
+    };
+    let file_name = path.file_name()?.to_os_string();
+    let (sender, receiver) = channel();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            for changed in event.paths {
+                if changed.file_name() == Some(file_name.as_os_str()) {
+                    sender.send(changed).ok();
+                }
+            }
+        }
+    });
+    let mut watcher = match watcher {
+        Ok(n) => n,
+        Err(e) => {
+            warn!("Can't watch settings file, changes need a restart: {}", e);
+            return None;
+        }
+    };
+    if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+        warn!(
+            "Can't watch {}, settings changes need a restart: {}",
+            dir.display(),
+            e
+        );
+        return None;
+    }
+    debug!("Watching {} for settings changes.", path.display());
+    Some((watcher, receiver))
+}
+
+/// Starts watching the directories of every group for dictionary files, so
+/// dictionaries copied into them are loaded without a restart.
+fn watch_dictionaries(opt: &Opt) -> Option<(RecommendedWatcher, Receiver<PathBuf>)> {
+    let (sender, receiver) = channel();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            for changed in event.paths {
+                //Files of a directory copied at once may be written before the
+                //directory itself is watched, so only the directory is noticed.
+                let is_dictionary_file = changed
+                    .extension()
+                    .is_some_and(|e| e == "ifo" || e == "idx" || e == "dict" || e == "dz");
+                if is_dictionary_file || changed.is_dir() {
+                    sender.send(changed).ok();
+                }
+            }
+        }
+    });
+    let mut watcher = match watcher {
+        Ok(n) => n,
+        Err(e) => {
+            warn!(
+                "Can't watch dictionary directories, new ones need a restart: {}",
+                e
+            );
+            return None;
+        }
+    };
+    let mut paths: Vec<&PathBuf> = opt.groups.values().flat_map(|g| &g.paths).collect();
+    paths.sort();
+    paths.dedup();
+    let mut watching = false;
+    for path in paths.into_iter().filter(|p| p.is_dir()) {
+        match watcher.watch(path, RecursiveMode::Recursive) {
+            Ok(()) => {
+                debug!("Watching {} for new dictionaries.", path.display());
+                watching = true;
+            }
+            Err(e) => warn!(
+                "Can't watch {}, new dictionaries in it need a restart: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+    watching.then_some((watcher, receiver))
+}
+
+/// Settings of the changed settings file applied over the command line arguments
+/// `matches`. `None` with a warning when the file isn't valid json or has problems, so a
+/// file in the middle of being saved or with a wrong value doesn't replace the `current`
+/// settings.
+fn changed_settings(current: &Opt, matches: &ArgMatches) -> Option<Opt> {
+    let settings = match current.read_settings_file() {
+        Ok(n) => n,
+        Err(e) => {
+            warn!(
+                "Can't read the changed settings file, keeping the current settings: {}",
+                e
+            );
+            return None;
+        }
+    };
+    let problems = check_settings(&settings);
+    if !problems.is_empty() {
+        for problem in &problems {
+            warn!("{}", problem);
+        }
+        warn!("The changed settings file has problems, keeping the current settings.");
+        return None;
+    }
+    let mut new_opt = Opt::from_clap(matches);
+    new_opt.apply_settings(matches, &settings);
+    Some(new_opt)
+}
+
+/// Loads the dictionaries found in the paths of groups that they don't have yet, returns
+/// their booknames. Dictionaries missing their .idx or .dict(.dz) file are left for a later
+/// scan.
+fn add_new_dictionaries(
+    opt: &Opt,
+    all_dicts: &mut Registry,
+    groups: &mut HashMap<String, GroupEntry>,
+) -> Vec<String> {
+    let mut added = Vec::new();
+    for (name, group) in &opt.groups {
+        let entry = match groups.get_mut(name) {
+            Some(n) => n,
+            None => continue,
+        };
+        for mut dictionary in load_dicts_from_paths_and_subpaths(&group.paths, opt.scan_depth) {
+            let registered = all_dicts.get(&dictionary);
+            if registered.is_some_and(|r| entry.0.iter().any(|d| Arc::ptr_eq(d, r)))
+                || !dictionary.idx_path.is_file()
+                || !dictionary.dict_path.is_file()
+            {
+                continue;
+            }
+            if !all_dicts.contains(&dictionary) {
+                info!(
+                    "Loaded new dictionary {} from {}.",
+                    &dictionary.bookname,
+                    dictionary.ifo_path.display()
+                );
+                added.push(dictionary.bookname.clone());
+            }
+            group.configure_dictionary(&mut dictionary);
+            opt.configure_dictionary(&mut dictionary);
+            debug!("Added {} to group {}.", &dictionary.bookname, name);
+            entry.0.push(all_dicts.register(dictionary));
+        }
+    }
+    added
+}
+
+/// Whether a watched file changed since the last call, a save usually sends several
+/// events.
+fn has_events(events: &Receiver<PathBuf>) -> bool {
+    let mut changed = false;
+    while events.try_recv().is_ok() {
+        changed = true;
+    }
+    changed
+}
+
+/// Describes the differences between two settings the server cares about.
+fn settings_changes(old: &Opt, new: &Opt) -> Vec<String> {
+    let mut changes = Vec::new();
+    let mut names: Vec<&String> = old.groups.keys().chain(new.groups.keys()).collect();
+    names.sort();
+    names.dedup();
+    for name in names {
+        match (old.groups.get(name), new.groups.get(name)) {
+            (None, Some(_)) => changes.push(format!("added group {}", name)),
+            (Some(_), None) => changes.push(format!("removed group {}", name)),
+            (Some(o), Some(n)) => {
+                if o.paths != n.paths {
+                    changes.push(format!("paths of group {} changed", name));
+                }
+                if o.matcher_type != n.matcher_type || o.matcher_depth != n.matcher_depth {
+                    changes.push(format!(
+                        "matcher of group {} is now {} {}",
+                        name, n.matcher_type, n.matcher_depth
+                    ));
+                }
+                if o.morpher != n.morpher {
+                    changes.push(format!("morpher of group {} is now {}", name, n.morpher));
+                }
+                if o.transliteration != n.transliteration {
+                    changes.push(format!("transliteration of group {} changed", name));
+                }
+                if o.normalization != n.normalization {
+                    changes.push(format!(
+                        "normalization of group {} is now {:?}",
+                        name, n.normalization
+                    ));
+                }
+            }
+            (None, None) => (),
+        }
+    }
+    if old.threads != new.threads {
+        changes.push(String::from("threads change after a restart"));
+    }
+    if old.listen != new.listen {
+        changes.push(format!(
+            "the server listens on {} after a restart",
+            new.listen
+        ));
+    }
+    if old.io_parallelism != new.io_parallelism {
+        changes.push(format!(
+            "{} dictionaries are now read at the same time",
+            new.io_parallelism
+        ));
+    }
+    if old.search_algorithm != new.search_algorithm || old.search_depth != new.search_depth {
+        changes.push(format!(
+            "default matcher is now {} {}",
+            new.search_algorithm, new.search_depth
+        ));
+    }
+    if old.labels != new.labels {
+        changes.push(String::from("dictionary labels changed"));
+    }
+    if old.langs != new.langs {
+        changes.push(String::from("dictionary languages changed"));
+    }
+    if old.matchers != new.matchers {
+        changes.push(String::from("dictionary matchers changed"));
+    }
+    if old.postprocess != new.postprocess {
+        changes.push(String::from("dictionary post-processing changed"));
+    }
+    if old.timeout != new.timeout {
+        changes.push(format!("search timeout is now {:?} ms", new.timeout));
+    }
+    if old.admin_token != new.admin_token {
+        changes.push(String::from("admin token changed"));
+    }
+    changes
+}
+
+/// Adds (`POST`) or removes (`DELETE`) a dictionary of the running server, optionally
+/// saving its path to the settings file so it's also loaded after a restart.
+fn admin_dictionaries(
+    request: &mut Request,
+    opt: &Opt,
+    all_dicts: &mut Registry,
+    groups: &mut HashMap<String, GroupEntry>,
+) -> ResponseBox {
+    let token = match &opt.admin_token {
+        Some(n) => n,
+        None => return json_response(403, "Admin endpoints are disabled."),
+    };
+    if !is_authorized(request, token) {
+        return json_response(401, "Missing or wrong admin token.");
+    }
+
+    match request.method() {
+        Method::Post => {
+            let body: AddDictionaryBody = match serde_json::from_reader(request.as_reader()) {
+                Ok(n) => n,
+                Err(e) => return json_response(400, &format!("Invalid request body: {}", e)),
+            };
+            let mut dictionary = match Dictionary::load_metadata(&body.path) {
+                Ok(n) => n,
+                Err(e) => return json_response(400, &format!("Can't load dictionary: {}", e)),
+            };
+            if all_dicts.contains(&dictionary) || all_dicts.has_bookname(&dictionary.bookname) {
+                return json_response(409, "A dictionary with this bookname is already loaded.");
+            }
+            let group = match &body.group {
+                Some(group) => match groups.get_mut(group) {
+                    Some(group) => Some(group),
+                    None => return json_response(404, "No such group."),
+                },
+                None => None,
+            };
+            if body.persist {
+                if let Err(e) = opt.add_path_to_settings_file(&body.path, body.group.as_deref()) {
+                    error!("Can't save dictionary path to settings file: {}", e);
+                }
+            }
+            info!(
+                "Added dictionary {} from {}.",
+                &dictionary.bookname,
+                body.path.display()
+            );
+            if let Some(lang_group) = body.group.as_ref().and_then(|n| opt.groups.get(n)) {
+                lang_group.configure_dictionary(&mut dictionary);
+            }
+            opt.configure_dictionary(&mut dictionary);
+            let message = format!("Added {}.", &dictionary.bookname);
+            let dictionary = all_dicts.register(dictionary);
+            if let Some(group) = group {
+                group.0.push(dictionary);
+            }
+            json_response(201, &message)
+        }
+        Method::Delete => {
+            let body: RemoveDictionaryBody = match serde_json::from_reader(request.as_reader()) {
+                Ok(n) => n,
+                Err(e) => return json_response(400, &format!("Invalid request body: {}", e)),
+            };
+            let removed = all_dicts.remove_bookname(&body.bookname);
+            if removed.is_empty() {
+                return json_response(404, "No such dictionary.");
+            }
+            for group in groups.values_mut() {
+                group.0.retain(|d| d.bookname != body.bookname);
+            }
+            if body.persist {
+                for dictionary in &removed {
+                    //A dictionary can be configured by its own directory or its .ifo file.
+                    let mut paths = vec![dictionary.ifo_path.clone()];
+                    paths.extend(dictionary.ifo_path.parent().map(Path::to_path_buf));
+                    for path in paths {
+                        if let Err(e) = opt.remove_path_from_settings_file(&path) {
+                            error!("Can't remove dictionary path from settings file: {}", e);
+                        }
+                    }
+                }
+            }
+            info!("Removed dictionary {}.", &body.bookname);
+            json_response(200, &format!("Removed {}.", &body.bookname))
+        }
+        _ => json_response(405, "Only POST and DELETE are supported."),
+    }
+}
+
+fn is_authorized(request: &Request, token: &str) -> bool {
+    let expected = format!("Bearer {}", token);
+    request
+        .headers()
+        .iter()
+        .filter(|h| h.field.equiv("Authorization"))
+        .any(|h| constant_time_eq(h.value.as_str().as_bytes(), expected.as_bytes()))
+}
+
+/// Compares without returning early so response times don't leak how much of a token matched.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn json_response(status: u16, message: &str) -> ResponseBox {
+    let body = serde_json::json!({ "message": message }).to_string();
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(json_header())
+        .boxed()
+}
+
+fn json_header() -> Header {
+    Header::from_bytes(
+        &b"Content-Type"[..],
+        &b"application/json; charset=utf-8"[..],
+    )
+    .unwrap()
+}
+
+/// Changes whenever the set of loaded dictionaries changes, part of every ETag so
+/// clients don't keep results from before a dictionary was added or updated.
+fn dictionary_set_version(all_dicts: &Registry) -> u64 {
+    let mut dicts: Vec<&Dictionary> = all_dicts.values().collect();
+    dicts.sort_by(|a, b| a.bookname.cmp(&b.bookname));
+    let mut hasher = DefaultHasher::new();
+    for d in dicts {
+        d.bookname.hash(&mut hasher);
+        d.label.hash(&mut hasher);
+        d.lang.hash(&mut hasher);
+        d.wordcount.hash(&mut hasher);
+        d.ifo_path.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// ETag of the results of `req_body`, they change with the dictionaries and with the
+/// settings.
+fn etag(dicts_version: u64, settings_generation: u64, req_body: &RequestBody) -> String {
+    let mut hasher = DefaultHasher::new();
+    dicts_version.hash(&mut hasher);
+    settings_generation.hash(&mut hasher);
+    req_body.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+fn etag_header(etag: &str) -> Header {
+    Header::from_bytes(&b"ETag"[..], etag.as_bytes()).unwrap()
+}
+
+/// Whether the request's If-None-Match header lists `etag`.
+fn if_none_match(request: &Request, etag: &str) -> bool {
+    request
+        .headers()
+        .iter()
+        .filter(|h| h.field.equiv("If-None-Match"))
+        .any(|h| matches_etag(h.value.as_str(), etag))
+}
+
+fn matches_etag(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::LangGroup;
+    use crate::tests::write_dictionary;
+    use std::fs;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    fn group(paths: Vec<PathBuf>) -> LangGroup {
+        LangGroup {
+            paths,
+            matcher_type: String::from("exact"),
+            matcher_depth: 0,
+            morpher: String::from("none"),
+            transliteration: HashMap::new(),
+            normalization: Vec::new(),
+            max_results: None,
+        }
+    }
+
+    #[test]
+    fn should_share_dictionaries_between_groups() {
+        let dir = tempdir().unwrap();
+        let first = write_dictionary(dir.path(), "first", &[("elma", "apple")]);
+        let second = write_dictionary(dir.path(), "second", &[("armut", "pear")]);
+        let third = write_dictionary(dir.path(), "third", &[("ayva", "quince")]);
+        let mut opt = Opt::new();
+        opt.groups.insert(
+            String::from("a"),
+            group(vec![first.clone(), second.clone()]),
+        );
+        //Same dictionary reached through a different path.
+        opt.groups.insert(
+            String::from("b"),
+            group(vec![second.join("../second"), third]),
+        );
+        opt.groups.insert(String::from("c"), group(vec![first]));
+
+        let (registry, groups) = load_groups(&opt, Registry::default());
+        assert_eq!(registry.len(), 3);
+        let booknames = |name: &str| {
+            let mut names: Vec<String> =
+                groups[name].0.iter().map(|d| d.bookname.clone()).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(booknames("a"), vec!["first", "second"]);
+        assert_eq!(booknames("b"), vec!["second", "third"]);
+        assert_eq!(booknames("c"), vec!["first"]);
+        let shared = |group: &str, bookname: &str| {
+            groups[group]
+                .0
+                .iter()
+                .find(|d| d.bookname == bookname)
+                .unwrap()
+                .clone()
+        };
+        assert!(Arc::ptr_eq(&shared("a", "second"), &shared("b", "second")));
+        assert!(Arc::ptr_eq(&shared("a", "first"), &shared("c", "first")));
+    }
+
+    #[test]
+    fn should_describe_settings_changes() {
+        let mut old = Opt::new();
+        old.groups
+            .insert(String::from("tr"), group(vec![PathBuf::from("tdk")]));
+        old.groups
+            .insert(String::from("en"), group(vec![PathBuf::from("gnu")]));
+        let mut new = Opt::new();
+        new.groups
+            .insert(String::from("tr"), group(vec![PathBuf::from("tdk")]));
+        new.groups.get_mut("tr").unwrap().matcher_depth = 2;
+        new.groups
+            .insert(String::from("de"), group(vec![PathBuf::from("dwds")]));
+        new.timeout = Some(100);
+        assert_eq!(
+            settings_changes(&old, &new),
+            vec![
+                "added group de",
+                "removed group en",
+                "matcher of group tr is now exact 2",
+                "search timeout is now Some(100) ms",
+            ]
+        );
+        assert!(settings_changes(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn should_notice_settings_file_changes() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        fs::write(&path, "{}").unwrap();
+        let mut opt = Opt::new();
+        opt.settings_path = path.clone();
+        let (_watcher, events) = watch_settings(&opt).unwrap();
+        fs::write(dir.path().join("other.json"), "{}").unwrap();
+        fs::write(&path, r#"{"timeout": 10}"#).unwrap();
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while !has_events(&events) {
+            assert!(std::time::Instant::now() < deadline, "no change noticed");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn should_keep_settings_when_changed_file_is_invalid() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        let args = vec!["sozluk", "--settings-path", path.to_str().unwrap()];
+        let matches = Opt::clap().get_matches_from(args);
+        let current = Opt::from_clap(&matches);
+        fs::write(&path, r#"{"groups": {"tr": {"paths": [], "matcher_ty"#).unwrap();
+        assert!(changed_settings(&current, &matches).is_none());
+        fs::write(&path, r#"{"search_depth": -1}"#).unwrap();
+        assert!(changed_settings(&current, &matches).is_none());
+        fs::write(&path, r#"{"search_depth": 2}"#).unwrap();
+        assert_eq!(
+            changed_settings(&current, &matches).unwrap().search_depth,
+            2
+        );
+    }
+
+    #[test]
+    fn should_load_dictionaries_copied_into_watched_directories() {
+        let dir = tempdir().unwrap();
+        write_dictionary(dir.path(), "first", &[("elma", "apple")]);
+        let mut opt = Opt::new();
+        opt.groups
+            .insert(String::from("a"), group(vec![dir.path().to_path_buf()]));
+        let (mut registry, mut groups) = load_groups(&opt, Registry::default());
+        let (_watcher, events) = watch_dictionaries(&opt).unwrap();
+
+        let incomplete = write_dictionary(dir.path(), "incomplete", &[("ayva", "quince")]);
+        fs::remove_file(incomplete.join("incomplete.dict")).unwrap();
+        write_dictionary(dir.path(), "second", &[("armut", "pear")]);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !has_events(&events) {
+            assert!(Instant::now() < deadline, "no change noticed");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(
+            add_new_dictionaries(&opt, &mut registry, &mut groups),
+            vec!["second"]
+        );
+        assert_eq!(registry.len(), 2);
+        assert_eq!(groups["a"].0.len(), 2);
+        assert!(add_new_dictionaries(&opt, &mut registry, &mut groups).is_empty());
+    }
+
+    #[test]
+    fn should_remove_dictionaries_by_bookname() {
+        let dir = tempdir().unwrap();
+        let first = write_dictionary(dir.path(), "first", &[("elma", "apple")]);
+        let mut registry = Registry::default();
+        let dictionary = registry.register(Dictionary::load_metadata(&first).unwrap());
+        assert!(registry.contains(&dictionary));
+        assert_eq!(registry.remove_bookname("second").len(), 0);
+        assert_eq!(registry.remove_bookname("first").len(), 1);
+        assert!(!registry.contains(&dictionary));
+    }
+
+    #[test]
+    fn should_give_same_etag_for_same_query() {
+        let body = |word: &str, group: Option<&str>| RequestBody {
+            word: word.to_string(),
+            group: group.map(String::from),
+            lang: None,
+            types: Vec::new(),
+            fulltext: false,
+        };
+        assert_eq!(
+            etag(1, 0, &body("elma", None)),
+            etag(1, 0, &body("elma", None))
+        );
+        assert_ne!(
+            etag(1, 0, &body("elma", None)),
+            etag(2, 0, &body("elma", None))
+        );
+        assert_ne!(
+            etag(1, 0, &body("elma", None)),
+            etag(1, 1, &body("elma", None))
+        );
+        assert_ne!(
+            etag(1, 0, &body("elma", None)),
+            etag(1, 0, &body("elma", Some("tr")))
+        );
+        assert_ne!(
+            etag(1, 0, &body("elma", None)),
+            etag(1, 0, &body("armut", None))
+        );
+    }
+
+    #[test]
+    fn should_parse_search_routes() {
+        let route = search_route(&Method::Get, "/g/tr/search?word=%C3%A7i%C3%A7ek").unwrap();
+        assert_eq!(route.word, "çiçek");
+        assert_eq!(route.group.as_deref(), Some("tr"));
+        let route = search_route(&Method::Get, "/g/en%20us/search?x=1&word=ice+cream").unwrap();
+        assert_eq!(route.word, "ice cream");
+        assert_eq!(route.group.as_deref(), Some("en us"));
+        assert_eq!(route.lang, None);
+        let route = search_route(&Method::Get, "/g/c++/search?word=c%2B%2B+dili").unwrap();
+        assert_eq!(route.group.as_deref(), Some("c++"));
+        assert_eq!(route.word, "c++ dili");
+        let route = search_route(&Method::Get, "/g/all/search?word=elma&lang=tr").unwrap();
+        assert_eq!(route.lang.as_deref(), Some("tr"));
+        assert!(route.types.is_empty());
+        let route = search_route(&Method::Get, "/g/tr/search?word=elma&type=html,meaning").unwrap();
+        assert_eq!(route.types, vec!["html", "meaning"]);
+        assert!(!route.fulltext);
+        let route = search_route(&Method::Get, "/search?word=apple+tree&fulltext=true").unwrap();
+        assert!(route.fulltext);
+        assert!(search_route(&Method::Post, "/g/tr/search?word=elma").is_none());
+        assert!(search_route(&Method::Get, "/g/tr/search").is_none());
+        assert!(search_route(&Method::Get, "/g//search?word=elma").is_none());
+        assert!(search_route(&Method::Get, "/g/tr/lookup?word=elma").is_none());
+        let route = search_route(&Method::Get, "/search?word=foo&group=en").unwrap();
+        assert_eq!(route.word, "foo");
+        assert_eq!(route.group.as_deref(), Some("en"));
+        let route = search_route(&Method::Get, "/search?word=foo").unwrap();
+        assert_eq!(route.group, None);
+        assert!(search_route(&Method::Get, "/search?group=en").is_none());
+        assert!(search_route(&Method::Get, "/searches?word=foo").is_none());
+        assert!(search_route(&Method::Get, "/g/tr/search?word=%ff").is_none());
+    }
+
+    #[test]
+    fn should_parse_resource_routes() {
+        assert_eq!(
+            resource_route(&Method::Get, "T%C3%BCrk%C3%A7e/sounds/elma.ogg"),
+            Some((String::from("Türkçe"), String::from("sounds/elma.ogg")))
+        );
+        assert_eq!(
+            resource_route(&Method::Get, "tr/elma+armut.png"),
+            Some((String::from("tr"), String::from("elma+armut.png")))
+        );
+        assert_eq!(
+            resource_route(&Method::Get, "tr/Elma.png?x=1"),
+            Some((String::from("tr"), String::from("Elma.png")))
+        );
+        assert!(resource_route(&Method::Post, "tr/Elma.png").is_none());
+        assert!(resource_route(&Method::Get, "tr").is_none());
+        assert!(resource_route(&Method::Get, "tr/").is_none());
+        assert!(resource_route(&Method::Get, "/Elma.png").is_none());
+    }
+
+    #[test]
+    fn should_serve_resources_of_dictionaries() {
+        let dir = tempdir().unwrap();
+        let path = write_dictionary(dir.path(), "tr", &[("elma", "<img src=\"Elma.png\">")]);
+        fs::create_dir(path.join("res")).unwrap();
+        fs::write(path.join("res/Elma.png"), b"\x89PNG").unwrap();
+        let mut registry = Registry::default();
+        registry.register(Dictionary::load_metadata(&path).unwrap());
+        assert_eq!(
+            resource_response(&registry, "tr", "Elma.png")
+                .status_code()
+                .0,
+            200
+        );
+        assert_eq!(
+            resource_response(&registry, "tr", "b.jpg").status_code().0,
+            404
+        );
+        assert_eq!(
+            resource_response(&registry, "en", "Elma.png")
+                .status_code()
+                .0,
+            404
+        );
+    }
+
+    #[test]
+    fn should_answer_500_when_definitions_cant_be_read() {
+        let dir = tempdir().unwrap();
+        let path = write_dictionary(dir.path(), "tr", &[("elma", "apple fruit")]);
+        fs::write(path.join("tr.dict"), "apple").unwrap();
+        let mut opt = Opt::new();
+        opt.settings_path = dir.path().join("settings.json");
+        opt.groups.insert(String::from("tr"), group(vec![path]));
+        opt.listen = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
             .unwrap();
+        let matches = Opt::clap().get_matches_from(vec!["sozluk"]);
+        let stop = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            s.spawn(|| serve_http(&opt, &matches, &stop));
+            let deadline = Instant::now() + Duration::from_secs(5);
+            let mut stream = loop {
+                match std::net::TcpStream::connect(opt.listen) {
+                    Ok(n) => break n,
+                    Err(_) if Instant::now() < deadline => {
+                        std::thread::sleep(Duration::from_millis(20))
+                    }
+                    Err(e) => panic!("server didn't start: {}", e),
+                }
+            };
+            stream
+                .write_all(b"GET /search?word=elma HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            stop.store(true, Ordering::SeqCst);
+            assert!(response.starts_with("HTTP/1.1 500"), "{}", response);
+        });
+    }
+
+    #[test]
+    fn should_compare_tokens() {
+        assert!(constant_time_eq(b"Bearer secret", b"Bearer secret"));
+        assert!(!constant_time_eq(b"Bearer secreT", b"Bearer secret"));
+        assert!(!constant_time_eq(b"Bearer secret2", b"Bearer secret"));
+    }
+
+    #[test]
+    fn should_match_if_none_match_lists() {
+        let etag = "\"00ff\"";
+        assert!(matches_etag("\"00ff\"", etag));
+        assert!(matches_etag("\"1234\", W/\"00ff\"", etag));
+        assert!(matches_etag("*", etag));
+        assert!(!matches_etag("\"1234\"", etag));
     }
 }
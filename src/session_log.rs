@@ -0,0 +1,104 @@
+//! Markdown notes of a session for `--session-log`: the words searched and the
+//! definitions read, so a study session can be gone over later.
+use crate::dictionary::Definition;
+use chrono::Local;
+use log::warn;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Notes of the session written to `out` as they are taken. A note that can't be
+/// written is skipped with a warning, the session goes on.
+pub struct SessionLog<W: Write = File> {
+    out: W,
+}
+
+impl SessionLog<File> {
+    /// Appends the notes of a new session to `path`, creating it if needed.
+    pub fn open(path: &Path) -> io::Result<SessionLog<File>> {
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        let first = file.metadata()?.len() == 0;
+        let mut log = SessionLog::new(file);
+        if !first {
+            log.write("\n");
+        }
+        log.heading();
+        Ok(log)
+    }
+}
+
+impl<W: Write> SessionLog<W> {
+    pub fn new(out: W) -> SessionLog<W> {
+        SessionLog { out }
+    }
+
+    fn write(&mut self, text: &str) {
+        if let Err(e) = self
+            .out
+            .write_all(text.as_bytes())
+            .and_then(|_| self.out.flush())
+        {
+            warn!("Can't write the session log: {}", e);
+        }
+    }
+
+    /// Starts the notes of a session with the time it started.
+    pub fn heading(&mut self) {
+        let started = Local::now().format("%Y-%m-%d %H:%M");
+        self.write(&format!("# Session of {}\n\n", started));
+    }
+
+    /// Notes that `word` was searched and found `results` definitions.
+    pub fn query(&mut self, word: &str, results: usize) {
+        self.write(&format!("## {}\n\n", word));
+        if results == 0 {
+            self.write("No results.\n\n");
+        }
+    }
+
+    /// Notes `definition` read from the dictionary labeled `source`. Its lines end with
+    /// hard breaks so it keeps its layout.
+    pub fn definition(&mut self, definition: &Definition, source: &str) {
+        let text = definition
+            .readable()
+            .lines()
+            .map(str::trim_end)
+            .collect::<Vec<_>>()
+            .join("  \n");
+        self.write(&format!(
+            "### {}, {}\n\n{}\n\n",
+            definition.word, source, text
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dictionary::SameTypeSequence;
+
+    #[test]
+    fn should_note_queries_and_definitions() {
+        let mut log = SessionLog::new(Vec::new());
+        log.query("elma", 1);
+        let definition = Definition::new_from_utf8(
+            "elma",
+            b"<b>apple</b><br>a fruit".to_vec(),
+            &SameTypeSequence::HTML,
+        );
+        log.definition(&definition, "tr");
+        log.query("armutt", 0);
+        assert_eq!(
+            String::from_utf8(log.out).unwrap(),
+            "## elma\n\n### elma, tr\n\napple  \na fruit\n\n## armutt\n\nNo results.\n\n"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.md");
+        SessionLog::open(&path).unwrap().query("elma", 1);
+        SessionLog::open(&path).unwrap();
+        let notes = std::fs::read_to_string(&path).unwrap();
+        assert!(notes.starts_with("# Session of "));
+        assert_eq!(notes.matches("\n\n# Session of ").count(), 1);
+    }
+}
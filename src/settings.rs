@@ -1,47 +1,759 @@
 use log::{debug, warn};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Map, Value};
 use std::collections::HashMap;
-use std::convert::TryInto;
 use std::env::{current_dir, home_dir};
-use std::fs::{File, OpenOptions};
-use std::io::{BufReader, Write};
-use std::path::PathBuf;
-use structopt::clap::App;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use structopt::clap::ArgMatches;
 use structopt::StructOpt;
 
+use crate::browser::PageStyle;
+use crate::dictionary::{Dictionary, DEFINITION_TYPES};
+use crate::filter::Filter;
+use crate::i18n::{Language, LANGUAGES};
+use crate::matcher::{Charset, HeadwordFilter, CHARSETS};
+use crate::normalization::{Pipeline, Step, STEP_NAMES};
+use crate::postprocess::{Transform, TRANSFORM_NAMES};
+use crate::resource_cache::cache_root;
+use crate::transliteration;
+use crate::DEFAULT_IO_PARALLELISM;
+use crate::OUTPUT_FIELDS;
+
 const SETTINGS_PATH: &str = "~/.config/sozluk/settings.json";
+/// Where the http server listens unless `listen` says otherwise.
+const LISTEN_ADDRESS: &str = "127.0.0.1:51881";
+/// Bytes of a definition read unless `definition_limit` says otherwise.
+const DEFINITION_LIMIT: &str = "65536";
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct LangGroup {
     pub paths: Vec<PathBuf>,
     pub matcher_type: String,
     pub matcher_depth: usize,
     pub morpher: String,
+    /// Letters and the letters they may stand for, queries are also searched with them
+    /// replaced. Given as the name of a builtin table or an object in the settings file.
+    #[serde(default)]
+    pub transliteration: HashMap<String, String>,
+    /// Steps applied to queries and headwords in this order before they are compared.
+    #[serde(default)]
+    pub normalization: Vec<Step>,
+    /// Most results each dictionary of the group gives, a dictionary in several groups
+    /// takes the limit of the first one it's loaded for.
+    #[serde(default)]
+    pub max_results: Option<usize>,
+}
+
+impl LangGroup {
+    /// Normalization pipeline of the group, groups that only set a transliteration
+    /// table transliterate queries as they did before pipelines were configurable.
+    pub fn pipeline(&self) -> Pipeline {
+        let steps = if self.normalization.is_empty() && !self.transliteration.is_empty() {
+            vec![Step::Transliterate]
+        } else {
+            self.normalization.clone()
+        };
+        Pipeline::new(steps, self.transliteration.clone())
+    }
+
+    /// Applies the options of the group that concern single dictionaries, the result
+    /// limit. `Opt::configure_dictionary` overrides them for single booknames.
+    pub fn configure_dictionary(&self, dictionary: &mut Dictionary) {
+        if self.max_results.is_some() {
+            dictionary.max_results = self.max_results;
+        }
+    }
+}
+/// Matcher a single dictionary is searched with, overriding its .ifo fields.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DictionaryMatcher {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matcher_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matcher_depth: Option<u8>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Searches a word, the same as giving it without a subcommand.
+    Search { word: String },
+    /// Runs the http server until killed, supports systemd socket activation.
+    Serve,
+    /// Lists the dictionaries in the paths, the same as `--list-dictionaries`.
+    List,
+    /// Builds the missing and stale index caches, the same as `cache build`.
+    Index {
+        /// Also builds the keyword index of definitions `--reverse` and `--fulltext`
+        /// search for current caches written without one.
+        #[structopt(long)]
+        keywords: bool,
+    },
+    /// Inspects the settings file.
+    Config(ConfigCommand),
+    /// Describes the dictionaries.
+    Info(InfoCommand),
+    /// Prints the headwords matching a regular expression without reading definitions.
+    Grep {
+        pattern: String,
+        /// Prints the dictionary of each headword before it, separated by a tab.
+        #[structopt(long)]
+        with_dictionary: bool,
+    },
+    /// Manages the index caches of the dictionaries and the pictures and pages
+    /// extracted from them.
+    Cache(CacheCommand),
+    /// Prints random headwords of every dictionary.
+    Random {
+        /// Headwords taken from each dictionary, fewer if it doesn't have as many.
+        #[structopt(long, default_value = "10")]
+        count: usize,
+        /// Skips headwords with whitespace in them.
+        #[structopt(long)]
+        no_spaces: bool,
+        /// Prints an array of the dictionaries and their headwords as json.
+        #[structopt(long)]
+        json: bool,
+    },
+    /// Writes the single word headwords of dictionaries one per line, for spellcheckers.
+    Wordlist {
+        /// Bookname or label of the dictionary, every dictionary if it isn't given.
+        #[structopt(long)]
+        dict: Option<String>,
+        /// File to write the list to instead of the standard output.
+        #[structopt(long, parse(from_os_str))]
+        out: Option<PathBuf>,
+        /// File of words and their counts, written after each word.
+        #[structopt(long, parse(from_os_str))]
+        frequencies: Option<PathBuf>,
+    },
+    /// Reports which dictionaries under a directory are incomplete or have broken
+    /// caches, and how to fix them.
+    Doctor {
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub struct InfoCommand {
+    /// Reads every index and reports its entry count, estimated size in memory,
+    /// cache file size and load time.
+    #[structopt(long)]
+    pub memory: bool,
+    /// Reports the headword lengths, most common letters and definitions size of every
+    /// index, and whether its entry count is the one the .ifo file gives.
+    #[structopt(long, conflicts_with = "memory")]
+    pub stats: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum CacheCommand {
+    /// Builds the missing and stale index caches of the dictionaries in the paths and
+    /// groups of the settings, so the first search doesn't have to.
+    Build,
+    /// Removes the index caches of the dictionaries in the paths and groups of the
+    /// settings and the resource caches of every dictionary.
+    Clear,
+    /// Shows whether the index cache of each dictionary is current.
+    Status,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum ConfigCommand {
+    /// Reports every key of the settings file that is unknown or has a wrong type.
+    Check,
+}
+
+/// Type a settings key should have.
+enum Expected {
+    Bool,
+    UnsignedInt,
+    Str,
+    /// A string or an array of strings.
+    Paths,
+    OneOf(&'static [&'static str]),
+    /// An object of group objects, see `GROUP_KEYS`.
+    Groups,
+    /// An object of strings keyed by bookname.
+    Labels,
+    /// An object of objects with `MATCHER_KEYS`.
+    Matchers,
+    /// An object of objects with `PAGE_KEYS`.
+    Pages,
+    /// The name of a builtin table or an object of strings.
+    Transliteration,
+    /// An array of these strings.
+    ArrayOf(&'static [&'static str]),
+    /// An object of arrays of these strings keyed by bookname.
+    DictionaryArrays(&'static [&'static str]),
+    /// An object of non-negative integers keyed by bookname.
+    DictionaryInts,
+    /// An object of strings or arrays of strings keyed by word.
+    Synonyms,
+    /// An IP address and port like `127.0.0.1:51881`.
+    Address,
+    /// An object of objects with `PROFILE_KEYS`.
+    Profiles,
+}
+
+pub(crate) const MATCHERS: &[&str] = &["levenshtein", "exact", "prefix", "contains", "regex"];
+pub(crate) const MORPHERS: &[&str] = &["none", "tr", "en"];
+
+const SETTINGS_KEYS: &[(&str, Expected)] = &[
+    ("paths", Expected::Paths),
+    ("scan_depth", Expected::UnsignedInt),
+    ("threads", Expected::UnsignedInt),
+    ("io_parallelism", Expected::UnsignedInt),
+    ("group", Expected::Str),
+    ("groups", Expected::Groups),
+    ("labels", Expected::Labels),
+    ("langs", Expected::Labels),
+    ("lang", Expected::Str),
+    ("matchers", Expected::Matchers),
+    ("postprocess", Expected::DictionaryArrays(TRANSFORM_NAMES)),
+    ("pages", Expected::Pages),
+    ("max_results", Expected::DictionaryInts),
+    ("synonyms", Expected::Synonyms),
+    ("search_algorithm", Expected::OneOf(MATCHERS)),
+    ("search_depth", Expected::UnsignedInt),
+    ("morpher", Expected::OneOf(MORPHERS)),
+    ("list", Expected::Bool),
+    ("exit", Expected::Bool),
+    ("json_output", Expected::Bool),
+    ("words_only", Expected::Bool),
+    ("background", Expected::Bool),
+    ("open", Expected::Bool),
+    ("copy", Expected::Bool),
+    ("full", Expected::Bool),
+    ("study", Expected::Bool),
+    ("types", Expected::ArrayOf(DEFINITION_TYPES)),
+    ("fields", Expected::ArrayOf(OUTPUT_FIELDS)),
+    ("snapshot", Expected::Str),
+    ("session_log", Expected::Str),
+    ("dictionary_registry", Expected::Str),
+    ("listen", Expected::Address),
+    ("timeout", Expected::UnsignedInt),
+    ("max_width", Expected::UnsignedInt),
+    ("definition_limit", Expected::UnsignedInt),
+    ("min_len", Expected::UnsignedInt),
+    ("max_len", Expected::UnsignedInt),
+    ("charset", Expected::OneOf(CHARSETS)),
+    ("ui_lang", Expected::OneOf(LANGUAGES)),
+    ("timelog_file", Expected::Str),
+    ("timelog", Expected::Bool),
+    ("admin_token", Expected::Str),
+    ("profile", Expected::Str),
+    ("profiles", Expected::Profiles),
+];
+
+/// Keys a profile can set, they take the place of the same keys of the settings file
+/// when the profile is selected.
+const PROFILE_KEYS: &[(&str, Expected)] = &[
+    ("group", Expected::Str),
+    ("lang", Expected::Str),
+    ("search_algorithm", Expected::OneOf(MATCHERS)),
+    ("search_depth", Expected::UnsignedInt),
+    ("morpher", Expected::OneOf(MORPHERS)),
+    ("timeout", Expected::UnsignedInt),
+    ("max_width", Expected::UnsignedInt),
+    ("definition_limit", Expected::UnsignedInt),
+    ("min_len", Expected::UnsignedInt),
+    ("max_len", Expected::UnsignedInt),
+    ("charset", Expected::OneOf(CHARSETS)),
+    ("types", Expected::ArrayOf(DEFINITION_TYPES)),
+    ("fields", Expected::ArrayOf(OUTPUT_FIELDS)),
+    ("json_output", Expected::Bool),
+    ("words_only", Expected::Bool),
+    ("list", Expected::Bool),
+    ("full", Expected::Bool),
+    ("exit", Expected::Bool),
+];
+
+/// Keys of a group and the value used when one is missing, `None` for optional ones.
+const GROUP_KEYS: &[(&str, Expected, Option<&str>)] = &[
+    ("paths", Expected::Paths, Some("no dictionaries")),
+    (
+        "matcher_type",
+        Expected::OneOf(MATCHERS),
+        Some("exact matching"),
+    ),
+    ("matcher_depth", Expected::UnsignedInt, Some("0")),
+    ("morpher", Expected::OneOf(MORPHERS), Some("none")),
+    ("transliteration", Expected::Transliteration, None),
+    ("normalization", Expected::ArrayOf(STEP_NAMES), None),
+    ("max_results", Expected::UnsignedInt, None),
+];
+
+/// Keys of a dictionary's matcher, both are optional.
+const MATCHER_KEYS: &[(&str, Expected)] = &[
+    ("matcher_type", Expected::OneOf(MATCHERS)),
+    ("matcher_depth", Expected::UnsignedInt),
+];
+
+const PAGE_KEYS: &[(&str, Expected)] =
+    &[("stylesheet", Expected::Str), ("template", Expected::Str)];
+
+/// A key of the settings file that won't be read the way its author meant.
+#[derive(Debug, PartialEq)]
+pub struct SettingsProblem {
+    /// Dotted path of the key, like `groups.tr.matcher_depth`.
+    pub key: String,
+    pub message: String,
 }
+
+impl fmt::Display for SettingsProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.key, self.message)
+    }
+}
+
+/// Checks every key of a parsed settings file.
+pub fn check_settings(settings: &Value) -> Vec<SettingsProblem> {
+    let mut problems = Vec::new();
+    let map = match settings {
+        Value::Object(n) => n,
+        other => {
+            problems.push(SettingsProblem {
+                key: String::from("(root)"),
+                message: format!("expected an object, found {}", type_name(other)),
+            });
+            return problems;
+        }
+    };
+    check_object(
+        map,
+        SETTINGS_KEYS.iter().map(|(k, e)| (*k, e)),
+        "",
+        &mut problems,
+    );
+    problems
+}
+
+fn check_object<'e>(
+    map: &Map<String, Value>,
+    keys: impl Iterator<Item = (&'static str, &'e Expected)> + Clone,
+    prefix: &str,
+    problems: &mut Vec<SettingsProblem>,
+) {
+    for (key, value) in map {
+        let path = format!("{}{}", prefix, key);
+        match keys.clone().find(|(k, _)| k == key) {
+            Some((_, expected)) => check_value(value, expected, &path, problems),
+            None => {
+                let suggestion = keys
+                    .clone()
+                    .map(|(k, _)| (strsim::levenshtein(k, key), k))
+                    .filter(|(distance, _)| *distance <= 2)
+                    .min();
+                let message = match suggestion {
+                    Some((_, k)) => format!("unknown key, did you mean `{}`?", k),
+                    None => String::from("unknown key, it is ignored"),
+                };
+                problems.push(SettingsProblem { key: path, message });
+            }
+        }
+    }
+}
+
+fn check_value(
+    value: &Value,
+    expected: &Expected,
+    path: &str,
+    problems: &mut Vec<SettingsProblem>,
+) {
+    let mismatch = |problems: &mut Vec<SettingsProblem>, what: &str| {
+        problems.push(SettingsProblem {
+            key: path.to_string(),
+            message: format!("expected {}, found {}", what, type_name(value)),
+        })
+    };
+    //Options that are unset serialize as null.
+    if value.is_null() {
+        return;
+    }
+    match expected {
+        Expected::Bool if !value.is_boolean() => mismatch(problems, "true or false"),
+        Expected::UnsignedInt if !value.is_u64() => mismatch(problems, "a non-negative integer"),
+        Expected::Str if !value.is_string() => mismatch(problems, "a string"),
+        Expected::Address => match value {
+            Value::String(n) if n.parse::<SocketAddr>().is_ok() => (),
+            Value::String(n) => problems.push(SettingsProblem {
+                key: path.to_string(),
+                message: format!("{:?} isn't an address like {}", n, LISTEN_ADDRESS),
+            }),
+            _ => mismatch(problems, &format!("an address like {}", LISTEN_ADDRESS)),
+        },
+        Expected::Paths => match value {
+            Value::String(_) => (),
+            Value::Array(paths) => {
+                for (i, path_j) in paths.iter().enumerate() {
+                    if !path_j.is_string() {
+                        check_value(
+                            path_j,
+                            &Expected::Str,
+                            &format!("{}[{}]", path, i),
+                            problems,
+                        );
+                    }
+                }
+            }
+            _ => mismatch(problems, "a path or an array of paths"),
+        },
+        Expected::OneOf(allowed) => match value {
+            Value::String(n) if allowed.contains(&n.as_str()) => (),
+            Value::String(n) => problems.push(SettingsProblem {
+                key: path.to_string(),
+                message: format!(
+                    "unknown value {:?}, expected one of {}",
+                    n,
+                    allowed.join(", ")
+                ),
+            }),
+            _ => mismatch(problems, &format!("one of {}", allowed.join(", "))),
+        },
+        Expected::Groups => match value {
+            Value::Object(groups) => {
+                for (name, group) in groups {
+                    let group_path = format!("{}.{}", path, name);
+                    match group {
+                        Value::Object(group) => {
+                            let prefix = format!("{}.", group_path);
+                            check_object(
+                                group,
+                                GROUP_KEYS.iter().map(|(k, e, _)| (*k, e)),
+                                &prefix,
+                                problems,
+                            );
+                            for (key, _, default) in GROUP_KEYS {
+                                let default = match default {
+                                    Some(n) => n,
+                                    None => continue,
+                                };
+                                if !group.contains_key(*key) {
+                                    problems.push(SettingsProblem {
+                                        key: format!("{}{}", prefix, key),
+                                        message: format!("missing, defaults to {}", default),
+                                    });
+                                }
+                            }
+                        }
+                        other => problems.push(SettingsProblem {
+                            key: group_path,
+                            message: format!("expected a group object, found {}", type_name(other)),
+                        }),
+                    }
+                }
+            }
+            _ => mismatch(problems, "an object of groups"),
+        },
+        Expected::Labels => match value {
+            Value::Object(labels) => {
+                for (bookname, label) in labels {
+                    check_value(
+                        label,
+                        &Expected::Str,
+                        &format!("{}.{}", path, bookname),
+                        problems,
+                    );
+                }
+            }
+            _ => mismatch(problems, "an object of strings keyed by bookname"),
+        },
+        Expected::Matchers => match value {
+            Value::Object(matchers) => {
+                for (bookname, matcher) in matchers {
+                    let matcher_path = format!("{}.{}", path, bookname);
+                    match matcher {
+                        Value::Object(matcher) => check_object(
+                            matcher,
+                            MATCHER_KEYS.iter().map(|(k, e)| (*k, e)),
+                            &format!("{}.", matcher_path),
+                            problems,
+                        ),
+                        other => problems.push(SettingsProblem {
+                            key: matcher_path,
+                            message: format!(
+                                "expected a matcher object, found {}",
+                                type_name(other)
+                            ),
+                        }),
+                    }
+                }
+            }
+            _ => mismatch(problems, "an object of matchers keyed by bookname"),
+        },
+        Expected::Pages => match value {
+            Value::Object(pages) => {
+                for (bookname, page) in pages {
+                    let page_path = format!("{}.{}", path, bookname);
+                    match page {
+                        Value::Object(page) => check_object(
+                            page,
+                            PAGE_KEYS.iter().map(|(k, e)| (*k, e)),
+                            &format!("{}.", page_path),
+                            problems,
+                        ),
+                        other => problems.push(SettingsProblem {
+                            key: page_path,
+                            message: format!(
+                                "expected an object of stylesheet and template, found {}",
+                                type_name(other)
+                            ),
+                        }),
+                    }
+                }
+            }
+            _ => mismatch(problems, "an object of page styles keyed by bookname"),
+        },
+        Expected::Profiles => match value {
+            Value::Object(profiles) => {
+                for (name, profile) in profiles {
+                    let profile_path = format!("{}.{}", path, name);
+                    match profile {
+                        Value::Object(profile) => check_object(
+                            profile,
+                            PROFILE_KEYS.iter().map(|(k, e)| (*k, e)),
+                            &format!("{}.", profile_path),
+                            problems,
+                        ),
+                        other => problems.push(SettingsProblem {
+                            key: profile_path,
+                            message: format!(
+                                "expected a profile object, found {}",
+                                type_name(other)
+                            ),
+                        }),
+                    }
+                }
+            }
+            _ => mismatch(problems, "an object of profiles keyed by name"),
+        },
+        Expected::Transliteration => match value {
+            Value::String(n) if transliteration::builtin_table(n).is_some() => (),
+            Value::String(n) => problems.push(SettingsProblem {
+                key: path.to_string(),
+                message: format!(
+                    "unknown table {:?}, expected one of {} or an object",
+                    n,
+                    transliteration::builtin_names().join(", ")
+                ),
+            }),
+            Value::Object(table) => {
+                for (from, to) in table {
+                    let key = format!("{}.{}", path, from);
+                    if from.chars().count() != 1 {
+                        problems.push(SettingsProblem {
+                            key,
+                            message: String::from("keys must be a single letter"),
+                        });
+                    } else {
+                        check_value(to, &Expected::Str, &key, problems);
+                    }
+                }
+            }
+            _ => mismatch(problems, "a table name or an object of letters"),
+        },
+        Expected::ArrayOf(allowed) => match value {
+            Value::Array(values) => {
+                for (i, n) in values.iter().enumerate() {
+                    check_value(
+                        n,
+                        &Expected::OneOf(allowed),
+                        &format!("{}[{}]", path, i),
+                        problems,
+                    );
+                }
+            }
+            _ => mismatch(problems, &format!("an array of {}", allowed.join(", "))),
+        },
+        Expected::DictionaryArrays(allowed) => match value {
+            Value::Object(arrays) => {
+                for (bookname, array) in arrays {
+                    check_value(
+                        array,
+                        &Expected::ArrayOf(allowed),
+                        &format!("{}.{}", path, bookname),
+                        problems,
+                    );
+                }
+            }
+            _ => mismatch(problems, "an object of arrays keyed by bookname"),
+        },
+        Expected::DictionaryInts => match value {
+            Value::Object(ints) => {
+                for (bookname, int) in ints {
+                    check_value(
+                        int,
+                        &Expected::UnsignedInt,
+                        &format!("{}.{}", path, bookname),
+                        problems,
+                    );
+                }
+            }
+            _ => mismatch(
+                problems,
+                "an object of non-negative integers keyed by bookname",
+            ),
+        },
+        Expected::Synonyms => match value {
+            Value::Object(synonyms) => {
+                for (word, expansions) in synonyms {
+                    let key = format!("{}.{}", path, word);
+                    match expansions {
+                        Value::String(_) => (),
+                        Value::Array(values) => {
+                            for (i, n) in values.iter().enumerate() {
+                                check_value(
+                                    n,
+                                    &Expected::Str,
+                                    &format!("{}[{}]", key, i),
+                                    problems,
+                                );
+                            }
+                        }
+                        other => problems.push(SettingsProblem {
+                            key,
+                            message: format!(
+                                "expected a word or an array of words, found {}",
+                                type_name(other)
+                            ),
+                        }),
+                    }
+                }
+            }
+            _ => mismatch(problems, "an object of synonyms keyed by word"),
+        },
+        _ => (),
+    }
+}
+
+fn type_name(value: &Value) -> String {
+    match value {
+        Value::Null => String::from("null"),
+        Value::Bool(n) => format!("boolean {}", n),
+        Value::Number(n) => format!("number {}", n),
+        Value::String(n) => format!("string {:?}", n),
+        Value::Array(_) => String::from("an array"),
+        Value::Object(_) => String::from("an object"),
+    }
+}
+
+/// `path` with a leading `~` replaced by the home directory.
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Calls `func` with the "paths" list of a settings object, a single path string is read
+/// as a list of one.
+fn update_paths<F: FnOnce(&mut Vec<Value>)>(settings: &mut Map<String, Value>, func: F) {
+    let mut paths = match settings.remove("paths") {
+        Some(Value::Array(n)) => n,
+        Some(Value::String(n)) => vec![Value::String(n)],
+        _ => Vec::new(),
+    };
+    func(&mut paths);
+    settings.insert(String::from("paths"), Value::Array(paths));
+}
+
 /// Options structure that manages how program operates. Parses cli arguments,
 /// updates them with settings file argument.
 #[derive(Debug, StructOpt, Serialize, Deserialize)]
 #[structopt(about = "Dictonary for Stardict format.")]
 pub struct Opt {
+    #[structopt(subcommand)]
+    #[serde(skip)]
+    pub command: Option<Command>,
+
     #[structopt(parse(from_os_str), short, long)]
     pub paths: Option<Vec<PathBuf>>,
 
+    /// Levels of subdirectories of each path searched for dictionaries, symbolic links
+    /// included
+    #[structopt(long, default_value = "1")]
+    pub scan_depth: usize,
+
+    /// Threads loading and searching dictionaries, one for each core when not set
+    #[structopt(long)]
+    pub threads: Option<usize>,
+
+    /// Dictionaries read at the same time while loading, 0 to read as many as there
+    /// are threads
+    #[structopt(long, default_value = "4")]
+    pub io_parallelism: usize,
+
     #[structopt(parse(from_os_str), long, default_value = SETTINGS_PATH)]
+    #[serde(skip)]
     pub settings_path: PathBuf,
 
+    /// Groups to search, comma separated names or `all`
     #[structopt(short, long)]
     pub group: Option<String>,
 
     #[structopt(skip)]
     pub groups: HashMap<String, LangGroup>,
 
+    /// Short labels to show instead of booknames, keyed by bookname.
+    #[structopt(skip)]
+    pub labels: HashMap<String, String>,
+
+    /// Languages of dictionaries, keyed by bookname, override the `lang` field of .ifo files.
+    #[structopt(skip)]
+    pub langs: HashMap<String, String>,
+
+    /// Only searches dictionaries in this language
+    #[structopt(long)]
+    pub lang: Option<String>,
+
+    /// Language of the messages, from the locale when not given
+    #[structopt(long, possible_values = LANGUAGES)]
+    pub ui_lang: Option<String>,
+
+    /// Matchers of single dictionaries, keyed by bookname.
+    #[structopt(skip)]
+    pub matchers: HashMap<String, DictionaryMatcher>,
+
+    /// Transforms applied to the definitions of single dictionaries, keyed by bookname.
+    #[structopt(skip)]
+    pub postprocess: HashMap<String, Vec<Transform>>,
+
+    /// Stylesheets and templates of the pages definitions of single dictionaries are
+    /// opened in, keyed by bookname.
+    #[structopt(skip)]
+    pub pages: HashMap<String, PageStyle>,
+
+    /// Most results single dictionaries give, keyed by bookname.
+    #[structopt(skip)]
+    pub max_results: HashMap<String, usize>,
+
+    /// Words also searched for a word, like "formula one" for "f1", keyed by the lower
+    /// cased word.
+    #[structopt(skip)]
+    pub synonyms: HashMap<String, Vec<String>>,
+
+    /// levenshtein, exact, prefix to complete words, contains to find the words
+    /// having the query in them or regex to search with a regular expression.
     #[structopt(short = "-a", long, default_value = "levenshtein")]
     pub search_algorithm: String,
 
+    /// Edits a levenshtein match may be away from the query.
     #[structopt(short = "-d", long, default_value = "2")]
     pub search_depth: usize,
 
+    /// Whether `-a` or `-d` was given, the matcher they choose wins over the ones
+    /// dictionaries prefer. Set by `apply_settings_file`.
+    #[structopt(skip)]
+    #[serde(skip)]
+    pub matcher_on_command_line: bool,
+
     #[structopt(short = "-m", long, default_value = "none")]
     pub morpher: String,
 
@@ -49,6 +761,7 @@ pub struct Opt {
     pub list: bool,
 
     #[structopt(long)]
+    #[serde(skip)]
     pub list_dictionaries: bool,
 
     #[structopt(short = "-x", long)]
@@ -57,45 +770,231 @@ pub struct Opt {
     #[structopt(long = "--json")]
     pub json_output: bool,
 
+    /// Prints only the matching headwords, one per line, without reading definitions
+    #[structopt(long, conflicts_with_all = &["json-output", "list"])]
+    pub words_only: bool,
+
+    /// Also runs the http server, answering requests while searching in the terminal
     #[structopt(long)]
     pub background: bool,
 
-    #[structopt(long, default_value = "timelog.json")]
-    pub timelog_file: PathBuf,
+    /// Answers search, complete and define requests read from stdin as JSON lines, for
+    /// editor plugins
+    #[structopt(long, conflicts_with_all = &["json-output", "list", "words-only"])]
+    #[serde(skip)]
+    pub stdio: bool,
+
+    /// Opens html and MediaWiki definitions in the default browser.
+    #[structopt(long)]
+    pub open: bool,
+
+    /// Copies the printed definitions to the clipboard.
+    #[structopt(long)]
+    pub copy: bool,
+
+    /// Shows whole definitions next to headwords in list mode instead of a preview
+    #[structopt(long)]
+    pub full: bool,
+
+    /// Queues the found headwords spelled like the searched word in the study file next to
+    /// the settings
+    #[structopt(long)]
+    pub study: bool,
+
+    /// Finds headwords made of the same letters as the word instead of similar ones
+    #[structopt(long)]
+    #[serde(skip)]
+    pub anagram: bool,
+
+    /// Finds headwords matching the word as a pattern, `?` stands for any one letter
+    #[structopt(long, conflicts_with = "anagram")]
+    #[serde(skip)]
+    pub crossword: bool,
+
+    /// Finds headwords whose definitions have words close to the word, up to
+    /// --search-depth edits away with levenshtein
+    #[structopt(long, conflicts_with_all = &["anagram", "crossword"])]
+    #[serde(skip)]
+    pub reverse: bool,
+
+    /// Finds headwords whose definitions contain the words as a phrase
+    #[structopt(long, conflicts_with_all = &["anagram", "crossword", "reverse"])]
+    #[serde(skip)]
+    pub fulltext: bool,
+
+    /// Checks .idx and .dict files against the checksums recorded in their caches.
+    #[structopt(long)]
+    #[serde(skip)]
+    pub verify: bool,
+
+    /// Only shows definitions of these types, comma separated
+    #[structopt(long = "type", possible_values = DEFINITION_TYPES, use_delimiter = true)]
+    pub types: Vec<String>,
+
+    /// Only shows results the expression is true for, like 'word.len() <= 8 && dict == "gts"'
+    #[structopt(long)]
+    #[serde(skip)]
+    pub filter: Option<Filter>,
+
+    /// Only outputs these fields of each result with --json, comma separated
+    #[structopt(long, possible_values = OUTPUT_FIELDS, use_delimiter = true)]
+    pub fields: Vec<String>,
 
+    /// File the http server saves loaded indices to on shutdown and restores them from on start.
+    #[structopt(parse(from_os_str), long)]
+    pub snapshot: Option<PathBuf>,
+
+    /// Markdown file the words searched and the definitions read are noted in
+    #[structopt(parse(from_os_str), long)]
+    pub session_log: Option<PathBuf>,
+
+    /// URL of a JSON list of dictionaries the first-run setup offers to download a
+    /// starter dictionary from, see `setup::fetch_registry`
+    #[structopt(long)]
+    pub dictionary_registry: Option<String>,
+
+    /// Address and port the http server listens on, 0.0.0.0:<port> to serve the network
+    #[structopt(long, default_value = LISTEN_ADDRESS)]
+    pub listen: SocketAddr,
+
+    /// Milliseconds after which a search is stopped and partial results are shown.
+    #[structopt(long)]
+    pub timeout: Option<u64>,
+
+    /// Maximum width of printed definitions, the terminal width is used when it's narrower.
+    #[structopt(long)]
+    pub max_width: Option<usize>,
+
+    /// Bytes of a definition read at most, longer ones are cut until asked for whole.
+    /// 0 reads every definition whole
+    #[structopt(long, default_value = DEFINITION_LIMIT)]
+    pub definition_limit: usize,
+
+    /// Only matches headwords at least this many letters long
+    #[structopt(long)]
+    pub min_len: Option<usize>,
+
+    /// Only matches headwords at most this many letters long
+    #[structopt(long)]
+    pub max_len: Option<usize>,
+
+    /// Only matches headwords made of these characters
+    #[structopt(long, possible_values = CHARSETS)]
+    pub charset: Option<String>,
+
+    /// Appends timings of searches to this file, implies --timelog
+    #[structopt(long)]
+    pub timelog_file: Option<PathBuf>,
+
+    /// Appends timings of searches to timelog.json in the cache directory
     #[structopt(long)]
     pub timelog: bool,
 
+    /// Token the http server's admin endpoints require as `Authorization: Bearer <token>`.
+    /// Admin endpoints are disabled without one, only read from the settings file.
+    #[structopt(skip)]
+    pub admin_token: Option<String>,
+
+    /// Logs debug messages too, like the synonyms a word is searched as.
     #[structopt(short = "v")]
+    #[serde(skip)]
     pub verbose: bool,
 
-    pub word: String,
+    /// Profile of the settings file to search with, its values take the place of the
+    /// settings file's own
+    #[structopt(long)]
+    pub profile: Option<String>,
+
+    /// Word to search, required unless a subcommand is given.
+    #[serde(skip)]
+    pub word: Option<String>,
 }
 
-impl<'a> Opt {
-    ///Reads and returns a corresponding `serde::json::Value` from settings file.
-    ///Returns None on failure to find key on the file.
-    fn from_settings_file<S: ToString + ?Sized>(&self, key: &'a S) -> Option<Value> {
-        let settings_file: File = match File::open(&self.settings_path) {
+impl Opt {
+    /// Parses the settings file, a missing one is an empty object.
+    pub fn read_settings_file(&self) -> io::Result<Value> {
+        debug!("Reading settings from {:?}", &self.settings_path);
+        let file = match File::open(self.settings_file()) {
             Ok(n) => n,
-            Err(_) => {
-                debug!("Corrupt or nonexisting settings file.");
-                return None;
-            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Value::Object(Map::new())),
+            Err(e) => return Err(e),
         };
-        println!("Reading settings from {:?}", &self.settings_path);
+        serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
 
-        let v: Option<Value> = serde_json::from_reader(BufReader::new(settings_file)).unwrap();
+    ///Reads and returns a corresponding `serde::json::Value` from settings file, from the
+    ///selected profile if it has the key.
+    ///Returns None on failure to find key on the file.
+    fn settings_file_value(&self, key: &str) -> Option<Value> {
+        match self.read_settings_file() {
+            Ok(settings) => self.settings_value(&settings, key),
+            Err(e) => {
+                debug!("Corrupt settings file: {}", e);
+                None
+            }
+        }
+    }
 
-        v.and_then(|x| x.get(key.to_string()).and_then(|x| Some(x.clone())))
+    /// Value of `key` in the parsed `settings`, from the selected profile if it has the key.
+    fn settings_value(&self, settings: &Value, key: &str) -> Option<Value> {
+        let profile_value = self
+            .profile
+            .as_ref()
+            .filter(|_| PROFILE_KEYS.iter().any(|(k, _)| *k == key))
+            .and_then(|name| settings.get("profiles")?.get(name)?.get(key));
+        profile_value.or_else(|| settings.get(key)).cloned()
+    }
+
+    /// Values of the object `key` of the settings file keyed by bookname, the ones that
+    /// aren't a `T` are left out with a warning.
+    fn bookname_map<T: DeserializeOwned>(&self, settings: &Value, key: &str) -> HashMap<String, T> {
+        let mut map = HashMap::new();
+        if let Some(Value::Object(n)) = self.settings_value(settings, key) {
+            for (bookname, value) in n {
+                match serde_json::from_value(value) {
+                    Ok(value) => {
+                        map.insert(bookname, value);
+                    }
+                    Err(e) => warn!("{}.{} is invalid, ignoring it: {}", key, bookname, e),
+                }
+            }
+        }
+        map
+    }
+
+    /// Whether the settings file has a profile named `name`.
+    pub fn has_profile(&self, name: &str) -> bool {
+        self.settings_file_value("profiles")
+            .is_some_and(|n| n.get(name).is_some_and(Value::is_object))
     }
 
     /// Replaces default values with values from settings file. Keeps the values that user themselves has given.
-    pub fn apply_settings_file(&mut self, cli_clap: App) {
-        let argmatches = cli_clap.get_matches();
+    pub fn apply_settings_file(&mut self, argmatches: &ArgMatches) {
+        let settings = self.read_settings_file().unwrap_or_else(|e| {
+            debug!("Corrupt settings file: {}", e);
+            Value::Object(Map::new())
+        });
+        self.apply_settings(argmatches, &settings);
+    }
+
+    /// Applies the already parsed `settings` like `apply_settings_file`.
+    pub fn apply_settings(&mut self, argmatches: &ArgMatches, settings: &Value) {
+        if let Some(Value::String(n)) = self.settings_value(settings, "profile") {
+            if argmatches.occurrences_of("profile") == 0 {
+                self.profile = Some(n);
+            }
+        };
         if argmatches.occurrences_of("paths") == 0 {
-            if let Some(Value::String(n)) = self.from_settings_file("paths") {
+            if let Some(Value::String(n)) = self.settings_value(settings, "paths") {
                 self.paths = Some(vec![PathBuf::from(&n)]);
+            } else if let Some(Value::Array(n)) = self.settings_value(settings, "paths") {
+                self.paths = Some(
+                    n.iter()
+                        .filter_map(|p| p.as_str())
+                        .map(PathBuf::from)
+                        .collect(),
+                );
             } else {
                 let mut home = home_dir().unwrap();
                 let current_dir = current_dir().unwrap();
@@ -120,7 +1019,7 @@ impl<'a> Opt {
         // for ---------------------->key: String, group: Value::Object( <- second if let
         //               |
         //               |----------> key: String, group: Value::Object(
-        if let Some(Value::Object(n)) = self.from_settings_file("groups") {
+        if let Some(Value::Object(n)) = self.settings_value(settings, "groups") {
             debug!("groups object {:?}", n);
             for (key, value) in n {
                 if let Value::Object(group) = value {
@@ -141,9 +1040,9 @@ impl<'a> Opt {
                         }
                     };
 
-                    let matcher_depth = match group.get("matcher_depth") {
-                        Some(Value::Number(m)) => m.as_u64().unwrap() as usize,
-                        _ => {
+                    let matcher_depth = match group.get("matcher_depth").and_then(Value::as_u64) {
+                        Some(m) => m as usize,
+                        None => {
                             warn!("Empty matcher field on group, possibly misconfigurated file.");
                             0
                         }
@@ -156,6 +1055,33 @@ impl<'a> Opt {
                             String::default()
                         }
                     };
+                    let transliteration = match group.get("transliteration") {
+                        Some(Value::String(name)) => transliteration::builtin_table(name)
+                            .unwrap_or_else(|| {
+                                warn!("Unknown transliteration table {} on group {}.", name, key);
+                                HashMap::new()
+                            }),
+                        Some(Value::Object(table)) => table
+                            .iter()
+                            .filter_map(|(from, to)| Some((from.clone(), to.as_str()?.to_string())))
+                            .collect(),
+                        _ => HashMap::new(),
+                    };
+                    let mut normalization = Vec::new();
+                    if let Some(Value::Array(steps)) = group.get("normalization") {
+                        for step in steps {
+                            match serde_json::from_value::<Step>(step.clone()) {
+                                Ok(step) => normalization.push(step),
+                                Err(_) => {
+                                    warn!("Unknown normalization step {} on group {}.", step, key)
+                                }
+                            }
+                        }
+                    }
+                    let max_results = group
+                        .get("max_results")
+                        .and_then(Value::as_u64)
+                        .map(|n| n as usize);
                     self.groups.insert(
                         key,
                         LangGroup {
@@ -163,22 +1089,208 @@ impl<'a> Opt {
                             matcher_type,
                             matcher_depth,
                             morpher,
+                            transliteration,
+                            normalization,
+                            max_results,
                         },
                     );
                 };
             }
         };
 
-        if let Some(Value::String(n)) = self.from_settings_file("search_algorithm") {
-            if argmatches.occurrences_of("search_algorithm") == 0 {
-                self.search_algorithm = String::from(n);
+        self.labels.extend(self.bookname_map(settings, "labels"));
+        self.langs.extend(self.bookname_map(settings, "langs"));
+        if let Some(Value::String(n)) = self.settings_value(settings, "group") {
+            if argmatches.occurrences_of("group") == 0 {
+                self.group = Some(n);
+            }
+        };
+        if let Some(Value::String(n)) = self.settings_value(settings, "lang") {
+            if argmatches.occurrences_of("lang") == 0 {
+                self.lang = Some(n);
+            }
+        };
+
+        self.matchers
+            .extend(self.bookname_map(settings, "matchers"));
+        self.postprocess
+            .extend(self.bookname_map(settings, "postprocess"));
+        self.pages.extend(self.bookname_map(settings, "pages"));
+        self.max_results
+            .extend(self.bookname_map(settings, "max_results"));
+
+        if let Some(Value::Object(n)) = self.settings_value(settings, "synonyms") {
+            for (word, expansions) in n {
+                let expansions: Vec<String> = match expansions {
+                    Value::String(n) => vec![n],
+                    Value::Array(n) => n
+                        .into_iter()
+                        .filter_map(|n| n.as_str().map(String::from))
+                        .collect(),
+                    _ => {
+                        warn!("Synonyms of {} are not words, ignoring them.", word);
+                        continue;
+                    }
+                };
+                self.synonyms.insert(word.to_lowercase(), expansions);
+            }
+        };
+
+        self.matcher_on_command_line = argmatches.occurrences_of("search-algorithm") > 0
+            || argmatches.occurrences_of("search-depth") > 0;
+        if let Some(Value::String(n)) = self.settings_value(settings, "search_algorithm") {
+            if argmatches.occurrences_of("search-algorithm") == 0 {
+                self.search_algorithm = n;
+            }
+        };
+        if let Some(Value::String(n)) = self.settings_value(settings, "morpher") {
+            if argmatches.occurrences_of("morpher") == 0 {
+                self.morpher = n;
+            }
+        };
+        if let Some(Value::Number(n)) = self.settings_value(settings, "search_depth") {
+            if argmatches.occurrences_of("search-depth") == 0 {
+                if let Some(n) = n.as_u64() {
+                    self.search_depth = n as usize;
+                }
+            }
+        };
+        if let Some(Value::Number(n)) = self.settings_value(settings, "scan_depth") {
+            if argmatches.occurrences_of("scan-depth") == 0 {
+                if let Some(n) = n.as_u64() {
+                    self.scan_depth = n as usize;
+                }
+            }
+        };
+        if let Some(Value::Number(n)) = self.settings_value(settings, "threads") {
+            if argmatches.occurrences_of("threads") == 0 {
+                self.threads = n.as_u64().map(|n| n as usize);
+            }
+        };
+        if let Some(Value::Number(n)) = self.settings_value(settings, "io_parallelism") {
+            if argmatches.occurrences_of("io-parallelism") == 0 {
+                if let Some(n) = n.as_u64() {
+                    self.io_parallelism = n as usize;
+                }
+            }
+        };
+        if let Some(Value::String(n)) = self.settings_value(settings, "admin_token") {
+            self.admin_token = Some(n);
+        };
+        if let Some(Value::Number(n)) = self.settings_value(settings, "timeout") {
+            if argmatches.occurrences_of("timeout") == 0 {
+                self.timeout = n.as_u64();
             }
         };
-        if let Some(Value::Number(n)) = self.from_settings_file("search_depth") {
-            if argmatches.occurrences_of("search_depth") == 0 {
-                self.search_depth = n.as_u64().unwrap().try_into().unwrap();
+        if let Some(Value::String(n)) = self.settings_value(settings, "listen") {
+            if argmatches.occurrences_of("listen") == 0 {
+                match n.parse() {
+                    Ok(addr) => self.listen = addr,
+                    Err(_) => warn!("Invalid listen address {:?}, using {}.", n, self.listen),
+                }
             }
         };
+        if let Some(Value::String(n)) = self.settings_value(settings, "snapshot") {
+            if argmatches.occurrences_of("snapshot") == 0 {
+                self.snapshot = Some(PathBuf::from(n));
+            }
+        };
+        if let Some(Value::String(n)) = self.settings_value(settings, "session_log") {
+            if argmatches.occurrences_of("session-log") == 0 {
+                self.session_log = Some(PathBuf::from(n));
+            }
+        };
+        if let Some(Value::String(n)) = self.settings_value(settings, "dictionary_registry") {
+            if argmatches.occurrences_of("dictionary-registry") == 0 {
+                self.dictionary_registry = Some(n);
+            }
+        };
+        if let Some(Value::Array(n)) = self.settings_value(settings, "types") {
+            if argmatches.occurrences_of("types") == 0 {
+                self.types = n
+                    .iter()
+                    .filter_map(|t| t.as_str())
+                    .map(String::from)
+                    .collect();
+            }
+        };
+        if let Some(Value::Array(n)) = self.settings_value(settings, "fields") {
+            if argmatches.occurrences_of("fields") == 0 {
+                self.fields = n
+                    .iter()
+                    .filter_map(|f| f.as_str())
+                    .map(String::from)
+                    .collect();
+            }
+        };
+        if let Some(Value::Number(n)) = self.settings_value(settings, "max_width") {
+            if argmatches.occurrences_of("max-width") == 0 {
+                self.max_width = n.as_u64().map(|n| n as usize);
+            }
+        };
+        if let Some(Value::Number(n)) = self.settings_value(settings, "definition_limit") {
+            if argmatches.occurrences_of("definition-limit") == 0 {
+                if let Some(n) = n.as_u64() {
+                    self.definition_limit = n as usize;
+                }
+            }
+        };
+        if let Some(Value::Number(n)) = self.settings_value(settings, "min_len") {
+            if argmatches.occurrences_of("min-len") == 0 {
+                self.min_len = n.as_u64().map(|n| n as usize);
+            }
+        };
+        if let Some(Value::Number(n)) = self.settings_value(settings, "max_len") {
+            if argmatches.occurrences_of("max-len") == 0 {
+                self.max_len = n.as_u64().map(|n| n as usize);
+            }
+        };
+        if let Some(Value::String(n)) = self.settings_value(settings, "charset") {
+            if argmatches.occurrences_of("charset") == 0 {
+                self.charset = Some(n);
+            }
+        };
+        if let Some(Value::String(n)) = self.settings_value(settings, "timelog_file") {
+            if argmatches.occurrences_of("timelog-file") == 0 {
+                self.timelog_file = Some(PathBuf::from(n));
+            }
+        };
+        if let Some(Value::String(n)) = self.settings_value(settings, "ui_lang") {
+            if argmatches.occurrences_of("ui-lang") == 0 {
+                self.ui_lang = Some(n);
+            }
+        };
+        //Flags can only be turned on, one given on the command line stays on.
+        if let Some(Value::Bool(true)) = self.settings_value(settings, "json_output") {
+            self.json_output = true;
+        };
+        if let Some(Value::Bool(true)) = self.settings_value(settings, "words_only") {
+            self.words_only = true;
+        };
+        if let Some(Value::Bool(true)) = self.settings_value(settings, "list") {
+            self.list = true;
+        };
+        if let Some(Value::Bool(true)) = self.settings_value(settings, "full") {
+            self.full = true;
+        };
+        if let Some(Value::Bool(true)) = self.settings_value(settings, "exit") {
+            self.exit = true;
+        };
+        if let Some(Value::Bool(true)) = self.settings_value(settings, "study") {
+            self.study = true;
+        };
+        if let Some(Value::Bool(true)) = self.settings_value(settings, "background") {
+            self.background = true;
+        };
+        if let Some(Value::Bool(true)) = self.settings_value(settings, "open") {
+            self.open = true;
+        };
+        if let Some(Value::Bool(true)) = self.settings_value(settings, "copy") {
+            self.copy = true;
+        };
+        if let Some(Value::Bool(true)) = self.settings_value(settings, "timelog") {
+            self.timelog = true;
+        };
     }
 
     /// Creates an empty settings file on default path.
@@ -192,62 +1304,345 @@ impl<'a> Opt {
         writeln!(settings_file, "{}", json).unwrap();
     }
 
+    /// Adds `path` to the "paths" list of `group` in the settings file, creating the file if
+    /// needed. Without `group` it goes to the group chosen with --group when that names a
+    /// single one and to the top level "paths" otherwise.
+    pub fn add_path_to_settings_file(&self, path: &Path, group: Option<&str>) -> io::Result<()> {
+        let selected = self.selected_groups();
+        let group = group.or(match selected.as_slice() {
+            [(name, _)] => Some(*name),
+            _ => None,
+        });
+        let path = Value::String(path.to_string_lossy().to_string());
+        self.update_settings_file(|settings| {
+            let entry = match group {
+                Some(name) => match settings
+                    .get_mut("groups")
+                    .and_then(|groups| groups.get_mut(name))
+                    .and_then(Value::as_object_mut)
+                {
+                    Some(n) => n,
+                    None => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::NotFound,
+                            format!("there is no group named {} in the settings file", name),
+                        ))
+                    }
+                },
+                None => settings,
+            };
+            update_paths(entry, |paths| {
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            });
+            Ok(())
+        })
+    }
+
+    /// Removes `path` from the top level "paths" list of the settings file and from those
+    /// of every group.
+    pub fn remove_path_from_settings_file(&self, path: &Path) -> io::Result<()> {
+        let path = Value::String(path.to_string_lossy().to_string());
+        self.update_settings_file(|settings| {
+            if let Some(Value::Object(groups)) = settings.get_mut("groups") {
+                for group in groups.values_mut().filter_map(Value::as_object_mut) {
+                    if group.contains_key("paths") {
+                        update_paths(group, |paths| paths.retain(|p| *p != path));
+                    }
+                }
+            }
+            update_paths(settings, |paths| paths.retain(|p| *p != path));
+            Ok(())
+        })
+    }
+
+    fn update_settings_file<F>(&self, func: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut Map<String, Value>) -> io::Result<()>,
+    {
+        let mut settings: Value = match File::open(self.settings_file()) {
+            Ok(n) => serde_json::from_reader(BufReader::new(n))?,
+            Err(_) => Value::Object(Map::new()),
+        };
+        let settings_map = match settings.as_object_mut() {
+            Some(n) => n,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "settings file is not a json object",
+                ))
+            }
+        };
+        func(settings_map)?;
+        let json = serde_json::to_string_pretty(&settings)?;
+        fs::write(self.settings_file(), json)
+    }
+
+    /// Path of the file `name` next to the settings file, a leading `~` is the home
+    /// directory.
+    pub fn data_file(&self, name: &str) -> PathBuf {
+        expand_home(&self.settings_path.with_file_name(name))
+    }
+
+    /// File timings are appended to, `None` unless they are asked for with --timelog or
+    /// --timelog-file.
+    pub fn timelog_path(&self) -> Option<PathBuf> {
+        match &self.timelog_file {
+            Some(path) => Some(expand_home(path)),
+            None if self.timelog => cache_root().map(|n| n.join("timelog.json")),
+            None => None,
+        }
+    }
+
+    /// Path of the settings file, a leading `~` is the home directory.
+    pub fn settings_file(&self) -> PathBuf {
+        expand_home(&self.settings_path)
+    }
+
+    /// Checks the settings file, see `check_settings`. A missing file has no problems,
+    /// one that isn't valid json is an error.
+    pub fn check_settings_file(&self) -> io::Result<Vec<SettingsProblem>> {
+        Ok(check_settings(&self.read_settings_file()?))
+    }
+
+    /// Applies the options that concern single dictionaries, the label, language,
+    /// matcher and result limit configured for `dictionary`'s bookname and checksum
+    /// verification. A matcher given on the command line replaces the one it prefers.
+    pub fn configure_dictionary(&self, dictionary: &mut Dictionary) {
+        dictionary.label = self.labels.get(&dictionary.bookname).cloned();
+        if let Some(lang) = self.langs.get(&dictionary.bookname) {
+            dictionary.lang = Some(lang.clone());
+        }
+        dictionary.verify = self.verify;
+        if let Some(matcher) = self.matchers.get(&dictionary.bookname) {
+            if matcher.matcher_type.is_some() {
+                dictionary.preferred_algorithm = matcher.matcher_type.clone();
+            }
+            if matcher.matcher_depth.is_some() {
+                dictionary.preferred_depth = matcher.matcher_depth;
+            }
+        }
+        if self.matcher_on_command_line {
+            dictionary.preferred_algorithm = None;
+            dictionary.preferred_depth = None;
+        }
+        if let Some(transforms) = self.postprocess.get(&dictionary.bookname) {
+            dictionary.postprocess = transforms.clone();
+        }
+        if let Some(limit) = self.max_results.get(&dictionary.bookname) {
+            dictionary.max_results = Some(*limit);
+        }
+        if let Some(page) = self.pages.get(&dictionary.bookname) {
+            dictionary.page_style = page.clone();
+        }
+        dictionary.headword_filter = self.headword_filter();
+        dictionary.definition_limit = Some(self.definition_limit).filter(|n| *n > 0);
+    }
+
+    /// Language of the messages, `--ui-lang` or the one of the locale.
+    pub fn ui_language(&self) -> Language {
+        self.ui_lang
+            .as_deref()
+            .and_then(Language::from_name)
+            .unwrap_or_else(Language::from_env)
+    }
+
+    /// Word given to search, either alone or with the `search` subcommand.
+    pub fn take_word(&mut self) -> Option<String> {
+        match self.command.take() {
+            Some(Command::Search { word }) => Some(word),
+            command => {
+                self.command = command;
+                self.word.take()
+            }
+        }
+    }
+
+    /// Configured synonyms of `word` searched along with it, ignoring case.
+    pub fn expansions(&self, word: &str) -> &[String] {
+        self.synonyms
+            .get(&word.trim().to_lowercase())
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Filter of `--min-len`, `--max-len` and `--charset`.
+    pub fn headword_filter(&self) -> HeadwordFilter {
+        HeadwordFilter {
+            min_length: self.min_len,
+            max_length: self.max_len,
+            charset: self.charset.as_deref().and_then(Charset::from_name),
+        }
+    }
+
+    /// Groups given with `--group` as comma separated names or `all`, in the given order.
+    /// Names that aren't configured are skipped with a warning.
+    pub fn selected_groups(&self) -> Vec<(&str, &LangGroup)> {
+        let spec = match &self.group {
+            Some(n) => n,
+            None => return Vec::new(),
+        };
+        if spec == "all" {
+            let mut all: Vec<(&str, &LangGroup)> =
+                self.groups.iter().map(|(k, v)| (k.as_str(), v)).collect();
+            all.sort_by_key(|(k, _)| *k);
+            return all;
+        }
+        let mut selected: Vec<(&str, &LangGroup)> = Vec::new();
+        for name in spec.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            match self.groups.get_key_value(name) {
+                Some(_) if selected.iter().any(|(k, _)| *k == name) => (),
+                Some((k, group)) => selected.push((k.as_str(), group)),
+                None => warn!("There is no group named {}.", name),
+            }
+        }
+        selected
+    }
+
+    /// When a search starting now has to end by the configured timeout, `None` if
+    /// searches should never be cut short. Taken once for each query, every root,
+    /// spelling and group of it is searched until the same deadline.
+    pub fn search_deadline(&self) -> Option<Instant> {
+        self.timeout
+            .map(|n| Instant::now() + Duration::from_millis(n))
+    }
+
     pub fn new() -> Opt {
         Opt {
+            command: None,
             paths: Some(vec![PathBuf::from("")]),
+            scan_depth: 1,
+            threads: None,
+            io_parallelism: DEFAULT_IO_PARALLELISM,
             group: None,
             groups: HashMap::new(),
+            labels: HashMap::new(),
+            langs: HashMap::new(),
+            lang: None,
+            ui_lang: None,
+            matchers: HashMap::new(),
+            postprocess: HashMap::new(),
+            pages: HashMap::new(),
+            max_results: HashMap::new(),
+            synonyms: HashMap::new(),
             settings_path: PathBuf::from(""),
             search_algorithm: String::from(""),
             search_depth: 0,
+            matcher_on_command_line: false,
             morpher: String::default(),
             list: false,
             list_dictionaries: false,
             exit: false,
             json_output: false,
+            words_only: false,
             timelog: false,
-            timelog_file: PathBuf::from(""),
+            admin_token: None,
+            timelog_file: None,
             background: false,
+            stdio: false,
+            open: false,
+            copy: false,
+            full: false,
+            study: false,
+            anagram: false,
+            crossword: false,
+            reverse: false,
+            fulltext: false,
+            verify: false,
+            types: Vec::new(),
+            filter: None,
+            fields: Vec::new(),
+            snapshot: None,
+            session_log: None,
+            dictionary_registry: None,
+            listen: LISTEN_ADDRESS.parse().unwrap(),
+            timeout: None,
+            max_width: None,
+            definition_limit: DEFINITION_LIMIT.parse().unwrap(),
+            min_len: None,
+            max_len: None,
+            charset: None,
             verbose: false,
-            word: String::from(""),
+            profile: None,
+            word: None,
         }
     }
 }
 
+impl Default for Opt {
+    fn default() -> Self {
+        Opt::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::io::Write;
     use tempfile::NamedTempFile;
 
+    /// `Opt` with the settings file `json` applied, the file is removed when the
+    /// returned one is dropped.
+    fn opt_with_settings(json: &str) -> (Opt, NamedTempFile) {
+        opt_with_settings_and_args(json, &["word"])
+    }
+
+    /// Same as `opt_with_settings`, with `args` given on the command line.
+    fn opt_with_settings_and_args(json: &str, args: &[&str]) -> (Opt, NamedTempFile) {
+        let settings_file = NamedTempFile::new().unwrap();
+        fs::write(settings_file.path(), json).unwrap();
+        let path = settings_file.path().to_str().unwrap();
+        let matches =
+            Opt::clap().get_matches_from([&["sozluk", "--settings-path", path], args].concat());
+        let mut opt = Opt::from_clap(&matches);
+        opt.apply_settings_file(&matches);
+        (opt, settings_file)
+    }
+
     #[test]
     fn should_parse_settings_file() {
         let mut opt = Opt::new();
         opt.paths = Some(vec![PathBuf::from("./dic")]);
         opt.groups = HashMap::new();
-        // opt.groups.insert(
-        //     "en".to_string(),
-        //     vec![PathBuf::from("oxford"), PathBuf::from("gnu")],
-        // );
-        // opt.groups.insert(
-        //     "tr".to_string(),
-        //     vec![PathBuf::from("tdk"), PathBuf::from("kubbealtı")],
-        // );
+        opt.groups.insert(
+            "en".to_string(),
+            LangGroup {
+                paths: vec![PathBuf::from("oxford"), PathBuf::from("gnu")],
+                matcher_type: String::from("levenshtein"),
+                matcher_depth: 2,
+                morpher: String::from("en"),
+                transliteration: HashMap::new(),
+                normalization: Vec::new(),
+                max_results: None,
+            },
+        );
+        opt.groups.insert(
+            "tr".to_string(),
+            LangGroup {
+                paths: vec![PathBuf::from("tdk"), PathBuf::from("kubbealtı")],
+                matcher_type: String::from("levenshtein"),
+                matcher_depth: 2,
+                morpher: String::from("tr"),
+                transliteration: HashMap::new(),
+                normalization: Vec::new(),
+                max_results: None,
+            },
+        );
         let json = serde_json::to_string(&opt).unwrap();
         println!("index content {:?}", &json);
         let mut settings_file = NamedTempFile::new().unwrap();
         opt.settings_path = settings_file.path().to_path_buf();
-        settings_file.write(json.as_bytes()).unwrap();
+        settings_file.write_all(json.as_bytes()).unwrap();
         settings_file.flush().unwrap();
         assert_eq!(
-            opt.from_settings_file("paths").unwrap(),
+            opt.settings_file_value("paths").unwrap(),
             Value::Array(vec!(Value::String("./dic".to_string())))
         );
-        assert!(opt.from_settings_file("groups").unwrap().is_object());
-        if let Value::Object(map) = opt.from_settings_file("groups").unwrap() {
+        assert!(opt.settings_file_value("groups").unwrap().is_object());
+        if let Value::Object(map) = opt.settings_file_value("groups").unwrap() {
             assert!(map.contains_key("en"));
             assert!(map.contains_key("tr"));
-            if let Some(Value::Array(en_dicts)) = map.get("en") {
+            if let Some(Value::Array(en_dicts)) = map.get("en").and_then(|g| g.get("paths")) {
                 assert!(en_dicts.contains(&Value::String("oxford".to_string())));
                 assert!(en_dicts.contains(&Value::String("gnu".to_string())));
             };
@@ -258,8 +1653,8 @@ mod tests {
     fn should_apply_default() {
         let mut default_opt = Opt::new();
         let mut command_line_opt = Opt::new();
-        default_opt.word = String::from("default");
-        command_line_opt.word = String::from("");
+        default_opt.word = Some(String::from("default"));
+        command_line_opt.word = Some(String::from(""));
     }
 
     #[test]
@@ -273,6 +1668,9 @@ mod tests {
                 matcher_type: String::from("en"),
                 matcher_depth: 2,
                 morpher: String::from("en"),
+                transliteration: HashMap::new(),
+                normalization: Vec::new(),
+                max_results: None,
             },
         );
         opt.groups.insert(
@@ -282,14 +1680,13 @@ mod tests {
                 matcher_type: "tr".to_string(),
                 matcher_depth: 2,
                 morpher: "tr".to_string(),
+                transliteration: HashMap::new(),
+                normalization: Vec::new(),
+                max_results: None,
             },
         );
         let json = serde_json::to_string(&opt).unwrap();
-        let mut settings_file = NamedTempFile::new().unwrap();
-        opt.settings_path = settings_file.path().to_path_buf();
-        settings_file.write(json.as_bytes()).unwrap();
-        settings_file.flush().unwrap();
-        opt.apply_settings_file(Opt::clap());
+        let (opt, _settings_file) = opt_with_settings(&json);
         assert!(opt.groups.contains_key("en"));
         assert_eq!(opt.groups.get("en").unwrap().matcher_type, "en".to_string());
         assert!(opt
@@ -306,6 +1703,486 @@ mod tests {
             .contains(&PathBuf::from("kubbealtı")));
     }
 
+    #[test]
+    fn should_add_and_remove_paths_in_settings_file() {
+        let mut opt = Opt::new();
+        let settings_file = NamedTempFile::new().unwrap();
+        opt.settings_path = settings_file.path().to_path_buf();
+        fs::write(
+            &opt.settings_path,
+            r#"{"paths": "/usr/share/stardict", "search_depth": 1}"#,
+        )
+        .unwrap();
+        opt.add_path_to_settings_file(Path::new("/tmp/tdk"), None)
+            .unwrap();
+        opt.add_path_to_settings_file(Path::new("/tmp/tdk"), None)
+            .unwrap();
+        assert_eq!(
+            opt.settings_file_value("paths").unwrap(),
+            serde_json::json!(["/usr/share/stardict", "/tmp/tdk"])
+        );
+        opt.remove_path_from_settings_file(Path::new("/usr/share/stardict"))
+            .unwrap();
+        assert_eq!(
+            opt.settings_file_value("paths").unwrap(),
+            serde_json::json!(["/tmp/tdk"])
+        );
+        assert_eq!(opt.settings_file_value("search_depth").unwrap(), 1);
+    }
+
+    #[test]
+    fn should_add_paths_to_selected_group_in_settings_file() {
+        let (opt, _settings_file) = opt_with_settings_and_args(
+            r#"{"paths": ["/usr/share/stardict"],
+                "groups": {"tr": {"paths": ["/tmp/tdk"], "matcher_type": "exact", "matcher_depth": 0},
+                           "en": {"paths": [], "matcher_type": "exact", "matcher_depth": 0}}}"#,
+            &["--group", "tr"],
+        );
+        opt.add_path_to_settings_file(Path::new("/tmp/kubbealti"), None)
+            .unwrap();
+        opt.add_path_to_settings_file(Path::new("/tmp/oxford"), Some("en"))
+            .unwrap();
+        assert!(opt
+            .add_path_to_settings_file(Path::new("/tmp/larousse"), Some("fr"))
+            .is_err());
+        let groups = opt.settings_file_value("groups").unwrap();
+        assert_eq!(
+            groups["tr"]["paths"],
+            serde_json::json!(["/tmp/tdk", "/tmp/kubbealti"])
+        );
+        assert_eq!(groups["en"]["paths"], serde_json::json!(["/tmp/oxford"]));
+        assert_eq!(
+            opt.settings_file_value("paths").unwrap(),
+            serde_json::json!(["/usr/share/stardict"])
+        );
+
+        opt.remove_path_from_settings_file(Path::new("/tmp/tdk"))
+            .unwrap();
+        let groups = opt.settings_file_value("groups").unwrap();
+        assert_eq!(groups["tr"]["paths"], serde_json::json!(["/tmp/kubbealti"]));
+    }
+
+    #[test]
+    fn should_label_dictionaries() {
+        let (opt, _settings_file) = opt_with_settings(
+            r#"{"labels": {"Wiktionary English-English 2018-10-07": "wikt", "TDK": 5}}"#,
+        );
+        assert_eq!(opt.labels.len(), 1);
+
+        let mut dictionary = Dictionary::new(Path::new("wikt.ifo"));
+        dictionary.bookname = String::from("Wiktionary English-English 2018-10-07");
+        opt.configure_dictionary(&mut dictionary);
+        assert_eq!(dictionary.label(), "wikt");
+        dictionary.bookname = String::from("TDK");
+        opt.configure_dictionary(&mut dictionary);
+        assert_eq!(dictionary.label(), "TDK");
+    }
+
+    #[test]
+    fn should_set_languages_of_dictionaries() {
+        let (opt, _settings_file) = opt_with_settings(r#"{"langs": {"TDK": "tr"}, "lang": "tr"}"#);
+        assert_eq!(opt.lang.as_deref(), Some("tr"));
+
+        let mut dictionary = Dictionary::new(Path::new("tdk.ifo"));
+        dictionary.bookname = String::from("TDK");
+        dictionary.lang = Some(String::from("en"));
+        opt.configure_dictionary(&mut dictionary);
+        assert!(dictionary.has_lang("TR"));
+        dictionary.bookname = String::from("Wiktionary");
+        dictionary.lang = None;
+        opt.configure_dictionary(&mut dictionary);
+        assert!(!dictionary.has_lang("tr"));
+    }
+
+    #[test]
+    fn should_select_several_groups() {
+        let mut opt = Opt::new();
+        for name in &["tr", "en", "de"] {
+            opt.groups.insert(
+                name.to_string(),
+                LangGroup {
+                    paths: Vec::new(),
+                    matcher_type: String::from("exact"),
+                    matcher_depth: 0,
+                    morpher: String::from("none"),
+                    transliteration: HashMap::new(),
+                    normalization: Vec::new(),
+                    max_results: None,
+                },
+            );
+        }
+        let names = |opt: &Opt| -> Vec<String> {
+            opt.selected_groups()
+                .iter()
+                .map(|(k, _)| k.to_string())
+                .collect()
+        };
+        assert!(names(&opt).is_empty());
+        opt.group = Some(String::from("tr, en,fr,tr"));
+        assert_eq!(names(&opt), vec!["tr", "en"]);
+        opt.group = Some(String::from("all"));
+        assert_eq!(names(&opt), vec!["de", "en", "tr"]);
+    }
+
+    #[test]
+    fn should_keep_values_given_on_command_line() {
+        let (opt, _settings_file) = opt_with_settings_and_args(
+            r#"{"search_algorithm": "exact", "search_depth": 1, "max_width": 40}"#,
+            &["-d", "3", "--max-width", "60", "word"],
+        );
+        assert_eq!(opt.search_algorithm, "exact");
+        assert_eq!(opt.search_depth, 3);
+        assert_eq!(opt.max_width, Some(60));
+    }
+
+    #[test]
+    fn should_apply_every_key_it_accepts() {
+        let settings = serde_json::json!({
+            "background": true,
+            "open": true,
+            "copy": true,
+            "timelog_file": "times.json",
+            "anagram": true
+        });
+        let (opt, _settings_file) = opt_with_settings(&settings.to_string());
+        assert!(opt.background && opt.open && opt.copy);
+        assert_eq!(opt.timelog_path(), Some(PathBuf::from("times.json")));
+        assert!(!opt.anagram);
+        let problems: Vec<String> = check_settings(&settings)
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("anagram: unknown key"));
+    }
+
+    #[test]
+    fn should_postprocess_definitions_of_dictionaries() {
+        let settings = r#"{"postprocess": {"TDK": ["unescape_newlines", "strip_headword"], "Wiktionary": ["uppercase"]}}"#;
+        let (opt, _settings_file) = opt_with_settings(settings);
+        assert_eq!(opt.postprocess.len(), 1);
+        let problems = check_settings(&serde_json::from_str(settings).unwrap());
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].key, "postprocess.Wiktionary[0]");
+
+        let mut dictionary = Dictionary::new(Path::new("tdk.ifo"));
+        dictionary.bookname = String::from("TDK");
+        opt.configure_dictionary(&mut dictionary);
+        assert_eq!(
+            dictionary.postprocess,
+            vec![Transform::UnescapeNewlines, Transform::StripHeadword]
+        );
+    }
+
+    #[test]
+    fn should_style_pages_of_dictionaries() {
+        let settings = r#"{"pages": {"Lingvo": {"stylesheet": "lingvo.css"}, "Wiktionary": {"template": 5}, "TDK": "tdk.css"}}"#;
+        let (opt, _settings_file) = opt_with_settings(settings);
+        assert_eq!(opt.pages.len(), 1);
+        let problems = check_settings(&serde_json::from_str(settings).unwrap());
+        let keys: Vec<&str> = problems.iter().map(|p| p.key.as_str()).collect();
+        assert_eq!(keys, vec!["pages.TDK", "pages.Wiktionary.template"]);
+
+        let mut dictionary = Dictionary::new(Path::new("lingvo.ifo"));
+        dictionary.bookname = String::from("Lingvo");
+        opt.configure_dictionary(&mut dictionary);
+        assert_eq!(
+            dictionary.page_style.stylesheet,
+            Some(PathBuf::from("lingvo.css"))
+        );
+        assert_eq!(dictionary.page_style.template, None);
+    }
+
+    #[test]
+    fn should_read_headword_filter() {
+        let (opt, _settings_file) = opt_with_settings_and_args(
+            r#"{"min_len": 3, "max_len": 5, "charset": "alpha"}"#,
+            &["--min-len", "4", "word"],
+        );
+        let filter = opt.headword_filter();
+        //Given on the command line, so the settings file doesn't override it.
+        assert_eq!(filter.min_length, Some(4));
+        assert_eq!(filter.max_length, Some(5));
+        assert_eq!(filter.charset, Some(Charset::Alpha));
+    }
+
+    #[test]
+    fn should_override_matchers_of_dictionaries() {
+        let (opt, _settings_file) = opt_with_settings(
+            r#"{"matchers": {"TDK": {"matcher_depth": 1}, "Wiktionary": {"matcher_depth": "1"}}}"#,
+        );
+        assert_eq!(opt.matchers.len(), 1);
+
+        let mut dictionary = Dictionary::new(Path::new("tdk.ifo"));
+        dictionary.bookname = String::from("TDK");
+        dictionary.preferred_algorithm = Some(String::from("levenshtein"));
+        dictionary.preferred_depth = Some(3);
+        opt.configure_dictionary(&mut dictionary);
+        assert_eq!(
+            dictionary.preferred_algorithm.as_deref(),
+            Some("levenshtein")
+        );
+        assert_eq!(dictionary.preferred_depth, Some(1));
+
+        let args = ["-a", "exact", "word"];
+        let (opt, _settings_file) =
+            opt_with_settings_and_args(r#"{"matchers": {"TDK": {"matcher_depth": 1}}}"#, &args);
+        opt.configure_dictionary(&mut dictionary);
+        assert_eq!(dictionary.preferred_algorithm, None);
+        assert_eq!(dictionary.preferred_depth, None);
+    }
+
+    #[test]
+    fn should_limit_results_of_dictionaries_and_groups() {
+        let settings = r#"{"max_results": {"Wiktionary": 20, "TDK": -1},
+            "groups": {"tr": {"paths": [], "matcher_type": "exact", "matcher_depth": 0,
+                "morpher": "none", "max_results": 50}}}"#;
+        let (opt, _settings_file) = opt_with_settings(settings);
+        assert_eq!(opt.max_results.len(), 1);
+        let problems = opt.check_settings_file().unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].key, "max_results.TDK");
+
+        let group = &opt.groups["tr"];
+        assert_eq!(group.max_results, Some(50));
+        let mut wiktionary = Dictionary::new(Path::new("wiktionary.ifo"));
+        wiktionary.bookname = String::from("Wiktionary");
+        group.configure_dictionary(&mut wiktionary);
+        opt.configure_dictionary(&mut wiktionary);
+        assert_eq!(wiktionary.max_results, Some(20));
+        let mut tdk = Dictionary::new(Path::new("tdk.ifo"));
+        tdk.bookname = String::from("TDK");
+        group.configure_dictionary(&mut tdk);
+        opt.configure_dictionary(&mut tdk);
+        assert_eq!(tdk.max_results, Some(50));
+    }
+
+    #[test]
+    fn should_take_word_of_search_subcommand() {
+        let mut opt = Opt::from_iter(vec!["sozluk", "search", "elma"]);
+        assert_eq!(opt.take_word().as_deref(), Some("elma"));
+        assert!(opt.command.is_none());
+        let mut opt = Opt::from_iter(vec!["sozluk", "elma"]);
+        assert_eq!(opt.take_word().as_deref(), Some("elma"));
+        let mut opt = Opt::from_iter(vec!["sozluk", "index", "--keywords"]);
+        assert_eq!(opt.take_word(), None);
+        assert!(matches!(
+            opt.command,
+            Some(Command::Index { keywords: true })
+        ));
+        assert!(Opt::from_iter_safe(vec!["sozluk", "search"]).is_err());
+    }
+
+    #[test]
+    fn should_expand_words_with_synonyms() {
+        let settings = r#"{"synonyms": {"F1": "formula one",
+            "tv": ["television", "televizyon"], "pc": 5}}"#;
+        let (opt, _settings_file) = opt_with_settings(settings);
+        assert_eq!(opt.expansions("f1"), ["formula one"]);
+        assert_eq!(opt.expansions(" TV"), ["television", "televizyon"]);
+        assert!(opt.expansions("pc").is_empty());
+        assert!(opt.expansions("word").is_empty());
+        let problems = opt.check_settings_file().unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].key, "synonyms.pc");
+    }
+
+    #[test]
+    fn should_read_concurrency_limits() {
+        let settings = r#"{"threads": 2, "io_parallelism": 1}"#;
+        let (opt, _settings_file) = opt_with_settings(settings);
+        assert_eq!((opt.threads, opt.io_parallelism), (Some(2), 1));
+        assert!(opt.check_settings_file().unwrap().is_empty());
+
+        let args = ["--io-parallelism", "0", "word"];
+        let (opt, _settings_file) = opt_with_settings_and_args(settings, &args);
+        assert_eq!((opt.threads, opt.io_parallelism), (Some(2), 0));
+    }
+
+    #[test]
+    fn should_only_log_timings_when_asked() {
+        let mut opt = Opt::new();
+        assert_eq!(opt.timelog_path(), None);
+        opt.timelog = true;
+        let path = opt.timelog_path().unwrap();
+        assert!(path.ends_with("sozluk/timelog.json"));
+        opt.timelog_file = Some(PathBuf::from("times.json"));
+        assert_eq!(opt.timelog_path(), Some(PathBuf::from("times.json")));
+    }
+
+    #[test]
+    fn should_read_listen_address() {
+        let (opt, _settings_file) = opt_with_settings(r#"{"listen": "0.0.0.0:8080"}"#);
+        assert_eq!(opt.listen, "0.0.0.0:8080".parse().unwrap());
+        assert!(opt.check_settings_file().unwrap().is_empty());
+
+        fs::write(&opt.settings_path, r#"{"listen": "localhost"}"#).unwrap();
+        let problems = opt.check_settings_file().unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].key, "listen");
+        let args = ["--listen", "127.0.0.1:9000", "word"];
+        let (opt, _settings_file) =
+            opt_with_settings_and_args(r#"{"listen": "0.0.0.0:8080"}"#, &args);
+        assert_eq!(opt.listen.port(), 9000);
+    }
+
+    #[test]
+    fn should_read_normalization_pipeline_of_groups() {
+        let (opt, _settings_file) = opt_with_settings(
+            r#"{"groups": {
+                "tr": {"paths": [], "matcher_type": "exact", "matcher_depth": 0, "morpher": "none",
+                       "transliteration": "tr", "normalization": ["case_fold", "transliterate", "fold"]},
+                "old": {"paths": [], "matcher_type": "exact", "matcher_depth": 0, "morpher": "none",
+                        "transliteration": "tr"}
+            }}"#,
+        );
+        let tr = opt.groups["tr"].pipeline();
+        assert_eq!(tr.steps, vec![Step::CaseFold, Step::Transliterate]);
+        assert_eq!(
+            opt.groups["old"].pipeline().steps,
+            vec![Step::Transliterate]
+        );
+        let problems: Vec<String> = opt
+            .check_settings_file()
+            .unwrap()
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                "groups.tr.normalization[2]: unknown value \"fold\", expected one of \
+                  case_fold, accent_fold, transliterate, strip_punctuation"
+            ]
+        );
+    }
+
+    #[test]
+    fn should_expand_home_in_settings_path() {
+        let home = home_dir().unwrap();
+        let mut opt = Opt::new();
+        opt.settings_path = PathBuf::from("~/.config/sozluk/settings.json");
+        assert_eq!(
+            opt.settings_file(),
+            home.join(".config/sozluk/settings.json")
+        );
+        assert_eq!(
+            opt.data_file("history.json"),
+            home.join(".config/sozluk/history.json")
+        );
+        opt.settings_path = PathBuf::from("/etc/sozluk/~/settings.json");
+        assert_eq!(
+            opt.settings_file(),
+            PathBuf::from("/etc/sozluk/~/settings.json")
+        );
+    }
+
+    #[test]
+    fn should_apply_parsed_settings_without_reading_file() {
+        let mut opt = Opt::new();
+        opt.settings_path = PathBuf::from("/nonexistent/settings.json");
+        let settings = serde_json::json!({"search_depth": 1, "profile": "close",
+            "profiles": {"close": {"search_depth": 3}}});
+        opt.apply_settings(&Opt::clap().get_matches_from(vec!["sozluk"]), &settings);
+        assert_eq!(opt.profile.as_deref(), Some("close"));
+        assert_eq!(opt.search_depth, 3);
+    }
+
+    #[test]
+    fn should_skip_negative_numbers() {
+        let (opt, _settings_file) = opt_with_settings(
+            r#"{"search_depth": -1, "scan_depth": -1, "io_parallelism": -3,
+                "groups": {"tr": {"paths": [], "matcher_type": "exact", "matcher_depth": -2}}}"#,
+        );
+        let (defaults, _settings_file) = opt_with_settings("{}");
+        assert_eq!(opt.search_depth, defaults.search_depth);
+        assert_eq!(opt.scan_depth, defaults.scan_depth);
+        assert_eq!(opt.io_parallelism, defaults.io_parallelism);
+        assert_eq!(opt.groups["tr"].matcher_depth, 0);
+    }
+
+    #[test]
+    fn should_apply_selected_profile() {
+        let settings = r#"{"search_algorithm": "exact", "search_depth": 1, "group": "en",
+            "profiles": {"reading-fr": {"group": "fr", "search_algorithm": "prefix",
+            "morpher": "en", "definition_limit": 4096, "json_output": true,
+            "paths": ["elsewhere"]}}}"#;
+        let args = ["--profile", "reading-fr", "-a", "levenshtein", "word"];
+        let (opt, _settings_file) = opt_with_settings_and_args(settings, &args);
+        assert_eq!(opt.group.as_deref(), Some("fr"));
+        assert_eq!(opt.search_algorithm, "levenshtein");
+        assert_eq!(opt.search_depth, 1);
+        assert_eq!(opt.morpher, "en");
+        assert_eq!(opt.definition_limit, 4096);
+        assert!(opt.json_output);
+        assert_ne!(opt.paths, Some(vec![PathBuf::from("elsewhere")]));
+        assert!(opt.has_profile("reading-fr"));
+        assert!(!opt.has_profile("reading-de"));
+
+        let (opt, _settings_file) = opt_with_settings(settings);
+        assert_eq!(opt.group.as_deref(), Some("en"));
+        assert_eq!(opt.search_algorithm, "exact");
+        assert!(!opt.json_output);
+
+        let problems =
+            check_settings(&serde_json::json!({"profiles": {"a": {"search_depth": "2"}, "b": 1}}));
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].key, "profiles.a.search_depth");
+        assert_eq!(problems[1].key, "profiles.b");
+    }
+
+    #[test]
+    fn should_accept_serialized_settings() {
+        let mut opt = Opt::new();
+        opt.paths = Some(vec![PathBuf::from("./dic")]);
+        opt.search_algorithm = String::from("levenshtein");
+        opt.morpher = String::from("none");
+        opt.groups.insert(
+            "tr".to_string(),
+            LangGroup {
+                paths: vec![PathBuf::from("tdk")],
+                matcher_type: String::from("levenshtein"),
+                matcher_depth: 2,
+                morpher: String::from("tr"),
+                transliteration: HashMap::new(),
+                normalization: Vec::new(),
+                max_results: None,
+            },
+        );
+        assert_eq!(check_settings(&serde_json::to_value(&opt).unwrap()), vec![]);
+    }
+
+    #[test]
+    fn should_report_key_paths_of_problems() {
+        let settings = serde_json::json!({
+            "search_dept": 2,
+            "timeout": "100",
+            "paths": ["/usr/share/stardict", 5],
+            "groups": {
+                "tr": {"paths": ["tdk"], "matcher_type": "fuzzy", "matcher_depth": -1},
+                "en": []
+            },
+            "labels": {"TDK": 5}
+        });
+        let problems: Vec<String> = check_settings(&settings)
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        for expected in &[
+            "search_dept: unknown key, did you mean `search_depth`?",
+            "timeout: expected a non-negative integer, found string \"100\"",
+            "paths[1]: expected a string, found number 5",
+            "groups.tr.matcher_type: unknown value \"fuzzy\", expected one of levenshtein, exact, prefix, contains, regex",
+            "groups.tr.matcher_depth: expected a non-negative integer, found number -1",
+            "groups.tr.morpher: missing, defaults to none",
+            "groups.en: expected a group object, found an array",
+            "labels.TDK: expected a string, found number 5",
+        ] {
+            assert!(problems.contains(&expected.to_string()), "{:?}", problems);
+        }
+        assert_eq!(problems.len(), 8);
+    }
+
     #[test]
     fn should_not_apply_default() {}
 }
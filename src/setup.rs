@@ -0,0 +1,461 @@
+//! First-run setup. When sozluk starts without a settings file and finds no dictionaries
+//! it asks where the dictionaries are and which group to search by default, and writes
+//! the answers to a new settings file. If those directories have no dictionaries either,
+//! it offers to download one from a dictionary registry, a JSON array of
+//! `RegistryEntry` like
+//!
+//! ```json
+//! [{"name": "tr-en", "description": "Turkish-English",
+//!   "files": ["https://host/tr-en.ifo", "https://host/tr-en.idx", "https://host/tr-en.dict.dz"]}]
+//! ```
+use crate::load_dicts_from_paths_and_subpaths;
+use crate::settings::{expand_home, Opt, MATCHERS, MORPHERS};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Directories StarDict dictionaries are usually installed to.
+const COMMON_DIRECTORIES: &[&str] = &[
+    "~/.sozluk",
+    "~/.stardict/dic",
+    "~/.local/share/stardict/dic",
+    "/usr/share/stardict/dic",
+    "/usr/local/share/stardict/dic",
+];
+
+/// The usual dictionary directories that have dictionaries in them, with the number of
+/// dictionaries in each.
+pub fn candidate_directories(depth: usize) -> Vec<(PathBuf, usize)> {
+    COMMON_DIRECTORIES
+        .iter()
+        .map(|n| expand_home(Path::new(n)))
+        .filter(|n| n.is_dir())
+        .map(|n| {
+            let count = load_dicts_from_paths_and_subpaths(std::slice::from_ref(&n), depth).len();
+            (n, count)
+        })
+        .filter(|(_, count)| *count > 0)
+        .collect()
+}
+
+/// A dictionary of a registry.
+#[derive(Debug, Deserialize)]
+pub struct RegistryEntry {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// URLs of its .ifo, .idx, .dict or .dict.dz and optional .syn files.
+    pub files: Vec<String>,
+}
+
+/// Body of `url`, read with curl since there is no http client among the dependencies.
+/// file:// URLs are read too.
+fn download(url: &str) -> io::Result<Vec<u8>> {
+    let output = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location", url])
+        .stdin(Stdio::null())
+        .output();
+    let output = match output {
+        Ok(n) => n,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "curl isn't installed",
+            ))
+        }
+        Err(e) => return Err(e),
+    };
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "curl exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+/// Dictionaries of the registry at `url`.
+pub fn fetch_registry(url: &str) -> io::Result<Vec<RegistryEntry>> {
+    serde_json::from_slice(&download(url)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// `name` if it names a file in the directory it's joined to, not one elsewhere.
+fn file_name(name: &str) -> io::Result<&str> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{:?} isn't a file name", name),
+        ));
+    }
+    Ok(name)
+}
+
+/// Downloads the files of `entry` into a directory named after it in `dir`, returns that
+/// directory.
+pub fn download_dictionary(entry: &RegistryEntry, dir: &Path) -> io::Result<PathBuf> {
+    let target = dir.join(file_name(&entry.name)?);
+    fs::create_dir_all(&target)?;
+    for url in &entry.files {
+        let path = url.split(['?', '#']).next().unwrap_or_default();
+        let name = file_name(path.rsplit('/').next().unwrap_or_default())?;
+        fs::write(target.join(name), download(url)?)?;
+    }
+    Ok(target)
+}
+
+/// Settings written by the setup, `paths` searched by default through the group `group`.
+pub fn settings_json(
+    paths: &[PathBuf],
+    group: &str,
+    matcher: &str,
+    depth: usize,
+    morpher: &str,
+) -> Value {
+    let paths: Vec<String> = paths
+        .iter()
+        .map(|n| n.to_string_lossy().to_string())
+        .collect();
+    json!({
+        "paths": paths,
+        "group": group,
+        "groups": {
+            group: {
+                "paths": paths,
+                "matcher_type": matcher,
+                "matcher_depth": depth,
+                "morpher": morpher,
+            }
+        }
+    })
+}
+
+/// Asks `question` and returns the trimmed answer, `default` if it's empty. `None` when
+/// the input ends.
+fn ask<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    question: &str,
+    default: &str,
+) -> io::Result<Option<String>> {
+    write!(output, "{} [{}]: ", question, default)?;
+    output.flush()?;
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        writeln!(output)?;
+        return Ok(None);
+    }
+    let answer = line.trim();
+    Ok(Some(
+        if answer.is_empty() { default } else { answer }.to_string(),
+    ))
+}
+
+/// Asks a yes or no question, yes unless the answer is something else. `None` when
+/// the input ends.
+fn confirm<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    question: &str,
+) -> io::Result<Option<bool>> {
+    Ok(ask(input, output, question, "Y/n")?.map(|n| {
+        matches!(
+            n.to_lowercase().as_str(),
+            "y/n" | "y" | "yes" | "e" | "evet"
+        )
+    }))
+}
+
+/// Lists the dictionaries of the registry at `url` and downloads the one chosen into
+/// `dir`. Returns whether one was downloaded, `None` when the input ends.
+fn offer_registry<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    url: &str,
+    dir: &Path,
+) -> io::Result<Option<bool>> {
+    let entries = match fetch_registry(url) {
+        Ok(n) if !n.is_empty() => n,
+        Ok(_) => {
+            writeln!(output, "The registry at {} has no dictionaries.", url)?;
+            return Ok(Some(false));
+        }
+        Err(e) => {
+            writeln!(output, "Can't read the registry at {}: {}", url, e)?;
+            return Ok(Some(false));
+        }
+    };
+    writeln!(output, "Dictionaries of the registry:")?;
+    for (n, entry) in entries.iter().enumerate() {
+        if entry.description.is_empty() {
+            writeln!(output, "  {}) {}", n + 1, entry.name)?;
+        } else {
+            writeln!(
+                output,
+                "  {}) {} - {}",
+                n + 1,
+                entry.name,
+                entry.description
+            )?;
+        }
+    }
+    let entry = loop {
+        match ask(input, output, "Dictionary to download, none to skip", "1")? {
+            Some(n) if n == "none" => return Ok(Some(false)),
+            Some(n) => match n.parse::<usize>() {
+                Ok(n) if n >= 1 && n <= entries.len() => break &entries[n - 1],
+                _ => writeln!(output, "{} isn't one of the dictionaries.", n)?,
+            },
+            None => return Ok(None),
+        }
+    };
+    writeln!(output, "Downloading {}.", entry.name)?;
+    match download_dictionary(entry, dir) {
+        Ok(path) => {
+            writeln!(output, "Downloaded {} to {}.", entry.name, path.display())?;
+            Ok(Some(true))
+        }
+        Err(e) => {
+            writeln!(output, "Can't download {}: {}", entry.name, e)?;
+            Ok(Some(false))
+        }
+    }
+}
+
+/// Runs the setup, reading answers from `input`. `candidates` are the directories
+/// offered, see `candidate_directories`. Returns whether the settings file of `opt` was
+/// written, it isn't if the user declines or the input ends before every question is
+/// answered.
+pub fn run<R: BufRead, W: Write>(
+    mut input: R,
+    mut output: W,
+    opt: &Opt,
+    candidates: &[(PathBuf, usize)],
+) -> io::Result<bool> {
+    let settings_file = opt.settings_file();
+    writeln!(
+        output,
+        "There is no settings file at {} and no dictionaries were found.",
+        settings_file.display()
+    )?;
+    if confirm(&mut input, &mut output, "Set up sozluk now?")? != Some(true) {
+        return Ok(false);
+    }
+
+    let default = if candidates.is_empty() {
+        writeln!(
+            output,
+            "None of the usual dictionary directories have dictionaries."
+        )?;
+        String::from("~/.sozluk")
+    } else {
+        writeln!(output, "Dictionaries were found in:")?;
+        for (n, (path, count)) in candidates.iter().enumerate() {
+            writeln!(
+                output,
+                "  {}) {} ({} dictionaries)",
+                n + 1,
+                path.display(),
+                count
+            )?;
+        }
+        (1..=candidates.len())
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    let answer = match ask(
+        &mut input,
+        &mut output,
+        "Directories to search, numbers of the ones above or paths separated by commas",
+        &default,
+    )? {
+        Some(n) => n,
+        None => return Ok(false),
+    };
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for part in answer.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        let path = match part.parse::<usize>() {
+            Ok(n) if n >= 1 && n <= candidates.len() => candidates[n - 1].0.clone(),
+            _ => expand_home(Path::new(part)),
+        };
+        if !path.exists() {
+            let question = format!("{} doesn't exist, create it?", path.display());
+            match confirm(&mut input, &mut output, &question)? {
+                Some(true) => fs::create_dir_all(&path)?,
+                Some(false) => continue,
+                None => return Ok(false),
+            }
+        }
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    if paths.is_empty() {
+        writeln!(output, "No directories given, not writing settings.")?;
+        return Ok(false);
+    }
+    if load_dicts_from_paths_and_subpaths(&paths, opt.scan_depth).is_empty() {
+        writeln!(
+            output,
+            "There are no dictionaries in these directories yet."
+        )?;
+        let registry = match ask(
+            &mut input,
+            &mut output,
+            "URL of a dictionary registry to download a starter dictionary from, none to skip",
+            opt.dictionary_registry.as_deref().unwrap_or("none"),
+        )? {
+            Some(n) => n,
+            None => return Ok(false),
+        };
+        let downloaded = if registry == "none" {
+            false
+        } else {
+            match offer_registry(&mut input, &mut output, &registry, &paths[0])? {
+                Some(n) => n,
+                None => return Ok(false),
+            }
+        };
+        if !downloaded {
+            writeln!(
+                output,
+                "Copy the .ifo, .idx and .dict.dz files of StarDict dictionaries into {} \
+                 and they are found on the next search.",
+                paths[0].display()
+            )?;
+        }
+    }
+
+    let group = match ask(
+        &mut input,
+        &mut output,
+        "Name of the default group",
+        "default",
+    )? {
+        Some(n) => n,
+        None => return Ok(false),
+    };
+    let question = format!("Matcher of the group, one of {}", MATCHERS.join(", "));
+    let default = if MATCHERS.contains(&opt.search_algorithm.as_str()) {
+        opt.search_algorithm.as_str()
+    } else {
+        MATCHERS[0]
+    };
+    let matcher = loop {
+        match ask(&mut input, &mut output, &question, default)? {
+            Some(n) if MATCHERS.contains(&n.as_str()) => break n,
+            Some(n) => writeln!(output, "{} isn't a matcher.", n)?,
+            None => return Ok(false),
+        }
+    };
+    let question = format!("Morpher of the group, one of {}", MORPHERS.join(", "));
+    let morpher = loop {
+        match ask(&mut input, &mut output, &question, "none")? {
+            Some(n) if MORPHERS.contains(&n.as_str()) => break n,
+            Some(n) => writeln!(output, "{} isn't a morpher.", n)?,
+            None => return Ok(false),
+        }
+    };
+
+    let settings = settings_json(&paths, &group, &matcher, opt.search_depth, &morpher);
+    if let Some(parent) = settings_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&settings_file, serde_json::to_string_pretty(&settings)?)?;
+    writeln!(output, "Wrote settings to {}.", settings_file.display())?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::write_dictionary;
+
+    #[test]
+    fn should_write_settings_from_answers() {
+        let dir = tempfile::tempdir().unwrap();
+        write_dictionary(&dir.path().join("dic"), "tr", &[("elma", "apple")]);
+        let candidates = vec![(dir.path().join("dic"), 1)];
+        let extra = dir.path().join("extra");
+        let mut opt = Opt::new();
+        opt.settings_path = dir.path().join("config").join("settings.json");
+
+        assert!(!run(&b"n\n"[..], Vec::new(), &opt, &candidates).unwrap());
+        assert!(!opt.settings_path.exists());
+
+        let answers = format!("\n1, {}\n\ntr\nfuzzy\n\nnone\n", extra.display());
+        let mut output = Vec::new();
+        assert!(run(answers.as_bytes(), &mut output, &opt, &candidates).unwrap());
+        assert!(extra.is_dir());
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("fuzzy isn't a matcher."));
+        let settings: Value =
+            serde_json::from_str(&fs::read_to_string(&opt.settings_path).unwrap()).unwrap();
+        assert_eq!(
+            settings,
+            settings_json(
+                &[dir.path().join("dic"), extra],
+                "tr",
+                "levenshtein",
+                opt.search_depth,
+                "none"
+            )
+        );
+        assert!(crate::settings::check_settings(&settings).is_empty());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn should_download_starter_dictionary_from_registry() {
+        let dir = tempfile::tempdir().unwrap();
+        let remote = write_dictionary(&dir.path().join("remote"), "tr", &[("elma", "apple")]);
+        let url = |name: &str| format!("file://{}", remote.join(name).display());
+        let registry = json!([
+            {"name": "../tr", "files": [url("tr.ifo")]},
+            {"name": "tr", "description": "Turkish", "files": [url("tr.ifo"), url("tr.idx"), url("tr.dict")]},
+        ]);
+        let registry_path = dir.path().join("registry.json");
+        fs::write(&registry_path, registry.to_string()).unwrap();
+        let dic = dir.path().join("dic");
+        let mut opt = Opt::new();
+        opt.settings_path = dir.path().join("settings.json");
+        opt.dictionary_registry = Some(format!("file://{}", registry_path.display()));
+
+        let answers = format!("\n{}\n\n\n1\n\n\n\n\n\n", dic.display());
+        let mut output = Vec::new();
+        assert!(run(answers.as_bytes(), &mut output, &opt, &[]).unwrap());
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("2) tr - Turkish"));
+        assert!(output.contains("Can't download ../tr"));
+        assert!(!dir.path().join("tr").exists());
+
+        let answers = format!("\n{}\n\n2\n\n\n\n", dic.display());
+        let mut output = Vec::new();
+        assert!(run(answers.as_bytes(), &mut output, &opt, &[]).unwrap());
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(&format!("Downloaded tr to {}.", dic.join("tr").display())));
+        assert!(!output.contains("Copy the .ifo"));
+        let dicts = load_dicts_from_paths_and_subpaths(&[dic], 1);
+        assert_eq!(dicts.len(), 1);
+        assert_eq!(dicts[0].bookname, "tr");
+    }
+
+    #[test]
+    fn should_skip_unreadable_registry() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut opt = Opt::new();
+        opt.settings_path = dir.path().join("settings.json");
+        let missing = format!("file://{}", dir.path().join("missing.json").display());
+        let answers = format!("\n{}\n{}\n\n\n\n", dir.path().display(), missing);
+        let mut output = Vec::new();
+        assert!(run(answers.as_bytes(), &mut output, &opt, &[]).unwrap());
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Can't read the registry at"));
+        assert!(output.contains("Copy the .ifo"));
+    }
+}
@@ -0,0 +1,141 @@
+//! A single file holding the indices of every loaded dictionary, written by the
+//! server on shutdown and read on start so restarts don't read dictionaries one by one.
+use crate::dictionary::{Dictionary, FileStamp, Index, CACHE_MAGIC};
+use crate::registry::Registry;
+use bincode::{deserialize, serialize};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Identifies snapshot files in the current format. They hold `Index` entries like .sozl
+/// caches, so the low byte is the cache version and a new cache format is also a new
+/// snapshot format.
+const SNAPSHOT_MAGIC: u64 = 0x736f_7a6c_736e_0200 | (CACHE_MAGIC & 0xff);
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    ifo_path: PathBuf,
+    /// Stamp of the .idx file, the entry is only used while it still matches.
+    idx: FileStamp,
+    indices: Vec<Index>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    magic: u64,
+    entries: Vec<Entry>,
+}
+
+/// Writes the indices of dictionaries that have them loaded to `path`, returns how
+/// many were written.
+pub fn save<'a>(path: &Path, dicts: impl Iterator<Item = &'a Dictionary>) -> io::Result<usize> {
+    let mut entries = Vec::new();
+    for dictionary in dicts {
+        if let Some(indices) = dictionary.loaded_indices() {
+            entries.push(Entry {
+                ifo_path: Registry::id(dictionary),
+                idx: FileStamp::of(&dictionary.idx_path)?,
+                indices: indices.to_vec(),
+            });
+        }
+    }
+    let count = entries.len();
+    let snapshot = Snapshot {
+        magic: SNAPSHOT_MAGIC,
+        entries,
+    };
+    let bytes = serialize(&snapshot).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    //Written next to the snapshot and renamed so a crash never leaves half of one.
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, bytes)?;
+    fs::rename(&temp, path)?;
+    Ok(count)
+}
+
+/// Gives the dictionaries their indices from the snapshot at `path`, skipping ones
+/// whose .idx file changed since. Returns how many were restored.
+pub fn restore<'a>(path: &Path, dicts: impl Iterator<Item = &'a Dictionary>) -> io::Result<usize> {
+    let bytes = fs::read(path)?;
+    let snapshot: Snapshot =
+        deserialize(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if snapshot.magic != SNAPSHOT_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a snapshot or written by another version",
+        ));
+    }
+    let mut entries: HashMap<PathBuf, Entry> = snapshot
+        .entries
+        .into_iter()
+        .map(|e| (e.ifo_path.clone(), e))
+        .collect();
+    let mut restored = 0;
+    for dictionary in dicts {
+        let entry = match entries.remove(&Registry::id(dictionary)) {
+            Some(n) => n,
+            None => continue,
+        };
+        if FileStamp::of(&dictionary.idx_path).ok().as_ref() != Some(&entry.idx) {
+            debug!(
+                "{} changed since the snapshot.",
+                dictionary.idx_path.display()
+            );
+            continue;
+        }
+        if dictionary.set_indices(entry.indices) {
+            restored += 1;
+        }
+    }
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::write_dictionary;
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_restore_saved_indices() {
+        let dir = tempdir().unwrap();
+        let first = write_dictionary(dir.path(), "first", &[("elma", "apple")]);
+        let second = write_dictionary(dir.path(), "second", &[("armut", "pear")]);
+        let snapshot = dir.path().join("snapshot");
+
+        let loaded = Dictionary::load_metadata(&first).unwrap();
+        let unloaded = Dictionary::load_metadata(&second).unwrap();
+        loaded.indices();
+        assert_eq!(
+            save(&snapshot, vec![&loaded, &unloaded].into_iter()).unwrap(),
+            1
+        );
+
+        let restarted = Dictionary::load_metadata(&first).unwrap();
+        let other = Dictionary::load_metadata(&second).unwrap();
+        assert_eq!(
+            restore(&snapshot, vec![&restarted, &other].into_iter()).unwrap(),
+            1
+        );
+        assert!(restarted.is_index_loaded());
+        assert_eq!(restarted.loaded_indices().unwrap()[0].word.as_str(), "elma");
+        assert!(!other.is_index_loaded());
+    }
+
+    #[test]
+    fn should_skip_dictionaries_changed_since_snapshot() {
+        let dir = tempdir().unwrap();
+        let first = write_dictionary(dir.path(), "first", &[("elma", "apple")]);
+        let snapshot = dir.path().join("snapshot");
+        let loaded = Dictionary::load_metadata(&first).unwrap();
+        loaded.indices();
+        save(&snapshot, std::iter::once(&loaded)).unwrap();
+
+        write_dictionary(dir.path(), "first", &[("armut", "pear"), ("elma", "apple")]);
+        let restarted = Dictionary::load_metadata(&first).unwrap();
+        assert_eq!(restore(&snapshot, std::iter::once(&restarted)).unwrap(), 0);
+        assert!(!restarted.is_index_loaded());
+    }
+}
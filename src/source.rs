@@ -0,0 +1,146 @@
+//! Backends headwords and definitions are read from. StarDict files are read by
+//! `Dictionary` itself, other formats like word lists, databases or remote dictionaries
+//! implement `DictSource` and are wrapped with `Dictionary::from_source`, so every search
+//! of lib.rs works on them without knowing where they come from.
+use crate::dictionary::{stardict_order, Definition, Dictionary, Index};
+use std::cmp::Ordering;
+use std::fmt;
+use std::io;
+
+pub trait DictSource: fmt::Debug + Send + Sync {
+    /// Name of the dictionary, like the bookname of a .ifo file.
+    fn bookname(&self) -> &str;
+
+    /// Language of the headwords, `None` if it isn't known.
+    fn lang(&self) -> Option<&str> {
+        None
+    }
+
+    /// Every entry in any order, each made with `Index::new` from its headword and an
+    /// offset and size the source finds its definition by.
+    fn headwords(&self) -> io::Result<Vec<Index>>;
+
+    /// Definition of an entry given by `headwords`.
+    fn read_definition(&self, index: &Index) -> io::Result<Definition>;
+
+    /// Definitions of the headwords equal to `word` with ASCII letters compared case
+    /// insensitively. Sources that can look words up faster should do it themselves.
+    fn lookup(&self, word: &str) -> io::Result<Vec<Definition>> {
+        self.headwords()?
+            .iter()
+            .filter(|n| stardict_order(&n.word, word) == Ordering::Equal)
+            .map(|n| self.read_definition(n))
+            .collect()
+    }
+
+    /// Number of entries.
+    fn wordcount(&self) -> io::Result<u64> {
+        Ok(self.headwords()?.len() as u64)
+    }
+}
+
+/// StarDict dictionaries read from their .ifo, .idx and .dict files.
+impl DictSource for Dictionary {
+    fn bookname(&self) -> &str {
+        &self.bookname
+    }
+
+    fn lang(&self) -> Option<&str> {
+        self.lang.as_deref()
+    }
+
+    fn headwords(&self) -> io::Result<Vec<Index>> {
+        Ok(self.indices().to_vec())
+    }
+
+    fn read_definition(&self, index: &Index) -> io::Result<Definition> {
+        Dictionary::read_definition(self, index)
+    }
+
+    fn lookup(&self, word: &str) -> io::Result<Vec<Definition>> {
+        self.search_exact(word)
+            .into_iter()
+            .map(|n| Dictionary::read_definition(self, n))
+            .collect()
+    }
+
+    fn wordcount(&self) -> io::Result<u64> {
+        Ok(self.wordcount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search_in_dicts;
+    use crate::tests::write_dictionary;
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    /// Headwords and definitions kept in memory, found by their position.
+    #[derive(Debug)]
+    struct WordList(Vec<(&'static str, &'static str)>);
+
+    impl DictSource for WordList {
+        fn bookname(&self) -> &str {
+            "words"
+        }
+
+        fn headwords(&self) -> io::Result<Vec<Index>> {
+            Ok(self
+                .0
+                .iter()
+                .enumerate()
+                .map(|(n, (word, definition))| Index::new(word, n as u64, definition.len() as u32))
+                .collect())
+        }
+
+        fn read_definition(&self, index: &Index) -> io::Result<Definition> {
+            let (word, definition) = self.0[index.offset() as usize];
+            Ok(Definition::from_text(word, definition))
+        }
+    }
+
+    #[test]
+    fn should_search_other_sources_like_stardict() {
+        let source = WordList(vec![
+            ("elmas", "diamond"),
+            ("armut", "pear"),
+            ("elma", "apple"),
+        ]);
+        assert_eq!(source.wordcount().unwrap(), 3);
+        assert_eq!(source.lookup("ELMA").unwrap()[0].definition, "apple");
+
+        let mut dictionary = Dictionary::from_source(Arc::new(source));
+        dictionary.definition_limit = Some(1);
+        assert_eq!(dictionary.bookname, "words");
+        assert_eq!(dictionary.wordcount, 3);
+        let comp = crate::build_matcher("levenshtein", 1);
+        let pairs = search_in_dicts(
+            &mut std::iter::once(&dictionary),
+            comp.as_ref(),
+            "elmaz",
+            None,
+        );
+        assert_eq!(pairs.len(), 1);
+        let words: Vec<&str> = pairs[0].index.iter().map(|n| n.word.as_str()).collect();
+        assert_eq!(words, vec!["elma", "elmas"]);
+        let definition = pairs[0].read_definition(0).unwrap();
+        assert_eq!(definition.definition, "apple");
+        assert!(!definition.truncated && !dictionary.cuts(pairs[0].index[0]));
+    }
+
+    #[test]
+    fn should_look_up_stardict_headwords() {
+        let dir = tempdir().unwrap();
+        let path = write_dictionary(dir.path(), "tr", &[("Elma", "apple"), ("elmas", "diamond")]);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        let source: &dyn DictSource = &dictionary;
+        assert_eq!(source.bookname(), "tr");
+        assert_eq!(source.wordcount().unwrap(), 2);
+        assert_eq!(source.headwords().unwrap().len(), 2);
+        let found = source.lookup("elma").unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].definition, "apple");
+    }
+}
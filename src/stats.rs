@@ -0,0 +1,105 @@
+//! Statistics of the headwords of an index, computed when its cache is built and stored
+//! in it. Searches skip dictionaries that can't have a match, `info --stats` shows them.
+use crate::dictionary::Index;
+use crate::matcher::Lookup;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexStats {
+    pub entries: u64,
+    /// Lengths of the shortest and longest headwords in chars.
+    pub min_word_len: usize,
+    pub max_word_len: usize,
+    pub mean_word_len: f64,
+    /// How many times each char occurs in the headwords, ASCII letters lowercased as
+    /// lookups compare them.
+    pub letters: BTreeMap<char, u64>,
+    /// Bytes of every definition, uncompressed.
+    pub definitions_size: u64,
+}
+
+impl IndexStats {
+    pub fn of(indices: &[Index]) -> IndexStats {
+        let mut stats = IndexStats {
+            entries: indices.len() as u64,
+            min_word_len: usize::MAX,
+            ..IndexStats::default()
+        };
+        let mut total_len = 0;
+        for index in indices {
+            let mut len = 0;
+            for c in index.word.chars() {
+                *stats.letters.entry(c.to_ascii_lowercase()).or_insert(0) += 1;
+                len += 1;
+            }
+            stats.min_word_len = stats.min_word_len.min(len);
+            stats.max_word_len = stats.max_word_len.max(len);
+            total_len += len;
+            stats.definitions_size += u64::from(index.size());
+        }
+        if indices.is_empty() {
+            stats.min_word_len = 0;
+        } else {
+            stats.mean_word_len = total_len as f64 / indices.len() as f64;
+        }
+        stats
+    }
+
+    /// Whether a headword can match `word` looked up with `lookup`, false when the word
+    /// is too short or long for every headword or has a letter none of them has.
+    pub fn may_match(&self, word: &str, lookup: Lookup) -> bool {
+        let len = word.chars().count();
+        let has_letters = || {
+            word.chars()
+                .all(|c| self.letters.contains_key(&c.to_ascii_lowercase()))
+        };
+        match lookup {
+            Lookup::Exact => self.min_word_len <= len && len <= self.max_word_len && has_letters(),
+            Lookup::Prefix => len <= self.max_word_len && has_letters(),
+            Lookup::Edits(edits) => {
+                self.min_word_len <= len + edits && len <= self.max_word_len + edits
+            }
+        }
+    }
+
+    /// The `n` chars occurring most in the headwords, the most frequent first.
+    pub fn top_letters(&self, n: usize) -> Vec<(char, u64)> {
+        let mut letters: Vec<(char, u64)> = self.letters.iter().map(|(c, n)| (*c, *n)).collect();
+        letters.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        letters.truncate(n);
+        letters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_count_headwords_and_skip_impossible_lookups() {
+        let indices = vec![
+            Index::new("Elma", 0, 5),
+            Index::new("çiçek", 5, 6),
+            Index::new("ev", 11, 4),
+        ];
+        let stats = IndexStats::of(&indices);
+        assert_eq!(stats.entries, 3);
+        assert_eq!((stats.min_word_len, stats.max_word_len), (2, 5));
+        assert!((stats.mean_word_len - 11.0 / 3.0).abs() < 1e-9);
+        assert_eq!(stats.definitions_size, 15);
+        assert_eq!(stats.letters[&'e'], 3);
+        assert_eq!(stats.top_letters(2), vec![('e', 3), ('ç', 2)]);
+
+        assert!(stats.may_match("ELMA", Lookup::Exact));
+        assert!(!stats.may_match("elmalar", Lookup::Exact));
+        assert!(!stats.may_match("x", Lookup::Prefix));
+        assert!(stats.may_match("çi", Lookup::Prefix));
+        assert!(stats.may_match("elmalar", Lookup::Edits(2)));
+        assert!(!stats.may_match("elmalarım", Lookup::Edits(2)));
+
+        let empty = IndexStats::of(&[]);
+        assert_eq!(empty.min_word_len, 0);
+        assert!(!empty.may_match("elma", Lookup::Exact));
+    }
+}
@@ -0,0 +1,261 @@
+//! Requests of editor plugins for `--stdio`, one JSON object per line on the standard
+//! input, each answered with one line on the standard output. A plugin keeps a single
+//! process with loaded indices for a whole editing session.
+//!
+//! `{"id": 1, "method": "search", "word": "elma"}` searches like the terminal does,
+//! `{"id": 2, "method": "complete", "prefix": "elm", "limit": 5}` gives headwords
+//! starting with a prefix and `{"id": 3, "method": "define", "word": "elma"}` the
+//! definitions of exactly that headword. They are answered with
+//! `{"id": 1, "result": ...}` or `{"id": 1, "error": "..."}`.
+use crate::dictionary::{Definition, Dictionary};
+use crate::matcher::{ExactMatcher, PrefixMatcher};
+use crate::normalization::clean_query;
+use crate::{retain_types, search_in_dicts, IndexDictPair};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::io::{self, BufRead, Write};
+
+/// Headwords a `complete` request gives when it has no limit.
+pub const COMPLETION_LIMIT: usize = 20;
+
+#[derive(Deserialize)]
+struct Request {
+    /// Given back in the response so a plugin can match it to the request.
+    #[serde(default)]
+    id: Value,
+    #[serde(flatten)]
+    method: Method,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "method", rename_all = "lowercase")]
+enum Method {
+    Search {
+        word: String,
+        /// Only returns definitions of these types, see `DEFINITION_TYPES`.
+        #[serde(default)]
+        types: Vec<String>,
+    },
+    Complete {
+        prefix: String,
+        limit: Option<usize>,
+    },
+    Define {
+        word: String,
+    },
+}
+
+#[derive(Serialize)]
+struct Response {
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// A definition with the label of the dictionary it's from.
+#[derive(Serialize)]
+struct Entry {
+    dictionary: String,
+    #[serde(flatten)]
+    definition: Definition,
+}
+
+/// Answers the requests read from `input` until it ends. `dicts` are the dictionaries
+/// completions and definitions come from, `search` searches a word the way the
+/// command line would.
+pub fn serve_stdio<'a, R, W, F>(
+    input: R,
+    mut output: W,
+    dicts: &[&'a Dictionary],
+    search: F,
+) -> io::Result<()>
+where
+    R: BufRead,
+    W: Write,
+    F: Fn(&str) -> Vec<IndexDictPair<'a>>,
+{
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = answer(&line, dicts, &search);
+        serde_json::to_writer(&mut output, &response)?;
+        output.write_all(b"\n")?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+fn answer<'a, F>(line: &str, dicts: &[&'a Dictionary], search: &F) -> Response
+where
+    F: Fn(&str) -> Vec<IndexDictPair<'a>>,
+{
+    let request: Request = match serde_json::from_str(line) {
+        Ok(n) => n,
+        Err(e) => {
+            debug!("Can't read request {}: {}", line, e);
+            return Response {
+                //The id is still given back if only the rest of the request is wrong.
+                id: serde_json::from_str::<Value>(line)
+                    .ok()
+                    .and_then(|v| v.get("id").cloned())
+                    .unwrap_or_default(),
+                result: None,
+                error: Some(format!("Invalid request: {}", e)),
+            };
+        }
+    };
+    let result = match request.method {
+        Method::Search { word, types } => clean_query(&word).map(|word| {
+            let mut pairs = search(&word);
+            retain_types(&mut pairs, &types);
+            entries(&pairs)
+        }),
+        Method::Complete { prefix, limit } => Some(Ok(complete(
+            dicts,
+            &clean_query(&prefix).unwrap_or_default(),
+            limit.unwrap_or(COMPLETION_LIMIT),
+        ))),
+        Method::Define { word } => clean_query(&word).map(|word| {
+            entries(&search_in_dicts(
+                &mut dicts.iter().copied(),
+                &ExactMatcher {},
+                &word,
+                None,
+            ))
+        }),
+    };
+    let (result, error) = match result {
+        Some(Ok(n)) => (Some(n), None),
+        Some(Err(e)) => (None, Some(format!("Can't read definitions: {}", e))),
+        None => (
+            None,
+            Some(String::from("The word to search can't be empty.")),
+        ),
+    };
+    Response {
+        id: request.id,
+        result,
+        error,
+    }
+}
+
+/// Definitions of every result of `pairs` in their order.
+fn entries(pairs: &[IndexDictPair]) -> io::Result<Value> {
+    let mut entries = Vec::new();
+    for pair in pairs {
+        for n in 0..pair.index.len() {
+            entries.push(Entry {
+                dictionary: pair.label(),
+                definition: pair.read_definition(n)?,
+            });
+        }
+    }
+    Ok(serde_json::to_value(entries).unwrap())
+}
+
+/// At most `limit` headwords of `dicts` starting with `prefix`, each once, the
+/// shortest first. Nothing for an empty prefix.
+fn complete(dicts: &[&Dictionary], prefix: &str, limit: usize) -> Value {
+    if prefix.is_empty() {
+        return Value::Array(Vec::new());
+    }
+    let pairs = search_in_dicts(&mut dicts.iter().copied(), &PrefixMatcher {}, prefix, None);
+    let mut words: Vec<&str> = pairs
+        .iter()
+        .flat_map(|pair| &pair.index)
+        .map(|index| index.word.as_str())
+        .collect();
+    words.sort_by_key(|w| (w.chars().count(), *w));
+    let mut seen = HashSet::new();
+    words.retain(|w| seen.insert(*w));
+    words.truncate(limit);
+    serde_json::json!(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_matcher;
+    use crate::tests::write_dictionary;
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_answer_each_request_on_its_own_line() {
+        let dir = tempdir().unwrap();
+        let path = write_dictionary(
+            dir.path(),
+            "tr",
+            &[
+                ("elma", "apple"),
+                ("elmas", "diamond"),
+                ("elmacık", "cheekbone"),
+            ],
+        );
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        let dicts = [&dictionary];
+        let comp = build_matcher("levenshtein", 1);
+        let input = concat!(
+            "{\"id\": 1, \"method\": \"search\", \"word\": \"elmx\"}\n",
+            "\n",
+            "{\"id\": 2, \"method\": \"complete\", \"prefix\": \"elm\", \"limit\": 2}\n",
+            "{\"id\": \"d\", \"method\": \"define\", \"word\": \"elmas\"}\n",
+            "{\"id\": 4, \"method\": \"translate\"}\n",
+            "not json\n",
+            "{\"id\": 6, \"method\": \"search\", \"word\": \" \\t\"}\n",
+        );
+        let mut output = Vec::new();
+        serve_stdio(input.as_bytes(), &mut output, &dicts, |word| {
+            search_in_dicts(&mut dicts.iter().copied(), comp.as_ref(), word, None)
+        })
+        .unwrap();
+        let responses: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(responses.len(), 6);
+
+        assert_eq!(responses[0]["id"], 1);
+        let words: Vec<&str> = responses[0]["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["word"].as_str().unwrap())
+            .collect();
+        assert_eq!(words, vec!["elma"]);
+        assert_eq!(responses[0]["result"][0]["dictionary"], "tr");
+        assert_eq!(responses[0]["result"][0]["definition"], "apple");
+
+        assert_eq!(responses[1]["result"], serde_json::json!(["elma", "elmas"]));
+
+        assert_eq!(responses[2]["id"], "d");
+        assert_eq!(responses[2]["result"][0]["definition"], "diamond");
+        assert_eq!(responses[2]["result"].as_array().unwrap().len(), 1);
+
+        assert_eq!(responses[3]["id"], 4);
+        assert!(responses[3]["error"].is_string());
+        assert!(responses[3].get("result").is_none());
+        assert_eq!(responses[4]["id"], Value::Null);
+        assert!(responses[4]["error"].is_string());
+        assert_eq!(responses[5]["id"], 6);
+        assert!(responses[5]["error"].is_string());
+    }
+
+    #[test]
+    fn should_not_complete_empty_prefix() {
+        let dir = tempdir().unwrap();
+        let path = write_dictionary(dir.path(), "tr", &[("elma", "apple")]);
+        let dictionary = Dictionary::load_metadata(&path).unwrap();
+        assert_eq!(complete(&[&dictionary], "", 5), serde_json::json!([]));
+        assert_eq!(
+            complete(&[&dictionary], "el", 5),
+            serde_json::json!(["elma"])
+        );
+    }
+}
@@ -0,0 +1,131 @@
+//! Minimal support for running under systemd, socket activation through
+//! `LISTEN_FDS` and readiness notification through `NOTIFY_SOCKET`.
+//! Both are no-ops when the variables are not set or not on Unix.
+use log::{debug, warn};
+use std::env;
+use std::net::TcpListener;
+
+/// First file descriptor passed by systemd, see sd_listen_fds(3).
+#[cfg(unix)]
+const SD_LISTEN_FDS_START: i32 = 3;
+
+/// Returns the listening socket passed by systemd, if this process was socket activated.
+/// Only the first socket is used when there are more.
+#[cfg(unix)]
+pub fn listen_fds() -> Option<TcpListener> {
+    use std::os::unix::io::FromRawFd;
+
+    let pid: u32 = env::var("LISTEN_PID").ok()?.parse().ok()?;
+    if pid != std::process::id() {
+        debug!("LISTEN_PID is set for another process.");
+        return None;
+    }
+    let fds: i32 = env::var("LISTEN_FDS").ok()?.parse().ok()?;
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+    if fds < 1 {
+        return None;
+    }
+    if fds > 1 {
+        warn!(
+            "systemd passed {} sockets, only the first one is used.",
+            fds
+        );
+    }
+    //Safe as long as systemd is the one that set LISTEN_PID to our pid, the fd is then
+    //an open listening socket owned by nobody else in this process.
+    let listener = unsafe { TcpListener::from_raw_fd(SD_LISTEN_FDS_START) };
+    Some(listener)
+}
+
+#[cfg(not(unix))]
+pub fn listen_fds() -> Option<TcpListener> {
+    None
+}
+
+/// Tells systemd that the service finished starting up, for `Type=notify` units.
+#[cfg(unix)]
+pub fn notify_ready() {
+    notify("READY=1");
+}
+
+#[cfg(not(unix))]
+pub fn notify_ready() {}
+
+/// Tells systemd that the service is shutting down.
+#[cfg(unix)]
+pub fn notify_stopping() {
+    notify("STOPPING=1");
+}
+
+#[cfg(not(unix))]
+pub fn notify_stopping() {}
+
+#[cfg(unix)]
+fn notify(state: &str) {
+    let path = match env::var("NOTIFY_SOCKET") {
+        Ok(n) => n,
+        Err(_) => return,
+    };
+    match send_state(&path, state) {
+        Ok(_) => debug!("Sent {} to systemd.", state),
+        Err(e) => warn!("Can't notify systemd: {}", e),
+    }
+}
+
+/// Sends `state` to the socket at `path`, a name starting with `@` is an abstract socket.
+#[cfg(unix)]
+fn send_state(path: &str, state: &str) -> std::io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let socket = UnixDatagram::unbound()?;
+    if let Some(name) = path.strip_prefix('@') {
+        send_to_abstract(&socket, name, state)
+    } else {
+        socket.send_to(state.as_bytes(), path).map(|_| ())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn send_to_abstract(
+    socket: &std::os::unix::net::UnixDatagram,
+    name: &str,
+    state: &str,
+) -> std::io::Result<()> {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::SocketAddr;
+
+    let addr = SocketAddr::from_abstract_name(name.as_bytes())?;
+    socket.send_to_addr(state.as_bytes(), &addr).map(|_| ())
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn send_to_abstract(
+    _socket: &std::os::unix::net::UnixDatagram,
+    _name: &str,
+    _state: &str,
+) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "abstract sockets are only supported on Linux",
+    ))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixDatagram;
+    use tempfile::tempdir;
+
+    #[test]
+    fn should_notify_through_socket_path() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("notify");
+        let receiver = UnixDatagram::bind(&path).unwrap();
+        send_state(path.to_str().unwrap(), "READY=1").unwrap();
+        let mut buffer = [0; 16];
+        let n = receiver.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..n], b"READY=1");
+    }
+}
@@ -0,0 +1,258 @@
+//! Shows pictures of definitions inline in terminals with the kitty, iTerm2 or sixel
+//! graphics protocol, otherwise stores them in the resource cache and prints their path.
+//! Sixels are only drawn from the images `Bitmap` can decode, the paths of others are
+//! printed.
+use crate::bitmap::Bitmap;
+use crate::resource_cache::{sanitize, ResourceCache};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use log::debug;
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Graphics protocols images can be sent to the terminal with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    /// Kitty's graphics protocol, only PNG images are sent as they are.
+    Kitty,
+    /// iTerm2's inline images, also understood by WezTerm.
+    Iterm2,
+    /// DEC sixel graphics, drawn with the colors of a 6x6x6 color cube.
+    Sixel,
+}
+
+/// Bytes of base64 sent in each kitty escape sequence, the most it accepts.
+const KITTY_CHUNK: usize = 4096;
+
+/// Widest and tallest images drawn as sixels, larger ones are scaled down.
+const SIXEL_MAX_WIDTH: usize = 800;
+const SIXEL_MAX_HEIGHT: usize = 600;
+
+/// Protocol of the terminal from its environment variables, `None` if it has none we
+/// know.
+pub fn detect() -> Option<Protocol> {
+    let var = |name: &str| env::var(name).unwrap_or_default();
+    protocol_of(
+        &var("TERM"),
+        &var("TERM_PROGRAM"),
+        env::var_os("KITTY_WINDOW_ID").is_some(),
+    )
+}
+
+fn protocol_of(term: &str, term_program: &str, kitty_window: bool) -> Option<Protocol> {
+    if kitty_window || term == "xterm-kitty" || term_program == "ghostty" {
+        Some(Protocol::Kitty)
+    } else if term_program == "iTerm.app" || term_program == "WezTerm" {
+        Some(Protocol::Iterm2)
+    } else if term.starts_with("foot") || term.starts_with("mlterm") || term.contains("sixel") {
+        Some(Protocol::Sixel)
+    } else {
+        None
+    }
+}
+
+/// File extension of an image from its first bytes, `bin` if it's not one we know.
+pub fn extension(data: &[u8]) -> &'static str {
+    if data.starts_with(b"\x89PNG") {
+        "png"
+    } else if data.starts_with(b"\xff\xd8\xff") {
+        "jpg"
+    } else if data.starts_with(b"GIF8") {
+        "gif"
+    } else if data.starts_with(b"BM") {
+        "bmp"
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        "webp"
+    } else if data.starts_with(b"<svg") || data.starts_with(b"<?xml") {
+        "svg"
+    } else {
+        "bin"
+    }
+}
+
+/// Escape sequences drawing `data` with `protocol`, `None` if it can't draw the image.
+pub fn encode(protocol: Protocol, data: &[u8]) -> Option<String> {
+    if protocol == Protocol::Sixel {
+        let bitmap = Bitmap::decode(data)?.fit(SIXEL_MAX_WIDTH, SIXEL_MAX_HEIGHT);
+        return Some(sixel(&bitmap));
+    }
+    let encoded = STANDARD.encode(data);
+    match protocol {
+        Protocol::Kitty if extension(data) == "png" => {
+            let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK).collect();
+            let mut sequence = String::new();
+            for (i, chunk) in chunks.iter().enumerate() {
+                let more = if i + 1 < chunks.len() { 1 } else { 0 };
+                let keys = if i == 0 {
+                    format!("f=100,a=T,m={}", more)
+                } else {
+                    format!("m={}", more)
+                };
+                //Base64 is ASCII.
+                let chunk = std::str::from_utf8(chunk).unwrap();
+                sequence.push_str(&format!("\x1b_G{};{}\x1b\\", keys, chunk));
+            }
+            Some(sequence)
+        }
+        Protocol::Kitty | Protocol::Sixel => None,
+        Protocol::Iterm2 => Some(format!(
+            "\x1b]1337;File=inline=1;size={}:{}\x07",
+            data.len(),
+            encoded
+        )),
+    }
+}
+
+/// Appends the sixel `c` repeated `count` times, run length encoded when it's shorter.
+fn push_sixels(sequence: &mut String, c: char, count: usize) {
+    if count > 3 {
+        sequence.push_str(&format!("!{}{}", count, c));
+    } else {
+        sequence.extend(std::iter::repeat_n(c, count));
+    }
+}
+
+/// Sixel escape sequence drawing `bitmap`. Colors are rounded to the nearest one of a
+/// 6x6x6 color cube, mostly transparent pixels are left out.
+fn sixel(bitmap: &Bitmap) -> String {
+    let level = |v: u8| (v as usize * 5 + 127) / 255;
+    let colors: Vec<Option<usize>> = bitmap
+        .pixels
+        .chunks(4)
+        .map(|p| Some(36 * level(p[0]) + 6 * level(p[1]) + level(p[2])).filter(|_| p[3] >= 128))
+        .collect();
+    let mut sequence = format!("\x1bP0;1;0q\"1;1;{};{}", bitmap.width, bitmap.height);
+    let mut defined = [false; 216];
+    for top in (0..bitmap.height).step_by(6) {
+        let band = &colors[top * bitmap.width..bitmap.height.min(top + 6) * bitmap.width];
+        let mut used: Vec<usize> = band.iter().flatten().copied().collect();
+        used.sort_unstable();
+        used.dedup();
+        for color in used {
+            if defined[color] {
+                sequence.push_str(&format!("#{}", color));
+            } else {
+                let (r, g, b) = (color / 36, color / 6 % 6, color % 6);
+                sequence.push_str(&format!("#{};2;{};{};{}", color, r * 20, g * 20, b * 20));
+                defined[color] = true;
+            }
+            let mut run = ('?', 0);
+            for x in 0..bitmap.width {
+                let bits = band
+                    .chunks(bitmap.width)
+                    .enumerate()
+                    .filter(|(_, row)| row[x] == Some(color))
+                    .fold(0, |bits, (y, _)| bits | 1 << y);
+                let c = char::from(63 + bits);
+                if c == run.0 {
+                    run.1 += 1;
+                } else {
+                    push_sixels(&mut sequence, run.0, run.1);
+                    run = (c, 1);
+                }
+            }
+            push_sixels(&mut sequence, run.0, run.1);
+            sequence.push('$');
+        }
+        sequence.push('-');
+    }
+    sequence.push_str("\x1b\\");
+    sequence
+}
+
+/// Name of the file a picture of `word` is stored in, the same for the same picture.
+pub fn file_name(data: &[u8], word: &str) -> String {
+    format!(
+        "{}-{:08x}.{}",
+        sanitize(word),
+        crc32fast::hash(data),
+        extension(data)
+    )
+}
+
+/// Draws `data` in the terminal or prints the path of a file holding it, `path` if
+/// the image already is in one. Otherwise it's written to `cache`.
+pub fn show(data: &[u8], word: &str, path: Option<&Path>, cache: &ResourceCache) -> io::Result<()> {
+    if let Some(sequence) = detect().and_then(|p| encode(p, data)) {
+        println!("{}", sequence);
+        return Ok(());
+    }
+    let path: PathBuf = match path {
+        Some(n) => n.to_path_buf(),
+        None => cache.store(&file_name(data, word), data)?,
+    };
+    debug!("Terminal can't draw images, printing the path.");
+    println!("[image: {}]", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_detect_protocol_of_terminal() {
+        assert_eq!(protocol_of("xterm-kitty", "", false), Some(Protocol::Kitty));
+        assert_eq!(protocol_of("xterm", "", true), Some(Protocol::Kitty));
+        assert_eq!(
+            protocol_of("xterm-256color", "iTerm.app", false),
+            Some(Protocol::Iterm2)
+        );
+        assert_eq!(protocol_of("foot", "", false), Some(Protocol::Sixel));
+        assert_eq!(protocol_of("xterm-256color", "", false), None);
+    }
+
+    #[test]
+    fn should_encode_images_for_protocols() {
+        let png = b"\x89PNG\r\n";
+        assert_eq!(
+            encode(Protocol::Kitty, png).unwrap(),
+            "\x1b_Gf=100,a=T,m=0;iVBORw0K\x1b\\"
+        );
+        assert_eq!(encode(Protocol::Kitty, b"GIF89a"), None);
+        assert_eq!(
+            encode(Protocol::Iterm2, b"GIF89a").unwrap(),
+            "\x1b]1337;File=inline=1;size=6:R0lGODlh\x07"
+        );
+        let large = [&png[..], &[0; 4000]].concat();
+        assert_eq!(
+            encode(Protocol::Kitty, &large)
+                .unwrap()
+                .matches("\x1b_G")
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn should_encode_decodable_images_as_sixels() {
+        assert_eq!(encode(Protocol::Sixel, b"GIF89a"), None);
+        //A red pixel over a blue one, then a transparent one over a red one.
+        let rows = [
+            &[0, 255, 0, 0, 255, 0, 0, 0, 0][..],
+            &[0, 0, 0, 255, 255, 255, 0, 0, 255],
+        ]
+        .concat();
+        let png = crate::bitmap::tests::png(2, 2, 8, 6, &[], &rows);
+        assert_eq!(
+            encode(Protocol::Sixel, &png).unwrap(),
+            "\x1bP0;1;0q\"1;1;2;2#5;2;0;0;100A?$#180;2;100;0;0@A$-\x1b\\"
+        );
+
+        let wide = Bitmap {
+            width: 5,
+            height: 1,
+            pixels: [0, 0, 0, 255].repeat(5),
+        };
+        assert_eq!(sixel(&wide), "\x1bP0;1;0q\"1;1;5;1#0;2;0;0;0!5@$-\x1b\\");
+    }
+
+    #[test]
+    fn should_name_image_files_with_their_extension() {
+        let name = file_name(b"\xff\xd8\xff\xe0", "el/ma");
+        assert!(name.starts_with("elma-"));
+        assert!(name.ends_with(".jpg"));
+        assert_eq!(name, file_name(b"\xff\xd8\xff\xe0", "el/ma"));
+    }
+}
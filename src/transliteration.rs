@@ -0,0 +1,108 @@
+//! Alternative spellings of a query for users typing without their language's
+//! keyboard, "cicek" is also searched as "çiçek".
+use std::collections::HashMap;
+
+/// Upper bound of variants generated for one query, each one is a separate search.
+const MAX_VARIANTS: usize = 32;
+
+/// Mapping of ASCII letters to the letters they may stand for, keyed by name.
+const BUILTIN_TABLES: &[(&str, &[(char, &str)])] = &[(
+    "tr",
+    &[
+        ('c', "ç"),
+        ('s', "ş"),
+        ('g', "ğ"),
+        ('u', "ü"),
+        ('o', "ö"),
+        ('i', "ı"),
+        ('C', "Ç"),
+        ('S', "Ş"),
+        ('G', "Ğ"),
+        ('U', "Ü"),
+        ('O', "Ö"),
+        ('I', "İ"),
+    ],
+)];
+
+/// Names of the tables `builtin_table` knows.
+pub fn builtin_names() -> Vec<&'static str> {
+    BUILTIN_TABLES.iter().map(|(name, _)| *name).collect()
+}
+
+/// Table named `name` in the format of the settings file, a letter and the letters
+/// it may stand for.
+pub fn builtin_table(name: &str) -> Option<HashMap<String, String>> {
+    BUILTIN_TABLES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, table)| {
+            table
+                .iter()
+                .map(|(from, to)| (from.to_string(), to.to_string()))
+                .collect()
+        })
+}
+
+/// `word` followed by the spellings it may stand for according to `table`, at most
+/// `MAX_VARIANTS` of them. Keys of `table` that aren't a single letter are ignored.
+pub fn variants(word: &str, table: &HashMap<String, String>) -> Vec<String> {
+    let mut variants = vec![String::new()];
+    for c in word.chars() {
+        let replacements = table
+            .iter()
+            .find(|(from, _)| from.chars().eq(std::iter::once(c)))
+            .map(|(_, to)| to.as_str())
+            .unwrap_or_default();
+        let mut next = Vec::with_capacity(variants.len());
+        for variant in &variants {
+            let mut kept = variant.clone();
+            kept.push(c);
+            next.push(kept);
+        }
+        //Variants that replace a letter come after every one that keeps it, so the
+        //closest spellings survive when the limit is hit.
+        for to in replacements.chars() {
+            for variant in &variants {
+                if next.len() == MAX_VARIANTS {
+                    break;
+                }
+                let mut replaced = variant.clone();
+                replaced.push(to);
+                next.push(replaced);
+            }
+        }
+        variants = next;
+    }
+    variants
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_generate_turkish_spellings() {
+        let table = builtin_table("tr").unwrap();
+        let variants = variants("cicek", &table);
+        assert_eq!(variants[0], "cicek");
+        assert!(variants.contains(&String::from("çiçek")));
+        assert!(variants.contains(&String::from("çıçek")));
+        //c, i and c each have one alternative.
+        assert_eq!(variants.len(), 8);
+    }
+
+    #[test]
+    fn should_keep_words_without_mapped_letters() {
+        let table = builtin_table("tr").unwrap();
+        assert_eq!(variants("elma", &table), vec!["elma"]);
+        assert_eq!(variants("cicek", &HashMap::new()), vec!["cicek"]);
+    }
+
+    #[test]
+    fn should_limit_number_of_variants() {
+        let table = builtin_table("tr").unwrap();
+        let variants = variants("cocuksusucu", &table);
+        assert_eq!(variants.len(), MAX_VARIANTS);
+        assert_eq!(variants[0], "cocuksusucu");
+    }
+}
@@ -0,0 +1,105 @@
+//! Word lists for spellcheckers made from the headwords of dictionaries, for
+//! `sozluk wordlist`. hunspell and aspell take one word per line.
+use crate::dictionary::Dictionary;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+
+/// Headwords of `dictionaries` that are single words and pass their headword filters,
+/// each once, in the order they are first found.
+pub fn headwords<'a>(dictionaries: &[&'a Dictionary]) -> Vec<&'a str> {
+    let mut seen = HashSet::new();
+    let mut words = Vec::new();
+    for dictionary in dictionaries {
+        for index in dictionary.indices() {
+            let word = index.word.as_str();
+            if word.is_empty()
+                || word.contains(char::is_whitespace)
+                || !dictionary.headword_filter.accepts(word)
+            {
+                continue;
+            }
+            if seen.insert(word) {
+                words.push(word);
+            }
+        }
+    }
+    words
+}
+
+/// Reads a frequency list, lines of a word and its count separated by whitespace.
+/// Lines without a count are skipped, a word given twice takes the sum of its counts.
+pub fn read_frequencies<R: BufRead>(reader: R) -> io::Result<HashMap<String, u64>> {
+    let mut frequencies = HashMap::new();
+    for line in reader.lines() {
+        let line = line?;
+        let mut fields = line.split_whitespace();
+        let word = fields.next();
+        if let (Some(word), Some(Ok(count))) = (word, fields.next().map(str::parse::<u64>)) {
+            *frequencies.entry(word.to_string()).or_insert(0) += count;
+        }
+    }
+    Ok(frequencies)
+}
+
+/// Writes `words` one per line. With `frequencies` each word is followed by a tab and
+/// its count, 0 if the list doesn't have it, the most frequent words first.
+pub fn write_wordlist<W: Write>(
+    out: &mut W,
+    words: &[&str],
+    frequencies: Option<&HashMap<String, u64>>,
+) -> io::Result<()> {
+    match frequencies {
+        Some(frequencies) => {
+            let mut counted: Vec<(&str, u64)> = words
+                .iter()
+                .map(|w| (*w, frequencies.get(*w).copied().unwrap_or(0)))
+                .collect();
+            counted.sort_by_key(|(_, count)| Reverse(*count));
+            for (word, count) in counted {
+                writeln!(out, "{}\t{}", word, count)?;
+            }
+        }
+        None => {
+            for word in words {
+                writeln!(out, "{}", word)?;
+            }
+        }
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::write_dictionary;
+
+    #[test]
+    fn should_write_single_headwords_with_frequencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let tr = write_dictionary(
+            dir.path(),
+            "tr",
+            &[("armut", "pear"), ("elma", "apple"), ("elma ağacı", "tree")],
+        );
+        let other = write_dictionary(dir.path(), "other", &[("elma", "apple"), ("erik", "plum")]);
+        let tr = Dictionary::load_metadata(&tr).unwrap();
+        let other = Dictionary::load_metadata(&other).unwrap();
+        let words = headwords(&[&tr, &other]);
+        assert_eq!(words, vec!["armut", "elma", "erik"]);
+
+        let mut out = Vec::new();
+        write_wordlist(&mut out, &words, None).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "armut\nelma\nerik\n");
+
+        let list = "elma 3\nerik\tx\nerik 5\nelma 4\n\n";
+        let frequencies = read_frequencies(list.as_bytes()).unwrap();
+        assert_eq!(frequencies.len(), 2);
+        let mut out = Vec::new();
+        write_wordlist(&mut out, &words, Some(&frequencies)).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "elma\t7\nerik\t5\narmut\t0\n"
+        );
+    }
+}